@@ -0,0 +1,123 @@
+//! Runs the same programs through the tree-walking interpreter and the VM.
+//!
+//! Run with `cargo +nightly bench`, or `cargo +nightly bench -- fib` for a
+//! single workload. Criterion keeps the previous results in `target/criterion`
+//! and reports the change against them.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use monkey_interp::{compile_and_run, eval_str};
+
+// Recursion goes through a parameter since the compiler has no global
+// self reference
+const WORKLOADS: [(&str, &str); 3] = [
+    (
+        "fib",
+        "let fib = fn(fib, n) { if (n < 2) { n } else { fib(fib, n - 1) + fib(fib, n - 2) } };
+        fib(fib, 20);",
+    ),
+    (
+        "array_building",
+        "let build = fn(build, arr, n) { if (n == 0) { arr } else { build(build, push(arr, n), n - 1) } };
+        len(build(build, [], 300));",
+    ),
+    (
+        "string_concat",
+        r#"let repeat = fn(repeat, s, n) { if (n == 0) { s } else { repeat(repeat, s + "ab", n - 1) } };
+        len(repeat(repeat, "", 300));"#,
+    ),
+];
+
+fn interpreters(c: &mut Criterion) {
+    for (name, src) in WORKLOADS {
+        assert_eq!(eval_str(src), compile_and_run(src), "{} differs", name);
+
+        let mut group = c.benchmark_group(name);
+        group.bench_function("eval", |b| b.iter(|| eval_str(src).unwrap()));
+        group.bench_function("vm", |b| b.iter(|| compile_and_run(src).unwrap()));
+        group.finish();
+    }
+}
+
+/// `push(push(push([], x), x), x)` nested `n` deep. Every intermediate array
+/// is only referenced by the next call, so it gets extended in place and the
+/// run time should grow linearly with `n`
+fn nested_push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nested_push");
+    for n in [100, 200, 400] {
+        let src = format!(
+            "let x = 1; len({}[]{})",
+            "push(".repeat(n),
+            ", x)".repeat(n)
+        );
+        assert_eq!(
+            compile_and_run(&src),
+            Ok(monkey_interp::eval::Object::Integer(n as i64))
+        );
+
+        group.bench_with_input(BenchmarkId::new("eval", n), &src, |b, src| {
+            b.iter(|| eval_str(src).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("vm", n), &src, |b, src| {
+            b.iter(|| compile_and_run(src).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// `a = push(a, i)` in a loop running `n` times. The array is moved out of
+/// `a` for each call, so it gets extended in place and the run time should
+/// grow linearly with `n`
+fn push_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push_loop");
+    for n in [1000, 2000, 4000] {
+        let src = format!(
+            "let a = []; let i = 0; while (i < {}) {{ a = push(a, i); i += 1; }} len(a)",
+            n
+        );
+        assert_eq!(
+            compile_and_run(&src),
+            Ok(monkey_interp::eval::Object::Integer(n as i64))
+        );
+
+        group.bench_with_input(BenchmarkId::new("eval", n), &src, |b, src| {
+            b.iter(|| eval_str(src).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("vm", n), &src, |b, src| {
+            b.iter(|| compile_and_run(src).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// `a[i] = i` over an array of length `n`. The array is updated in place, so
+/// the run time should grow linearly with `n`
+fn index_assign_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index_assign_loop");
+    for n in [1000, 2000, 4000] {
+        let src = format!(
+            "let a = range(0, {}); let i = 0; while (i < {}) {{ a[i] = i * 2; i += 1; }} a[-1]",
+            n, n
+        );
+        assert_eq!(
+            compile_and_run(&src),
+            Ok(monkey_interp::eval::Object::Integer((n as i64 - 1) * 2))
+        );
+
+        group.bench_with_input(BenchmarkId::new("eval", n), &src, |b, src| {
+            b.iter(|| eval_str(src).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("vm", n), &src, |b, src| {
+            b.iter(|| compile_and_run(src).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    interpreters,
+    nested_push,
+    push_loop,
+    index_assign_loop
+);
+criterion_main!(benches);
@@ -1,13 +1,58 @@
+mod modify;
 mod parser;
-use crate::lexer::TokenType;
+mod source;
+use crate::{eval::BigInt, lexer::TokenType};
 use std::fmt::Display;
 
-pub use parser::Parser;
+pub use modify::{modify_expr, modify_program};
+pub use parser::{ParseErrorKind, Parser};
 
 pub type Ident = String;
 
+/// An identifier used as a value, along with where it is written. Ones the
+/// program builds itself, like in macro expansions, are at line 0
+#[derive(Debug, Clone)]
+pub struct IdentExpr {
+    pub name: Ident,
+    pub line: usize,
+    pub col: usize,
+}
+
+// Where an identifier is written doesn't change what it means
+impl PartialEq for IdentExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for IdentExpr {}
+
+impl From<&str> for IdentExpr {
+    fn from(name: &str) -> Self {
+        Ident::from(name).into()
+    }
+}
+
+impl From<Ident> for IdentExpr {
+    fn from(name: Ident) -> Self {
+        Self {
+            name,
+            line: 0,
+            col: 0,
+        }
+    }
+}
+
+impl Display for IdentExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 pub struct Program {
     pub statements: Vec<Statement>,
+    /// Line each statement starts on
+    pub lines: Vec<usize>,
 }
 
 impl Display for Program {
@@ -19,19 +64,53 @@ impl Display for Program {
     }
 }
 
+/// Statements between `{` and `}`, with the line each one starts on
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+    pub lines: Vec<usize>,
+}
+
+impl std::ops::Deref for Block {
+    type Target = [Statement];
+
+    fn deref(&self) -> &Self::Target {
+        &self.statements
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Statement {
     Let(LetStmt),
+    /// `const name = expr;`, a binding which can't be reassigned
+    Const(LetStmt),
+    LetArray(LetArrayStmt),
+    Assign(AssignStmt),
+    AssignArray(AssignArrayStmt),
+    AssignIndex(AssignIndexStmt),
     Return(ReturnStmt),
     Expression(Expression),
+    While(WhileStmt),
+    For(ForStmt),
+    Break,
+    Continue,
 }
 
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Statement::Let(s) => write!(f, "{}", s),
+            Statement::Const(s) => write!(f, "const {} = {};", s.ident, s.expr),
+            Statement::LetArray(s) => write!(f, "{}", s),
+            Statement::Assign(s) => write!(f, "{} = {};", s.ident, s.expr),
+            Statement::AssignArray(s) => write!(f, "{}", s),
+            Statement::AssignIndex(s) => write!(f, "{}", s),
             Statement::Return(s) => write!(f, "{}", s),
             Statement::Expression(s) => write!(f, "{}", s),
+            Statement::While(s) => write!(f, "{}", s),
+            Statement::For(s) => write!(f, "{}", s),
+            Statement::Break => write!(f, "break;"),
+            Statement::Continue => write!(f, "continue;"),
         }
     }
 }
@@ -41,36 +120,178 @@ pub struct LetStmt {
     pub ident: Ident,
     pub expr: Expression,
 }
+/// `let [a, b, ...rest] = expr;`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LetArrayStmt {
+    pub names: Vec<Ident>,
+    pub rest: Option<Ident>,
+    pub expr: Expression,
+}
+/// `name = expr;`, assigns to an existing binding
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AssignStmt {
+    pub ident: Ident,
+    pub expr: Expression,
+}
+/// `[a, b] = expr;`, assigns to existing bindings
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AssignArrayStmt {
+    pub names: Vec<Ident>,
+    pub expr: Expression,
+}
+/// `name[i][j] = expr;`, replaces an element of an existing binding
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AssignIndexStmt {
+    pub ident: Ident,
+    pub indexes: Vec<Expression>,
+    pub expr: Expression,
+}
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ReturnStmt {
     pub expr: Expression,
 }
+/// `while (condition) { body }`, runs the body for as long as the condition
+/// is truthy
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WhileStmt {
+    pub condition: Expression,
+    pub body: Block,
+}
+/// `for (ident in iterable) { body }`, runs the body once for each element
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ForStmt {
+    pub ident: Ident,
+    pub iterable: Expression,
+    pub body: Block,
+}
+
+impl AssignStmt {
+    /// The callee and the other arguments when this is `name = f(name, ...)`
+    /// and those arguments can't read `name` or run code that might, so
+    /// `name` can be handed to `f` without keeping a copy of it
+    pub fn update_call(&self) -> Option<(&Ident, &[Expression])> {
+        let Expression::Call(call) = &self.expr else {
+            return None;
+        };
+        let Expression::Ident(callee) = &*call.func else {
+            return None;
+        };
+        match call.arguments.split_first()? {
+            (Expression::Ident(first), rest)
+                if first.name == self.ident
+                    && rest.iter().all(|arg| arg.is_plain_without(&self.ident)) =>
+            {
+                Some((&callee.name, rest))
+            }
+            _ => None,
+        }
+    }
+}
 
 impl Display for LetStmt {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "let {} = {};", self.ident, self.expr)
     }
 }
+impl Display for LetArrayStmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<String> = self.names.clone();
+        if let Some(rest) = &self.rest {
+            names.push(format!("...{}", rest));
+        }
+        write!(f, "let [{}] = {};", names.join(", "), self.expr)
+    }
+}
+impl Display for AssignArrayStmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] = {};", self.names.join(", "), self.expr)
+    }
+}
+impl Display for AssignIndexStmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.ident)?;
+        for i in &self.indexes {
+            write!(f, "[{}]", i)?;
+        }
+        write!(f, " = {};", self.expr)
+    }
+}
 impl Display for ReturnStmt {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "return {};", self.expr)
     }
 }
+impl Display for ForStmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "for ({} in {}) {{", self.ident, self.iterable)?;
+        for s in self.body.iter() {
+            writeln!(f, "  {}", s)?;
+        }
+        write!(f, "}}")
+    }
+}
+impl Display for WhileStmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "while ({}) {{", self.condition)?;
+        for s in self.body.iter() {
+            writeln!(f, "  {}", s)?;
+        }
+        write!(f, "}}")
+    }
+}
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
-    Ident(Ident),
+    Ident(IdentExpr),
     Number(i64),
+    /// An integer literal too large for an `i64`
+    BigNumber(BigInt),
+    Float(f64),
     String(String),
+    /// `"a {x} b"`
+    Interpolated(InterpolatedExpr),
     Prefix(PrefixExpr),
     Infix(InfixExpr),
     Bool(bool),
+    Null,
     If(IfExpr),
     Func(FuncExpr),
+    Macro(MacroExpr),
     Call(CallExpr),
     Array(ArrayExpr),
     Index(IndexExpr),
+    /// `left[start:end]`, either bound may be left out
+    Slice(SliceExpr),
+    /// `module.name`
+    Member(MemberExpr),
     Hash(HashExpr),
+    /// `{ stmts }` in expression position, valued as its last statement
+    Block(Block),
+}
+
+// Float literals are never NaN, so comparing them is reflexive
+impl Eq for Expression {}
+
+impl Expression {
+    /// Whether this only combines literals and variables other than `name`,
+    /// so evaluating it calls nothing and doesn't depend on `name`
+    fn is_plain_without(&self, name: &str) -> bool {
+        match self {
+            Expression::Ident(i) => i.name != name,
+            Expression::Number(_)
+            | Expression::BigNumber(_)
+            | Expression::Float(_)
+            | Expression::String(_)
+            | Expression::Bool(_)
+            | Expression::Null => true,
+            Expression::Prefix(p) => p.right.is_plain_without(name),
+            Expression::Infix(i) => i.left.is_plain_without(name) && i.right.is_plain_without(name),
+            Expression::Index(i) => i.left.is_plain_without(name) && i.index.is_plain_without(name),
+            Expression::Array(a) => a.elements.iter().all(|e| e.is_plain_without(name)),
+            Expression::Interpolated(i) => i.exprs.iter().all(|e| e.is_plain_without(name)),
+            _ => false,
+        }
+    }
 }
 
 impl Display for Expression {
@@ -78,20 +299,65 @@ impl Display for Expression {
         match self {
             Expression::Ident(i) => write!(f, "{}", i),
             Expression::Number(x) => write!(f, "{}", x),
+            Expression::BigNumber(x) => write!(f, "{}", x),
+            Expression::Float(x) => write!(f, "{:?}", x),
             Expression::String(s) => write!(f, "{}", s),
+            Expression::Interpolated(i) => write!(f, "{}", i),
             Expression::Prefix(p) => write!(f, "{}", p),
             Expression::Infix(p) => write!(f, "{}", p),
             Expression::Bool(b) => write!(f, "{}", b),
+            Expression::Null => write!(f, "null"),
             Expression::If(i) => write!(f, "{}", i),
             Expression::Func(i) => write!(f, "{}", i),
+            Expression::Macro(i) => write!(f, "{}", i),
             Expression::Call(i) => write!(f, "{}", i),
             Expression::Array(i) => write!(f, "{}", i),
             Expression::Index(i) => write!(f, "{}", i),
+            Expression::Slice(s) => write!(f, "{}", s),
+            Expression::Member(m) => write!(f, "{}", m),
             Expression::Hash(i) => write!(f, "{}", i),
+            Expression::Block(b) => {
+                writeln!(f, "{{")?;
+                for s in b.iter() {
+                    writeln!(f, "  {}", s)?;
+                }
+                write!(f, "}}")
+            }
         }
     }
 }
 
+/// Both engines run it as a call to the `format` builtin, which user code
+/// can't shadow here
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct InterpolatedExpr {
+    /// The text before, between and after the expressions, so there is
+    /// always one more of them than of `exprs`
+    pub parts: Vec<String>,
+    pub exprs: Vec<Expression>,
+}
+
+impl InterpolatedExpr {
+    /// The `format` template with `{}` for each expression, braces in the
+    /// text are doubled so they come out as written
+    pub fn template(&self) -> String {
+        self.parts
+            .iter()
+            .map(|p| p.replace('{', "{{").replace('}', "}}"))
+            .collect::<Vec<_>>()
+            .join("{}")
+    }
+}
+
+impl Display for InterpolatedExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (part, expr) in self.parts.iter().zip(&self.exprs) {
+            write!(f, "{}${{{}}}", part, expr)?;
+        }
+        write!(f, "{}", self.parts.last().map_or("", String::as_str))
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct PrefixExpr {
     pub operator: TokenType,
@@ -120,20 +386,20 @@ impl Display for InfixExpr {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct IfExpr {
     pub condition: Box<Expression>,
-    pub if_branch: Vec<Statement>,
-    pub else_branch: Option<Vec<Statement>>,
+    pub if_branch: Block,
+    pub else_branch: Option<Block>,
 }
 
 impl Display for IfExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "if ({}) {{", self.condition)?;
-        for s in &self.if_branch {
+        for s in self.if_branch.iter() {
             writeln!(f, "  {}", s)?;
         }
         write!(f, "}}")?;
         if let Some(else_branch) = &self.else_branch {
             writeln!(f, " else {{")?;
-            for s in else_branch {
+            for s in else_branch.iter() {
                 writeln!(f, "  {}", s)?;
             }
             write!(f, "}}")?;
@@ -146,23 +412,58 @@ impl Display for IfExpr {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct FuncExpr {
     pub params: Vec<Ident>,
-    pub body: Vec<Statement>,
+    /// Default values of the last `defaults.len()` params
+    pub defaults: Vec<Expression>,
+    /// `...name` collecting any arguments past `params`
+    pub rest: Option<Ident>,
+    pub body: Block,
+}
+
+impl FuncExpr {
+    /// Number of params callers have to pass
+    pub fn required(&self) -> usize {
+        self.params.len() - self.defaults.len()
+    }
 }
 
 impl Display for FuncExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "fn (")?;
-        for p in &self.params {
-            write!(f, "{}", p)?;
+        let mut params: Vec<String> = self.params.clone();
+        for (p, d) in params[self.required()..].iter_mut().zip(&self.defaults) {
+            *p = format!("{} = {}", p, d);
         }
-        writeln!(f, ") {{")?;
-        for s in &self.body {
-            writeln!(f, "  {}", s)?;
+        if let Some(rest) = &self.rest {
+            params.push(format!("...{}", rest));
         }
-        write!(f, "}}")?;
+        write_func(f, "fn", &params, &self.body)
+    }
+}
 
-        Ok(())
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MacroExpr {
+    pub params: Vec<Ident>,
+    pub body: Block,
+}
+
+impl Display for MacroExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_func(f, "macro", &self.params, &self.body)
+    }
+}
+
+fn write_func(
+    f: &mut std::fmt::Formatter<'_>,
+    keyword: &str,
+    params: &[String],
+    body: &[Statement],
+) -> std::fmt::Result {
+    writeln!(f, "{} ({}) {{", keyword, params.join(", "))?;
+    for s in body {
+        writeln!(f, "  {}", s)?;
     }
+    write!(f, "}}")?;
+
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -219,6 +520,39 @@ impl Display for IndexExpr {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SliceExpr {
+    pub left: Box<Expression>,
+    pub start: Option<Box<Expression>>,
+    pub end: Option<Box<Expression>>,
+}
+
+impl Display for SliceExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}[", self.left)?;
+        if let Some(start) = &self.start {
+            write!(f, "{}", start)?;
+        }
+        write!(f, ":")?;
+        if let Some(end) = &self.end {
+            write!(f, "{}", end)?;
+        }
+        write!(f, "])")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MemberExpr {
+    pub left: Box<Expression>,
+    pub name: Ident,
+}
+
+impl Display for MemberExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.left, self.name)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct HashExpr {
     pub pairs: Vec<(Expression, Expression)>,
@@ -0,0 +1,162 @@
+use super::*;
+
+/// Rebuilds the program bottom-up, passing every expression through `f`
+/// after its children have been modified
+pub fn modify_program<E>(
+    prog: Program,
+    f: &mut impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Program, E> {
+    let statements = modify_stmts(prog.statements, f)?;
+    Ok(Program {
+        statements,
+        lines: prog.lines,
+    })
+}
+
+pub fn modify_expr<E>(
+    expr: Expression,
+    f: &mut impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Expression, E> {
+    let expr = match expr {
+        Expression::Prefix(p) => Expression::Prefix(PrefixExpr {
+            operator: p.operator,
+            right: Box::new(modify_expr(*p.right, f)?),
+        }),
+        Expression::Infix(i) => Expression::Infix(InfixExpr {
+            left: Box::new(modify_expr(*i.left, f)?),
+            operator: i.operator,
+            right: Box::new(modify_expr(*i.right, f)?),
+        }),
+        Expression::If(i) => Expression::If(IfExpr {
+            condition: Box::new(modify_expr(*i.condition, f)?),
+            if_branch: modify_block(i.if_branch, f)?,
+            else_branch: i.else_branch.map(|b| modify_block(b, f)).transpose()?,
+        }),
+        Expression::Func(func) => Expression::Func(FuncExpr {
+            params: func.params,
+            defaults: modify_exprs(func.defaults, f)?,
+            rest: func.rest,
+            body: modify_block(func.body, f)?,
+        }),
+        Expression::Call(c) => Expression::Call(CallExpr {
+            func: Box::new(modify_expr(*c.func, f)?),
+            arguments: modify_exprs(c.arguments, f)?,
+        }),
+        Expression::Array(a) => Expression::Array(ArrayExpr {
+            elements: modify_exprs(a.elements, f)?,
+        }),
+        Expression::Index(i) => Expression::Index(IndexExpr {
+            left: Box::new(modify_expr(*i.left, f)?),
+            index: Box::new(modify_expr(*i.index, f)?),
+        }),
+        Expression::Slice(s) => Expression::Slice(SliceExpr {
+            left: Box::new(modify_expr(*s.left, f)?),
+            start: s
+                .start
+                .map(|e| modify_expr(*e, f).map(Box::new))
+                .transpose()?,
+            end: s
+                .end
+                .map(|e| modify_expr(*e, f).map(Box::new))
+                .transpose()?,
+        }),
+        Expression::Member(m) => Expression::Member(MemberExpr {
+            left: Box::new(modify_expr(*m.left, f)?),
+            name: m.name,
+        }),
+        Expression::Hash(h) => Expression::Hash(HashExpr {
+            pairs: h
+                .pairs
+                .into_iter()
+                .map(|(k, v)| Ok((modify_expr(k, f)?, modify_expr(v, f)?)))
+                .collect::<Result<_, E>>()?,
+        }),
+        Expression::Interpolated(i) => Expression::Interpolated(InterpolatedExpr {
+            parts: i.parts,
+            exprs: modify_exprs(i.exprs, f)?,
+        }),
+        Expression::Block(b) => Expression::Block(modify_block(b, f)?),
+        Expression::Ident(_)
+        | Expression::Number(_)
+        | Expression::BigNumber(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Bool(_)
+        | Expression::Null
+        | Expression::Macro(_) => expr,
+    };
+
+    f(expr)
+}
+
+fn modify_stmt<E>(
+    stmt: Statement,
+    f: &mut impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Statement, E> {
+    Ok(match stmt {
+        Statement::Let(l) => Statement::Let(LetStmt {
+            ident: l.ident,
+            expr: modify_expr(l.expr, f)?,
+        }),
+        Statement::Const(l) => Statement::Const(LetStmt {
+            ident: l.ident,
+            expr: modify_expr(l.expr, f)?,
+        }),
+        Statement::LetArray(l) => Statement::LetArray(LetArrayStmt {
+            names: l.names,
+            rest: l.rest,
+            expr: modify_expr(l.expr, f)?,
+        }),
+        Statement::Assign(a) => Statement::Assign(AssignStmt {
+            ident: a.ident,
+            expr: modify_expr(a.expr, f)?,
+        }),
+        Statement::AssignArray(a) => Statement::AssignArray(AssignArrayStmt {
+            names: a.names,
+            expr: modify_expr(a.expr, f)?,
+        }),
+        Statement::AssignIndex(a) => Statement::AssignIndex(AssignIndexStmt {
+            ident: a.ident,
+            indexes: modify_exprs(a.indexes, f)?,
+            expr: modify_expr(a.expr, f)?,
+        }),
+        Statement::Return(r) => Statement::Return(ReturnStmt {
+            expr: modify_expr(r.expr, f)?,
+        }),
+        Statement::Expression(e) => Statement::Expression(modify_expr(e, f)?),
+        Statement::While(w) => Statement::While(WhileStmt {
+            condition: modify_expr(w.condition, f)?,
+            body: modify_block(w.body, f)?,
+        }),
+        Statement::For(l) => Statement::For(ForStmt {
+            ident: l.ident,
+            iterable: modify_expr(l.iterable, f)?,
+            body: modify_block(l.body, f)?,
+        }),
+        Statement::Break | Statement::Continue => stmt,
+    })
+}
+
+fn modify_block<E>(
+    block: Block,
+    f: &mut impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Block, E> {
+    Ok(Block {
+        statements: modify_stmts(block.statements, f)?,
+        lines: block.lines,
+    })
+}
+
+fn modify_stmts<E>(
+    stmts: Vec<Statement>,
+    f: &mut impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Vec<Statement>, E> {
+    stmts.into_iter().map(|s| modify_stmt(s, f)).collect()
+}
+
+fn modify_exprs<E>(
+    exprs: Vec<Expression>,
+    f: &mut impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Vec<Expression>, E> {
+    exprs.into_iter().map(|e| modify_expr(e, f)).collect()
+}
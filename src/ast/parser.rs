@@ -1,5 +1,5 @@
 use super::*;
-use crate::lexer::{Lexer, Token, TokenType};
+use crate::lexer::{Lexer, Token, TokenLiteral, TokenType};
 
 pub struct Parser {
     lexer: Lexer,
@@ -22,18 +22,23 @@ impl Parser {
 
     pub fn parse(&mut self) -> ParseResult<Program> {
         let mut statements = vec![];
+        let mut lines = vec![];
         let mut errors = vec![];
 
         while self.cur_token.ty != TokenType::Eof {
+            let line = self.cur_token.line;
             match self.parse_stmt() {
-                Ok(s) => statements.push(s),
+                Ok(s) => {
+                    statements.push(s);
+                    lines.push(line);
+                }
                 Err(mut e) => errors.append(&mut e),
             }
             self.next();
         }
 
         if errors.is_empty() {
-            Ok(Program { statements })
+            Ok(Program { statements, lines })
         } else {
             Err(errors)
         }
@@ -44,13 +49,86 @@ impl Parser {
     fn parse_stmt(&mut self) -> ParseResult<Statement> {
         match self.cur_token.ty {
             TokenType::Let => self.parse_let(),
+            TokenType::Const => Ok(Statement::Const(self.parse_binding()?)),
             TokenType::Return => self.parse_return(),
+            TokenType::While => self.parse_while(),
+            TokenType::For => self.parse_for(),
+            TokenType::Break | TokenType::Continue => self.parse_loop_control(),
             _ => self.parse_expr_stmt(),
         }
     }
 
+    fn parse_while(&mut self) -> ParseResult<Statement> {
+        self.expect_peek(TokenType::LParen)?;
+        self.next();
+        let condition = self.parse_expr(Precedence::Lowest)?;
+        self.expect_peek(TokenType::RParen)?;
+        self.expect_peek(TokenType::LBrace)?;
+        self.next();
+
+        let body = self.parse_block()?;
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next();
+        }
+
+        Ok(Statement::While(WhileStmt { condition, body }))
+    }
+
+    fn parse_for(&mut self) -> ParseResult<Statement> {
+        self.expect_peek(TokenType::LParen)?;
+        self.expect_peek(TokenType::Ident)?;
+        let ident = self.cur_token.literal.ident().unwrap().into();
+        self.expect_peek(TokenType::In)?;
+        self.next();
+        let iterable = self.parse_expr(Precedence::Lowest)?;
+        self.expect_peek(TokenType::RParen)?;
+        self.expect_peek(TokenType::LBrace)?;
+        self.next();
+
+        let body = self.parse_block()?;
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next();
+        }
+
+        Ok(Statement::For(ForStmt {
+            ident,
+            iterable,
+            body,
+        }))
+    }
+
+    fn parse_loop_control(&mut self) -> ParseResult<Statement> {
+        let stmt = match self.cur_token.ty {
+            TokenType::Break => Statement::Break,
+            TokenType::Continue => Statement::Continue,
+            _ => return Err(vec![ParseErrorKind::InvalidParseFn]),
+        };
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next();
+        }
+
+        Ok(stmt)
+    }
+
     fn parse_expr_stmt(&mut self) -> ParseResult<Statement> {
         let expr = self.parse_expr(Precedence::Lowest)?;
+        self.finish_expr_stmt(expr)
+    }
+
+    /// The rest of a statement starting with the already parsed `expr`
+    fn finish_expr_stmt(&mut self, expr: Expression) -> ParseResult<Statement> {
+        if self.peek_token_is(TokenType::Assign) {
+            return self.parse_assign(expr);
+        }
+        if matches!(
+            self.peek_token.ty,
+            TokenType::PlusAssign
+                | TokenType::MinusAssign
+                | TokenType::StarAssign
+                | TokenType::SlashAssign
+        ) {
+            return self.parse_compound_assign(expr);
+        }
 
         if self.peek_token_is(TokenType::Semicolon) {
             self.next();
@@ -59,6 +137,84 @@ impl Parser {
         Ok(Statement::Expression(expr))
     }
 
+    /// `target = expr;` where the target has already been parsed
+    fn parse_assign(&mut self, target: Expression) -> ParseResult<Statement> {
+        let invalid = || vec![ParseErrorKind::InvalidAssignTarget(target.to_string())];
+        if let Expression::Index(_) = target {
+            let (ident, indexes) = index_target(target.clone()).ok_or_else(invalid)?;
+            let expr = self.parse_assigned()?;
+            return Ok(Statement::AssignIndex(AssignIndexStmt {
+                ident,
+                indexes,
+                expr,
+            }));
+        }
+        let names = match &target {
+            Expression::Ident(i) => vec![i.name.clone()],
+            Expression::Array(a) => a
+                .elements
+                .iter()
+                .map(|e| match e {
+                    Expression::Ident(i) => Some(i.name.clone()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+
+        let expr = self.parse_assigned()?;
+        Ok(match target {
+            Expression::Ident(ident) => Statement::Assign(AssignStmt {
+                ident: ident.name,
+                expr,
+            }),
+            _ => Statement::AssignArray(AssignArrayStmt { names, expr }),
+        })
+    }
+
+    /// The expression after the `=` of an assignment
+    fn parse_assigned(&mut self) -> ParseResult<Expression> {
+        self.next();
+        self.next();
+        let expr = self.parse_expr(Precedence::Lowest)?;
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next();
+        }
+        Ok(expr)
+    }
+
+    /// Desugars `name += expr` into `name = name + expr`
+    fn parse_compound_assign(&mut self, target: Expression) -> ParseResult<Statement> {
+        let Expression::Ident(ident) = target else {
+            return Err(vec![ParseErrorKind::InvalidAssignTarget(
+                target.to_string(),
+            )]);
+        };
+        let operator = match self.peek_token.ty {
+            TokenType::PlusAssign => TokenType::Plus,
+            TokenType::MinusAssign => TokenType::Minus,
+            TokenType::StarAssign => TokenType::Star,
+            _ => TokenType::Slash,
+        };
+
+        self.next();
+        self.next();
+        let right = self.parse_expr(Precedence::Lowest)?;
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next();
+        }
+
+        Ok(Statement::Assign(AssignStmt {
+            expr: Expression::Infix(InfixExpr {
+                left: Box::new(Expression::Ident(ident.clone())),
+                operator,
+                right: Box::new(right),
+            }),
+            ident: ident.name,
+        }))
+    }
+
     fn parse_return(&mut self) -> ParseResult<Statement> {
         self.next(); // Skip 'Return' token
 
@@ -71,6 +227,15 @@ impl Parser {
     }
 
     fn parse_let(&mut self) -> ParseResult<Statement> {
+        if self.peek_token_is(TokenType::LBracket) {
+            self.next();
+            return self.parse_let_array();
+        }
+        Ok(Statement::Let(self.parse_binding()?))
+    }
+
+    /// Parses `name = expr;` following `let` or `const`
+    fn parse_binding(&mut self) -> ParseResult<LetStmt> {
         self.expect_peek(TokenType::Ident)?;
         let ident: String = self.cur_token.literal.ident().unwrap().into();
 
@@ -82,7 +247,37 @@ impl Parser {
             self.next();
         }
 
-        Ok(Statement::Let(LetStmt { ident, expr }))
+        Ok(LetStmt { ident, expr })
+    }
+
+    fn parse_let_array(&mut self) -> ParseResult<Statement> {
+        let mut names = vec![];
+        let mut rest = None;
+        while !self.peek_token_is(TokenType::RBracket) {
+            if self.peek_token_is(TokenType::Ellipsis) {
+                // The rest pattern has to be the last one
+                self.next();
+                self.expect_peek(TokenType::Ident)?;
+                rest = Some(self.cur_token.literal.ident().unwrap().into());
+                break;
+            }
+
+            self.expect_peek(TokenType::Ident)?;
+            names.push(self.cur_token.literal.ident().unwrap().into());
+            if !self.peek_token_is(TokenType::RBracket) {
+                self.expect_peek(TokenType::Comma)?;
+            }
+        }
+        self.expect_peek(TokenType::RBracket)?;
+        self.expect_peek(TokenType::Assign)?;
+        self.next();
+
+        let expr = self.parse_expr(Precedence::Lowest)?;
+        if self.peek_token_is(TokenType::Semicolon) {
+            self.next();
+        }
+
+        Ok(Statement::LetArray(LetArrayStmt { names, rest, expr }))
     }
 
     fn parse_expr(&mut self, prec: Precedence) -> ParseResult<Expression> {
@@ -92,14 +287,33 @@ impl Parser {
                 TokenType::Plus
                 | TokenType::Minus
                 | TokenType::Slash
+                | TokenType::FloorDiv
+                | TokenType::Percent
+                | TokenType::DotDot
+                | TokenType::DotDotEq
                 | TokenType::Star
+                | TokenType::Pow
                 | TokenType::Eq
                 | TokenType::NotEq
                 | TokenType::Lt
-                | TokenType::Gt => {
+                | TokenType::Gt
+                | TokenType::LtEq
+                | TokenType::GtEq
+                | TokenType::Ampersand
+                | TokenType::Pipe
+                | TokenType::Caret
+                | TokenType::Shl
+                | TokenType::Shr
+                | TokenType::NullCoalesce
+                | TokenType::And
+                | TokenType::Or => {
                     self.next();
                     left = self.parse_infix(left)?;
                 }
+                TokenType::Pipeline => {
+                    self.next();
+                    left = self.parse_pipeline(left)?;
+                }
                 TokenType::LParen => {
                     self.next();
                     left = self.parse_call(left)?;
@@ -108,6 +322,10 @@ impl Parser {
                     self.next();
                     left = self.parse_index(left)?;
                 }
+                TokenType::Dot => {
+                    self.next();
+                    left = self.parse_member(left)?;
+                }
                 _ => return Ok(left),
             }
         }
@@ -119,14 +337,21 @@ impl Parser {
         match self.cur_token.ty {
             TokenType::Ident => self.parse_ident(),
             TokenType::Number => self.parse_number(),
+            TokenType::Float => self.parse_float(),
             TokenType::String => self.parse_string(),
+            TokenType::StringStart => self.parse_interpolated(),
             TokenType::True | TokenType::False => self.parse_bool(),
-            TokenType::Bang | TokenType::Minus => self.parse_prefix(),
+            TokenType::Null => Ok(Expression::Null),
+            TokenType::Bang | TokenType::Minus | TokenType::Tilde => self.parse_prefix(),
             TokenType::LParen => self.parse_group(),
             TokenType::LBracket => self.parse_arr(),
             TokenType::If => self.parse_if(),
             TokenType::Fn => self.parse_func(),
+            TokenType::Macro => self.parse_macro(),
             TokenType::LBrace => self.parse_hash(),
+            TokenType::Illegal => Err(vec![ParseErrorKind::Illegal(
+                self.cur_token.literal.string().unwrap_or_default().into(),
+            )]),
             _ => Err(vec![ParseErrorKind::UnknownPrefixExpr(self.cur_token.ty)]),
         }
     }
@@ -169,16 +394,28 @@ impl Parser {
             .literal
             .ident()
             .ok_or(vec![ParseErrorKind::InvalidParseFn])?;
-        Ok(Expression::Ident(ident.into()))
+        Ok(Expression::Ident(IdentExpr {
+            name: ident.into(),
+            line: self.cur_token.line,
+            col: self.cur_token.col,
+        }))
     }
 
     fn parse_number(&mut self) -> ParseResult<Expression> {
+        match &self.cur_token.literal {
+            TokenLiteral::Num(n) => Ok(Expression::Number(*n)),
+            TokenLiteral::BigNum(n) => Ok(Expression::BigNumber(n.clone())),
+            _ => Err(vec![ParseErrorKind::InvalidParseFn]),
+        }
+    }
+
+    fn parse_float(&mut self) -> ParseResult<Expression> {
         let num = self
             .cur_token
             .literal
-            .num()
+            .float()
             .ok_or(vec![ParseErrorKind::InvalidParseFn])?;
-        Ok(Expression::Number(num))
+        Ok(Expression::Float(num))
     }
 
     fn parse_string(&mut self) -> ParseResult<Expression> {
@@ -190,6 +427,38 @@ impl Parser {
         Ok(Expression::String(s.into()))
     }
 
+    /// `"a ${x} b"`, which the lexer splits into the text around the tokens
+    /// of each expression
+    fn parse_interpolated(&mut self) -> ParseResult<Expression> {
+        let mut parts = vec![];
+        let mut exprs = vec![];
+        loop {
+            let text = self
+                .cur_token
+                .literal
+                .string()
+                .ok_or(vec![ParseErrorKind::InvalidParseFn])?;
+            parts.push(text.to_string());
+            if self.cur_token_is(TokenType::StringEnd) {
+                break;
+            }
+
+            self.next();
+            exprs.push(self.parse_expr(Precedence::Lowest)?);
+            if !matches!(
+                self.peek_token.ty,
+                TokenType::StringMid | TokenType::StringEnd
+            ) {
+                return Err(vec![ParseErrorKind::UnclosedInterpolation(
+                    self.peek_token.ty,
+                )]);
+            }
+            self.next();
+        }
+
+        Ok(Expression::Interpolated(InterpolatedExpr { parts, exprs }))
+    }
+
     fn parse_bool(&mut self) -> ParseResult<Expression> {
         match self.cur_token.ty {
             TokenType::True => Ok(Expression::Bool(true)),
@@ -211,15 +480,34 @@ impl Parser {
 
     fn parse_infix(&mut self, left: Expression) -> ParseResult<Expression> {
         let operator = self.cur_token.ty;
-        let prec = self.cur_precedence();
+        let prec = match operator {
+            // Right associative, so the right side may contain another `**`
+            TokenType::Pow => Precedence::Prodcut,
+            _ => self.cur_precedence(),
+        };
         self.next();
         let right = Box::new(self.parse_expr(prec)?);
-
-        Ok(Expression::Infix(InfixExpr {
+        let expr = Expression::Infix(InfixExpr {
             left: Box::new(left),
             operator,
             right,
-        }))
+        });
+
+        // `1 < 2 < 3` would compare the bool `1 < 2` with 3
+        if prec == Precedence::Ltgt && self.peek_precedence() == Precedence::Ltgt {
+            self.next();
+            let next = self.cur_token.ty;
+            self.next();
+            let last = self.parse_expr(Precedence::Ltgt)?;
+            return Err(vec![ParseErrorKind::ChainedComparison(format!(
+                "{} {} {}",
+                expr.to_source(),
+                next,
+                last.to_source()
+            ))]);
+        }
+
+        Ok(expr)
     }
 
     fn parse_if(&mut self) -> ParseResult<Expression> {
@@ -257,86 +545,177 @@ impl Parser {
         self.expect_peek(TokenType::LParen)?;
         self.next();
 
-        let params = self.parse_params()?;
+        let (params, defaults, rest) = self.parse_params()?;
 
         self.expect_peek(TokenType::LBrace)?;
         self.next();
         let body = self.parse_block()?;
 
-        Ok(Expression::Func(FuncExpr { params, body }))
+        Ok(Expression::Func(FuncExpr {
+            params,
+            defaults,
+            rest,
+            body,
+        }))
     }
 
+    fn parse_macro(&mut self) -> ParseResult<Expression> {
+        self.expect_peek(TokenType::LParen)?;
+        self.next();
+
+        let (params, defaults, rest) = self.parse_params()?;
+        if !defaults.is_empty() || rest.is_some() {
+            return Err(vec![ParseErrorKind::InvalidMacroParams]);
+        }
+
+        self.expect_peek(TokenType::LBrace)?;
+        self.next();
+        let body = self.parse_block()?;
+
+        Ok(Expression::Macro(MacroExpr { params, body }))
+    }
+
+    /// A hash literal, or a block expression when what follows the `{` isn't
+    /// a key. `{}` and `{name}` are hashes, `{ name; }` is a block
     fn parse_hash(&mut self) -> ParseResult<Expression> {
         self.next();
 
         if self.cur_token_is(TokenType::RBrace) {
             return Ok(Expression::Hash(HashExpr { pairs: vec![] }));
         }
+        if matches!(
+            self.cur_token.ty,
+            TokenType::Let
+                | TokenType::Const
+                | TokenType::Return
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Break
+                | TokenType::Continue
+        ) {
+            return self.parse_block_expr(Block::default());
+        }
 
+        let line = self.cur_token.line;
         let key = self.parse_expr(Precedence::Lowest)?;
-        self.expect_peek(TokenType::Colon)?;
-        self.next();
-        let value = self.parse_expr(Precedence::Lowest)?;
-        let mut res = vec![(key, value)];
+        let is_key = self.peek_token_is(TokenType::Colon)
+            || self.peek_token_is(TokenType::Comma)
+            || (matches!(key, Expression::Ident(_)) && self.peek_token_is(TokenType::RBrace));
+        if !is_key {
+            let first = Block {
+                statements: vec![self.finish_expr_stmt(key)?],
+                lines: vec![line],
+            };
+            self.next();
+            return self.parse_block_expr(first);
+        }
+
+        let mut res = vec![self.parse_hash_pair(key)?];
 
         while self.peek_token_is(TokenType::Comma) {
             self.next();
+            if self.peek_token_is(TokenType::RBrace) {
+                break;
+            }
             self.next();
             let key = self.parse_expr(Precedence::Lowest)?;
-            self.expect_peek(TokenType::Colon)?;
-            self.next();
-            let value = self.parse_expr(Precedence::Lowest)?;
-            res.push((key, value));
+            res.push(self.parse_hash_pair(key)?);
         }
-        self.next();
+        self.expect_peek(TokenType::RBrace)?;
 
         Ok(Expression::Hash(HashExpr { pairs: res }))
     }
 
-    fn parse_params(&mut self) -> ParseResult<Vec<Ident>> {
+    /// The statements of a block expression after the ones in `block`, up
+    /// to the closing `}`
+    fn parse_block_expr(&mut self, mut block: Block) -> ParseResult<Expression> {
+        let rest = self.parse_block()?;
+        if !self.cur_token_is(TokenType::RBrace) {
+            return Err(vec![ParseErrorKind::UnexpectedToken(UnexpectedErr::new(
+                TokenType::RBrace,
+                self.cur_token.ty,
+            ))]);
+        }
+        block.statements.extend(rest.statements);
+        block.lines.extend(rest.lines);
+        Ok(Expression::Block(block))
+    }
+
+    /// Parses `: value` after `key`, or nothing for the shorthand `name`
+    /// meaning `"name": name`
+    fn parse_hash_pair(&mut self, key: Expression) -> ParseResult<(Expression, Expression)> {
+        if let Expression::Ident(name) = &key {
+            if self.peek_token_is(TokenType::Comma) || self.peek_token_is(TokenType::RBrace) {
+                return Ok((Expression::String(name.name.clone()), key));
+            }
+        }
+        self.expect_peek(TokenType::Colon)?;
+        self.next();
+        let value = self.parse_expr(Precedence::Lowest)?;
+        Ok((key, value))
+    }
+
+    /// Parses `a, b = expr, ...rest)`, returning the names, the defaults of
+    /// the trailing params which have one and the rest param
+    fn parse_params(&mut self) -> ParseResult<(Vec<Ident>, Vec<Expression>, Option<Ident>)> {
+        let mut params: Vec<Ident> = vec![];
+        let mut defaults = vec![];
         if self.cur_token_is(TokenType::RParen) {
-            return Ok(vec![]);
+            return Ok((params, defaults, None));
         }
 
-        let mut res: Vec<Ident> = vec![];
-        while self.peek_token_is(TokenType::Comma) {
-            let ident =
-                self.cur_token
-                    .literal
-                    .ident()
-                    .ok_or(vec![ParseErrorKind::UnexpectedToken(UnexpectedErr::new(
-                        TokenType::Ident,
-                        self.cur_token.ty,
-                    ))])?;
-            res.push(ident.into());
-
-            self.expect_peek(TokenType::Comma)?;
+        loop {
+            if self.cur_token_is(TokenType::Ellipsis) {
+                self.expect_peek(TokenType::Ident)?;
+                let rest = self.cur_token.literal.ident().unwrap().into();
+                if !self.peek_token_is(TokenType::RParen) {
+                    return Err(vec![ParseErrorKind::RestNotLast]);
+                }
+                self.next();
+                return Ok((params, defaults, Some(rest)));
+            }
+
+            let ident: Ident = self
+                .cur_token
+                .literal
+                .ident()
+                .ok_or(vec![ParseErrorKind::UnexpectedToken(UnexpectedErr::new(
+                    TokenType::Ident,
+                    self.cur_token.ty,
+                ))])?
+                .into();
+
+            if self.peek_token_is(TokenType::Assign) {
+                self.next();
+                self.next();
+                defaults.push(self.parse_expr(Precedence::Lowest)?);
+            } else if !defaults.is_empty() {
+                return Err(vec![ParseErrorKind::RequiredAfterDefault(ident)]);
+            }
+            params.push(ident);
+
+            if !self.peek_token_is(TokenType::Comma) {
+                break;
+            }
+            self.next();
             self.next();
         }
-        let ident = self
-            .cur_token
-            .literal
-            .ident()
-            .ok_or(vec![ParseErrorKind::UnexpectedToken(UnexpectedErr::new(
-                TokenType::Ident,
-                self.cur_token.ty,
-            ))])?;
-        res.push(ident.into());
         self.expect_peek(TokenType::RParen)?;
 
-        Ok(res)
+        Ok((params, defaults, None))
     }
 
-    fn parse_block(&mut self) -> ParseResult<Vec<Statement>> {
-        let mut statements = vec![];
+    fn parse_block(&mut self) -> ParseResult<Block> {
+        let mut block = Block::default();
 
         while !self.cur_token_is(TokenType::RBrace) && !self.cur_token_is(TokenType::Eof) {
+            block.lines.push(self.cur_token.line);
             let s = self.parse_stmt()?;
-            statements.push(s);
+            block.statements.push(s);
             self.next();
         }
 
-        Ok(statements)
+        Ok(block)
     }
 
     fn parse_call(&mut self, func: Expression) -> ParseResult<Expression> {
@@ -348,14 +727,57 @@ impl Parser {
         }))
     }
 
+    /// Desugars `x |> f` into `f(x)`
+    fn parse_pipeline(&mut self, arg: Expression) -> ParseResult<Expression> {
+        self.next();
+        let func = self.parse_expr(Precedence::Pipeline)?;
+        Ok(Expression::Call(CallExpr {
+            func: Box::new(func),
+            arguments: vec![arg],
+        }))
+    }
+
+    /// `left[index]`, or a slice when there's a `:` inside the brackets
     fn parse_index(&mut self, left: Expression) -> ParseResult<Expression> {
         self.next();
-        let index = self.parse_expr(Precedence::Lowest)?;
+        let mut start = None;
+        if !self.cur_token_is(TokenType::Colon) {
+            let index = self.parse_expr(Precedence::Lowest)?;
+            if !self.peek_token_is(TokenType::Colon) {
+                self.expect_peek(TokenType::RBracket)?;
+                return Ok(Expression::Index(IndexExpr {
+                    left: Box::new(left),
+                    index: Box::new(index),
+                }));
+            }
+            self.next();
+            start = Some(Box::new(index));
+        }
+
+        let mut end = None;
+        if !self.peek_token_is(TokenType::RBracket) {
+            self.next();
+            end = Some(Box::new(self.parse_expr(Precedence::Lowest)?));
+        }
         self.expect_peek(TokenType::RBracket)?;
 
-        Ok(Expression::Index(IndexExpr {
+        Ok(Expression::Slice(SliceExpr {
+            left: Box::new(left),
+            start,
+            end,
+        }))
+    }
+
+    fn parse_member(&mut self, left: Expression) -> ParseResult<Expression> {
+        self.expect_peek(TokenType::Ident)?;
+        let name = self
+            .cur_token
+            .literal
+            .ident()
+            .ok_or(vec![ParseErrorKind::InvalidParseFn])?;
+        Ok(Expression::Member(MemberExpr {
             left: Box::new(left),
-            index: Box::new(index),
+            name: name.into(),
         }))
     }
 
@@ -375,11 +797,14 @@ impl Parser {
 
         while self.peek_token_is(TokenType::Comma) {
             self.next();
+            if self.peek_token_is(end) {
+                break;
+            }
             self.next();
             let expr = self.parse_expr(Precedence::Lowest)?;
             res.push(expr);
         }
-        self.next();
+        self.expect_peek(end)?;
 
         Ok(res)
     }
@@ -402,6 +827,48 @@ pub enum ParseErrorKind {
     UnexpectedToken(UnexpectedErr),
     UnknownPrefixExpr(TokenType),
     InvalidParseFn,
+    Illegal(String),
+    InvalidAssignTarget(String),
+    /// A param without a default following one with a default
+    RequiredAfterDefault(Ident),
+    /// Macros only take plain params
+    InvalidMacroParams,
+    RestNotLast,
+    /// Comparisons like `1 < 2 < 3`, which need parentheses to be explicit
+    ChainedComparison(String),
+    /// Something other than the `}` ending an expression in a string
+    UnclosedInterpolation(TokenType),
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedToken(e) => {
+                write!(f, "expected {}, got {}", e.expected, e.found)
+            }
+            ParseErrorKind::UnknownPrefixExpr(ty) => write!(f, "unexpected token: {}", ty),
+            ParseErrorKind::InvalidParseFn => write!(f, "malformed token"),
+            ParseErrorKind::Illegal(msg) => write!(f, "{}", msg),
+            ParseErrorKind::InvalidAssignTarget(target) => {
+                write!(f, "invalid assignment target: {}", target)
+            }
+            ParseErrorKind::RequiredAfterDefault(param) => write!(
+                f,
+                "parameter without a default after one with a default: {}",
+                param
+            ),
+            ParseErrorKind::InvalidMacroParams => write!(f, "macro parameters must be names"),
+            ParseErrorKind::RestNotLast => write!(f, "rest parameter must be the last one"),
+            ParseErrorKind::ChainedComparison(expr) => {
+                write!(f, "comparisons need parentheses to be chained: {}", expr)
+            }
+            ParseErrorKind::UnclosedInterpolation(ty) => write!(
+                f,
+                "expected `}}` to end the interpolation, got {} (write `\\${{` for a literal `${{`)",
+                ty
+            ),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -417,26 +884,62 @@ impl UnexpectedErr {
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
 pub enum Precedence {
     Lowest,
+    Coalesce,
+    Pipeline,
+    Or,
+    And,
     Equals,
     Ltgt,
+    Range,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
     Sum,
     Prodcut,
+    Power,
     Prefix,
     Call,
     Index,
 }
 
-fn token_precedence(ty: TokenType) -> Precedence {
+pub(super) fn token_precedence(ty: TokenType) -> Precedence {
     match ty {
+        TokenType::NullCoalesce => Precedence::Coalesce,
+        TokenType::Pipeline => Precedence::Pipeline,
+        TokenType::Or => Precedence::Or,
+        TokenType::And => Precedence::And,
         TokenType::Eq | TokenType::NotEq => Precedence::Equals,
-        TokenType::Lt | TokenType::Gt => Precedence::Ltgt,
+        TokenType::Lt | TokenType::Gt | TokenType::LtEq | TokenType::GtEq => Precedence::Ltgt,
+        TokenType::DotDot | TokenType::DotDotEq => Precedence::Range,
+        TokenType::Pipe => Precedence::BitOr,
+        TokenType::Caret => Precedence::BitXor,
+        TokenType::Ampersand => Precedence::BitAnd,
+        TokenType::Shl | TokenType::Shr => Precedence::Shift,
         TokenType::Plus | TokenType::Minus => Precedence::Sum,
-        TokenType::Star | TokenType::Slash => Precedence::Prodcut,
+        TokenType::Star | TokenType::Slash | TokenType::FloorDiv | TokenType::Percent => {
+            Precedence::Prodcut
+        }
+        TokenType::Pow => Precedence::Power,
         TokenType::LParen => Precedence::Call,
-        TokenType::LBracket => Precedence::Index,
+        TokenType::LBracket | TokenType::Dot => Precedence::Index,
         _ => Precedence::Lowest,
     }
 }
+
+/// Splits `name[i][j]` into the name and its indexes, or `None` when the
+/// innermost expression isn't a name
+fn index_target(target: Expression) -> Option<(Ident, Vec<Expression>)> {
+    match target {
+        Expression::Ident(ident) => Some((ident.name, Vec::new())),
+        Expression::Index(i) => {
+            let (ident, mut indexes) = index_target(*i.left)?;
+            indexes.push(*i.index);
+            Some((ident, indexes))
+        }
+        _ => None,
+    }
+}
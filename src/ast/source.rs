@@ -0,0 +1,337 @@
+use super::parser::{token_precedence, Precedence};
+use super::*;
+use std::fmt::Write;
+
+impl Program {
+    /// Renders the program back to Monkey source which parses to the same
+    /// statements, one per line
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        for stmt in &self.statements {
+            write_stmt(&mut out, stmt, 0);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Statement {
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        write_stmt(&mut out, self, 0);
+        out
+    }
+}
+
+impl Expression {
+    /// Renders the expression with only the parentheses its precedence needs
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        write_expr(&mut out, self, 0);
+        out
+    }
+}
+
+fn write_stmt(out: &mut String, stmt: &Statement, indent: usize) {
+    match stmt {
+        Statement::Let(l) | Statement::Const(l) => {
+            let keyword = match stmt {
+                Statement::Const(_) => "const",
+                _ => "let",
+            };
+            write!(out, "{} {} = ", keyword, l.ident).unwrap();
+            write_expr(out, &l.expr, indent);
+        }
+        Statement::LetArray(l) => {
+            let mut names = l.names.clone();
+            if let Some(rest) = &l.rest {
+                names.push(format!("...{}", rest));
+            }
+            write!(out, "let [{}] = ", names.join(", ")).unwrap();
+            write_expr(out, &l.expr, indent);
+        }
+        Statement::Assign(a) => {
+            write!(out, "{} = ", a.ident).unwrap();
+            write_expr(out, &a.expr, indent);
+        }
+        Statement::AssignArray(a) => {
+            write!(out, "[{}] = ", a.names.join(", ")).unwrap();
+            write_expr(out, &a.expr, indent);
+        }
+        Statement::AssignIndex(a) => {
+            out.push_str(&a.ident);
+            for i in &a.indexes {
+                out.push('[');
+                write_expr(out, i, indent);
+                out.push(']');
+            }
+            out.push_str(" = ");
+            write_expr(out, &a.expr, indent);
+        }
+        Statement::Return(r) => {
+            out.push_str("return ");
+            write_expr(out, &r.expr, indent);
+        }
+        Statement::Expression(e) => write_expr(out, e, indent),
+        Statement::While(w) => {
+            out.push_str("while (");
+            write_expr(out, &w.condition, indent);
+            out.push_str(") ");
+            write_block(out, &w.body, indent);
+        }
+        Statement::For(l) => {
+            write!(out, "for ({} in ", l.ident).unwrap();
+            write_expr(out, &l.iterable, indent);
+            out.push_str(") ");
+            write_block(out, &l.body, indent);
+        }
+        Statement::Break => out.push_str("break"),
+        Statement::Continue => out.push_str("continue"),
+    }
+    out.push(';');
+}
+
+fn write_block(out: &mut String, block: &Block, indent: usize) {
+    if block.is_empty() {
+        out.push_str("{}");
+        return;
+    }
+    out.push_str("{\n");
+    for stmt in block.iter() {
+        push_indent(out, indent + 1);
+        write_stmt(out, stmt, indent + 1);
+        out.push('\n');
+    }
+    push_indent(out, indent);
+    out.push('}');
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("    ");
+    }
+}
+
+/// How tightly the expression binds, atoms binding tightest
+fn precedence(expr: &Expression) -> Precedence {
+    match expr {
+        Expression::Infix(i) => token_precedence(i.operator),
+        Expression::Prefix(_) => Precedence::Prefix,
+        Expression::Number(n) if *n < 0 => Precedence::Prefix,
+        Expression::BigNumber(n) if *n < BigInt::default() => Precedence::Prefix,
+        Expression::Float(n) if n.is_sign_negative() => Precedence::Prefix,
+        Expression::Call(_) => Precedence::Call,
+        _ => Precedence::Index,
+    }
+}
+
+/// Writes `expr`, parenthesized if it binds looser than `min`
+fn write_operand(out: &mut String, expr: &Expression, min: Precedence, indent: usize) {
+    if precedence(expr) < min {
+        out.push('(');
+        write_expr(out, expr, indent);
+        out.push(')');
+    } else {
+        write_expr(out, expr, indent);
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expression, indent: usize) {
+    match expr {
+        Expression::Ident(i) => out.push_str(&i.name),
+        Expression::Number(n) => write!(out, "{}", n).unwrap(),
+        Expression::BigNumber(n) => write!(out, "{}", n).unwrap(),
+        Expression::Float(n) => write_float(out, *n),
+        Expression::String(s) => write_string(out, s),
+        Expression::Interpolated(i) => {
+            out.push('"');
+            for (part, expr) in i.parts.iter().zip(&i.exprs) {
+                write_string_text(out, part);
+                out.push_str("${");
+                write_expr(out, expr, indent);
+                out.push('}');
+            }
+            write_string_text(out, i.parts.last().map_or("", String::as_str));
+            out.push('"');
+        }
+        Expression::Bool(b) => write!(out, "{}", b).unwrap(),
+        Expression::Null => out.push_str("null"),
+        Expression::Prefix(p) => {
+            write!(out, "{}", p.operator).unwrap();
+            // `-(-a)` rather than `--a`
+            if precedence(&p.right) == Precedence::Prefix {
+                out.push('(');
+                write_expr(out, &p.right, indent);
+                out.push(')');
+            } else {
+                write_operand(out, &p.right, Precedence::Prefix, indent);
+            }
+        }
+        Expression::Infix(i) => {
+            let prec = token_precedence(i.operator);
+            // `**` is right associative, comparisons don't chain and
+            // everything else is left associative
+            let (left, right) = match i.operator {
+                TokenType::Pow => (Precedence::Prefix, Precedence::Power),
+                _ if prec == Precedence::Ltgt => (next_precedence(prec), next_precedence(prec)),
+                _ => (prec, next_precedence(prec)),
+            };
+            write_operand(out, &i.left, left, indent);
+            write!(out, " {} ", i.operator).unwrap();
+            write_operand(out, &i.right, right, indent);
+        }
+        Expression::If(i) => {
+            out.push_str("if (");
+            write_expr(out, &i.condition, indent);
+            out.push_str(") ");
+            write_block(out, &i.if_branch, indent);
+            if let Some(else_branch) = &i.else_branch {
+                out.push_str(" else ");
+                write_block(out, else_branch, indent);
+            }
+        }
+        Expression::Func(func) => {
+            out.push_str("fn(");
+            let required = func.required();
+            for (idx, p) in func.params.iter().enumerate() {
+                if idx > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(p);
+                if idx >= required {
+                    out.push_str(" = ");
+                    write_expr(out, &func.defaults[idx - required], indent);
+                }
+            }
+            if let Some(rest) = &func.rest {
+                if !func.params.is_empty() {
+                    out.push_str(", ");
+                }
+                write!(out, "...{}", rest).unwrap();
+            }
+            out.push_str(") ");
+            write_block(out, &func.body, indent);
+        }
+        Expression::Macro(m) => {
+            write!(out, "macro({}) ", m.params.join(", ")).unwrap();
+            write_block(out, &m.body, indent);
+        }
+        Expression::Call(c) => {
+            write_operand(out, &c.func, Precedence::Call, indent);
+            out.push('(');
+            write_list(out, &c.arguments, indent);
+            out.push(')');
+        }
+        Expression::Array(a) => {
+            out.push('[');
+            write_list(out, &a.elements, indent);
+            out.push(']');
+        }
+        Expression::Index(i) => {
+            write_operand(out, &i.left, Precedence::Call, indent);
+            out.push('[');
+            write_expr(out, &i.index, indent);
+            out.push(']');
+        }
+        Expression::Slice(s) => {
+            write_operand(out, &s.left, Precedence::Call, indent);
+            out.push('[');
+            if let Some(start) = &s.start {
+                write_expr(out, start, indent);
+            }
+            out.push(':');
+            if let Some(end) = &s.end {
+                write_expr(out, end, indent);
+            }
+            out.push(']');
+        }
+        Expression::Member(m) => {
+            write_operand(out, &m.left, Precedence::Call, indent);
+            out.push('.');
+            out.push_str(&m.name);
+        }
+        // Never empty when parsed, `{}` being a hash
+        Expression::Block(b) => write_block(out, b, indent),
+        Expression::Hash(h) => {
+            out.push('{');
+            for (idx, (k, v)) in h.pairs.iter().enumerate() {
+                if idx > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, k, indent);
+                out.push_str(": ");
+                write_expr(out, v, indent);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_list(out: &mut String, exprs: &[Expression], indent: usize) {
+    for (idx, e) in exprs.iter().enumerate() {
+        if idx > 0 {
+            out.push_str(", ");
+        }
+        write_expr(out, e, indent);
+    }
+}
+
+/// Plain decimal digits, as the lexer has no exponents. Whole numbers keep
+/// a `.0` so they read back as floats
+fn write_float(out: &mut String, n: f64) {
+    let start = out.len();
+    write!(out, "{}", n).unwrap();
+    if !out[start..].contains('.') {
+        out.push_str(".0");
+    }
+}
+
+/// Quotes the string, escaping whatever the lexer wouldn't read back as is
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    write_string_text(out, s);
+    out.push('"');
+}
+
+/// The contents of a string literal without the quotes
+fn write_string_text(out: &mut String, s: &str) {
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            // Would start an interpolation otherwise
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            ch if ch.is_control() => write!(out, "\\u{{{:x}}}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+}
+
+/// The precedence one step tighter than `prec`, which the right operand of
+/// a left associative operator must have to go without parentheses
+fn next_precedence(prec: Precedence) -> Precedence {
+    match prec {
+        Precedence::Lowest => Precedence::Coalesce,
+        Precedence::Coalesce => Precedence::Pipeline,
+        Precedence::Pipeline => Precedence::Or,
+        Precedence::Or => Precedence::And,
+        Precedence::And => Precedence::Equals,
+        Precedence::Equals => Precedence::Ltgt,
+        Precedence::Ltgt => Precedence::Range,
+        Precedence::Range => Precedence::BitOr,
+        Precedence::BitOr => Precedence::BitXor,
+        Precedence::BitXor => Precedence::BitAnd,
+        Precedence::BitAnd => Precedence::Shift,
+        Precedence::Shift => Precedence::Sum,
+        Precedence::Sum => Precedence::Prodcut,
+        Precedence::Prodcut => Precedence::Power,
+        Precedence::Power => Precedence::Prefix,
+        Precedence::Prefix => Precedence::Call,
+        Precedence::Call | Precedence::Index => Precedence::Index,
+    }
+}
@@ -38,13 +38,164 @@ fn let_stmt() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         assert_eq!(statements[0], expect);
     }
 }
 
+#[test]
+fn let_array_stmt() {
+    let inputs = vec![
+        (
+            "let [a, b] = x;",
+            Statement::LetArray(LetArrayStmt {
+                names: vec!["a".into(), "b".into()],
+                rest: None,
+                expr: Expression::Ident("x".into()),
+            }),
+        ),
+        (
+            "let [head, ...tail] = [1, 2];",
+            Statement::LetArray(LetArrayStmt {
+                names: vec!["head".into()],
+                rest: Some("tail".into()),
+                expr: Expression::Array(ArrayExpr {
+                    elements: vec![Expression::Number(1), Expression::Number(2)],
+                }),
+            }),
+        ),
+        (
+            "let [] = x;",
+            Statement::LetArray(LetArrayStmt {
+                names: vec![],
+                rest: None,
+                expr: Expression::Ident("x".into()),
+            }),
+        ),
+    ];
+
+    for (inp, expect) in inputs {
+        let lexer = Lexer::new(inp.into());
+        let mut parser = Parser::new(lexer);
+
+        let Program { statements, .. } = parser.parse().unwrap();
+
+        assert_eq!(1, statements.len());
+        assert_eq!(statements[0], expect);
+        assert_eq!(statements[0].to_string(), inp);
+    }
+
+    for inp in ["let [a, ...b, c] = x;", "let [1] = x;", "let [a b] = x;"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
+#[test]
+fn assign_stmt() {
+    let mut parser = Parser::new(Lexer::new("a = a + 1;".into()));
+    let Program { statements, .. } = parser.parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![Statement::Assign(AssignStmt {
+            ident: "a".into(),
+            expr: Expression::Infix(InfixExpr {
+                left: Box::new(Expression::Ident("a".into())),
+                operator: TokenType::Plus,
+                right: Box::new(Expression::Number(1)),
+            }),
+        })]
+    );
+    assert_eq!(statements[0].to_string(), "a = (a + 1);");
+    assert_eq!(statements[0].to_source(), "a = a + 1;");
+
+    for inp in ["1 = a;", "a.b = c;", "a = ;"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
+#[test]
+fn compound_assign_stmt() {
+    for (inp, exp) in [
+        ("a += 1;", "a = a + 1;"),
+        ("a -= b * 2", "a = a - b * 2;"),
+        ("a *= 1 + 2;", "a = a * (1 + 2);"),
+        ("a /= 2", "a = a / 2;"),
+    ] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        let Program { statements, .. } = parser.parse().unwrap();
+        assert_eq!(statements.len(), 1, "{}", inp);
+        assert_eq!(statements[0].to_source(), exp, "{}", inp);
+    }
+
+    for inp in ["[a] += [1];", "1 -= a;", "a *= ;"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
+#[test]
+fn assign_array_stmt() {
+    let mut parser = Parser::new(Lexer::new("[a, b] = [b, a];".into()));
+    let Program { statements, .. } = parser.parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![Statement::AssignArray(AssignArrayStmt {
+            names: vec!["a".into(), "b".into()],
+            expr: Expression::Array(ArrayExpr {
+                elements: vec![Expression::Ident("b".into()), Expression::Ident("a".into())],
+            }),
+        })]
+    );
+    assert_eq!(statements[0].to_string(), "[a, b] = [b, a];");
+
+    for inp in ["[a, 1] = x;", "a + b = c;", "[a, b] = ;"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
+#[test]
+fn assign_index_stmt() {
+    let mut parser = Parser::new(Lexer::new("a[i][\"k\"] = 1;".into()));
+    let Program { statements, .. } = parser.parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![Statement::AssignIndex(AssignIndexStmt {
+            ident: "a".into(),
+            indexes: vec![
+                Expression::Ident("i".into()),
+                Expression::String("k".into())
+            ],
+            expr: Expression::Number(1),
+        })]
+    );
+    assert_eq!(statements[0].to_string(), "a[i][k] = 1;");
+    assert_eq!(statements[0].to_source(), "a[i][\"k\"] = 1;");
+
+    for inp in ["f()[0] = 1;", "[1, 2][0] = 3;", "a[0] = ;", "a[0] += 1;"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
+#[test]
+fn const_stmt() {
+    let mut parser = Parser::new(Lexer::new("const PI = 3;".into()));
+    let Program { statements, .. } = parser.parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![Statement::Const(LetStmt {
+            ident: "PI".into(),
+            expr: Expression::Number(3),
+        })]
+    );
+    assert_eq!(statements[0].to_string(), "const PI = 3;");
+}
+
 #[test]
 fn return_stmt() {
     let inputs = vec![
@@ -72,13 +223,71 @@ fn return_stmt() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         assert_eq!(statements[0], expect);
     }
 }
 
+#[test]
+fn loop_control_stmt() {
+    let inputs = vec![
+        ("break;", Statement::Break),
+        ("break", Statement::Break),
+        ("continue;", Statement::Continue),
+    ];
+
+    for (inp, expect) in inputs {
+        let lexer = Lexer::new(inp.into());
+        let mut parser = Parser::new(lexer);
+
+        let Program { statements, .. } = parser.parse().unwrap();
+
+        assert_eq!(1, statements.len());
+        assert_eq!(statements[0], expect);
+    }
+}
+
+#[test]
+fn while_stmt() {
+    let lexer = Lexer::new("while (x < y) { x; break; };".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+
+    assert_eq!(
+        statements,
+        vec![Statement::While(WhileStmt {
+            condition: Expression::Infix(InfixExpr {
+                left: Box::new(Expression::Ident("x".into())),
+                operator: TokenType::Lt,
+                right: Box::new(Expression::Ident("y".into())),
+            }),
+            body: block(vec![
+                Statement::Expression(Expression::Ident("x".into())),
+                Statement::Break
+            ]),
+        })]
+    );
+}
+
+#[test]
+fn for_stmt() {
+    let lexer = Lexer::new("for (x in xs) { x; }".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+
+    assert_eq!(
+        statements,
+        vec![Statement::For(ForStmt {
+            ident: "x".into(),
+            iterable: Expression::Ident("xs".into()),
+            body: block(vec![Statement::Expression(Expression::Ident("x".into()))]),
+        })]
+    );
+
+    let lexer = Lexer::new("for (1 in xs) {}".into());
+    assert!(Parser::new(lexer).parse().is_err());
+}
+
 #[test]
 fn ident_expr() {
     let input = "foobar;".into();
@@ -86,7 +295,7 @@ fn ident_expr() {
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
 
-    let Program { statements } = parser.parse().unwrap();
+    let Program { statements, .. } = parser.parse().unwrap();
 
     assert_eq!(1, statements.len());
     let expr = match statements[0] {
@@ -95,7 +304,7 @@ fn ident_expr() {
     };
 
     match &expr {
-        Expression::Ident(i) => assert_eq!(i, "foobar"),
+        Expression::Ident(i) => assert_eq!(i.name, "foobar"),
         e => panic!("expected Ident expression, got {:?}", e),
     }
 }
@@ -107,7 +316,7 @@ fn number_expr() {
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
 
-    let Program { statements } = parser.parse().unwrap();
+    let Program { statements, .. } = parser.parse().unwrap();
 
     assert_eq!(1, statements.len());
     let expr = match statements[0] {
@@ -121,6 +330,77 @@ fn number_expr() {
     }
 }
 
+#[test]
+fn float_expr() {
+    let lexer = Lexer::new("2.5 + 1.x".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+    assert_eq!(statements[0].to_string(), "(2.5 + 1.x)");
+}
+
+#[test]
+fn interpolated_string() {
+    let lexer = Lexer::new(r#""${a} + ${b} = ${a + b}" "${x}${y}""#.into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+    assert_eq!(statements[0].to_source(), r#""${a} + ${b} = ${a + b}";"#);
+    assert_eq!(statements[1].to_string(), "${x}${y}");
+
+    for input in [r#""${a""#, r#""${a b}""#, r#""${}""#] {
+        let lexer = Lexer::new(input.into());
+        assert!(Parser::new(lexer).parse().is_err(), "{}", input);
+    }
+
+    let errors = Parser::new(Lexer::new(r#""a ${b""#.into()))
+        .parse()
+        .err()
+        .unwrap();
+    assert_eq!(
+        errors[0].to_string(),
+        "expected `}` to end the interpolation, got string (write `\\${` for a literal `${`)"
+    );
+}
+
+#[test]
+fn number_overflow() {
+    let lexer = Lexer::new("9223372036854775807".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![Statement::Expression(Expression::Number(i64::MAX))]
+    );
+
+    let lexer = Lexer::new("99999999999999999999".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![Statement::Expression(Expression::BigNumber(
+            crate::eval::BigInt::from_digits("99999999999999999999", 10).unwrap()
+        ))]
+    );
+}
+
+#[test]
+fn chained_comparison() {
+    for (inp, chain) in [
+        ("1 < 2 < 3", "1 < 2 < 3"),
+        ("a <= b + 1 > c * 2", "a <= b + 1 > c * 2"),
+        ("x == 1 >= 2 < 3", "1 >= 2 < 3"),
+    ] {
+        let errors = Parser::new(Lexer::new(inp.into())).parse().err().unwrap();
+        assert!(
+            matches!(
+                &errors[..],
+                [parser::ParseErrorKind::ChainedComparison(s)] if s == chain
+            ),
+            "{}: {:?}",
+            inp,
+            errors
+        );
+    }
+
+    let mut parser = Parser::new(Lexer::new("(1 < 2) == (2 < 3); 1 < 2 == true".into()));
+    assert!(parser.parse().is_ok());
+}
+
 #[test]
 fn string_expr() {
     let input = "\"hello there\";".into();
@@ -128,7 +408,7 @@ fn string_expr() {
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
 
-    let Program { statements } = parser.parse().unwrap();
+    let Program { statements, .. } = parser.parse().unwrap();
 
     assert_eq!(1, statements.len());
     let expr = match statements[0] {
@@ -165,7 +445,7 @@ fn prefix_expr() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -253,7 +533,7 @@ fn infix_expr() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -268,6 +548,27 @@ fn infix_expr() {
     }
 }
 
+#[test]
+fn null_expr() {
+    let lexer = Lexer::new("null; x ?? null == nullish".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+    assert_eq!(
+        statements,
+        vec![
+            Statement::Expression(Expression::Null),
+            Statement::Expression(Expression::Infix(InfixExpr {
+                left: Box::new(Expression::Ident("x".into())),
+                operator: TokenType::NullCoalesce,
+                right: Box::new(Expression::Infix(InfixExpr {
+                    left: Box::new(Expression::Null),
+                    operator: TokenType::Eq,
+                    right: Box::new(Expression::Ident("nullish".into())),
+                })),
+            })),
+        ]
+    );
+}
+
 #[test]
 fn bool_expr() {
     let inputs = [
@@ -279,7 +580,7 @@ fn bool_expr() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -301,7 +602,7 @@ fn if_else_expr() {
                     operator: TokenType::Lt,
                     right: Box::new(Expression::Ident("y".into())),
                 })),
-                if_branch: vec![Statement::Expression(Expression::Ident("x".into()))],
+                if_branch: block(vec![Statement::Expression(Expression::Ident("x".into()))]),
                 else_branch: None,
             },
         ),
@@ -313,8 +614,10 @@ fn if_else_expr() {
                     operator: TokenType::Lt,
                     right: Box::new(Expression::Ident("y".into())),
                 })),
-                if_branch: vec![Statement::Expression(Expression::Ident("x".into()))],
-                else_branch: Some(vec![Statement::Expression(Expression::Ident("y".into()))]),
+                if_branch: block(vec![Statement::Expression(Expression::Ident("x".into()))]),
+                else_branch: Some(block(vec![Statement::Expression(Expression::Ident(
+                    "y".into(),
+                ))])),
             },
         ),
     ];
@@ -323,7 +626,7 @@ fn if_else_expr() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -343,17 +646,19 @@ fn func_expr() {
     let input = "fn(x, y) { x * y; }";
     let expected = FuncExpr {
         params: vec!["x".into(), "y".into()],
-        body: vec![Statement::Expression(Expression::Infix(InfixExpr {
+        defaults: vec![],
+        rest: None,
+        body: block(vec![Statement::Expression(Expression::Infix(InfixExpr {
             left: Box::new(Expression::Ident("x".into())),
             operator: TokenType::Star,
             right: Box::new(Expression::Ident("y".into())),
-        }))],
+        }))]),
     };
 
     let lexer = Lexer::new(input.into());
     let mut parser = Parser::new(lexer);
 
-    let Program { statements } = parser.parse().unwrap();
+    let Program { statements, .. } = parser.parse().unwrap();
 
     assert_eq!(1, statements.len());
     let expr = match statements[0] {
@@ -366,6 +671,64 @@ fn func_expr() {
     }
 }
 
+#[test]
+fn macro_expr() {
+    let lexer = Lexer::new("macro(x, y) { x + y; }".into());
+    let mut parser = Parser::new(lexer);
+
+    let Program { statements, .. } = parser.parse().unwrap();
+    assert_eq!(1, statements.len());
+    assert_eq!(
+        statements[0],
+        Statement::Expression(Expression::Macro(MacroExpr {
+            params: vec!["x".into(), "y".into()],
+            body: block(vec![Statement::Expression(Expression::Infix(InfixExpr {
+                left: Box::new(Expression::Ident("x".into())),
+                operator: TokenType::Plus,
+                right: Box::new(Expression::Ident("y".into())),
+            }))]),
+        }))
+    );
+}
+
+#[test]
+fn func_defaults() {
+    let mut parser = Parser::new(Lexer::new("fn(x, y = 10, z = x) { x }".into()));
+    let Program { statements, .. } = parser.parse().unwrap();
+    let Statement::Expression(Expression::Func(func)) = &statements[0] else {
+        panic!("expected Func expression, got {:?}", statements[0]);
+    };
+    assert_eq!(func.params, ["x", "y", "z"]);
+    assert_eq!(
+        func.defaults,
+        [Expression::Number(10), Expression::Ident("x".into())]
+    );
+    assert_eq!(func.required(), 1);
+    assert_eq!(func.to_string(), "fn (x, y = 10, z = x) {\n  x\n}");
+
+    for inp in ["fn(x = 1, y) {}", "fn(x = ) {}", "macro(x = 1) {}"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
+#[test]
+fn func_rest_param() {
+    let mut parser = Parser::new(Lexer::new("fn(first, ...rest) { rest }".into()));
+    let Program { statements, .. } = parser.parse().unwrap();
+    let Statement::Expression(Expression::Func(func)) = &statements[0] else {
+        panic!("expected Func expression, got {:?}", statements[0]);
+    };
+    assert_eq!(func.params, ["first"]);
+    assert_eq!(func.rest.as_deref(), Some("rest"));
+    assert_eq!(func.to_string(), "fn (first, ...rest) {\n  rest\n}");
+
+    for inp in ["fn(...rest, x) {}", "fn(...) {}", "macro(...rest) {}"] {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
 #[test]
 fn func_params() {
     let inputs = [
@@ -381,7 +744,7 @@ fn func_params() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -418,7 +781,7 @@ fn call_expr() {
     let lexer = Lexer::new(input.into());
     let mut parser = Parser::new(lexer);
 
-    let Program { statements } = parser.parse().unwrap();
+    let Program { statements, .. } = parser.parse().unwrap();
 
     assert_eq!(1, statements.len());
     let expr = match statements[0] {
@@ -444,13 +807,18 @@ fn call_expr_arguments() {
         ),
         ("add(x)", vec![Expression::Ident("x".into())]),
         ("add()", vec![]),
+        (
+            "add(x, y,)",
+            vec![Expression::Ident("x".into()), Expression::Ident("y".into())],
+        ),
+        ("add(x,)", vec![Expression::Ident("x".into())]),
     ];
 
     for (inp, expect) in inputs {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -468,6 +836,12 @@ fn call_expr_arguments() {
 fn array_expr() {
     let inputs = [
         ("[]", Expression::Array(ArrayExpr { elements: vec![] })),
+        (
+            "[1, 2,]",
+            Expression::Array(ArrayExpr {
+                elements: vec![Expression::Number(1), Expression::Number(2)],
+            }),
+        ),
         (
             "[1, 2 * 2, 3 + 3]",
             Expression::Array(ArrayExpr {
@@ -492,7 +866,7 @@ fn array_expr() {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -503,6 +877,40 @@ fn array_expr() {
     }
 }
 
+#[test]
+fn slice_expr() {
+    let lexer = Lexer::new("a[1:n - 1]; a[:2]; a[-2:]; a[:]; {a[1]: a[1:]}".into());
+    let Program { statements, .. } = Parser::new(lexer).parse().unwrap();
+    assert_eq!(
+        statements[0],
+        Statement::Expression(Expression::Slice(SliceExpr {
+            left: Box::new(Expression::Ident("a".into())),
+            start: Some(Box::new(Expression::Number(1))),
+            end: Some(Box::new(Expression::Infix(InfixExpr {
+                left: Box::new(Expression::Ident("n".into())),
+                operator: TokenType::Minus,
+                right: Box::new(Expression::Number(1)),
+            }))),
+        }))
+    );
+    let shown: Vec<_> = statements.iter().map(|s| s.to_string()).collect();
+    assert_eq!(
+        shown,
+        [
+            "(a[1:(n - 1)])",
+            "(a[:2])",
+            "(a[(-2):])",
+            "(a[:])",
+            "{(a[1]): (a[1:])}"
+        ]
+    );
+
+    for input in ["a[1:2:3]", "a[1:2", "a[::]"] {
+        let lexer = Lexer::new(input.into());
+        assert!(Parser::new(lexer).parse().is_err(), "{}", input);
+    }
+}
+
 #[test]
 fn index_expr() {
     let input = "arr[1 + 3]";
@@ -518,7 +926,7 @@ fn index_expr() {
     let lexer = Lexer::new(input.into());
     let mut parser = Parser::new(lexer);
 
-    let Program { statements } = parser.parse().unwrap();
+    let Program { statements, .. } = parser.parse().unwrap();
 
     assert_eq!(1, statements.len());
     let expr = match statements[0] {
@@ -532,6 +940,12 @@ fn index_expr() {
 fn hash_expr() {
     let inputs = [
         ("{}", Expression::Hash(HashExpr { pairs: vec![] })),
+        (
+            r#"{"one": 1,}"#,
+            Expression::Hash(HashExpr {
+                pairs: vec![(Expression::String("one".into()), Expression::Number(1))],
+            }),
+        ),
         (
             r#"{"one": 1, "two": 5 - 3, "three": 3}"#,
             Expression::Hash(HashExpr {
@@ -549,13 +963,29 @@ fn hash_expr() {
                 ],
             }),
         ),
+        (
+            "{x, y: 2, z,}",
+            Expression::Hash(HashExpr {
+                pairs: vec![
+                    (
+                        Expression::String("x".into()),
+                        Expression::Ident("x".into()),
+                    ),
+                    (Expression::Ident("y".into()), Expression::Number(2)),
+                    (
+                        Expression::String("z".into()),
+                        Expression::Ident("z".into()),
+                    ),
+                ],
+            }),
+        ),
     ];
 
     for (inp, expect) in inputs {
         let lexer = Lexer::new(inp.into());
         let mut parser = Parser::new(lexer);
 
-        let Program { statements } = parser.parse().unwrap();
+        let Program { statements, .. } = parser.parse().unwrap();
 
         assert_eq!(1, statements.len());
         let expr = match statements[0] {
@@ -566,6 +996,74 @@ fn hash_expr() {
     }
 }
 
+#[test]
+fn block_expr() {
+    let inputs = [
+        (
+            "{ let a = 1; a + 2 }",
+            Expression::Block(block(vec![
+                Statement::Let(LetStmt {
+                    ident: "a".into(),
+                    expr: Expression::Number(1),
+                }),
+                Statement::Expression(Expression::Infix(InfixExpr {
+                    left: Box::new(Expression::Ident("a".into())),
+                    operator: TokenType::Plus,
+                    right: Box::new(Expression::Number(2)),
+                })),
+            ])),
+        ),
+        (
+            "{ 1 }",
+            Expression::Block(block(vec![Statement::Expression(Expression::Number(1))])),
+        ),
+        (
+            "{ x; }",
+            Expression::Block(block(vec![Statement::Expression(Expression::Ident(
+                "x".into(),
+            ))])),
+        ),
+        (
+            "{ return 1; }",
+            Expression::Block(block(vec![Statement::Return(ReturnStmt {
+                expr: Expression::Number(1),
+            })])),
+        ),
+        (
+            "{ {} }",
+            Expression::Block(block(vec![Statement::Expression(Expression::Hash(
+                HashExpr { pairs: vec![] },
+            ))])),
+        ),
+    ];
+
+    for (inp, expect) in inputs {
+        let Program { statements, .. } = Parser::new(Lexer::new(inp.into())).parse().unwrap();
+        assert_eq!(statements, [Statement::Expression(expect)], "{}", inp);
+    }
+
+    let mut parser = Parser::new(Lexer::new("let x = { 1; ".into()));
+    assert!(parser.parse().is_err());
+}
+
+#[test]
+fn bad_commas() {
+    let inputs = [
+        "[,]",
+        "[1,,]",
+        "[1,, 2]",
+        "{,}",
+        r#"{"a": 1,,}"#,
+        "add(,)",
+        "add(1,,)",
+        "[1 2]",
+    ];
+    for inp in inputs {
+        let mut parser = Parser::new(Lexer::new(inp.into()));
+        assert!(parser.parse().is_err(), "{}", inp);
+    }
+}
+
 #[test]
 fn operator_precedence() {
     let inputs = [
@@ -576,6 +1074,28 @@ fn operator_precedence() {
         ("a * b * c", "((a * b) * c)\n"),
         ("a * b / c", "((a * b) / c)\n"),
         ("a + b / c", "(a + (b / c))\n"),
+        ("a ** b ** c", "(a ** (b ** c))\n"),
+        ("a * b ** c", "(a * (b ** c))\n"),
+        ("a ** b * c", "((a ** b) * c)\n"),
+        ("-a ** b", "((-a) ** b)\n"),
+        ("a | b ^ c & d", "(a | (b ^ (c & d)))\n"),
+        ("a & b << c + d", "(a & (b << (c + d)))\n"),
+        ("a << b >> c", "((a << b) >> c)\n"),
+        ("a < b | c == d", "((a < (b | c)) == d)\n"),
+        ("x |> f |> g", "g(f(x))\n"),
+        ("a + 1 |> f == b", "(f == b)((a + 1))\n"),
+        ("x |> fn(a) { a }", "fn (a) {\n  a\n}(x)\n"),
+        ("a ?? b ?? c", "((a ?? b) ?? c)\n"),
+        ("-math.abs(x)", "(-math.abs(x))\n"),
+        ("a.b[0].c + 1", "((a.b[0]).c + 1)\n"),
+        ("a ?? b + 1 |> f", "(a ?? f((b + 1)))\n"),
+        ("a == b ?? c", "((a == b) ?? c)\n"),
+        ("a || b && c == d", "(a || (b && (c == d)))\n"),
+        ("a && b || c ?? d", "(((a && b) || c) ?? d)\n"),
+        ("a..b + 1 == c", "((a .. (b + 1)) == c)\n"),
+        ("0..=n | 1", "(0 ..= (n | 1))\n"),
+        ("a | b || c & d", "((a | b) || (c & d))\n"),
+        ("~a & ~b", "((~a) & (~b))\n"),
         ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)\n"),
         ("3 + 4; -5 * 5", "(3 + 4)\n((-5) * 5)\n"),
         ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))\n"),
@@ -617,6 +1137,31 @@ fn operator_precedence() {
     }
 }
 
+#[test]
+fn modify() {
+    let mut turn_one_into_two = |e| -> Result<Expression, ()> {
+        match e {
+            Expression::Number(1) => Ok(Expression::Number(2)),
+            e => Ok(e),
+        }
+    };
+
+    let inputs = [
+        ("1", "2\n"),
+        ("1 + 2; -1", "(2 + 2)\n(-2)\n"),
+        ("[1, 2][1]", "([2, 2][2])\n"),
+        ("let x = 1; return 1;", "let x = 2;\nreturn 2;\n"),
+        ("{1: 1}", "{2: 2}\n"),
+        ("f(1, x)", "f(2, x)\n"),
+    ];
+
+    for (inp, exp) in inputs {
+        let program = Parser::new(Lexer::new(inp.into())).parse().unwrap();
+        let program = modify_program(program, &mut turn_one_into_two).unwrap();
+        assert_eq!(program.to_string(), exp);
+    }
+}
+
 #[test]
 fn ast_to_string() {
     let ast = Program {
@@ -629,6 +1174,7 @@ fn ast_to_string() {
                 expr: Expression::Ident("y".into()),
             }),
         ],
+        lines: vec![1, 2],
     };
 
     let expected = r#"let myVar = anotherVar;
@@ -636,3 +1182,75 @@ return y;
 "#;
     assert_eq!(ast.to_string(), expected);
 }
+
+#[test]
+fn to_source_parens() {
+    let inputs = [
+        ("((1 + 2) * 3)", "(1 + 2) * 3;"),
+        ("-(-a)", "-(-a);"),
+        ("(1 + (2 + 3)) + 4", "1 + (2 + 3) + 4;"),
+        ("(1 * 2) + (3 * 4)", "1 * 2 + 3 * 4;"),
+        ("(2 ** 3) ** 2", "(2 ** 3) ** 2;"),
+        ("2 ** (3 ** 2)", "2 ** 3 ** 2;"),
+        ("-(a + b) * c", "-(a + b) * c;"),
+        ("(-a)[0]", "(-a)[0];"),
+        ("(a + b)(1)[2]", "(a + b)(1)[2];"),
+        ("!(1 < 2) == (3 == 4)", "!(1 < 2) == (3 == 4);"),
+        ("(1 < 2) < 3", "(1 < 2) < 3;"),
+        ("1 >= (2 <= 3)", "1 >= (2 <= 3);"),
+        ("(a ?? b) + 1", "(a ?? b) + 1;"),
+        ("(a + b).c.d(1)", "(a + b).c.d(1);"),
+        ("a ?? (b ?? c)", "a ?? (b ?? c);"),
+        ("(a || b) && !(c && d)", "(a || b) && !(c && d);"),
+        ("(0..n)[1] + len(1..=2)", "(0 .. n)[1] + len(1 ..= 2);"),
+        (r#""a\\\"b\n""#, r#""a\\\"b\n";"#),
+        ("(2.0 * 0.5)", "2.0 * 0.5;"),
+        ("10000000000000000000000.5", "10000000000000000000000.0;"),
+        ("0.0000001", "0.0000001;"),
+        (r#""${ {"a": 1}["a"] }""#, r#""${{"a": 1}["a"]}";"#),
+        (r#""$\${a}$${b}""#, r#""$\${a}$${b}";"#),
+    ];
+
+    for (inp, exp) in inputs {
+        let program = Parser::new(Lexer::new(inp.into())).parse().unwrap();
+        let source = program.statements[0].to_source();
+        assert_eq!(source, exp, "for {}", inp);
+
+        let reparsed = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements, reparsed.statements, "for {}", inp);
+    }
+}
+
+#[test]
+fn to_source_round_trip() {
+    let input = r#"
+let add = fn(a, b = 1 + 2, ...rest) { if (a > b) { return a; } else { a - -b } };
+const xs = [1, "two", {"k": [3][0]}, fn() {}];
+let [h, ...t] = xs;
+[h, t] = [t, h];
+macro(x) { quote(unquote(x) * 2) };
+if (true) {} else { let y = ~1 << 2 | 3 & 4 ^ 5; y ~/ 2 % 3 }
+while (h) { if (t) { continue; } break; }
+for (x in range(1, 3)) { puts(x); }
+puts("x = ${x + 1} {} \${}");
+puts("${ {"k": 1}["k"] }{{}}");
+xs[1:][:-1];
+let z = { let a = { 1 }; { a; } + 2 };
+"#;
+    let first = Parser::new(Lexer::new(input.into())).parse().unwrap();
+    let source = first.to_source();
+    let second = Parser::new(Lexer::new(source.clone())).parse().unwrap();
+    assert_eq!(second.to_source(), source);
+
+    // Line numbers only match once both come from the printed layout
+    let third = Parser::new(Lexer::new(second.to_source())).parse().unwrap();
+    assert_eq!(second.statements, third.statements);
+}
+
+/// Block of statements which all start on the first line
+fn block(statements: Vec<Statement>) -> Block {
+    Block {
+        lines: vec![1; statements.len()],
+        statements,
+    }
+}
@@ -1,8 +1,146 @@
 use crate::{
-    ast::Ident,
-    eval::{ArrayObj, Object},
+    ast::{Ident, Parser, Program, Statement},
+    error::InterpretError,
+    eval::{
+        check_alloc, num_neg, resolve_index, to_float, ArrayObj, BigInt, HashObj, MemoObj, Object,
+        PartialObj,
+    },
+    lexer::Lexer,
 };
-use std::{fmt::Display, ops::Deref, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    fmt::Display,
+    io::Write,
+    rc::Rc,
+};
+
+/// Where `puts` and `print` write to, stdout unless the engine was given
+/// another writer. Clones write to the same one
+#[derive(Clone)]
+pub struct Output(Rc<RefCell<dyn Write>>);
+
+impl Default for Output {
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new(std::io::stdout())))
+    }
+}
+
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Output")
+    }
+}
+
+impl PartialEq for Output {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Output {}
+
+impl Output {
+    pub fn new(w: Rc<RefCell<dyn Write>>) -> Self {
+        Self(w)
+    }
+
+    fn write(&self, s: &str) -> Result<(), InterpretError> {
+        let mut w = self.0.borrow_mut();
+        w.write_all(s.as_bytes())
+            .and_then(|_| w.flush())
+            .map_err(|e| InterpretError::Io(e.to_string()))
+    }
+}
+
+/// Spreads the bits of `seed` with splitmix64, so close seeds give unrelated
+/// sequences. xorshift never leaves 0, so that one is avoided
+fn rng_state(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    match z ^ (z >> 31) {
+        0 => 1,
+        z => z,
+    }
+}
+
+/// xorshift state behind `rand`, seeded from the clock until `seed` is
+/// called. Each engine keeps its own, so separate programs don't share a
+/// sequence
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rng(Cell<u64>);
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self(Cell::new(rng_state(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+        )))
+    }
+}
+
+impl Rng {
+    fn seed(&self, seed: u64) {
+        self.0.set(rng_state(seed));
+    }
+
+    fn next(&self) -> u64 {
+        let mut x = self.0.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0.set(x);
+        x
+    }
+}
+
+/// Calls a function object on behalf of a builtin, each engine runs it its
+/// own way
+pub type Apply<'a> = dyn FnMut(&Object, Vec<Rc<Object>>) -> Result<Rc<Object>, InterpretError> + 'a;
+
+/// How many calls one [`Apply`] counts as toward either engine's recursion
+/// limit. Calling back nests a whole run of the engine on the native stack,
+/// which takes far more room than a plain call
+pub const CALLBACK_COST: usize = 16;
+
+/// Function a host program registers under a name of its choosing
+pub type NativeFunc = dyn Fn(Vec<Rc<Object>>) -> Result<Rc<Object>, InterpretError>;
+
+#[derive(Clone)]
+pub struct NativeFn {
+    pub name: String,
+    func: Rc<NativeFunc>,
+}
+
+impl NativeFn {
+    pub fn new(name: &str, func: Box<NativeFunc>) -> Self {
+        Self {
+            name: name.to_string(),
+            func: func.into(),
+        }
+    }
+
+    pub fn call(&self, args: Vec<Rc<Object>>) -> Result<Rc<Object>, InterpretError> {
+        (self.func)(args)
+    }
+}
+
+impl std::fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeFn({})", self.name)
+    }
+}
+
+/// Natives are only equal to themselves
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for NativeFn {}
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Builtin {
@@ -12,6 +150,160 @@ pub enum Builtin {
     Rest,
     Push,
     Puts,
+    Keys,
+    Values,
+    Trim,
+    Upper,
+    Lower,
+    Format,
+    Delete,
+    Assert,
+    Print,
+    Entries,
+    MapValues,
+    Chars,
+    IsInt,
+    IsString,
+    IsArray,
+    IsHash,
+    IsFn,
+    IsNull,
+    Pop,
+    Set,
+    Lines,
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
+    Parse,
+    ToJson,
+    FromJson,
+    Partial,
+    Each,
+    Memoize,
+    Try,
+    IsError,
+    Abs,
+    Min,
+    Max,
+    Rand,
+    RandRange,
+    Seed,
+    Range,
+    ToArray,
+    Floor,
+    Ceil,
+    Round,
+    Sqrt,
+}
+
+/// Names of the builtins in the order of [`Builtin`], which is also the
+/// order of their symbols in the compiler
+pub const NAMES: [&str; std::mem::variant_count::<Builtin>()] = [
+    "len",
+    "first",
+    "last",
+    "rest",
+    "push",
+    "puts",
+    "keys",
+    "values",
+    "trim",
+    "upper",
+    "lower",
+    "format",
+    "delete",
+    "assert",
+    "print",
+    "entries",
+    "map_values",
+    "chars",
+    "is_int",
+    "is_string",
+    "is_array",
+    "is_hash",
+    "is_fn",
+    "is_null",
+    "pop",
+    "set",
+    "lines",
+    "wrapping_add",
+    "wrapping_sub",
+    "wrapping_mul",
+    "parse",
+    "to_json",
+    "from_json",
+    "partial",
+    "each",
+    "memoize",
+    "try",
+    "is_error",
+    // Only reachable through the `math` module
+    "math.abs",
+    "math.min",
+    "math.max",
+    "rand",
+    "rand_range",
+    "seed",
+    "range",
+    "to_array",
+    "floor",
+    "ceil",
+    "round",
+    "sqrt",
+];
+
+/// Builtins grouped under a module name, reached with `module.name`. Some
+/// are only reachable this way
+const MODULES: &[(&str, &[(&str, Builtin)])] = &[
+    (
+        "math",
+        &[
+            ("abs", Builtin::Abs),
+            ("min", Builtin::Min),
+            ("max", Builtin::Max),
+            ("floor", Builtin::Floor),
+            ("ceil", Builtin::Ceil),
+            ("round", Builtin::Round),
+            ("sqrt", Builtin::Sqrt),
+        ],
+    ),
+    (
+        "str",
+        &[
+            ("upper", Builtin::Upper),
+            ("lower", Builtin::Lower),
+            ("trim", Builtin::Trim),
+            ("chars", Builtin::Chars),
+            ("lines", Builtin::Lines),
+            ("format", Builtin::Format),
+        ],
+    ),
+];
+
+fn module_members(module: &str) -> Option<&'static [(&'static str, Builtin)]> {
+    MODULES
+        .iter()
+        .find(|(name, _)| *name == module)
+        .map(|(_, members)| *members)
+}
+
+/// The module `name` as a hash of its builtins
+pub fn module(name: &str) -> Option<Object> {
+    let members = module_members(name)?.iter().map(|(member, b)| {
+        (
+            Rc::new(Object::String(member.to_string())),
+            Rc::new(Object::Builtin(*b)),
+        )
+    });
+    Some(Object::Hash(members.collect()))
+}
+
+/// The builtin `module.name` refers to
+pub fn module_member(module: &str, name: &str) -> Option<Builtin> {
+    module_members(module)?
+        .iter()
+        .find(|(member, _)| *member == name)
+        .map(|(_, b)| *b)
 }
 
 impl Builtin {
@@ -20,10 +312,10 @@ impl Builtin {
     }
 
     pub fn from_u8(value: u8) -> Option<Self> {
-        if value > std::mem::variant_count::<Self>() as u8 {
+        if value >= std::mem::variant_count::<Self>() as u8 {
             None
         } else {
-            unsafe { Some(std::mem::transmute(value)) }
+            unsafe { Some(std::mem::transmute::<u8, Builtin>(value)) }
         }
     }
 
@@ -35,49 +327,293 @@ impl Builtin {
             "rest" => Some(Builtin::Rest),
             "push" => Some(Builtin::Push),
             "puts" => Some(Builtin::Puts),
+            "keys" => Some(Builtin::Keys),
+            "values" => Some(Builtin::Values),
+            "trim" => Some(Builtin::Trim),
+            "upper" => Some(Builtin::Upper),
+            "lower" => Some(Builtin::Lower),
+            "format" => Some(Builtin::Format),
+            "delete" => Some(Builtin::Delete),
+            "assert" => Some(Builtin::Assert),
+            "print" => Some(Builtin::Print),
+            "entries" => Some(Builtin::Entries),
+            "map_values" => Some(Builtin::MapValues),
+            "chars" => Some(Builtin::Chars),
+            "is_int" => Some(Builtin::IsInt),
+            "is_string" => Some(Builtin::IsString),
+            "is_array" => Some(Builtin::IsArray),
+            "is_hash" => Some(Builtin::IsHash),
+            "is_fn" => Some(Builtin::IsFn),
+            "is_null" => Some(Builtin::IsNull),
+            "pop" => Some(Builtin::Pop),
+            "set" => Some(Builtin::Set),
+            "lines" => Some(Builtin::Lines),
+            "wrapping_add" => Some(Builtin::WrappingAdd),
+            "wrapping_sub" => Some(Builtin::WrappingSub),
+            "wrapping_mul" => Some(Builtin::WrappingMul),
+            "parse" => Some(Builtin::Parse),
+            "to_json" => Some(Builtin::ToJson),
+            "from_json" => Some(Builtin::FromJson),
+            "partial" => Some(Builtin::Partial),
+            "each" => Some(Builtin::Each),
+            "memoize" => Some(Builtin::Memoize),
+            "try" => Some(Builtin::Try),
+            "is_error" => Some(Builtin::IsError),
+            "rand" => Some(Builtin::Rand),
+            "rand_range" => Some(Builtin::RandRange),
+            "seed" => Some(Builtin::Seed),
+            "range" => Some(Builtin::Range),
+            "to_array" => Some(Builtin::ToArray),
+            "floor" => Some(Builtin::Floor),
+            "ceil" => Some(Builtin::Ceil),
+            "round" => Some(Builtin::Round),
+            "sqrt" => Some(Builtin::Sqrt),
             _ => None,
         }
     }
 
-    pub fn call<T: From<Object> + Display>(&self, args: Vec<&Object>) -> Result<T, String> {
+    /// The name the builtin is called by in error messages
+    pub fn name(&self) -> &'static str {
+        NAMES[*self as usize]
+    }
+
+    /// Smallest and largest number of arguments accepted, `None` when there
+    /// is no upper bound
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            Builtin::Puts | Builtin::Print => (0, None),
+            Builtin::Rand => (0, Some(0)),
+            Builtin::Format | Builtin::Partial | Builtin::Try | Builtin::Min | Builtin::Max => {
+                (1, None)
+            }
+            Builtin::Assert | Builtin::Range => (1, Some(2)),
+            Builtin::Push
+            | Builtin::Delete
+            | Builtin::MapValues
+            | Builtin::Each
+            | Builtin::RandRange
+            | Builtin::WrappingAdd
+            | Builtin::WrappingSub
+            | Builtin::WrappingMul => (2, Some(2)),
+            Builtin::Set => (3, Some(3)),
+            Builtin::Len
+            | Builtin::First
+            | Builtin::Last
+            | Builtin::Rest
+            | Builtin::Keys
+            | Builtin::Values
+            | Builtin::Trim
+            | Builtin::Upper
+            | Builtin::Lower
+            | Builtin::Entries
+            | Builtin::Chars
+            | Builtin::IsInt
+            | Builtin::IsString
+            | Builtin::IsArray
+            | Builtin::IsHash
+            | Builtin::IsFn
+            | Builtin::IsNull
+            | Builtin::Pop
+            | Builtin::Lines
+            | Builtin::Parse
+            | Builtin::ToJson
+            | Builtin::FromJson
+            | Builtin::Memoize
+            | Builtin::IsError
+            | Builtin::Abs
+            | Builtin::Seed
+            | Builtin::ToArray
+            | Builtin::Floor
+            | Builtin::Ceil
+            | Builtin::Round
+            | Builtin::Sqrt => (1, Some(1)),
+        }
+    }
+
+    pub(crate) fn check_arity(&self, got: usize) -> Result<(), InterpretError> {
+        match self.arity() {
+            (min, max) if got < min || max.is_some_and(|max| got > max) => {
+                Err(InterpretError::arity(self.name(), min, max, got))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a call only depends on its arguments, so the compiler may run
+    /// it ahead of time when they are all constants. Ones that check the
+    /// allocation limit depend on the VM's, which the compiler doesn't know
+    pub fn is_pure(&self) -> bool {
+        matches!(
+            self,
+            Builtin::Len
+                | Builtin::First
+                | Builtin::Last
+                | Builtin::Rest
+                | Builtin::Keys
+                | Builtin::Values
+                | Builtin::Trim
+                | Builtin::Upper
+                | Builtin::Lower
+                | Builtin::Delete
+                | Builtin::Entries
+                | Builtin::Chars
+                | Builtin::IsInt
+                | Builtin::IsString
+                | Builtin::IsArray
+                | Builtin::IsHash
+                | Builtin::IsFn
+                | Builtin::IsNull
+                | Builtin::Pop
+                | Builtin::Set
+                | Builtin::Lines
+                | Builtin::WrappingAdd
+                | Builtin::WrappingSub
+                | Builtin::WrappingMul
+                | Builtin::FromJson
+                | Builtin::Partial
+                | Builtin::IsError
+                | Builtin::Abs
+                | Builtin::Min
+                | Builtin::Max
+                | Builtin::Floor
+                | Builtin::Ceil
+                | Builtin::Round
+                | Builtin::Sqrt
+        )
+    }
+
+    /// Like [`Builtin::call`] with arguments the caller owns, so `push` and
+    /// `set` can update the array in place instead of copying it. The first
+    /// argument is taken out on success, on errors `args` is left as it was
+    pub fn call_owned<T: From<Object> + Display>(
+        &self,
+        args: &mut [Object],
+        apply: &mut Apply,
+        rng: &Rng,
+        out: &Output,
+        max_alloc: usize,
+    ) -> Result<T, InterpretError> {
+        match (self, &mut *args) {
+            (Builtin::Push, [Object::Array(arr), value]) => {
+                check_alloc(arr.elements.len() + 1, max_alloc)?;
+                let value = std::mem::replace(value, Object::Null);
+                arr.elements.push(Rc::new(value));
+            }
+            (Builtin::Set, [Object::Array(arr), Object::Integer(i), value])
+                if element_index(*i, arr.elements.len()).is_some() =>
+            {
+                let i = element_index(*i, arr.elements.len()).expect("checked by the guard");
+                let value = std::mem::replace(value, Object::Null);
+                arr.elements[i] = Rc::new(value);
+            }
+            // Anything else, including the errors, goes the usual way
+            _ => return self.call(args.iter().collect(), apply, rng, out, max_alloc),
+        }
+        Ok(std::mem::replace(&mut args[0], Object::Null).into())
+    }
+
+    /// Runs the builtin, `rng`, `out` and `max_alloc` being the calling
+    /// engine's random number state, output and allocation limit
+    pub fn call<T: From<Object> + Display>(
+        &self,
+        args: Vec<&Object>,
+        apply: &mut Apply,
+        rng: &Rng,
+        out: &Output,
+        max_alloc: usize,
+    ) -> Result<T, InterpretError> {
+        self.check_arity(args.len())?;
         match self {
             Builtin::Len => len(args).map(Into::into),
             Builtin::First => first(args).map(Into::into),
             Builtin::Last => last(args).map(Into::into),
             Builtin::Rest => rest(args).map(Into::into),
-            Builtin::Push => push(args).map(Into::into),
-            Builtin::Puts => puts(args).map(Into::into),
+            Builtin::Push => push(args, max_alloc).map(Into::into),
+            Builtin::Puts => puts(args, out).map(Into::into),
+            Builtin::Keys => keys(args).map(Into::into),
+            Builtin::Values => values(args).map(Into::into),
+            Builtin::Trim => trim(args).map(Into::into),
+            Builtin::Upper => upper(args).map(Into::into),
+            Builtin::Lower => lower(args).map(Into::into),
+            Builtin::Format => format(args, max_alloc).map(Into::into),
+            Builtin::Delete => delete(args).map(Into::into),
+            Builtin::Assert => assert(args).map(Into::into),
+            Builtin::Print => print(args, out).map(Into::into),
+            Builtin::Entries => entries(args).map(Into::into),
+            Builtin::MapValues => map_values(args, apply).map(Into::into),
+            Builtin::Chars => chars(args).map(Into::into),
+            Builtin::IsInt => is_kind(args, &["INTEGER"]).map(Into::into),
+            Builtin::IsString => is_kind(args, &["STRING"]).map(Into::into),
+            Builtin::IsArray => is_kind(args, &["ARRAY"]).map(Into::into),
+            Builtin::IsHash => is_kind(args, &["HASH"]).map(Into::into),
+            Builtin::IsFn => {
+                is_kind(args, &["FUNCTION", "COMPILED FUNCTION", "BUILTIN"]).map(Into::into)
+            }
+            Builtin::IsNull => is_kind(args, &["NULL"]).map(Into::into),
+            Builtin::Pop => pop(args).map(Into::into),
+            Builtin::Set => set(args).map(Into::into),
+            Builtin::Lines => lines(args).map(Into::into),
+            Builtin::WrappingAdd => {
+                wrapping("wrapping_add", args, i64::wrapping_add).map(Into::into)
+            }
+            Builtin::WrappingSub => {
+                wrapping("wrapping_sub", args, i64::wrapping_sub).map(Into::into)
+            }
+            Builtin::WrappingMul => {
+                wrapping("wrapping_mul", args, i64::wrapping_mul).map(Into::into)
+            }
+            // It depends on where it's called, which only the engines know
+            Builtin::Parse => unreachable!("`parse` is run by each engine"),
+            Builtin::ToJson => to_json(args, max_alloc).map(Into::into),
+            Builtin::FromJson => from_json(args).map(Into::into),
+            Builtin::Partial => partial(args).map(Into::into),
+            Builtin::Each => each(args, apply).map(Into::into),
+            Builtin::Memoize => memoize(args).map(Into::into),
+            Builtin::Try => try_call(args, apply).map(Into::into),
+            Builtin::IsError => is_kind(args, &["ERROR"]).map(Into::into),
+            Builtin::Abs => abs(args).map(Into::into),
+            Builtin::Min => extreme("math.min", args, Ordering::Less).map(Into::into),
+            Builtin::Max => extreme("math.max", args, Ordering::Greater).map(Into::into),
+            Builtin::Rand => rand(rng).map(Into::into),
+            Builtin::RandRange => rand_range(args, rng).map(Into::into),
+            Builtin::Seed => seed(args, rng).map(Into::into),
+            Builtin::Range => range(args, max_alloc).map(Into::into),
+            Builtin::ToArray => to_array(args, max_alloc).map(Into::into),
+            Builtin::Floor => to_int("floor", args, f64::floor).map(Into::into),
+            Builtin::Ceil => to_int("ceil", args, f64::ceil).map(Into::into),
+            Builtin::Round => to_int("round", args, f64::round).map(Into::into),
+            Builtin::Sqrt => sqrt(args).map(Into::into),
         }
     }
 }
 
-fn len(args: Vec<&Object>) -> Result<Object, String> {
-    if args.len() != 1 {
-        return Err(format!(
-            "wrong number of arguments. expected 1, got {}",
-            args.len()
-        ));
-    }
+fn unsupported(name: &str, arg: &Object) -> InterpretError {
+    InterpretError::TypeMismatch(format!(
+        "argument to `{}` not supported, got {}",
+        name,
+        arg.kind()
+    ))
+}
 
-    match &*args[0] {
-        Object::String(s) => Ok(Object::Integer(s.len() as i64).into()),
-        Object::Array(a) => Ok(Object::Integer(a.elements.len() as i64).into()),
-        _ => Err(format!(
-            "argument to `len` not supported, got {}",
+/// Strings are counted in chars, like `chars` splits them and slices
+/// index them
+fn len(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::String(s) => Ok(Object::Integer(s.chars().count() as i64)),
+        Object::Array(a) => Ok(Object::Integer(a.elements.len() as i64)),
+        Object::Hash(h) => Ok(Object::Integer(h.len() as i64)),
+        Object::Range(r) if r.is_empty() => Ok(Object::Integer(0)),
+        // `i64::MIN..i64::MAX` has more elements than fit an `i64`
+        Object::Range(r) => Ok(BigInt::from(r.end).sub(&BigInt::from(r.start)).into()),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "argument to `len` not supported, got {}, expected STRING, ARRAY, HASH or RANGE",
             args[0].kind()
-        )),
+        ))),
     }
 }
 
-fn first(args: Vec<&Object>) -> Result<Object, String> {
-    if args.len() != 1 {
-        return Err(format!(
-            "wrong number of arguments. expected 1, got {}",
-            args.len()
-        ));
-    }
-
-    match &*args[0] {
+fn first(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
         Object::Array(a) => {
             let f = a
                 .elements
@@ -85,24 +621,14 @@ fn first(args: Vec<&Object>) -> Result<Object, String> {
                 .cloned()
                 .map(|r| (*r).clone())
                 .unwrap_or(Object::Null);
-            Ok(f.into())
+            Ok(f)
         }
-        _ => Err(format!(
-            "argument to `first` not supported, got {}",
-            args[0].kind()
-        )),
+        _ => Err(unsupported("first", args[0])),
     }
 }
 
-fn last(args: Vec<&Object>) -> Result<Object, String> {
-    if args.len() != 1 {
-        return Err(format!(
-            "wrong number of arguments. expected 1, got {}",
-            args.len()
-        ));
-    }
-
-    match &*args[0] {
+fn last(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
         Object::Array(a) => {
             let l = a
                 .elements
@@ -110,59 +636,842 @@ fn last(args: Vec<&Object>) -> Result<Object, String> {
                 .cloned()
                 .map(|r| (*r).clone())
                 .unwrap_or(Object::Null);
-            Ok(l.into())
+            Ok(l)
         }
-        _ => Err(format!(
-            "argument to `last` not supported, got {}",
-            args[0].kind()
-        )),
+        _ => Err(unsupported("last", args[0])),
     }
 }
 
-fn rest(args: Vec<&Object>) -> Result<Object, String> {
-    if args.len() != 1 {
-        return Err(format!(
-            "wrong number of arguments. expected 1, got {}",
-            args.len()
-        ));
-    }
-
-    match &*args[0] {
+fn rest(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
         Object::Array(a) => {
             let elements = a.elements.clone().into_iter().skip(1).collect();
-            Ok(Object::Array(ArrayObj { elements }).into())
+            Ok(Object::Array(ArrayObj { elements }))
         }
-        _ => Err(format!(
-            "argument to `rest` not supported, got {}",
-            args[0].kind()
-        )),
+        _ => Err(unsupported("rest", args[0])),
     }
 }
 
-fn push(args: Vec<&Object>) -> Result<Object, String> {
-    if args.len() != 2 {
-        return Err(format!(
-            "wrong number of arguments. expected 2, got {}",
-            args.len()
-        ));
+fn push(args: Vec<&Object>, max_alloc: usize) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Array(a) => {
+            check_alloc(a.elements.len() + 1, max_alloc)?;
+            let mut elements = a.elements.clone();
+            elements.push(args[1].clone().into());
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        _ => Err(unsupported("push", args[0])),
     }
+}
 
-    match &*args[0] {
+/// The array without its last element, the element itself is dropped
+/// rather than returned so `pop` mirrors `push`, use `last` to read it
+fn pop(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
         Object::Array(a) => {
             let mut elements = a.elements.clone();
-            elements.push(args[1].clone().into());
-            Ok(Object::Array(ArrayObj { elements }).into())
+            elements.pop();
+            Ok(Object::Array(ArrayObj { elements }))
         }
-        _ => Err(format!(
-            "argument to `push` not supported, got {}",
-            args[0].kind()
-        )),
+        _ => Err(unsupported("pop", args[0])),
     }
 }
 
-fn puts(args: Vec<&Object>) -> Result<Object, String> {
+/// The element `i` refers to in an array of `len`, negative ones counting
+/// back from the end like in `a[i]`
+fn element_index(i: i64, len: usize) -> Option<usize> {
+    resolve_index(i, len, "array").ok().filter(|&i| i < len)
+}
+
+/// A copy of the array with element `i` replaced
+fn set(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match (args[0], args[1]) {
+        (Object::Array(a), idx @ (Object::Integer(_) | Object::BigInt(_))) => {
+            let i = match idx {
+                Object::Integer(i) => element_index(*i, a.elements.len()),
+                _ => None,
+            };
+            let mut elements = a.elements.clone();
+            match i.and_then(|i| elements.get_mut(i)) {
+                Some(e) => *e = args[2].clone().into(),
+                None => {
+                    return Err(InterpretError::Runtime(format!(
+                        "index out of range: {} for array of length {}",
+                        idx,
+                        a.elements.len()
+                    )))
+                }
+            }
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        (Object::Array(_), idx) => Err(InterpretError::TypeMismatch(format!(
+            "array index must be INTEGER, got {}",
+            idx.kind()
+        ))),
+        _ => Err(unsupported("set", args[0])),
+    }
+}
+
+fn puts(args: Vec<&Object>, out: &Output) -> Result<Object, InterpretError> {
+    for arg in args {
+        out.write(&format!("{}\n", arg))?;
+    }
+    Ok(Object::Null)
+}
+
+/// Like `puts` but without separators or a trailing newline
+fn print(args: Vec<&Object>, out: &Output) -> Result<Object, InterpretError> {
     for arg in args {
-        println!("{}", arg);
+        out.write(&arg.to_string())?;
+    }
+    Ok(Object::Null)
+}
+
+fn keys(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Hash(h) => {
+            let elements = h.iter().map(|(k, _)| k.clone()).collect();
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        _ => Err(unsupported("keys", args[0])),
+    }
+}
+
+fn values(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Hash(h) => {
+            let elements = h.iter().map(|(_, v)| v.clone()).collect();
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        _ => Err(unsupported("values", args[0])),
+    }
+}
+
+/// `[key, value]` pairs of the hash in insertion order
+fn entries(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Hash(h) => {
+            let elements = h
+                .iter()
+                .map(|(k, v)| {
+                    Rc::new(Object::Array(ArrayObj {
+                        elements: vec![k.clone(), v.clone()],
+                    }))
+                })
+                .collect();
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        _ => Err(unsupported("entries", args[0])),
+    }
+}
+
+fn map_values(args: Vec<&Object>, apply: &mut Apply) -> Result<Object, InterpretError> {
+    match (args[0], args[1]) {
+        (Object::Hash(h), f) if f.is_callable() => {
+            let mut res = HashObj::default();
+            for (k, v) in h.iter() {
+                res.insert(k.clone(), apply(f, vec![v.clone()])?);
+            }
+            Ok(Object::Hash(res))
+        }
+        (Object::Hash(_), f) => Err(unsupported("map_values", f)),
+        _ => Err(unsupported("map_values", args[0])),
+    }
+}
+
+/// Calls the function with every element of an array, or with every key and
+/// value of a hash, only for its side effects
+fn each(args: Vec<&Object>, apply: &mut Apply) -> Result<Object, InterpretError> {
+    match (args[0], args[1]) {
+        (Object::Array(arr), f) if f.is_callable() => {
+            for e in &arr.elements {
+                apply(f, vec![e.clone()])?;
+            }
+        }
+        (Object::Hash(h), f) if f.is_callable() => {
+            for (k, v) in h.iter() {
+                apply(f, vec![k.clone(), v.clone()])?;
+            }
+        }
+        (Object::Array(_) | Object::Hash(_), f) => return Err(unsupported("each", f)),
+        _ => return Err(unsupported("each", args[0])),
+    }
+    Ok(Object::Null)
+}
+
+/// A function calling `args[0]` with the rest of `args` in front of the
+/// arguments it gets
+fn partial(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    let Some((&func, bound)) = args.split_first() else {
+        unreachable!("`call` checks the arity");
+    };
+    if !func.is_callable() {
+        return Err(unsupported("partial", func));
+    }
+
+    Ok(Object::Partial(PartialObj {
+        func: Rc::new(func.clone()),
+        args: bound.iter().map(|&a| Rc::new(a.clone())).collect(),
+    }))
+}
+
+/// Calls `args[0]` with the rest of `args`, turning an error it raises into
+/// an error value
+fn try_call(args: Vec<&Object>, apply: &mut Apply) -> Result<Object, InterpretError> {
+    let Some((&func, args)) = args.split_first() else {
+        unreachable!("`call` checks the arity");
+    };
+    if !func.is_callable() {
+        return Err(unsupported("try", func));
+    }
+
+    match apply(func, args.iter().map(|&a| Rc::new(a.clone())).collect()) {
+        Ok(res) => Ok(Rc::unwrap_or_clone(res)),
+        // The value is the same in both engines, so leave out where the VM
+        // ran into the error
+        Err(e) if e.is_catchable() => Ok(Object::Error(e.unlocated().clone())),
+        Err(e) => Err(e),
+    }
+}
+
+/// A function caching the results of `args[0]` by the arguments it got
+fn memoize(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    if !args[0].is_callable() {
+        return Err(unsupported("memoize", args[0]));
+    }
+
+    Ok(Object::Memo(MemoObj::new(args[0].clone())))
+}
+
+/// Returns a copy of the hash without the key, the original hash is left
+/// untouched. Deleting a missing key returns an equal hash
+fn delete(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match (args[0], args[1]) {
+        (Object::Hash(h), key) => {
+            key.check_hashable()?;
+            let mut h = h.clone();
+            h.remove(key);
+            Ok(Object::Hash(h))
+        }
+        _ => Err(unsupported("delete", args[0])),
+    }
+}
+
+/// Errors when the condition is falsy, with the optional second argument
+/// as the message
+fn assert(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    let (cond, msg) = match args[..] {
+        [cond] => (cond, None),
+        [cond, msg] => (cond, Some(msg)),
+        _ => unreachable!("`call` checks the arity"),
+    };
+
+    if cond.is_truthy() {
+        Ok(Object::Null)
+    } else {
+        let msg = msg.map_or("assertion failed".to_string(), |m| m.to_string());
+        Err(InterpretError::Runtime(msg))
+    }
+}
+
+/// `floor`, `ceil` or `round` of a float as an integer, a `BigInt` past
+/// the range of `i64`. Integers are whole already
+fn to_int(name: &str, args: Vec<&Object>, op: fn(f64) -> f64) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Integer(_) | Object::BigInt(_) => Ok(args[0].clone()),
+        Object::Float(x) => {
+            let x = op(*x);
+            if (i64::MIN as f64..i64::MAX as f64).contains(&x) {
+                Ok(Object::Integer(x as i64))
+            } else {
+                BigInt::from_f64(x).map(Into::into).ok_or_else(|| {
+                    InterpretError::Runtime(format!("`{}` of a non-finite number: {}", name, x))
+                })
+            }
+        }
+        x => Err(unsupported(name, x)),
+    }
+}
+
+fn sqrt(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match to_float(args[0]) {
+        Some(x) if x < 0.0 => Err(InterpretError::Runtime(format!(
+            "`sqrt` of a negative number: {}",
+            args[0]
+        ))),
+        Some(x) => Ok(Object::Float(x.sqrt())),
+        None => Err(unsupported("sqrt", args[0])),
+    }
+}
+
+fn trim(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::String(s) => Ok(Object::String(s.trim().to_string())),
+        _ => Err(unsupported("trim", args[0])),
+    }
+}
+
+fn upper(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::String(s) => Ok(Object::String(s.to_uppercase())),
+        _ => Err(unsupported("upper", args[0])),
+    }
+}
+
+/// Splits on `char` boundaries, so multi-byte characters stay whole
+fn chars(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::String(s) => {
+            let elements = s
+                .chars()
+                .map(|c| Rc::new(Object::String(c.to_string())))
+                .collect();
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        _ => Err(unsupported("chars", args[0])),
+    }
+}
+
+/// Splits on `\n`, dropping a trailing `\r` from each line and the empty
+/// line after a final newline
+fn lines(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::String(s) => {
+            let elements = s
+                .lines()
+                .map(|l| Rc::new(Object::String(l.to_string())))
+                .collect();
+            Ok(Object::Array(ArrayObj { elements }))
+        }
+        _ => Err(unsupported("lines", args[0])),
+    }
+}
+
+fn to_json(args: Vec<&Object>, max_alloc: usize) -> Result<Object, InterpretError> {
+    let mut out = String::new();
+    write_json(args[0], &mut out)?;
+    check_alloc(out.len(), max_alloc)?;
+    Ok(Object::String(out))
+}
+
+/// Appends `obj` as JSON. Hash pairs keep the order their keys were added
+/// in, keys that aren't strings are written as their string form
+fn write_json(obj: &Object, out: &mut String) -> Result<(), InterpretError> {
+    match obj {
+        Object::Integer(_) | Object::BigInt(_) | Object::Bool(_) | Object::Null => {
+            out.push_str(&obj.to_string())
+        }
+        Object::Float(x) if x.is_finite() => out.push_str(&obj.to_string()),
+        Object::Float(x) => {
+            return Err(InterpretError::Runtime(format!(
+                "cannot convert {} to JSON",
+                x
+            )))
+        }
+        Object::String(s) => write_json_str(s, out),
+        Object::Array(a) => {
+            out.push('[');
+            for (i, e) in a.elements.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(e, out)?;
+            }
+            out.push(']');
+        }
+        Object::Hash(h) => {
+            out.push('{');
+            for (i, (k, v)) in h.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                match &**k {
+                    Object::String(s) => write_json_str(s, out),
+                    k => write_json_str(&k.to_string(), out),
+                }
+                out.push(':');
+                write_json(v, out)?;
+            }
+            out.push('}');
+        }
+        _ => {
+            return Err(InterpretError::TypeMismatch(format!(
+                "cannot convert {} to JSON",
+                obj.kind()
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn write_json_str(s: &str, out: &mut String) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if ch < ' ' => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn from_json(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    let Object::String(s) = args[0] else {
+        return Err(unsupported("from_json", args[0]));
+    };
+
+    let mut parser = JsonParser { src: s, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < s.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+/// Arrays and objects nested deeper than this are rejected rather than
+/// risking the native stack
+const MAX_JSON_DEPTH: usize = 256;
+
+struct JsonParser<'a> {
+    src: &'a str,
+    /// Byte offset of the next character
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, msg: &str) -> InterpretError {
+        InterpretError::Runtime(format!("invalid JSON: {} at offset {}", msg, self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\n' | '\r' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace, then `ch` if it comes next
+    fn eat(&mut self, ch: char) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(ch);
+        if found {
+            self.pos += ch.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, ch: char) -> Result<(), InterpretError> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", ch)))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Object, InterpretError> {
+        if depth > MAX_JSON_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some('[') => self.array(depth),
+            Some('{') => self.object(depth),
+            Some('"') => self.string().map(Object::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                let rest = &self.src[self.pos..];
+                let (word, value) = [
+                    ("true", Object::Bool(true)),
+                    ("false", Object::Bool(false)),
+                    ("null", Object::Null),
+                ]
+                .into_iter()
+                .find(|(word, _)| rest.starts_with(word))
+                .ok_or_else(|| self.error("unexpected character"))?;
+                self.pos += word.len();
+                Ok(value)
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Object, InterpretError> {
+        self.pos += 1;
+        let mut elements = vec![];
+        if !self.eat(']') {
+            loop {
+                elements.push(Rc::new(self.value(depth + 1)?));
+                if self.eat(']') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        Ok(Object::Array(ArrayObj { elements }))
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Object, InterpretError> {
+        self.pos += 1;
+        let mut hash = HashObj::default();
+        if !self.eat('}') {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some('"') {
+                    return Err(self.error("expected a string key"));
+                }
+                let key = self.string()?;
+                self.expect(':')?;
+                let value = self.value(depth + 1)?;
+                hash.insert(Rc::new(Object::String(key)), Rc::new(value));
+                if self.eat('}') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        Ok(Object::Hash(hash))
+    }
+
+    /// The string starting at the opening quote
+    fn string(&mut self) -> Result<String, InterpretError> {
+        self.pos += 1;
+        let mut res = String::new();
+        loop {
+            let ch = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            match ch {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(res);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.pos += 1;
+                            res.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.pos += 1;
+                    res.push(escaped);
+                }
+                ch if ch < ' ' => return Err(self.error("control character in string")),
+                ch => {
+                    self.pos += ch.len_utf8();
+                    res.push(ch);
+                }
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, whose four hex digits come next.
+    /// Characters outside the BMP are written as two escaped surrogates
+    fn unicode_escape(&mut self) -> Result<char, InterpretError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid \\u escape"));
+        }
+        if !self.src[self.pos..].starts_with("\\u") {
+            return Err(self.error("unpaired surrogate"));
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, InterpretError> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    /// Integers of any size, or a float when there is a fraction or exponent
+    fn number(&mut self) -> Result<Object, InterpretError> {
+        let start = self.pos;
+        let neg = self.peek() == Some('-');
+        if neg {
+            self.pos += 1;
+        }
+        let digits_start = self.pos;
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.pos += 1;
+        }
+        let digits = &self.src[digits_start..self.pos];
+        if digits.is_empty() || (digits.len() > 1 && digits.starts_with('0')) {
+            return Err(self.error("invalid number"));
+        }
+        if matches!(self.peek(), Some('.' | 'e' | 'E')) {
+            return self.float(start);
+        }
+
+        if let Ok(i) = self.src[start..self.pos].parse::<i64>() {
+            return Ok(Object::Integer(i));
+        }
+        let ten = BigInt::from(10);
+        let big = digits.bytes().fold(BigInt::default(), |acc, d| {
+            acc.mul(&ten).add(&BigInt::from((d - b'0') as i64))
+        });
+        Ok(if neg { big.neg() } else { big }.into())
+    }
+
+    /// Rest of a number starting at `start` whose integer part was read
+    fn float(&mut self, start: usize) -> Result<Object, InterpretError> {
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            self.digits()?;
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            self.digits()?;
+        }
+        let x: f64 = self.src[start..self.pos].parse().unwrap();
+        Ok(Object::Float(x))
+    }
+
+    /// At least one digit
+    fn digits(&mut self) -> Result<(), InterpretError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("invalid number"));
+        }
+        Ok(())
+    }
+}
+
+/// The program made of the single expression in the argument of `parse`
+pub fn parse_arg(args: &[&Object]) -> Result<Program, InterpretError> {
+    Builtin::Parse.check_arity(args.len())?;
+    let Object::String(s) = args[0] else {
+        return Err(unsupported("parse", args[0]));
+    };
+
+    let program = Parser::new(Lexer::new(s.to_string())).parse()?;
+    match program.statements.as_slice() {
+        [Statement::Expression(_)] => Ok(program),
+        _ => Err(InterpretError::Runtime(
+            "`parse` expects a single expression".to_string(),
+        )),
+    }
+}
+
+/// Integer arithmetic that wraps around on overflow instead of growing
+fn wrapping(
+    name: &str,
+    args: Vec<&Object>,
+    op: fn(i64, i64) -> i64,
+) -> Result<Object, InterpretError> {
+    match (args[0], args[1]) {
+        (Object::Integer(a), Object::Integer(b)) => Ok(Object::Integer(op(*a, *b))),
+        (Object::BigInt(_), _) | (_, Object::BigInt(_)) => Err(InterpretError::Runtime(format!(
+            "arguments to `{}` must fit in 64 bits",
+            name
+        ))),
+        (Object::Integer(_), b) => Err(unsupported(name, b)),
+        (a, _) => Err(unsupported(name, a)),
+    }
+}
+
+fn abs(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Integer(x) if *x >= 0 => Ok(args[0].clone()),
+        Object::BigInt(x) if *x >= BigInt::from(0) => Ok(args[0].clone()),
+        Object::Float(x) => Ok(Object::Float(x.abs())),
+        x => num_neg(x).ok_or_else(|| unsupported("math.abs", x)),
+    }
+}
+
+/// The first of the numbers comparing as `pick` to all the others. NaNs
+/// compare to nothing, so they are only picked when first
+fn extreme(name: &str, args: Vec<&Object>, pick: Ordering) -> Result<Object, InterpretError> {
+    let Some((&first, rest)) = args.split_first() else {
+        unreachable!("`call` checks the arity");
+    };
+    if let Some(arg) = args.iter().find(|a| to_float(a).is_none()) {
+        return Err(unsupported(name, arg));
+    }
+
+    let mut best = first;
+    for &arg in rest {
+        if num_cmp(arg, best) == Some(pick) {
+            best = arg;
+        }
+    }
+    Ok(best.clone())
+}
+
+/// Integers compare exactly, mixing in a float compares as floats
+fn num_cmp(a: &Object, b: &Object) -> Option<Ordering> {
+    let big = |o: &Object| match o {
+        Object::Integer(x) => Some(BigInt::from(*x)),
+        Object::BigInt(x) => Some(x.clone()),
+        _ => None,
+    };
+    match (big(a), big(b)) {
+        (Some(x), Some(y)) => Some(x.cmp(&y)),
+        _ => to_float(a)?.partial_cmp(&to_float(b)?),
+    }
+}
+
+/// A pseudo-random integer from 0 to `i64::MAX`
+fn rand(rng: &Rng) -> Result<Object, InterpretError> {
+    Ok(Object::Integer((rng.next() >> 1) as i64))
+}
+
+/// A pseudo-random integer from `lo` up to but not including `hi`
+fn rand_range(args: Vec<&Object>, rng: &Rng) -> Result<Object, InterpretError> {
+    match (args[0], args[1]) {
+        (Object::Integer(lo), Object::Integer(hi)) if lo < hi => {
+            let width = (*hi as i128 - *lo as i128) as u128;
+            let offset = (rng.next() as u128 % width) as i128;
+            Ok(Object::Integer((*lo as i128 + offset) as i64))
+        }
+        (Object::Integer(lo), Object::Integer(hi)) => Err(InterpretError::Runtime(format!(
+            "`rand_range` expects lo < hi, got {} and {}",
+            lo, hi
+        ))),
+        (Object::Integer(_), hi) => Err(unsupported("rand_range", hi)),
+        (lo, _) => Err(unsupported("rand_range", lo)),
+    }
+}
+
+/// Restarts the sequence of `rand` and `rand_range`, the same seed always
+/// giving the same numbers
+fn seed(args: Vec<&Object>, rng: &Rng) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Integer(n) => {
+            rng.seed(*n as u64);
+            Ok(Object::Null)
+        }
+        _ => Err(unsupported("seed", args[0])),
+    }
+}
+
+/// `range(end)` or `range(start, end)`, the integers from `start`, 0 by
+/// default, up to but without `end`
+fn range(args: Vec<&Object>, max_alloc: usize) -> Result<Object, InterpretError> {
+    let (start, end) = match args[..] {
+        [end] => (&Object::Integer(0), end),
+        [start, end] => (start, end),
+        _ => unreachable!("`call` checks the arity"),
+    };
+
+    match (start, end) {
+        (Object::Integer(start), Object::Integer(end)) => {
+            let len = (*end as i128 - *start as i128).max(0);
+            check_alloc(usize::try_from(len).unwrap_or(usize::MAX), max_alloc)?;
+            Ok((*start..*end).collect::<Vec<_>>().into())
+        }
+        (Object::Integer(_), end) => Err(unsupported("range", end)),
+        (start, _) => Err(unsupported("range", start)),
+    }
+}
+
+/// The elements of a range, or a copy of an array
+fn to_array(args: Vec<&Object>, max_alloc: usize) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::Range(r) => {
+            check_alloc(r.len(), max_alloc)?;
+            Ok((0..r.len())
+                .filter_map(|i| r.get(i))
+                .collect::<Vec<_>>()
+                .into())
+        }
+        Object::Array(a) => Ok(Object::Array(a.clone())),
+        _ => Err(unsupported("to_array", args[0])),
+    }
+}
+
+/// Whether the single argument is of one of `kinds`
+fn is_kind(args: Vec<&Object>, kinds: &[&str]) -> Result<Object, InterpretError> {
+    Ok(Object::Bool(kinds.contains(&args[0].kind())))
+}
+
+fn lower(args: Vec<&Object>) -> Result<Object, InterpretError> {
+    match args[0] {
+        Object::String(s) => Ok(Object::String(s.to_lowercase())),
+        _ => Err(unsupported("lower", args[0])),
+    }
+}
+
+/// Replaces each `{}` in the template with the next argument, `{{` and `}}`
+/// are literal braces
+fn format(args: Vec<&Object>, max_alloc: usize) -> Result<Object, InterpretError> {
+    let Some((template, args)) = args.split_first() else {
+        unreachable!("`call` checks the arity");
+    };
+    let Object::String(template) = template else {
+        return Err(unsupported("format", template));
+    };
+
+    let given = args.len();
+    let mut res = String::new();
+    let mut args = args.iter();
+    let mut placeholders = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                res.push(ch);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                placeholders += 1;
+                if let Some(arg) = args.next() {
+                    res += &arg.to_string();
+                }
+            }
+            ('{' | '}', _) => {
+                return Err(InterpretError::Runtime(format!(
+                    "unmatched `{}` in format string",
+                    ch
+                )))
+            }
+            _ => res.push(ch),
+        }
+    }
+
+    if placeholders != given {
+        return Err(InterpretError::WrongArity(format!(
+            "format string has {} placeholders but {} arguments were given",
+            placeholders, given
+        )));
     }
-    Ok(Object::Null.into())
+    check_alloc(res.len(), max_alloc)?;
+    Ok(Object::String(res))
 }
@@ -1,4 +1,5 @@
 use super::instructions::OpCode;
+use crate::error::InterpretError;
 use std::fmt::Display;
 
 #[derive(Default, Debug, PartialEq, Clone, Eq)]
@@ -12,6 +13,11 @@ impl Bytes {
     }
 
     pub fn read<T: BytesRead>(&self, start: usize) -> T {
+        self.try_read(start).expect("read past the end of bytecode")
+    }
+
+    /// Like [`Bytes::read`] but errors on truncated or malformed bytecode
+    pub fn try_read<T: BytesRead>(&self, start: usize) -> Result<T, InterpretError> {
         T::read(self, start)
     }
 
@@ -19,6 +25,10 @@ impl Bytes {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn remove(&mut self, pos: usize) {
         self.data.truncate(pos);
     }
@@ -31,11 +41,31 @@ impl Bytes {
     }
 }
 
-impl Display for Bytes {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Bytes {
+    /// Disassembly with the source line of each instruction in front,
+    /// `|` when it is the same as the previous one
+    pub fn with_lines<'a>(&'a self, lines: &'a LineTable) -> WithLines<'a> {
+        WithLines { bytes: self, lines }
+    }
+
+    fn disassemble(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        lines: Option<&LineTable>,
+    ) -> std::fmt::Result {
         let mut idx = 0;
+        let mut prev_line = None;
 
         while idx < self.data.len() {
+            if let Some(lines) = lines {
+                let line = lines.line_at(idx);
+                match line {
+                    Some(l) if line != prev_line => write!(f, "{:>4} ", l)?,
+                    Some(_) => write!(f, "   | ")?,
+                    None => write!(f, "   ? ")?,
+                }
+                prev_line = line;
+            }
             write!(f, "{:0>4} ", idx)?;
             let op: OpCode = self.data[idx].into();
             idx += 1;
@@ -66,6 +96,58 @@ impl Display for Bytes {
         Ok(())
     }
 }
+
+impl Display for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.disassemble(f, None)
+    }
+}
+
+pub struct WithLines<'a> {
+    bytes: &'a Bytes,
+    lines: &'a LineTable,
+}
+
+impl Display for WithLines<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.bytes.disassemble(f, Some(self.lines))
+    }
+}
+
+/// Maps instruction offsets to the source line they were compiled from.
+/// Only the offsets where the line changes are stored
+#[derive(Default, Debug, PartialEq, Clone, Eq)]
+pub struct LineTable {
+    entries: Vec<(usize, usize)>,
+}
+
+impl LineTable {
+    /// Records that the instruction at `offset` and the ones after it come
+    /// from `line`
+    pub fn add(&mut self, offset: usize, line: usize) {
+        match self.entries.last_mut() {
+            Some((_, l)) if *l == line => {}
+            Some((o, l)) if *o == offset => *l = line,
+            _ => self.entries.push((offset, line)),
+        }
+    }
+
+    /// Line of the instruction at `offset`
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        let idx = self.entries.partition_point(|&(o, _)| o <= offset);
+        idx.checked_sub(1).map(|i| self.entries[i].1)
+    }
+
+    /// Drops the entries of instructions at or after `offset`
+    pub fn truncate(&mut self, offset: usize) {
+        self.entries.retain(|&(o, _)| o < offset);
+    }
+
+    /// `(offset, line)` pairs in order of offset
+    pub fn entries(&self) -> &[(usize, usize)] {
+        &self.entries
+    }
+}
 impl PartialEq<&[u8]> for Bytes {
     fn eq(&self, other: &&[u8]) -> bool {
         self.data == *other
@@ -76,8 +158,12 @@ pub trait BytesWrite {
     fn write(&self, b: &mut Bytes);
 }
 
-pub trait BytesRead {
-    fn read(b: &Bytes, idx: usize) -> Self;
+pub trait BytesRead: Sized {
+    fn read(b: &Bytes, idx: usize) -> Result<Self, InterpretError>;
+}
+
+fn end_of_bytecode() -> InterpretError {
+    InterpretError::Runtime("unexpected end of bytecode".to_string())
 }
 
 impl BytesWrite for OpCode {
@@ -87,8 +173,10 @@ impl BytesWrite for OpCode {
 }
 
 impl BytesRead for OpCode {
-    fn read(b: &Bytes, idx: usize) -> Self {
-        b.data[idx].into()
+    fn read(b: &Bytes, idx: usize) -> Result<Self, InterpretError> {
+        let byte = *b.data.get(idx).ok_or_else(end_of_bytecode)?;
+        OpCode::from_u8(byte)
+            .ok_or_else(|| InterpretError::Runtime(format!("invalid opcode {}", byte)))
     }
 }
 
@@ -101,9 +189,9 @@ macro_rules! impl_bytes_traits {
         }
 
         impl BytesRead for $ty {
-            fn read(b: &Bytes, idx: usize) -> Self {
-                let d = &b.data[idx..(idx + $size)];
-                Self::from_be_bytes(d.try_into().unwrap())
+            fn read(b: &Bytes, idx: usize) -> Result<Self, InterpretError> {
+                let d = b.data.get(idx..(idx + $size)).ok_or_else(end_of_bytecode)?;
+                Ok(Self::from_be_bytes(d.try_into().unwrap()))
             }
         }
     )*};
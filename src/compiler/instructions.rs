@@ -1,7 +1,7 @@
 use super::code::{Bytes, BytesWrite};
 use std::fmt::Display;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum OpCode {
     Constant,
 
@@ -10,26 +10,53 @@ pub enum OpCode {
     Sub,
     Mul,
     Div,
+    FloorDiv,
+    Mod,
+    Pow,
+    Range,
     True,
     False,
     Eq,
     NotEq,
     Greater,
+    Less,
+    GreaterEq,
+    LessEq,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Bang,
     Minus,
+    BitNot,
 
     Jump,
     JumpNotTrue,
+    JumpIfArg,
+    JumpNotNull,
+    JumpFalsy,
+    JumpTruthy,
 
     SetGlobal,
     GetGlobal,
     SetLocal,
     GetLocal,
+    UpdateGlobal,
+    UpdateLocal,
     GetBuiltin,
 
     Array,
     Hash,
     Index,
+    Slice,
+    SetIndexGlobal,
+    SetIndexLocal,
+    GetMember,
+    Unpack,
+    Iter,
+    IterNext,
+    IterEnd,
 
     Call,
     ReturnValue,
@@ -46,26 +73,70 @@ impl OpCode {
             OpCode::Sub => Definition::new("OpSub", &[]),
             OpCode::Mul => Definition::new("OpMul", &[]),
             OpCode::Div => Definition::new("OpDiv", &[]),
+            OpCode::FloorDiv => Definition::new("OpFloorDiv", &[]),
+            OpCode::Mod => Definition::new("OpMod", &[]),
+            OpCode::Pow => Definition::new("OpPow", &[]),
+            // Operand is 1 for `..=`
+            OpCode::Range => Definition::new("OpRange", &[1]),
             OpCode::True => Definition::new("OpTrue", &[]),
             OpCode::False => Definition::new("OpFalse", &[]),
             OpCode::Eq => Definition::new("OpEq", &[]),
             OpCode::NotEq => Definition::new("OpNotEq", &[]),
             OpCode::Greater => Definition::new("OpGreater", &[]),
+            OpCode::Less => Definition::new("OpLess", &[]),
+            OpCode::GreaterEq => Definition::new("OpGreaterEq", &[]),
+            OpCode::LessEq => Definition::new("OpLessEq", &[]),
+            OpCode::BitAnd => Definition::new("OpBitAnd", &[]),
+            OpCode::BitOr => Definition::new("OpBitOr", &[]),
+            OpCode::BitXor => Definition::new("OpBitXor", &[]),
+            OpCode::Shl => Definition::new("OpShl", &[]),
+            OpCode::Shr => Definition::new("OpShr", &[]),
             OpCode::Bang => Definition::new("OpBang", &[]),
             OpCode::Minus => Definition::new("OpMinus", &[]),
+            OpCode::BitNot => Definition::new("OpBitNot", &[]),
 
             OpCode::Jump => Definition::new("OpJump", &[2]),
             OpCode::JumpNotTrue => Definition::new("OpJumpNotTrue", &[2]),
+            // Skips the code computing a param's default when it was passed
+            OpCode::JumpIfArg => Definition::new("OpJumpIfArg", &[1, 2]),
+            // Keeps the top of the stack when jumping, pops it otherwise
+            OpCode::JumpNotNull => Definition::new("OpJumpNotNull", &[2]),
+            // Same, for `&&` and `||`
+            OpCode::JumpFalsy => Definition::new("OpJumpFalsy", &[2]),
+            OpCode::JumpTruthy => Definition::new("OpJumpTruthy", &[2]),
 
             OpCode::SetGlobal => Definition::new("OpSetGlobal", &[2]),
             OpCode::GetGlobal => Definition::new("OpGetGlobal", &[2]),
             OpCode::SetLocal => Definition::new("OpSetLocal", &[1]),
             OpCode::GetLocal => Definition::new("OpGetLocal", &[1]),
+            // Calls the builtin under the other arguments with the variable
+            // moved in as the first one, and stores the result back in it.
+            // Second operand is the number of arguments including the variable
+            OpCode::UpdateGlobal => Definition::new("OpUpdateGlobal", &[2, 1]),
+            OpCode::UpdateLocal => Definition::new("OpUpdateLocal", &[1, 1]),
             OpCode::GetBuiltin => Definition::new("OpGetLocal", &[1]),
 
             OpCode::Array => Definition::new("OpArray", &[2]),
             OpCode::Hash => Definition::new("OpHash", &[2]),
             OpCode::Index => Definition::new("OpIndex", &[]),
+            // Missing bounds are pushed as null
+            OpCode::Slice => Definition::new("OpSlice", &[]),
+            // Replaces an element of the variable in place. Second operand is
+            // the number of indexes below the new value
+            OpCode::SetIndexGlobal => Definition::new("OpSetIndexGlobal", &[2, 1]),
+            OpCode::SetIndexLocal => Definition::new("OpSetIndexLocal", &[1, 1]),
+            // Operand is the constant holding the member as written,
+            // `left.name`, so errors can show it like the evaluator does
+            OpCode::GetMember => Definition::new("OpGetMember", &[2]),
+            OpCode::Unpack => Definition::new("OpUnpack", &[2, 1]),
+            // Pushes the position of a `for` loop next to what it iterates
+            OpCode::Iter => Definition::new("OpIter", &[]),
+            // Pushes the next element and advances the position, or jumps
+            // once there are none left
+            OpCode::IterNext => Definition::new("OpIterNext", &[2]),
+            // Drops what `OpIter` pushed. Unlike two `OpPop`s it can't be
+            // mistaken for the end of an expression statement
+            OpCode::IterEnd => Definition::new("OpIterEnd", &[]),
 
             OpCode::Call => Definition::new("OpCall", &[1]),
             OpCode::ReturnValue => Definition::new("OpReturnValue", &[]),
@@ -80,16 +151,22 @@ impl Display for OpCode {
     }
 }
 
-impl From<u8> for OpCode {
-    fn from(value: u8) -> Self {
-        if value > std::mem::variant_count::<Self>() as u8 {
-            panic!("Invalid opcode: {}", value);
+impl OpCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        if value >= std::mem::variant_count::<Self>() as u8 {
+            None
         } else {
-            unsafe { std::mem::transmute(value) }
+            unsafe { Some(std::mem::transmute::<u8, OpCode>(value)) }
         }
     }
 }
 
+impl From<u8> for OpCode {
+    fn from(value: u8) -> Self {
+        Self::from_u8(value).unwrap_or_else(|| panic!("Invalid opcode: {}", value))
+    }
+}
+
 pub struct Definition {
     pub name: &'static str,
     pub operands: &'static [usize],
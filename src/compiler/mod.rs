@@ -2,9 +2,15 @@
 
 use std::rc::Rc;
 
-use crate::{ast::*, eval::Object, lexer::TokenType};
-
-pub use code::Bytes;
+use crate::{
+    ast::*,
+    builtin::{self, Builtin, Output, Rng},
+    error::InterpretError,
+    eval::{ArrayObj, Object},
+    lexer::TokenType,
+};
+
+pub use code::{Bytes, LineTable, WithLines};
 pub use instructions::{Instruction, OpCode};
 pub use symbol_table::*;
 
@@ -12,32 +18,72 @@ mod code;
 mod instructions;
 mod symbol_table;
 
+/// Target of a jump emitted before the code it jumps to, patched once
+/// that code has been compiled
+const JUMP_PLACEHOLDER: u32 = 9999;
+
 #[derive(Default)]
-struct Scope {
+struct CompilationScope {
     instructions: Bytes,
+    lines: LineTable,
 
     last: Option<Emmited>,
     prev: Option<Emmited>,
+
+    loops: Vec<Loop>,
+    /// Operands left on the stack for an instruction not emitted yet, which
+    /// `break` and `continue` pop before jumping out of the expression
+    operands: usize,
+}
+
+/// Jump targets of the innermost loop being compiled
+struct Loop {
+    /// Where `continue` jumps to
+    start: usize,
+    /// `break` jumps waiting to be patched with the loop end
+    breaks: Vec<usize>,
+    /// Operands already on the stack when the loop started
+    operands: usize,
 }
 
 pub struct Compiler {
     constants: Vec<Object>,
     symbol_table: SymbolTableRef,
-    scopes: Vec<Scope>,
+    scopes: Vec<CompilationScope>,
+    /// Source line of the statement being compiled
+    line: usize,
+    /// Whether defining a name twice in the same scope is an error
+    strict: bool,
+    /// The `let` whose value is being compiled
+    initializing: Option<Initializing>,
+}
+
+struct Initializing {
+    name: String,
+    /// The global the value goes to, defined up front so functions in the
+    /// value can refer to it once it's stored. Functions can't reach the
+    /// locals around them, so a local one is only defined afterwards
+    sym: Option<Symbol>,
+    /// What the name meant before, which the value itself still sees
+    previous: Option<Symbol>,
+    /// Scope the `let` is in, deeper ones are inside functions
+    depth: usize,
 }
 
 impl Default for Compiler {
     fn default() -> Self {
         let symbol_table = SymbolTable::empty();
-        let builtins = ["len", "first", "last", "rest", "push", "puts"];
-        for b in builtins {
+        for b in builtin::NAMES {
             symbol_table.borrow_mut().define_builtin(b);
         }
 
         Self {
             constants: vec![Object::Null],
             symbol_table,
-            scopes: vec![Scope::default()],
+            scopes: vec![CompilationScope::default()],
+            line: 0,
+            strict: false,
+            initializing: None,
         }
     }
 }
@@ -52,6 +98,10 @@ struct Emmited {
 pub struct Bytecode {
     pub instructions: Bytes,
     pub constants: Vec<Object>,
+    pub lines: LineTable,
+    /// The globals the program was compiled with, which `parse` compiles
+    /// against in the VM
+    pub symbols: Option<SymbolTableRef>,
 }
 
 impl Compiler {
@@ -63,18 +113,37 @@ impl Compiler {
         }
     }
 
+    /// Defines a global for a host function, returning the index to register
+    /// it under with [`Vm::register_native`]
+    ///
+    /// [`Vm::register_native`]: crate::vm::Vm::register_native
+    pub fn define_native(&mut self, name: &str) -> u16 {
+        self.symbol_table.borrow_mut().define(name).index
+    }
+
+    /// In strict mode a `let` of a name already defined in the same scope is
+    /// a compile error. Shadowing a name from an outer scope is still fine
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
     pub fn state(&self) -> (SymbolTableRef, Vec<Object>) {
         (self.symbol_table.clone(), self.constants.clone())
     }
 
     pub fn compile(&mut self, program: Program) -> CompileResult {
-        self.compile_block(program.statements)
+        self.compile_block(Block {
+            statements: program.statements,
+            lines: program.lines,
+        })
     }
 
     pub fn bytecode(self) -> Bytecode {
         Bytecode {
             instructions: self.current_scope().instructions.clone(),
+            lines: self.current_scope().lines.clone(),
             constants: self.constants,
+            symbols: Some(self.symbol_table),
         }
     }
 }
@@ -82,18 +151,61 @@ impl Compiler {
 impl Compiler {
     fn compile_stmt(&mut self, stmt: Statement) -> CompileResult {
         match stmt {
-            Statement::Let(l) => {
+            Statement::Let(l) => self.compile_let(&l.ident, l.expr, false),
+            Statement::Const(l) => self.compile_let(&l.ident, l.expr, true),
+            Statement::LetArray(l) => {
                 self.compile_expr(l.expr)?;
-                let sym = self.symbol_table.borrow_mut().define(&l.ident);
-                match sym.scope {
-                    symbol_table::Scope::Global => {
-                        self.emit(Instruction::new(OpCode::SetGlobal, &[sym.index as u32]))
-                    }
-                    symbol_table::Scope::Local => {
-                        self.emit(Instruction::new(OpCode::SetLocal, &[sym.index as u32]))
-                    }
+                let rest = l.rest.is_some() as u32;
+                self.emit(Instruction::new(
+                    OpCode::Unpack,
+                    &[l.names.len() as u32, rest],
+                ));
+                for name in l.names.iter().chain(&l.rest) {
+                    self.define_and_set(name)?;
+                }
+                Ok(())
+            }
+            Statement::Assign(a) => {
+                let sym = self.assign_target(&a.ident)?;
+                let update = a.update_call().and_then(|(callee, _)| {
+                    self.resolve_builtin(callee)
+                        .filter(|b| matches!(b, Builtin::Push | Builtin::Set))
+                });
+                if let Some(builtin) = update {
+                    return self.compile_update(sym, builtin, a.expr);
+                }
+                self.compile_expr(a.expr)?;
+                self.set_symbol(sym);
+                Ok(())
+            }
+            Statement::AssignArray(a) => {
+                let syms = a
+                    .names
+                    .iter()
+                    .map(|name| self.assign_target(name))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.compile_expr(a.expr)?;
+                self.emit(Instruction::new(OpCode::Unpack, &[syms.len() as u32, 0]));
+                for sym in syms {
+                    self.set_symbol(sym);
+                }
+                Ok(())
+            }
+            Statement::AssignIndex(a) => {
+                let sym = self.assign_target(&a.ident)?;
+                let indexes = a.indexes.len() as u32;
+                for index in a.indexes {
+                    self.compile_operand(index)?;
+                }
+                self.compile_expr(a.expr)?;
+                self.consume(indexes as usize);
+                let op = match sym.scope {
+                    symbol_table::Scope::Global => OpCode::SetIndexGlobal,
+                    symbol_table::Scope::Local => OpCode::SetIndexLocal,
                     _ => unreachable!(),
                 };
+                self.emit(Instruction::new(op, &[sym.index as u32, indexes]));
                 Ok(())
             }
             Statement::Return(r) => {
@@ -106,17 +218,83 @@ impl Compiler {
                 self.emit(Instruction::new(OpCode::Pop, &[]));
                 Ok(())
             }
+            Statement::While(w) => {
+                let start = self.instructions().len();
+                self.compile_expr(w.condition)?;
+                let jmp_end = self.emit(Instruction::new(OpCode::JumpNotTrue, &[JUMP_PLACEHOLDER]));
+
+                self.enter_loop(start);
+                self.compile_block(w.body)?;
+                self.emit(Instruction::new(OpCode::Jump, &[start as u32]));
+                self.leave_loop();
+
+                self.patch(
+                    jmp_end,
+                    Instruction::new(OpCode::JumpNotTrue, &[self.instructions().len() as u32]),
+                );
+                // Like other statements the loop leaves a value to pop, null
+                self.emit(Instruction::null());
+                self.emit(Instruction::new(OpCode::Pop, &[]));
+                Ok(())
+            }
+            Statement::For(l) => {
+                self.compile_expr(l.iterable)?;
+                self.emit(Instruction::new(OpCode::Iter, &[]));
+
+                let start = self.instructions().len();
+                let jmp_end = self.emit(Instruction::new(OpCode::IterNext, &[JUMP_PLACEHOLDER]));
+
+                self.enter_loop(start);
+                // The loop variable is only visible in the body
+                self.in_block(|c| {
+                    c.define_and_set(&l.ident)?;
+                    c.compile_block(l.body)
+                })?;
+                self.emit(Instruction::new(OpCode::Jump, &[start as u32]));
+                self.leave_loop();
+
+                self.patch(
+                    jmp_end,
+                    Instruction::new(OpCode::IterNext, &[self.instructions().len() as u32]),
+                );
+                self.emit(Instruction::new(OpCode::IterEnd, &[]));
+                self.emit(Instruction::null());
+                self.emit(Instruction::new(OpCode::Pop, &[]));
+                Ok(())
+            }
+            Statement::Break => {
+                if self.current_scope().loops.is_empty() {
+                    return Err(InterpretError::Compile("break outside loop".to_string()));
+                }
+                self.pop_loop_operands();
+                let pos = self.emit(Instruction::new(OpCode::Jump, &[JUMP_PLACEHOLDER]));
+                if let Some(l) = self.current_scope_mut().loops.last_mut() {
+                    l.breaks.push(pos);
+                }
+                Ok(())
+            }
+            Statement::Continue => {
+                let start = self
+                    .current_scope()
+                    .loops
+                    .last()
+                    .map(|l| l.start)
+                    .ok_or(InterpretError::Compile("continue outside loop".to_string()))?;
+                self.pop_loop_operands();
+                self.emit(Instruction::new(OpCode::Jump, &[start as u32]));
+                Ok(())
+            }
         }
     }
 
     fn compile_expr(&mut self, expr: Expression) -> CompileResult {
         match expr {
             Expression::Ident(i) => {
-                let sym = self
-                    .symbol_table
-                    .borrow()
-                    .resolve(&i)
-                    .ok_or(format!("undefined symbol: {}", i))?;
+                let Some(sym) = self.resolve(&i.name) else {
+                    let module = builtin::module(&i.name).ok_or_else(|| self.undefined(&i))?;
+                    self.emit_constant(module)?;
+                    return Ok(());
+                };
 
                 match sym.scope {
                     symbol_table::Scope::Global => {
@@ -132,14 +310,41 @@ impl Compiler {
             }
             Expression::Number(x) => {
                 let obj = Object::Integer(x);
-                let idx = self.add_constant(obj) as u32;
+                let idx = self.add_constant(obj)?;
+                self.emit(Instruction::new(OpCode::Constant, &[idx]));
+            }
+            Expression::BigNumber(x) => {
+                let obj = Object::BigInt(x);
+                let idx = self.add_constant(obj)?;
+                self.emit(Instruction::new(OpCode::Constant, &[idx]));
+            }
+            Expression::Float(x) => {
+                let obj = Object::Float(x);
+                let idx = self.add_constant(obj)?;
                 self.emit(Instruction::new(OpCode::Constant, &[idx]));
             }
             Expression::String(s) => {
                 let obj = Object::String(s);
-                let idx = self.add_constant(obj) as u32;
+                let idx = self.add_constant(obj)?;
                 self.emit(Instruction::new(OpCode::Constant, &[idx]));
             }
+            Expression::Interpolated(i) => {
+                // The builtin itself, so a `format` the program defines
+                // doesn't change what strings mean
+                self.emit(Instruction::new(
+                    OpCode::GetBuiltin,
+                    &[Builtin::Format as u32],
+                ));
+                let idx = self.add_constant(Object::String(i.template()))?;
+                self.emit(Instruction::new(OpCode::Constant, &[idx]));
+                self.current_scope_mut().operands += 2;
+                let args = i.exprs.len() as u32 + 1;
+                for e in i.exprs {
+                    self.compile_operand(e)?;
+                }
+                self.consume(args as usize + 1);
+                self.emit(Instruction::new(OpCode::Call, &[args]));
+            }
             Expression::Prefix(p) => self.compile_prefix(p)?,
             Expression::Infix(i) => self.compile_infix(i)?,
             Expression::Bool(b) => {
@@ -148,19 +353,19 @@ impl Compiler {
                     false => self.emit(Instruction::new(OpCode::False, &[])),
                 };
             }
+            Expression::Null => {
+                self.emit(Instruction::null());
+            }
             Expression::If(IfExpr {
                 condition,
                 if_branch,
                 else_branch,
             }) => {
                 self.compile_expr(*condition)?;
-                let jmp_if = self.emit(Instruction::new(OpCode::JumpNotTrue, &[9999]));
+                let jmp_if = self.emit(Instruction::new(OpCode::JumpNotTrue, &[JUMP_PLACEHOLDER]));
 
-                self.compile_block(if_branch)?;
-                if self.last_is(OpCode::Pop) {
-                    self.remove_last();
-                }
-                let jmp_else = self.emit(Instruction::new(OpCode::Jump, &[9999]));
+                self.in_block(|c| c.compile_branch(if_branch))?;
+                let jmp_else = self.emit(Instruction::new(OpCode::Jump, &[JUMP_PLACEHOLDER]));
 
                 self.patch(
                     jmp_if,
@@ -168,10 +373,7 @@ impl Compiler {
                 );
 
                 if let Some(else_branch) = else_branch {
-                    self.compile_block(else_branch)?;
-                    if self.last_is(OpCode::Pop) {
-                        self.remove_last();
-                    }
+                    self.in_block(|c| c.compile_branch(else_branch))?;
                 } else {
                     self.emit(Instruction::null());
                 }
@@ -180,38 +382,98 @@ impl Compiler {
                     Instruction::new(OpCode::Jump, &[self.instructions().len() as u32]),
                 )
             }
+            Expression::Macro(_) => {
+                return Err(InterpretError::Compile(
+                    "macros must be expanded before compiling".to_string(),
+                ))
+            }
             Expression::Func(f) => {
                 let idx = self.compile_func(f)?;
                 self.emit(Instruction::new(OpCode::Constant, &[idx]));
             }
             Expression::Call(c) => {
-                self.compile_expr(*c.func)?;
+                let builtin = match &*c.func {
+                    Expression::Ident(i) => {
+                        self.resolve_builtin(&i.name).map(|b| (i.name.clone(), b))
+                    }
+                    Expression::Member(m) => self.resolve_member(m)?.map(|b| (m.to_string(), b)),
+                    _ => None,
+                };
+                if let Some((name, b)) = &builtin {
+                    self.check_builtin_arity(name, *b, c.arguments.len())?;
+                }
+                let pure = builtin.map(|(_, b)| b).filter(Builtin::is_pure);
+                let (start, constants) = (self.instructions().len(), self.constants.len());
+                let last = self.current_scope().last;
+
+                self.compile_operand(*c.func)?;
+                let args_start = self.instructions().len();
                 let args = c.arguments.len();
                 for arg in c.arguments {
-                    self.compile_expr(arg)?;
+                    self.compile_operand(arg)?;
+                }
+                self.consume(args + 1);
+
+                if let Some(builtin) = pure {
+                    if let Some(values) = self.constant_args(args_start, args) {
+                        let obj = self.fold_builtin_call(builtin, values)?;
+                        self.truncate(start, last);
+                        self.constants.truncate(constants);
+                        self.emit_constant(obj)?;
+                        return Ok(());
+                    }
                 }
                 self.emit(Instruction::new(OpCode::Call, &[args as u32]));
             }
             Expression::Array(a) => {
                 let len = a.elements.len();
                 for e in a.elements {
-                    self.compile_expr(e)?;
+                    self.compile_operand(e)?;
                 }
+                self.consume(len);
                 self.emit(Instruction::new(OpCode::Array, &[len as u32]));
             }
             Expression::Index(i) => {
-                self.compile_expr(*i.left)?;
+                self.compile_operand(*i.left)?;
                 self.compile_expr(*i.index)?;
+                self.consume(1);
                 self.emit(Instruction::new(OpCode::Index, &[]));
             }
+            Expression::Slice(s) => {
+                self.compile_operand(*s.left)?;
+                for bound in [s.start, s.end] {
+                    match bound {
+                        Some(e) => self.compile_operand(*e)?,
+                        None => {
+                            self.emit(Instruction::null());
+                            self.current_scope_mut().operands += 1;
+                        }
+                    }
+                }
+                self.consume(3);
+                self.emit(Instruction::new(OpCode::Slice, &[]));
+            }
+            Expression::Member(m) => match self.resolve_member(&m)? {
+                Some(b) => {
+                    self.emit(Instruction::new(OpCode::GetBuiltin, &[b as u32]));
+                }
+                None => {
+                    let member = m.to_string();
+                    self.compile_expr(*m.left)?;
+                    let idx = self.add_constant(Object::String(member))?;
+                    self.emit(Instruction::new(OpCode::GetMember, &[idx]));
+                }
+            },
             Expression::Hash(h) => {
                 let len = h.pairs.len();
                 for (k, v) in h.pairs {
-                    self.compile_expr(k)?;
-                    self.compile_expr(v)?;
+                    self.compile_operand(k)?;
+                    self.compile_operand(v)?;
                 }
+                self.consume(len * 2);
                 self.emit(Instruction::new(OpCode::Hash, &[len as u32]));
             }
+            Expression::Block(b) => self.in_block(|c| c.compile_branch(b))?,
         }
 
         Ok(())
@@ -219,21 +481,321 @@ impl Compiler {
 }
 
 impl Compiler {
-    fn compile_block(&mut self, block: Vec<Statement>) -> CompileResult {
-        for stmt in block {
+    fn compile_block(&mut self, block: Block) -> CompileResult {
+        let outer_line = self.line;
+        for (stmt, line) in block.statements.into_iter().zip(block.lines) {
+            self.line = line;
+            // Nothing after a return in the same block can run
+            let returns = matches!(stmt, Statement::Return(_));
             self.compile_stmt(stmt)?;
+            if returns {
+                break;
+            }
+        }
+        self.line = outer_line;
+        Ok(())
+    }
+
+    /// Compiles a branch so it leaves exactly one value on the stack, `Null`
+    /// when the block is empty or ends in a statement
+    fn compile_branch(&mut self, block: Block) -> CompileResult {
+        let start = self.instructions().len();
+        self.compile_block(block)?;
+        if self.instructions().len() == start {
+            self.emit(Instruction::null());
+        } else if self.last_is(OpCode::Pop) {
+            self.remove_last();
+        } else if !self.last_is(OpCode::ReturnValue) {
+            self.emit(Instruction::null());
+        }
+        Ok(())
+    }
+
+    /// Compiles whatever `f` does with the names it defines going out of
+    /// scope afterwards
+    fn in_block(&mut self, f: impl FnOnce(&mut Self) -> CompileResult) -> CompileResult {
+        let outer = self.symbol_table.clone();
+        self.symbol_table = SymbolTable::new_block(&outer);
+        let compiled = f(self);
+        self.symbol_table = outer;
+        compiled
+    }
+
+    /// Points at the identifier itself when the parser recorded where it
+    /// is, otherwise at the line of the statement using it
+    fn undefined(&self, ident: &IdentExpr) -> InterpretError {
+        let at = match ident.line {
+            0 => format!("line {}", self.line),
+            line => format!("line {}:{}", line, ident.col),
+        };
+        if self
+            .initializing
+            .as_ref()
+            .is_some_and(|init| init.name == ident.name)
+        {
+            return InterpretError::Undefined(format!(
+                "cannot use '{}' in its own initializer ({})",
+                ident, at
+            ));
+        }
+        InterpretError::Undefined(format!("undefined symbol: {} ({})", ident, at))
+    }
+
+    /// The builtin `name` refers to, unless it's shadowed
+    fn resolve_builtin(&self, name: &str) -> Option<Builtin> {
+        match self.resolve(name) {
+            Some(sym) if sym.scope == symbol_table::Scope::Builtin => {
+                Builtin::from_u8(sym.index as u8)
+            }
+            _ => None,
         }
+    }
+
+    /// The builtin `module.name` refers to, when the left side is a module
+    /// that isn't shadowed. A name the module doesn't have is an error
+    fn resolve_member(&self, m: &MemberExpr) -> Result<Option<Builtin>, InterpretError> {
+        let Expression::Ident(module) = &*m.left else {
+            return Ok(None);
+        };
+        if self.resolve(&module.name).is_some() {
+            return Ok(None);
+        }
+        if builtin::module(&module.name).is_none() {
+            return Err(self.undefined(module));
+        }
+
+        builtin::module_member(&module.name, &m.name)
+            .map(Some)
+            .ok_or_else(|| {
+                InterpretError::Runtime(format!("unknown member: {} (line {})", m, self.line))
+            })
+    }
+
+    /// Calls to a builtin referenced by name get their argument count checked
+    /// here, any other callee is only checked when the VM calls it
+    fn check_builtin_arity(&self, name: &str, builtin: Builtin, got: usize) -> CompileResult {
+        match builtin.arity() {
+            (min, max) if got < min || max.is_some_and(|max| got > max) => {
+                Err(InterpretError::arity(name, min, max, got))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a call to a pure builtin whose arguments are all constants, so
+    /// its result can be emitted as a constant. Errors become compile errors
+    fn fold_builtin_call(
+        &self,
+        builtin: Builtin,
+        args: Vec<Object>,
+    ) -> Result<Object, InterpretError> {
+        builtin
+            .call(
+                args.iter().collect(),
+                &mut |_, _| unreachable!("pure builtins don't call functions"),
+                // Nor do they draw random numbers, write output or check the
+                // allocation limit
+                &Rng::default(),
+                &Output::default(),
+                usize::MAX,
+            )
+            .map_err(|e| e.map_message(|m| format!("{} (line {})", m, self.line)))
+    }
+
+    /// The values pushed by the instructions from `start` on, when there are
+    /// `count` of them and they only come from constants other than
+    /// functions, bools and arrays of those. Folded calls among them are
+    /// constants already, so nested calls fold without walking their
+    /// arguments again
+    fn constant_args(&self, start: usize, count: usize) -> Option<Vec<Object>> {
+        let ins = self.instructions();
+        let mut values = Vec::with_capacity(count);
+        let mut ip = start;
+        while ip < ins.len() {
+            let op: OpCode = ins.read(ip);
+            match op {
+                OpCode::Constant => {
+                    let idx = ins.read::<u16>(ip + 1) as usize;
+                    match &self.constants[idx] {
+                        // The constants its body refers to were added after
+                        // the call started, so folding would truncate them away
+                        Object::CompiledFunc(_) => return None,
+                        obj => values.push(obj.clone()),
+                    }
+                }
+                OpCode::True => values.push(Object::Bool(true)),
+                OpCode::False => values.push(Object::Bool(false)),
+                OpCode::Array => {
+                    let len = ins.read::<u16>(ip + 1) as usize;
+                    let elements = values.split_off(values.len().checked_sub(len)?);
+                    values.push(Object::Array(ArrayObj {
+                        elements: elements.into_iter().map(Rc::new).collect(),
+                    }));
+                }
+                _ => return None,
+            }
+            ip += op.def().len;
+        }
+        (values.len() == count).then_some(values)
+    }
+
+    fn emit_constant(&mut self, obj: Object) -> CompileResult {
+        match obj {
+            Object::Bool(true) => self.emit(Instruction::new(OpCode::True, &[])),
+            Object::Bool(false) => self.emit(Instruction::new(OpCode::False, &[])),
+            Object::Null => self.emit(Instruction::null()),
+            obj => {
+                let idx = self.add_constant(obj)?;
+                self.emit(Instruction::new(OpCode::Constant, &[idx]))
+            }
+        };
         Ok(())
     }
 
-    fn compile_func(&mut self, FuncExpr { params, body }: FuncExpr) -> Result<u32, String> {
+    fn define(&mut self, name: &str, constant: bool) -> Result<Symbol, InterpretError> {
+        let mut table = self.symbol_table.borrow_mut();
+        if self.strict && table.defined_here(name) {
+            return Err(InterpretError::Compile(format!(
+                "variable already defined: {} (line {})",
+                name, self.line
+            )));
+        }
+        Ok(if constant {
+            table.define_const(name)
+        } else {
+            table.define(name)
+        })
+    }
+
+    /// A global is bound before its value is compiled so functions in the
+    /// value, like a recursive one, can call it. A local is only bound after,
+    /// functions can't reach the locals around them
+    fn compile_let(&mut self, name: &str, expr: Expression, constant: bool) -> CompileResult {
+        let previous = self.symbol_table.borrow().resolve(name);
+        let global = self.symbol_table.borrow().is_global();
+        let sym = if global {
+            Some(self.define(name, constant)?)
+        } else {
+            None
+        };
+        let outer = self.initializing.replace(Initializing {
+            name: name.to_string(),
+            sym,
+            previous,
+            depth: self.scopes.len(),
+        });
+        let compiled = self.compile_expr(expr);
+        self.initializing = outer;
+        compiled?;
+
+        let sym = match sym {
+            Some(sym) => sym,
+            None => self.define(name, constant)?,
+        };
+        self.set_symbol(sym);
+        Ok(())
+    }
+
+    /// The symbol `name` refers to here, which differs from the symbol
+    /// table's answer in the value of a global `let` of that name
+    fn resolve(&self, name: &str) -> Option<Symbol> {
+        let sym = self.symbol_table.borrow().resolve(name);
+        match &self.initializing {
+            Some(init) if sym.is_some() && sym == init.sym && self.scopes.len() == init.depth => {
+                init.previous
+            }
+            _ => sym,
+        }
+    }
+
+    /// The existing symbol `name` can be assigned to
+    fn assign_target(&self, name: &str) -> Result<Symbol, InterpretError> {
+        match self.resolve(name) {
+            Some(sym) if sym.scope == symbol_table::Scope::Builtin => Err(InterpretError::Compile(
+                format!("cannot assign to builtin: {}", name),
+            )),
+            Some(sym) if sym.constant => Err(InterpretError::Compile(format!(
+                "cannot reassign constant: {}",
+                name
+            ))),
+            Some(sym) => Ok(sym),
+            None => Err(self.undefined(&name.into())),
+        }
+    }
+
+    /// `name = push(name, ...)` and `name = set(name, ...)` leave the array
+    /// in `name` for the VM to move into the call, so unless something else
+    /// refers to it the builtin updates it in place
+    fn compile_update(&mut self, sym: Symbol, builtin: Builtin, call: Expression) -> CompileResult {
+        let Expression::Call(c) = call else {
+            unreachable!("`update_call` only matches calls")
+        };
+        let args = c.arguments.len();
+        self.check_builtin_arity(builtin.name(), builtin, args)?;
+
+        self.compile_operand(*c.func)?;
+        for arg in c.arguments.into_iter().skip(1) {
+            self.compile_operand(arg)?;
+        }
+        self.consume(args);
+        let op = match sym.scope {
+            symbol_table::Scope::Global => OpCode::UpdateGlobal,
+            symbol_table::Scope::Local => OpCode::UpdateLocal,
+            _ => unreachable!(),
+        };
+        self.emit(Instruction::new(op, &[sym.index as u32, args as u32]));
+        Ok(())
+    }
+
+    /// Defines the symbol and stores the top of the stack in it
+    fn define_and_set(&mut self, name: &str) -> CompileResult {
+        let sym = self.define(name, false)?;
+        self.set_symbol(sym);
+        Ok(())
+    }
+
+    /// Stores the top of the stack in an already defined symbol
+    fn set_symbol(&mut self, sym: Symbol) {
+        match sym.scope {
+            symbol_table::Scope::Global => {
+                self.emit(Instruction::new(OpCode::SetGlobal, &[sym.index as u32]))
+            }
+            symbol_table::Scope::Local => {
+                self.emit(Instruction::new(OpCode::SetLocal, &[sym.index as u32]))
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    fn compile_func(&mut self, func: FuncExpr) -> Result<u32, InterpretError> {
+        let required = func.required();
+        let FuncExpr {
+            params,
+            defaults,
+            rest,
+            body,
+        } = func;
         self.enter_scope();
 
-        for p in &params {
-            self.symbol_table.borrow_mut().define(p);
+        for p in params.iter().chain(&rest) {
+            self.define(p, false)?;
+        }
+        let n_defaults = defaults.len();
+        for (i, default) in (required..).zip(defaults) {
+            let jmp = self.emit(Instruction::new(
+                OpCode::JumpIfArg,
+                &[i as u32, JUMP_PLACEHOLDER],
+            ));
+            self.compile_expr(default)?;
+            self.emit(Instruction::new(OpCode::SetLocal, &[i as u32]));
+            let after = self.instructions().len() as u32;
+            self.patch(jmp, Instruction::new(OpCode::JumpIfArg, &[i as u32, after]));
         }
 
+        // The implicit return belongs to the last line of the body
+        let end_line = body.lines.last().copied().unwrap_or(self.line);
         self.compile_block(body)?;
+        let outer_line = std::mem::replace(&mut self.line, end_line);
         if self.last_is(OpCode::Pop) {
             self.remove_last();
             self.emit(Instruction::new(OpCode::ReturnValue, &[]));
@@ -241,21 +803,33 @@ impl Compiler {
         if !self.last_is(OpCode::ReturnValue) {
             self.emit(Instruction::new(OpCode::Return, &[]));
         }
+        self.line = outer_line;
         let locals = self.symbol_table.borrow().symbols();
-        let body = self.leave_scope().instructions;
+        let scope = self.leave_scope();
 
-        Ok(self.add_constant(Object::CompiledFunc(Rc::new(
+        self.add_constant(Object::CompiledFunc(Rc::new(
             crate::eval::CompiledFuncObj {
-                instructions: body,
+                instructions: scope.instructions,
                 locals,
                 params: params.len(),
+                defaults: n_defaults,
+                rest: rest.is_some(),
+                lines: scope.lines,
             },
-        ))) as u32)
+        )))
     }
 
-    fn add_constant(&mut self, obj: Object) -> usize {
+    /// Index of the new constant, which has to fit the `u16` operand of
+    /// [`OpCode::Constant`]
+    fn add_constant(&mut self, obj: Object) -> Result<u32, InterpretError> {
+        if self.constants.len() > u16::MAX as usize {
+            return Err(InterpretError::Compile(format!(
+                "too many constants (line {})",
+                self.line
+            )));
+        }
         self.constants.push(obj);
-        self.constants.len() - 1
+        Ok(self.constants.len() as u32 - 1)
     }
 
     fn emit(&mut self, i: Instruction) -> usize {
@@ -263,6 +837,8 @@ impl Compiler {
 
         self.current_scope_mut().prev = self.current_scope().last;
         self.current_scope_mut().last = Some(Emmited { opcode: i.op, pos });
+        let line = self.line;
+        self.current_scope_mut().lines.add(pos, line);
 
         self.instructions_mut().push(i);
         pos
@@ -273,6 +849,7 @@ impl Compiler {
         match p.operator {
             TokenType::Minus => self.emit(Instruction::new(OpCode::Minus, &[])),
             TokenType::Bang => self.emit(Instruction::new(OpCode::Bang, &[])),
+            TokenType::Tilde => self.emit(Instruction::new(OpCode::BitNot, &[])),
             _ => unreachable!(),
         };
 
@@ -280,22 +857,46 @@ impl Compiler {
     }
 
     fn compile_infix(&mut self, i: InfixExpr) -> CompileResult {
-        match i.operator {
-            TokenType::Lt => self.compile_infix_rev(i),
-            _ => self.compile_infix_normal(i),
-        }
-    }
-
-    fn compile_infix_normal(&mut self, i: InfixExpr) -> CompileResult {
         self.compile_expr(*i.left)?;
+        // Skips the right side when the left one is the result
+        let skip = match i.operator {
+            TokenType::NullCoalesce => Some(OpCode::JumpNotNull),
+            TokenType::And => Some(OpCode::JumpFalsy),
+            TokenType::Or => Some(OpCode::JumpTruthy),
+            _ => None,
+        };
+        if let Some(op) = skip {
+            let jmp = self.emit(Instruction::new(op, &[JUMP_PLACEHOLDER]));
+            self.compile_expr(*i.right)?;
+            self.patch(
+                jmp,
+                Instruction::new(op, &[self.instructions().len() as u32]),
+            );
+            return Ok(());
+        }
+        self.current_scope_mut().operands += 1;
         self.compile_expr(*i.right)?;
+        self.consume(1);
 
         match i.operator {
             TokenType::Plus => self.emit(Instruction::new(OpCode::Add, &[])),
             TokenType::Minus => self.emit(Instruction::new(OpCode::Sub, &[])),
             TokenType::Star => self.emit(Instruction::new(OpCode::Mul, &[])),
             TokenType::Slash => self.emit(Instruction::new(OpCode::Div, &[])),
+            TokenType::FloorDiv => self.emit(Instruction::new(OpCode::FloorDiv, &[])),
+            TokenType::Percent => self.emit(Instruction::new(OpCode::Mod, &[])),
+            TokenType::Pow => self.emit(Instruction::new(OpCode::Pow, &[])),
+            TokenType::DotDot => self.emit(Instruction::new(OpCode::Range, &[0])),
+            TokenType::DotDotEq => self.emit(Instruction::new(OpCode::Range, &[1])),
             TokenType::Gt => self.emit(Instruction::new(OpCode::Greater, &[])),
+            TokenType::Lt => self.emit(Instruction::new(OpCode::Less, &[])),
+            TokenType::GtEq => self.emit(Instruction::new(OpCode::GreaterEq, &[])),
+            TokenType::LtEq => self.emit(Instruction::new(OpCode::LessEq, &[])),
+            TokenType::Ampersand => self.emit(Instruction::new(OpCode::BitAnd, &[])),
+            TokenType::Pipe => self.emit(Instruction::new(OpCode::BitOr, &[])),
+            TokenType::Caret => self.emit(Instruction::new(OpCode::BitXor, &[])),
+            TokenType::Shl => self.emit(Instruction::new(OpCode::Shl, &[])),
+            TokenType::Shr => self.emit(Instruction::new(OpCode::Shr, &[])),
             TokenType::Eq => self.emit(Instruction::new(OpCode::Eq, &[])),
             TokenType::NotEq => self.emit(Instruction::new(OpCode::NotEq, &[])),
             _ => unreachable!(),
@@ -303,17 +904,6 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_infix_rev(&mut self, i: InfixExpr) -> CompileResult {
-        self.compile_expr(*i.right)?;
-        self.compile_expr(*i.left)?;
-
-        match i.operator {
-            TokenType::Lt => self.emit(Instruction::new(OpCode::Greater, &[])),
-            _ => unreachable!(),
-        };
-        Ok(())
-    }
-
     fn last_is(&self, op: OpCode) -> bool {
         self.current_scope()
             .last
@@ -321,9 +911,17 @@ impl Compiler {
             .unwrap_or(false)
     }
 
+    /// Drops the instructions from `pos` on, `last` being the one before
+    fn truncate(&mut self, pos: usize, last: Option<Emmited>) {
+        self.instructions_mut().remove(pos);
+        self.current_scope_mut().lines.truncate(pos);
+        self.current_scope_mut().last = last;
+    }
+
     fn remove_last(&mut self) {
         let last = self.current_scope().last.expect("No instruction to remove");
         self.instructions_mut().remove(last.pos);
+        self.current_scope_mut().lines.truncate(last.pos);
 
         self.current_scope_mut().last = self.current_scope().prev;
     }
@@ -332,12 +930,56 @@ impl Compiler {
         self.instructions_mut().patch(pos, i);
     }
 
+    fn enter_loop(&mut self, start: usize) {
+        let operands = self.current_scope().operands;
+        self.current_scope_mut().loops.push(Loop {
+            start,
+            breaks: vec![],
+            operands,
+        });
+    }
+
+    /// Compiles an operand that stays on the stack while the ones after it
+    /// are compiled, until [`Compiler::consume`] is called for it
+    fn compile_operand(&mut self, e: Expression) -> CompileResult {
+        self.compile_expr(e)?;
+        self.current_scope_mut().operands += 1;
+        Ok(())
+    }
+
+    /// Marks `n` operands as taken by the instruction about to be emitted
+    fn consume(&mut self, n: usize) {
+        self.current_scope_mut().operands -= n;
+    }
+
+    /// Pops what the expressions around a `break` or `continue` left on the
+    /// stack since the innermost loop started
+    fn pop_loop_operands(&mut self) {
+        let scope = self.current_scope();
+        let loop_operands = scope.loops.last().map_or(0, |l| l.operands);
+        for _ in loop_operands..scope.operands {
+            self.emit(Instruction::new(OpCode::Pop, &[]));
+        }
+    }
+
+    fn leave_loop(&mut self) {
+        let l = self
+            .current_scope_mut()
+            .loops
+            .pop()
+            .expect("Cannot leave loop when not inside one");
+        let end = self.instructions().len() as u32;
+        for pos in l.breaks {
+            self.patch(pos, Instruction::new(OpCode::Jump, &[end]));
+        }
+    }
+
     fn enter_scope(&mut self) {
-        self.scopes.push(Scope::default());
+        self.scopes.push(CompilationScope::default());
         self.symbol_table = SymbolTable::new_enclosed(&self.symbol_table);
     }
 
-    fn leave_scope(&mut self) -> Scope {
+    fn leave_scope(&mut self) -> CompilationScope {
         let s = self.symbol_table.borrow_mut().outer.take();
         self.symbol_table = s.expect("Cannot leave out of global symbol table");
 
@@ -353,20 +995,20 @@ impl Compiler {
         &mut self.current_scope_mut().instructions
     }
 
-    fn current_scope(&self) -> &Scope {
+    fn current_scope(&self) -> &CompilationScope {
         self.scopes
             .last()
             .expect("There should always exist at least one scope")
     }
 
-    fn current_scope_mut(&mut self) -> &mut Scope {
+    fn current_scope_mut(&mut self) -> &mut CompilationScope {
         self.scopes
             .last_mut()
             .expect("There should always exist at least one scope")
     }
 }
 
-type CompileResult = Result<(), String>;
+type CompileResult = Result<(), InterpretError>;
 
 #[cfg(test)]
 mod test;
@@ -1,9 +1,14 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Symbol {
     pub scope: Scope,
     pub index: u16,
+    pub constant: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,7 +24,11 @@ pub type SymbolTableRef = Rc<RefCell<SymbolTable>>;
 pub struct SymbolTable {
     pub outer: Option<SymbolTableRef>,
     store: HashMap<String, Symbol>,
-    stored: usize,
+    /// Slots defined in the function, or at the top level, this table is
+    /// for. The blocks in it share the count
+    stored: Rc<Cell<usize>>,
+    /// Scope of the symbols defined here
+    scope: Scope,
 }
 
 impl SymbolTable {
@@ -27,7 +36,8 @@ impl SymbolTable {
         Rc::new(RefCell::new(Self {
             outer: None,
             store: HashMap::default(),
-            stored: 0,
+            stored: Rc::default(),
+            scope: Scope::Global,
         }))
     }
 
@@ -35,22 +45,51 @@ impl SymbolTable {
         Rc::new(RefCell::new(Self {
             outer: Some(outer.clone()),
             store: HashMap::default(),
-            stored: 0,
+            stored: Rc::default(),
+            scope: Scope::Local,
         }))
     }
 
-    pub fn define(&mut self, name: &str) -> Symbol {
-        let scope = if self.outer.is_some() {
-            Scope::Local
-        } else {
-            Scope::Global
+    /// Names defined in a block, which go out of scope after it. Their
+    /// slots are in the same frame as the ones of `outer`
+    pub fn new_block(outer: &SymbolTableRef) -> SymbolTableRef {
+        let (stored, scope) = {
+            let outer = outer.borrow();
+            (outer.stored.clone(), outer.scope)
         };
+        Rc::new(RefCell::new(Self {
+            outer: Some(outer.clone()),
+            store: HashMap::default(),
+            stored,
+            scope,
+        }))
+    }
+
+    /// A copy to define names in without affecting this table. Unlike
+    /// `clone` the slot count isn't shared, so neither are new slots
+    pub fn snapshot(&self) -> Self {
+        Self {
+            stored: Rc::new(Cell::new(self.stored.get())),
+            ..self.clone()
+        }
+    }
 
+    pub fn define(&mut self, name: &str) -> Symbol {
+        self.define_symbol(name, false)
+    }
+
+    /// Defines a symbol which assignments may not change
+    pub fn define_const(&mut self, name: &str) -> Symbol {
+        self.define_symbol(name, true)
+    }
+
+    fn define_symbol(&mut self, name: &str, constant: bool) -> Symbol {
         let sym = Symbol {
-            scope,
-            index: self.stored as u16,
+            scope: self.scope,
+            index: self.stored.get() as u16,
+            constant,
         };
-        self.stored += 1;
+        self.stored.set(self.stored.get() + 1);
         self.store.insert(name.to_string(), sym);
         self.store[name]
     }
@@ -59,11 +98,19 @@ impl SymbolTable {
         let sym = Symbol {
             scope: Scope::Builtin,
             index: self.store.len() as u16,
+            constant: false,
         };
         self.store.insert(name.to_string(), sym);
         self.store[name]
     }
 
+    /// Whether `name` was defined in this scope itself, builtins aside
+    pub fn defined_here(&self, name: &str) -> bool {
+        self.store
+            .get(name)
+            .is_some_and(|sym| sym.scope != Scope::Builtin)
+    }
+
     pub fn resolve(&self, name: &str) -> Option<Symbol> {
         self.store
             .get(name)
@@ -71,8 +118,34 @@ impl SymbolTable {
             .or_else(|| self.outer.as_ref().and_then(|o| o.borrow().resolve(name)))
     }
 
+    /// Every name visible from this scope sorted by name, shadowed names
+    /// only appear once with their innermost scope
+    pub fn defined_names(&self) -> Vec<(String, Scope)> {
+        let mut names: Vec<_> = self
+            .store
+            .iter()
+            .map(|(name, sym)| (name.clone(), sym.scope))
+            .collect();
+        if let Some(outer) = &self.outer {
+            for (name, scope) in outer.borrow().defined_names() {
+                if !self.store.contains_key(&name) {
+                    names.push((name, scope));
+                }
+            }
+        }
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        names
+    }
+
+    /// Number of slots the defined symbols take. Defining a name again
+    /// takes a new slot, so this can be more than the number of names
     pub fn symbols(&self) -> usize {
-        self.store.len()
+        self.stored.get()
+    }
+
+    /// Whether names defined here are globals
+    pub fn is_global(&self) -> bool {
+        self.scope == Scope::Global
     }
 }
 
@@ -80,6 +153,38 @@ impl SymbolTable {
 mod test {
     use super::*;
 
+    type Expected = (SymbolTableRef, &'static [(&'static str, Scope, u16)]);
+
+    #[test]
+    fn defined_names() {
+        let glob = SymbolTable::empty();
+        glob.borrow_mut().define_builtin("len");
+        glob.borrow_mut().define("a");
+        glob.borrow_mut().define("b");
+
+        let local = SymbolTable::new_enclosed(&glob);
+        local.borrow_mut().define("c");
+        local.borrow_mut().define("a");
+
+        assert_eq!(
+            glob.borrow().defined_names(),
+            [
+                ("a".to_string(), Scope::Global),
+                ("b".to_string(), Scope::Global),
+                ("len".to_string(), Scope::Builtin),
+            ]
+        );
+        assert_eq!(
+            local.borrow().defined_names(),
+            [
+                ("a".to_string(), Scope::Local),
+                ("b".to_string(), Scope::Global),
+                ("c".to_string(), Scope::Local),
+                ("len".to_string(), Scope::Builtin),
+            ]
+        );
+    }
+
     #[test]
     fn resolve_local() {
         let glob = SymbolTable::empty();
@@ -94,7 +199,7 @@ mod test {
         local2.borrow_mut().define("e");
         local2.borrow_mut().define("f");
 
-        let expected: &[(SymbolTableRef, &[(&'static str, Scope, u16)])] = &[
+        let expected: &[Expected] = &[
             (
                 local1,
                 &[
@@ -120,12 +225,13 @@ mod test {
                 let r = l
                     .borrow()
                     .resolve(e.0)
-                    .expect(&format!("Symbol {} not found", e.0));
+                    .unwrap_or_else(|| panic!("Symbol {} not found", e.0));
                 assert_eq!(
                     r,
                     Symbol {
                         scope: e.1,
-                        index: e.2
+                        index: e.2,
+                        constant: false,
                     },
                     "Symbol {} is wrong",
                     e.0
@@ -133,4 +239,26 @@ mod test {
             }
         }
     }
+    #[test]
+    fn resolve_block() {
+        let local = SymbolTable::new_enclosed(&SymbolTable::empty());
+        local.borrow_mut().define("a");
+
+        let block = SymbolTable::new_block(&local);
+        block.borrow_mut().define("a");
+        block.borrow_mut().define("b");
+        local.borrow_mut().define("c");
+
+        let local_sym = |index| Symbol {
+            scope: Scope::Local,
+            index,
+            constant: false,
+        };
+        assert_eq!(block.borrow().resolve("a"), Some(local_sym(1)));
+        assert_eq!(block.borrow().resolve("b"), Some(local_sym(2)));
+        assert_eq!(local.borrow().resolve("a"), Some(local_sym(0)));
+        assert_eq!(local.borrow().resolve("b"), None);
+        assert_eq!(local.borrow().resolve("c"), Some(local_sym(3)));
+        assert_eq!(local.borrow().symbols(), 4);
+    }
 }
@@ -51,6 +51,38 @@ fn integer_math() {
                 Instruction::new(OpCode::Pop, &[]),
             ],
         ),
+        (
+            "1.5 * 2",
+            &[Object::Float(1.5), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Mul, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            "2 ** 3 ** 2",
+            &[Object::Integer(2), Object::Integer(3), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Constant, &[3]),
+                Instruction::new(OpCode::Pow, &[]),
+                Instruction::new(OpCode::Pow, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            "7 % 2",
+            &[Object::Integer(7), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Mod, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
         (
             "-1",
             &[Object::Integer(1)],
@@ -63,6 +95,43 @@ fn integer_math() {
     )
 }
 
+#[test]
+fn bitwise() {
+    test!(
+        (
+            "6 & 3",
+            &[Object::Integer(6), Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::BitAnd, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            "1 << 4 | 2",
+            &[Object::Integer(1), Object::Integer(4), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Shl, &[]),
+                Instruction::new(OpCode::Constant, &[3]),
+                Instruction::new(OpCode::BitOr, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            "~0",
+            &[Object::Integer(0)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::BitNot, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+    )
+}
+
 #[test]
 fn bool_expressions() {
     test!(
@@ -82,6 +151,11 @@ fn bool_expressions() {
                 Instruction::new(OpCode::Pop, &[])
             ]
         ),
+        (
+            "null",
+            &[],
+            &[Instruction::null(), Instruction::new(OpCode::Pop, &[])]
+        ),
         (
             "1 > 2",
             &[Object::Integer(1), Object::Integer(2)],
@@ -94,11 +168,21 @@ fn bool_expressions() {
         ),
         (
             "1 < 2",
-            &[Object::Integer(2), Object::Integer(1)],
+            &[Object::Integer(1), Object::Integer(2)],
             &[
                 Instruction::new(OpCode::Constant, &[1]),
                 Instruction::new(OpCode::Constant, &[2]),
-                Instruction::new(OpCode::Greater, &[]),
+                Instruction::new(OpCode::Less, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            "1 <= 2",
+            &[Object::Integer(1), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::LessEq, &[]),
                 Instruction::new(OpCode::Pop, &[]),
             ],
         ),
@@ -134,6 +218,36 @@ fn bool_expressions() {
     )
 }
 
+#[test]
+fn logical_operators() {
+    test!(
+        (
+            "true && false; 3",
+            &[Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::True, &[]),
+                Instruction::new(OpCode::JumpFalsy, &[5]),
+                Instruction::new(OpCode::False, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "true || false; 3",
+            &[Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::True, &[]),
+                Instruction::new(OpCode::JumpTruthy, &[5]),
+                Instruction::new(OpCode::False, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+    )
+}
+
 #[test]
 fn conditionals() {
     test!(
@@ -169,7 +283,427 @@ fn conditionals() {
                 Instruction::new(OpCode::Pop, &[]),           // 17
             ]
         ),
-    )
+    )
+}
+
+#[test]
+fn null_coalescing() {
+    test!((
+        "1 ?? 2; 3",
+        &[Object::Integer(1), Object::Integer(2), Object::Integer(3)],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),    // 0
+            Instruction::new(OpCode::JumpNotNull, &[9]), // 3
+            Instruction::new(OpCode::Constant, &[2]),    // 6
+            Instruction::new(OpCode::Pop, &[]),          // 9
+            Instruction::new(OpCode::Constant, &[3]),    // 10
+            Instruction::new(OpCode::Pop, &[]),          // 13
+        ]
+    ))
+}
+
+#[test]
+fn module_members() {
+    test!(
+        (
+            "math.abs",
+            &[],
+            &[
+                Instruction::new(OpCode::GetBuiltin, &[Builtin::Abs as u32]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "str.upper",
+            &[],
+            &[
+                Instruction::new(OpCode::GetBuiltin, &[Builtin::Upper as u32]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "math.max(1, 3)",
+            &[Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            r#"let math = {"abs": 1}; math.abs"#,
+            &[
+                Object::String("abs".into()),
+                Object::Integer(1),
+                Object::String("math.abs".into()),
+            ],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Hash, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::GetMember, &[3]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+    );
+    test_err(&[
+        ("math.bogus", "unknown member: math.bogus (line 1)"),
+        ("nope.abs", "undefined symbol: nope (line 1:1)"),
+        ("math.abs(1, 2)", "math.abs expects 1 argument, got 2"),
+        (
+            "str.upper(1)",
+            "argument to `upper` not supported, got INTEGER (line 1)",
+        ),
+    ])
+}
+
+#[test]
+fn block_expressions() {
+    test!(
+        (
+            // `x` gets its slot before the value is compiled
+            "let x = { let a = 1; a + 2 };",
+            &[Object::Integer(1), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[1]),
+                Instruction::new(OpCode::GetGlobal, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Add, &[]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+            ]
+        ),
+        (
+            "{ let a = 1; };",
+            &[Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::null(),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+    )
+}
+
+#[test]
+fn let_array() {
+    test!(
+        (
+            "let [a, ...b] = [1, 2]; b",
+            &[Object::Integer(1), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Array, &[2]),
+                Instruction::new(OpCode::Unpack, &[1, 1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::SetGlobal, &[1]),
+                Instruction::new(OpCode::GetGlobal, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "fn(x) { let [a, b] = x; b }",
+            &[Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+                [
+                    Instruction::new(OpCode::GetLocal, &[0]),
+                    Instruction::new(OpCode::Unpack, &[2, 0]),
+                    Instruction::new(OpCode::SetLocal, &[1]),
+                    Instruction::new(OpCode::SetLocal, &[2]),
+                    Instruction::new(OpCode::GetLocal, &[2]),
+                    Instruction::new(OpCode::ReturnValue, &[]),
+                ]
+                .into_iter()
+                .fold(Bytes::default(), |mut b, i| {
+                    b.push(i);
+                    b
+                }),
+                3,
+                1,
+            )))],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+    )
+}
+
+#[test]
+fn assign_array() {
+    test!(
+        (
+            "let a = 1; let b = 2; [a, b] = [b, a];",
+            &[Object::Integer(1), Object::Integer(2)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::SetGlobal, &[1]),
+                Instruction::new(OpCode::GetGlobal, &[1]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Array, &[2]),
+                Instruction::new(OpCode::Unpack, &[2, 0]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::SetGlobal, &[1]),
+            ]
+        ),
+        (
+            "let a = 1; let b = 2; let c = 3; [a, b, c] = [b, c, a];",
+            &[Object::Integer(1), Object::Integer(2), Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::SetGlobal, &[1]),
+                Instruction::new(OpCode::Constant, &[3]),
+                Instruction::new(OpCode::SetGlobal, &[2]),
+                Instruction::new(OpCode::GetGlobal, &[1]),
+                Instruction::new(OpCode::GetGlobal, &[2]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Array, &[3]),
+                Instruction::new(OpCode::Unpack, &[3, 0]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::SetGlobal, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[2]),
+            ]
+        ),
+    );
+    test_err(&[
+        (
+            "let a = 1; [a, b] = [1, 2];",
+            "undefined symbol: b (line 1)",
+        ),
+        ("[len] = [1];", "cannot assign to builtin: len"),
+    ])
+}
+
+#[test]
+fn assign() {
+    test!(
+        (
+            "let a = 1; a = a + 1;",
+            &[Object::Integer(1), Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Add, &[]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+            ]
+        ),
+        (
+            "let a = 1; a += 1;",
+            &[Object::Integer(1), Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Add, &[]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+            ]
+        ),
+        (
+            "let a = []; a = push(a, 1);",
+            &[Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Array, &[0]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::GetBuiltin, &[Builtin::Push as u32]),
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::UpdateGlobal, &[0, 2]),
+            ]
+        ),
+        // The other arguments read `a`, so it has to stay where it is
+        (
+            "let a = []; a = push(a, a);",
+            &[],
+            &[
+                Instruction::new(OpCode::Array, &[0]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::GetBuiltin, &[Builtin::Push as u32]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Call, &[2]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+            ]
+        ),
+    );
+    test_err(&[
+        ("b = 1;", "undefined symbol: b (line 1)"),
+        ("len = 1;", "cannot assign to builtin: len"),
+        ("const PI = 3; PI = 4;", "cannot reassign constant: PI"),
+    ])
+}
+
+#[test]
+fn assign_index() {
+    test!((
+        "let a = [1]; a[0] = 2;",
+        &[Object::Integer(1), Object::Integer(0), Object::Integer(2)],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::Array, &[1]),
+            Instruction::new(OpCode::SetGlobal, &[0]),
+            Instruction::new(OpCode::Constant, &[2]),
+            Instruction::new(OpCode::Constant, &[3]),
+            Instruction::new(OpCode::SetIndexGlobal, &[0, 1]),
+        ]
+    ));
+    test!((
+        "fn(a) { a[0][1] = 2; }",
+        &[
+            Object::Integer(0),
+            Object::Integer(1),
+            Object::Integer(2),
+            Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+                [
+                    Instruction::new(OpCode::Constant, &[1]),
+                    Instruction::new(OpCode::Constant, &[2]),
+                    Instruction::new(OpCode::Constant, &[3]),
+                    Instruction::new(OpCode::SetIndexLocal, &[0, 2]),
+                    Instruction::new(OpCode::Return, &[]),
+                ]
+                .into_iter()
+                .fold(Bytes::default(), |mut b, i| {
+                    b.push(i);
+                    b
+                }),
+                1,
+                1,
+            ))),
+        ],
+        &[
+            Instruction::new(OpCode::Constant, &[4]),
+            Instruction::new(OpCode::Pop, &[]),
+        ]
+    ));
+    test_err(&[
+        ("b[0] = 1;", "undefined symbol: b (line 1)"),
+        ("len[0] = 1;", "cannot assign to builtin: len"),
+        ("const A = [1]; A[0] = 2;", "cannot reassign constant: A"),
+    ])
+}
+
+#[test]
+fn builtin_arity() {
+    test_err(&[
+        (r#"len("one", "two")"#, "len expects 1 argument, got 2"),
+        ("push([])", "push expects 2 arguments, got 1"),
+        ("assert()", "assert expects 1 or 2 arguments, got 0"),
+        ("format()", "format expects at least 1 argument, got 0"),
+        ("fn() { rest() }", "rest expects 1 argument, got 0"),
+    ]);
+
+    let program = Parser::new(Lexer::new(
+        "puts(); puts(1, 2, 3); let len = fn(a, b) { a }; len(1, 2); let f = first; f(1, 2)"
+            .to_string(),
+    ))
+    .parse()
+    .unwrap();
+    assert!(Compiler::default().compile(program).is_ok());
+}
+
+#[test]
+fn self_reference_in_let() {
+    test!((
+        "let f = fn() { f() };",
+        &[Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+            [
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Call, &[0]),
+                Instruction::new(OpCode::ReturnValue, &[]),
+            ]
+            .into_iter()
+            .fold(Bytes::default(), |mut b, i| {
+                b.push(i);
+                b
+            }),
+            0,
+            0,
+        )))],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::SetGlobal, &[0]),
+        ]
+    ));
+    // Functions in the value of a global only run once it's stored
+    test!((
+        "let x = [fn() { x }];",
+        &[Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+            [
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::ReturnValue, &[]),
+            ]
+            .into_iter()
+            .fold(Bytes::default(), |mut b, i| {
+                b.push(i);
+                b
+            }),
+            0,
+            0,
+        )))],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::Array, &[1]),
+            Instruction::new(OpCode::SetGlobal, &[0]),
+        ]
+    ));
+    test_err(&[
+        (
+            "let x = x + 1;",
+            "cannot use 'x' in its own initializer (line 1:9)",
+        ),
+        (
+            "let f = fn() {\n let x = [1, x]; };",
+            "cannot use 'x' in its own initializer (line 2:14)",
+        ),
+        (
+            "let f = fn() { const x = [fn() { x }]; };",
+            "cannot use 'x' in its own initializer (line 1:34)",
+        ),
+        (
+            "let g = fn() { let f = fn(n) { f(n - 1) }; f(3) };",
+            "cannot use 'f' in its own initializer (line 1:32)",
+        ),
+        (
+            "let x = { let y = 1; y + z };",
+            "undefined symbol: z (line 1:26)",
+        ),
+    ]);
+
+    // An earlier definition is still what the new one starts from
+    let program = Parser::new(Lexer::new(
+        "let x = 1; let x = x + 1; let f = fn(y) { let y = y + x; y }".to_string(),
+    ))
+    .parse()
+    .unwrap();
+    assert!(Compiler::default().compile(program).is_ok());
+}
+
+#[test]
+fn constants() {
+    test!((
+        "const PI = 3; PI",
+        &[Object::Integer(3)],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::SetGlobal, &[0]),
+            Instruction::new(OpCode::GetGlobal, &[0]),
+            Instruction::new(OpCode::Pop, &[]),
+        ]
+    ));
+    test_err(&[
+        ("const PI = 3; [PI] = [4];", "cannot reassign constant: PI"),
+        (
+            "const PI = 3; let f = fn() { [PI] = [4]; };",
+            "cannot reassign constant: PI",
+        ),
+    ])
 }
 
 #[test]
@@ -391,6 +925,114 @@ fn index() {
     )
 }
 
+#[test]
+fn undefined_symbol_line() {
+    test_err(&[
+        ("x", "undefined symbol: x (line 1:1)"),
+        (
+            "let a = 1;\nlet f = fn() {\n  a + b\n};",
+            "undefined symbol: b (line 3:7)",
+        ),
+        (
+            "let a = 1;\n\nif (a) { 1 } else {\n  [a, c] = [1, 2];\n}",
+            "undefined symbol: c (line 4)",
+        ),
+        (
+            "let a = 1;\nlet b = f(a);",
+            "undefined symbol: f (line 2:9)",
+        ),
+        // The statement starts a line above
+        ("let b = 1 +\n  c;", "undefined symbol: c (line 2:3)"),
+    ])
+}
+
+#[test]
+fn line_table() {
+    let input = "let x = 1;\nlet f = fn() {\n  x\n};\n\nf();";
+    let program = Parser::new(Lexer::new(input.into())).parse().unwrap();
+    let mut compiler = Compiler::default();
+    compiler.compile(program).unwrap();
+    let bytecode = compiler.bytecode();
+
+    assert_eq!(bytecode.lines.entries(), &[(0, 1), (6, 2), (12, 6)]);
+    assert_eq!(bytecode.lines.line_at(3), Some(1));
+    assert_eq!(bytecode.lines.line_at(15), Some(6));
+
+    let Object::CompiledFunc(f) = &bytecode.constants[2] else {
+        panic!(
+            "expected a compiled function, got {}",
+            bytecode.constants[2]
+        );
+    };
+    // The trailing pop is replaced by a return on the same line
+    assert_eq!(f.lines.entries(), &[(0, 3)]);
+
+    assert_eq!(
+        f.instructions.with_lines(&f.lines).to_string(),
+        "   3 0000 OpGetGlobal 0\n   | 0003 OpReturnValue\n"
+    );
+}
+
+#[test]
+fn dead_code_after_return() {
+    test!(
+        (
+            "fn() { return 1; 2; 3; }",
+            &[
+                Object::Integer(1),
+                Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+                    [
+                        Instruction::new(OpCode::Constant, &[1]),
+                        Instruction::new(OpCode::ReturnValue, &[]),
+                    ]
+                    .into_iter()
+                    .fold(Bytes::default(), |mut b, i| {
+                        b.push(i);
+                        b
+                    }),
+                    0,
+                    0,
+                ))),
+            ],
+            &[
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "fn() { if (true) { return 1; 2; } 3 }",
+            &[
+                Object::Integer(1),
+                Object::Integer(3),
+                Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+                    [
+                        Instruction::new(OpCode::True, &[]),
+                        Instruction::new(OpCode::JumpNotTrue, &[11]),
+                        Instruction::new(OpCode::Constant, &[1]),
+                        Instruction::new(OpCode::ReturnValue, &[]),
+                        Instruction::new(OpCode::Jump, &[14]),
+                        Instruction::null(),
+                        Instruction::new(OpCode::Pop, &[]),
+                        Instruction::new(OpCode::Constant, &[2]),
+                        Instruction::new(OpCode::ReturnValue, &[]),
+                    ]
+                    .into_iter()
+                    .fold(Bytes::default(), |mut b, i| {
+                        b.push(i);
+                        b
+                    }),
+                    0,
+                    0,
+                ))),
+            ],
+            &[
+                Instruction::new(OpCode::Constant, &[3]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+    )
+}
+
 #[test]
 fn functions() {
     test!(
@@ -683,20 +1325,91 @@ fn function_scopes() {
     )
 }
 
+#[test]
+fn default_params() {
+    test!((
+        "fn(a, b = 2) { a + b }",
+        &[
+            Object::Integer(2),
+            Object::CompiledFunc(Rc::new(CompiledFuncObj {
+                defaults: 1,
+                ..CompiledFuncObj::new(
+                    [
+                        Instruction::new(OpCode::JumpIfArg, &[1, 9]),
+                        Instruction::new(OpCode::Constant, &[1]),
+                        Instruction::new(OpCode::SetLocal, &[1]),
+                        Instruction::new(OpCode::GetLocal, &[0]),
+                        Instruction::new(OpCode::GetLocal, &[1]),
+                        Instruction::new(OpCode::Add, &[]),
+                        Instruction::new(OpCode::ReturnValue, &[]),
+                    ]
+                    .into_iter()
+                    .fold(Bytes::default(), |mut b, i| {
+                        b.push(i);
+                        b
+                    }),
+                    2,
+                    2,
+                )
+            })),
+        ],
+        &[
+            Instruction::new(OpCode::Constant, &[2]),
+            Instruction::new(OpCode::Pop, &[]),
+        ]
+    ));
+}
+
+#[test]
+fn rest_params() {
+    test!((
+        "fn(first, ...rest) { rest }(1, 2, 3)",
+        &[
+            Object::CompiledFunc(Rc::new(CompiledFuncObj {
+                rest: true,
+                ..CompiledFuncObj::new(
+                    [
+                        Instruction::new(OpCode::GetLocal, &[1]),
+                        Instruction::new(OpCode::ReturnValue, &[]),
+                    ]
+                    .into_iter()
+                    .fold(Bytes::default(), |mut b, i| {
+                        b.push(i);
+                        b
+                    }),
+                    2,
+                    1,
+                )
+            })),
+            Object::Integer(1),
+            Object::Integer(2),
+            Object::Integer(3),
+        ],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::Constant, &[2]),
+            Instruction::new(OpCode::Constant, &[3]),
+            Instruction::new(OpCode::Constant, &[4]),
+            Instruction::new(OpCode::Call, &[3]),
+            Instruction::new(OpCode::Pop, &[]),
+        ]
+    ));
+}
+
 #[test]
 fn builtins() {
     test!(
         (
             r#"
-            len([]);
-            push([], 1); "#,
+            puts([]);
+            print([], 1); "#,
             &[Object::Integer(1)],
             &[
-                Instruction::new(OpCode::GetBuiltin, &[0]),
+                Instruction::new(OpCode::GetBuiltin, &[5]),
                 Instruction::new(OpCode::Array, &[0]),
                 Instruction::new(OpCode::Call, &[1]),
                 Instruction::new(OpCode::Pop, &[]),
-                Instruction::new(OpCode::GetBuiltin, &[4]),
+                Instruction::new(OpCode::GetBuiltin, &[14]),
                 Instruction::new(OpCode::Array, &[0]),
                 Instruction::new(OpCode::Constant, &[1]),
                 Instruction::new(OpCode::Call, &[2]),
@@ -704,10 +1417,10 @@ fn builtins() {
             ]
         ),
         (
-            "fn() { len([]) }",
+            "fn() { puts([]) }",
             &[Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
                 [
-                    Instruction::new(OpCode::GetBuiltin, &[0]),
+                    Instruction::new(OpCode::GetBuiltin, &[5]),
                     Instruction::new(OpCode::Array, &[0]),
                     Instruction::new(OpCode::Call, &[1]),
                     Instruction::new(OpCode::ReturnValue, &[]),
@@ -728,6 +1441,285 @@ fn builtins() {
     )
 }
 
+#[test]
+fn fold_builtin_calls() {
+    test!(
+        (
+            r#"len("abc")"#,
+            &[Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            r#"first([1, 2, 3]) + len(rest(["a", "b"]))"#,
+            &[Object::Integer(1), Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Add, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "is_int(1); first([])",
+            &[],
+            &[
+                Instruction::new(OpCode::True, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+                Instruction::null(),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+        (
+            "let len = fn(x) { 1 }; len(2)",
+            &[
+                Object::Integer(1),
+                Object::CompiledFunc(Rc::new(CompiledFuncObj::new(
+                    [
+                        Instruction::new(OpCode::Constant, &[1]),
+                        Instruction::new(OpCode::ReturnValue, &[]),
+                    ]
+                    .into_iter()
+                    .fold(Bytes::default(), |mut b, i| {
+                        b.push(i);
+                        b
+                    }),
+                    1,
+                    1,
+                ))),
+                Object::Integer(2),
+            ],
+            &[
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::SetGlobal, &[0]),
+                Instruction::new(OpCode::GetGlobal, &[0]),
+                Instruction::new(OpCode::Constant, &[3]),
+                Instruction::new(OpCode::Call, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ]
+        ),
+    );
+    test_err(&[
+        (
+            "len(1)",
+            "argument to `len` not supported, got INTEGER, expected STRING, ARRAY, HASH or RANGE (line 1)",
+        ),
+        (
+            "\nset([1], 3, 0)",
+            "index out of range: 3 for array of length 1 (line 2)",
+        ),
+        ("len(first([1], 2))", "first expects 1 argument, got 2"),
+    ])
+}
+
+#[test]
+fn for_loop() {
+    test!((
+        "for (x in [1]) { x; }",
+        &[Object::Integer(1)],
+        &[
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::Array, &[1]),
+            Instruction::new(OpCode::Iter, &[]),
+            Instruction::new(OpCode::IterNext, &[20]),
+            Instruction::new(OpCode::SetGlobal, &[0]),
+            Instruction::new(OpCode::GetGlobal, &[0]),
+            Instruction::new(OpCode::Pop, &[]),
+            Instruction::new(OpCode::Jump, &[7]),
+            Instruction::new(OpCode::IterEnd, &[]),
+            Instruction::null(),
+            Instruction::new(OpCode::Pop, &[]),
+        ],
+    ));
+}
+
+#[test]
+fn loop_control_outside_loop() {
+    test_err(&[
+        ("break;", "break outside loop"),
+        ("continue;", "continue outside loop"),
+        ("if (true) { break; }", "break outside loop"),
+        ("fn() { continue; }", "continue outside loop"),
+        ("while (true) { fn() { break; } }", "break outside loop"),
+    ])
+}
+
+#[test]
+fn compile_error_kind() {
+    for input in ["break;", "continue;", "const x = 1; x = 2;", "len = 1;"] {
+        let program = Parser::new(Lexer::new(input.to_string()))
+            .parse()
+            .expect("Skill issue");
+        let res = Compiler::default().compile(program);
+        assert!(
+            matches!(res, Err(InterpretError::Compile(_))),
+            "{}: {:?}",
+            input,
+            res
+        );
+    }
+}
+
+#[test]
+fn while_loop() {
+    test!((
+        "while (true) { if (false) { continue; } break; } 1;",
+        &[Object::Integer(1)],
+        &[
+            Instruction::new(OpCode::True, &[]),
+            Instruction::new(OpCode::JumpNotTrue, &[27]),
+            Instruction::new(OpCode::False, &[]),
+            Instruction::new(OpCode::JumpNotTrue, &[17]),
+            Instruction::new(OpCode::Jump, &[0]),
+            Instruction::null(),
+            Instruction::new(OpCode::Jump, &[20]),
+            Instruction::null(),
+            Instruction::new(OpCode::Pop, &[]),
+            Instruction::new(OpCode::Jump, &[27]),
+            Instruction::new(OpCode::Jump, &[0]),
+            Instruction::null(),
+            Instruction::new(OpCode::Pop, &[]),
+            Instruction::new(OpCode::Constant, &[1]),
+            Instruction::new(OpCode::Pop, &[]),
+        ],
+    ));
+}
+
+#[test]
+fn slice() {
+    test!(
+        (
+            "[1, 2][1:]",
+            &[Object::Integer(1), Object::Integer(2), Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Array, &[2]),
+                Instruction::new(OpCode::Constant, &[3]),
+                Instruction::null(),
+                Instruction::new(OpCode::Slice, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            r#""ab"[:1]"#,
+            &[Object::from("ab"), Object::Integer(1)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::null(),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Slice, &[]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+    );
+}
+
+#[test]
+fn range_literal() {
+    test!(
+        (
+            "1..3",
+            &[Object::Integer(1), Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Range, &[0]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+        (
+            "1..=3",
+            &[Object::Integer(1), Object::Integer(3)],
+            &[
+                Instruction::new(OpCode::Constant, &[1]),
+                Instruction::new(OpCode::Constant, &[2]),
+                Instruction::new(OpCode::Range, &[1]),
+                Instruction::new(OpCode::Pop, &[]),
+            ],
+        ),
+    );
+}
+
+#[test]
+fn nested_loop_jumps() {
+    test!((
+        "while (true) { while (false) { break; } continue; }",
+        &[],
+        &[
+            Instruction::new(OpCode::True, &[]),
+            Instruction::new(OpCode::JumpNotTrue, &[24]),
+            Instruction::new(OpCode::False, &[]),
+            Instruction::new(OpCode::JumpNotTrue, &[14]),
+            Instruction::new(OpCode::Jump, &[14]),
+            Instruction::new(OpCode::Jump, &[4]),
+            Instruction::null(),
+            Instruction::new(OpCode::Pop, &[]),
+            Instruction::new(OpCode::Jump, &[0]),
+            Instruction::new(OpCode::Jump, &[0]),
+            Instruction::null(),
+            Instruction::new(OpCode::Pop, &[]),
+        ],
+    ));
+}
+
+#[test]
+fn strict_redefinition() {
+    let compile = |input: &str, strict: bool| {
+        let program = Parser::new(Lexer::new(input.to_string()))
+            .parse()
+            .expect("Skill issue");
+        let mut compiler = Compiler::default();
+        compiler.set_strict(strict);
+        compiler.compile(program).map_err(|e| e.to_string())
+    };
+
+    for allowed in [
+        "let x = 1; let f = fn(y) { let x = 2; x + y }; f(x)",
+        "let f = fn(x) { fn(x) { x } }; f(1)(2)",
+        "let len = 1; len",
+        "for (x in [1]) { x; } for (x in [2]) { x; }",
+        "let x = 1; for (x in [2]) { x; }",
+    ] {
+        assert_eq!(compile(allowed, true), Ok(()), "{}", allowed);
+    }
+    for (input, exp) in [
+        (
+            "let x = 1;\nlet x = 2;",
+            "variable already defined: x (line 2)",
+        ),
+        (
+            "const x = 1; let x = 2;",
+            "variable already defined: x (line 1)",
+        ),
+        (
+            "let x = 1; const x = 2;",
+            "variable already defined: x (line 1)",
+        ),
+        (
+            "let [a, a] = [1, 2];",
+            "variable already defined: a (line 1)",
+        ),
+        (
+            "fn(a) { let a = 1; }",
+            "variable already defined: a (line 1)",
+        ),
+        ("fn(a, a) { a }", "variable already defined: a (line 1)"),
+    ] {
+        assert_eq!(compile(input, true), Err(exp.to_string()));
+        assert_eq!(compile(input, false), Ok(()));
+    }
+}
+
+#[test]
+fn too_many_constants() {
+    // Null is constant 0, so the last literal would need index 65536
+    let input: String = (0..=u16::MAX).map(|i| format!("{};", i)).collect();
+    test_err(&[(&input, "too many constants (line 1)")]);
+}
+
 fn test(cases: &[(&str, &[Object], &[Instruction])]) {
     for (input, consts, instrs) in cases {
         let lexer = Lexer::new(input.to_string());
@@ -738,7 +1730,7 @@ fn test(cases: &[(&str, &[Object], &[Instruction])]) {
         compiler.compile(program).unwrap();
         let bytecode = compiler.bytecode();
 
-        let expected_bytes = instrs.into_iter().fold(Bytes::default(), |mut acc, x| {
+        let expected_bytes = instrs.iter().fold(Bytes::default(), |mut acc, x| {
             acc.push(x);
             acc
         });
@@ -761,6 +1753,20 @@ fn test(cases: &[(&str, &[Object], &[Instruction])]) {
     }
 }
 
+fn test_err(cases: &[(&str, &str)]) {
+    for (input, exp) in cases {
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse().expect("Skill issue");
+
+        let mut compiler = Compiler::default();
+        match compiler.compile(program) {
+            Ok(_) => panic!("compilation did not error:\n{}", input),
+            Err(e) => assert_eq!(&e.to_string(), exp),
+        }
+    }
+}
+
 fn print_objs(objs: &[Object]) -> String {
     let mut s = String::new();
     for o in objs {
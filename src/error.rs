@@ -0,0 +1,135 @@
+use crate::ast::ParseErrorKind;
+use std::fmt::Display;
+
+/// Error produced by any stage of interpreting a program. Every variant
+/// but `Located` carries the full message that gets displayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpretError {
+    Lex(String),
+    Parse(String),
+    Undefined(String),
+    TypeMismatch(String),
+    DivByZero(String),
+    StackOverflow(String),
+    WrongArity(String),
+    Io(String),
+    /// A program the compiler rejects before running any of it
+    Compile(String),
+    /// Any other error raised while running
+    Runtime(String),
+    /// An error the VM ran into, with the instruction it happened at. Its
+    /// message leaves the location out, only displaying it adds it
+    Located(Box<InterpretError>, String),
+}
+
+impl InterpretError {
+    pub fn message(&self) -> &str {
+        match self {
+            InterpretError::Lex(m)
+            | InterpretError::Parse(m)
+            | InterpretError::Undefined(m)
+            | InterpretError::TypeMismatch(m)
+            | InterpretError::DivByZero(m)
+            | InterpretError::StackOverflow(m)
+            | InterpretError::WrongArity(m)
+            | InterpretError::Io(m)
+            | InterpretError::Compile(m)
+            | InterpretError::Runtime(m) => m,
+            InterpretError::Located(e, _) => e.message(),
+        }
+    }
+
+    /// Rewrites the message while keeping the variant
+    pub fn map_message(mut self, f: impl FnOnce(&str) -> String) -> Self {
+        if let InterpretError::Located(e, at) = self {
+            return InterpretError::Located(Box::new(e.map_message(f)), at);
+        }
+        let m = match &mut self {
+            InterpretError::Lex(m)
+            | InterpretError::Parse(m)
+            | InterpretError::Undefined(m)
+            | InterpretError::TypeMismatch(m)
+            | InterpretError::DivByZero(m)
+            | InterpretError::StackOverflow(m)
+            | InterpretError::WrongArity(m)
+            | InterpretError::Io(m)
+            | InterpretError::Compile(m)
+            | InterpretError::Runtime(m) => m,
+            InterpretError::Located(..) => unreachable!("handled above"),
+        };
+        *m = f(m);
+        self
+    }
+
+    /// Attaches where the error happened, unless it already says where.
+    /// An error from a nested call keeps the innermost location
+    pub(crate) fn located(self, at: impl FnOnce() -> String) -> Self {
+        match self {
+            InterpretError::Located(..) => self,
+            e => InterpretError::Located(Box::new(e), at()),
+        }
+    }
+
+    /// The error without the location the VM attached to it
+    pub fn unlocated(&self) -> &Self {
+        match self {
+            InterpretError::Located(e, _) => e,
+            e => e,
+        }
+    }
+
+    /// Whether `try` may turn the error into a value. Running out of stack
+    /// isn't something the program could carry on from
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self.unlocated(), InterpretError::StackOverflow(_))
+    }
+
+    /// `callee` got `got` arguments but takes from `min` to `max`, with
+    /// `None` for no upper bound
+    pub(crate) fn arity(callee: impl Display, min: usize, max: Option<usize>, got: usize) -> Self {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let expected = match max {
+            Some(max) if max == min => format!("{} argument{}", min, plural(min)),
+            Some(max) if max == min + 1 => format!("{} or {} arguments", min, max),
+            Some(max) => format!("{} to {} arguments", min, max),
+            None => format!("at least {} argument{}", min, plural(min)),
+        };
+        InterpretError::WrongArity(format!("{} expects {}, got {}", callee, expected, got))
+    }
+
+    pub(crate) fn div_by_zero() -> Self {
+        InterpretError::DivByZero("division by zero".to_string())
+    }
+
+    pub(crate) fn mod_by_zero() -> Self {
+        InterpretError::DivByZero("modulo by zero".to_string())
+    }
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpretError::Located(e, at) => write!(f, "{} ({})", e, at),
+            e => write!(f, "{}", e.message()),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+/// Illegal tokens are reported as lexer errors, everything else as parse errors
+impl From<Vec<ParseErrorKind>> for InterpretError {
+    fn from(errors: Vec<ParseErrorKind>) -> Self {
+        let lex = matches!(errors.first(), Some(ParseErrorKind::Illegal(_)));
+        let msg = errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ");
+        if lex {
+            InterpretError::Lex(msg)
+        } else {
+            InterpretError::Parse(msg)
+        }
+    }
+}
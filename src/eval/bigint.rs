@@ -0,0 +1,357 @@
+use std::{cmp::Ordering, fmt::Display};
+
+/// Arbitrary precision integer. Values which fit an `i64` are kept as
+/// `Object::Integer`, so this only holds the big ones
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    neg: bool,
+    /// Little endian base 2^32 digits without leading zeros
+    mag: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_parts(neg: bool, mut mag: Vec<u32>) -> Self {
+        while mag.last() == Some(&0) {
+            mag.pop();
+        }
+        Self {
+            neg: neg && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    /// Reads unsigned `digits` in `radix`, `None` if one isn't a digit
+    pub fn from_digits(digits: &str, radix: u32) -> Option<Self> {
+        let base = BigInt::from(radix as i64);
+        digits.chars().try_fold(BigInt::default(), |acc, ch| {
+            let digit = ch.to_digit(radix)?;
+            Some(acc.mul(&base).add(&BigInt::from(digit as i64)))
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let m = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &d| (acc << 32) | d as u64);
+        if !self.neg {
+            i64::try_from(m).ok()
+        } else if m <= i64::MIN.unsigned_abs() {
+            Some((m as i64).wrapping_neg())
+        } else {
+            None
+        }
+    }
+
+    /// Nearest float, infinite when out of range
+    pub fn to_f64(&self) -> f64 {
+        let m = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0f64, |acc, &d| acc * 4294967296.0 + d as f64);
+        if self.neg {
+            -m
+        } else {
+            m
+        }
+    }
+
+    /// The float with its fraction dropped, `None` for NaN and the
+    /// infinities
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        let bits = x.abs().trunc().to_bits();
+        let exp = (bits >> 52) as i64;
+        if exp == 0 {
+            return Some(Self::default());
+        }
+        // x = mantissa * 2^(exp - 1075), with the implicit leading bit
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let shift = exp - 1075;
+        let mag = if shift < 0 {
+            Self::from((mantissa >> -shift) as i64)
+        } else {
+            Self::from(mantissa as i64).mul(&Self::from(2).pow(shift as u32))
+        };
+        Some(if x < 0.0 { mag.neg() } else { mag })
+    }
+
+    /// Number of significant bits in the magnitude
+    pub fn bits(&self) -> u64 {
+        match self.mag.last() {
+            Some(last) => self.mag.len() as u64 * 32 - last.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::from_parts(!self.neg, self.mag.clone())
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.neg == other.neg {
+            return Self::from_parts(self.neg, add_mag(&self.mag, &other.mag));
+        }
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => Self::from_parts(other.neg, sub_mag(&other.mag, &self.mag)),
+            _ => Self::from_parts(self.neg, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut res = vec![0u32; self.mag.len() + other.mag.len()];
+        for (i, &x) in self.mag.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &y) in other.mag.iter().enumerate() {
+                let t = res[i + j] as u64 + x as u64 * y as u64 + carry;
+                res[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            res[i + other.mag.len()] = carry as u32;
+        }
+        Self::from_parts(self.neg != other.neg, res)
+    }
+
+    /// Truncating division like `i64`, `None` when dividing by zero
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let q = match other.mag[..] {
+            [d] => div_small(&self.mag, d).0,
+            _ => div_mag(&self.mag, &other.mag),
+        };
+        Some(Self::from_parts(self.neg != other.neg, q))
+    }
+
+    /// Remainder of `div`, taking the sign of `self` like `i64`. `None` when
+    /// dividing by zero
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        Some(self.sub(&self.div(other)?.mul(other)))
+    }
+
+    /// Division rounding toward negative infinity, `None` when dividing by
+    /// zero
+    pub fn floor_div(&self, other: &Self) -> Option<Self> {
+        let q = self.div(other)?;
+        if self.neg != other.neg && &q.mul(other) != self {
+            Some(q.sub(&Self::from(1)))
+        } else {
+            Some(q)
+        }
+    }
+
+    pub fn pow(&self, mut exp: u32) -> Self {
+        let mut res = Self::from(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                res = res.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        res
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let m = value.unsigned_abs();
+        Self::from_parts(value < 0, vec![m as u32, (m >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Split into base 10^9 chunks, least significant first
+        let mut chunks = vec![];
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (q, r) = div_small(&mag, 1_000_000_000);
+            chunks.push(r);
+            mag = q;
+            while mag.last() == Some(&0) {
+                mag.pop();
+            }
+        }
+
+        if self.neg {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{}", first)?;
+        }
+        for c in chunks {
+            write!(f, "{:09}", c)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut res = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+    for (i, &x) in a.iter().enumerate() {
+        let t = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        res.push(t as u32);
+        carry = t >> 32;
+    }
+    res.push(carry as u32);
+    res
+}
+
+/// `a - b`, where `a >= b`
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut t = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (t < 0) as i64;
+        if t < 0 {
+            t += 1 << 32;
+        }
+        res.push(t as u32);
+    }
+    res
+}
+
+/// Returns the quotient and remainder of dividing by a single digit
+fn div_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut rem = 0u64;
+    for (i, &x) in a.iter().enumerate().rev() {
+        let cur = (rem << 32) | x as u64;
+        q[i] = (cur / d as u64) as u32;
+        rem = cur % d as u64;
+    }
+    (q, rem as u32)
+}
+
+/// Binary long division, only used for multi digit divisors
+fn div_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut q = vec![0u32; a.len()];
+    let mut rem: Vec<u32> = vec![];
+    for i in (0..a.len() * 32).rev() {
+        // rem = rem * 2 + bit i of a
+        let mut carry = (a[i / 32] >> (i % 32)) & 1;
+        for d in rem.iter_mut() {
+            let next = *d >> 31;
+            *d = (*d << 1) | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            rem.push(carry);
+        }
+
+        if cmp_mag(&rem, b) != Ordering::Less {
+            rem = sub_mag(&rem, b);
+            while rem.last() == Some(&0) {
+                rem.pop();
+            }
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+    q
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(x: i64) -> BigInt {
+        BigInt::from(x)
+    }
+
+    #[test]
+    fn round_trip() {
+        for x in [0, 1, -1, 42, i64::MAX, i64::MIN, 1 << 32, -(1 << 32)] {
+            assert_eq!(big(x).to_i64(), Some(x));
+            assert_eq!(big(x).to_string(), x.to_string());
+        }
+        assert_eq!(big(i64::MAX).add(&big(1)).to_i64(), None);
+        assert_eq!(big(i64::MIN).sub(&big(1)).to_i64(), None);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let x = big(i64::MAX).mul(&big(i64::MAX));
+        assert_eq!(x.to_string(), "85070591730234615847396907784232501249");
+        assert_eq!(x.div(&big(i64::MAX)), Some(big(i64::MAX)));
+        assert_eq!(x.neg().div(&big(i64::MAX)), Some(big(-i64::MAX)));
+        assert_eq!(x.add(&x.neg()), BigInt::default());
+        assert_eq!(x.sub(&big(1)).div(&big(i64::MAX)), Some(big(i64::MAX - 1)));
+        assert_eq!(x.div(&x.add(&big(1))), Some(big(0)));
+        assert_eq!(x.div(&big(0)), None);
+
+        assert_eq!(
+            big(2).pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(big(-3).pow(41).to_string(), "-36472996377170786403");
+        assert!(big(-3).pow(41) < big(i64::MIN));
+        assert!(big(2).pow(64) > big(i64::MAX));
+    }
+
+    #[test]
+    fn from_f64() {
+        for x in [0, 1, -1, 42, 1 << 53, -(1 << 62)] {
+            assert_eq!(BigInt::from_f64(x as f64), Some(big(x)));
+        }
+        assert_eq!(BigInt::from_f64(-2.75), Some(big(-2)));
+        assert_eq!(BigInt::from_f64(0.5), Some(big(0)));
+        assert_eq!(BigInt::from_f64(2f64.powi(100)), Some(big(2).pow(100)));
+        assert_eq!(
+            BigInt::from_f64(-1e22).map(|x| x.to_string()),
+            Some("-10000000000000000000000".into())
+        );
+        assert_eq!(BigInt::from_f64(f64::NAN), None);
+        assert_eq!(BigInt::from_f64(f64::NEG_INFINITY), None);
+    }
+}
@@ -1,28 +1,134 @@
-use super::Object;
-use crate::ast::Ident;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use super::{Object, DEFAULT_MAX_ALLOC, DEFAULT_MAX_DEPTH};
+use crate::{
+    ast::Ident,
+    builtin::{NativeFn, NativeFunc, Output, Rng},
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    io::Write,
+    rc::Rc,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Environment {
     store: HashMap<Ident, Rc<Object>>,
+    consts: HashSet<Ident>,
     outer: Option<Rc<RefCell<Environment>>>,
+    /// Shared by every environment enclosed in the same outermost one
+    calls: Rc<CallDepth>,
+    rng: Rc<Rng>,
+    max_alloc: Rc<MaxAlloc>,
+    output: Rc<RefCell<Output>>,
+}
+
+/// How deeply nested the function calls of one evaluation are
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct CallDepth {
+    pub(super) depth: Cell<usize>,
+    pub(super) max: Cell<usize>,
+}
+
+impl Default for CallDepth {
+    fn default() -> Self {
+        Self {
+            depth: Cell::new(0),
+            max: Cell::new(DEFAULT_MAX_DEPTH),
+        }
+    }
+}
+
+/// Largest string or collection evaluation may build, see
+/// [`DEFAULT_MAX_ALLOC`]
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct MaxAlloc(Cell<usize>);
+
+impl Default for MaxAlloc {
+    fn default() -> Self {
+        Self(Cell::new(DEFAULT_MAX_ALLOC))
+    }
 }
 
 impl Environment {
     pub fn new() -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
             store: HashMap::new(),
+            consts: HashSet::new(),
             outer: None,
+            calls: Rc::default(),
+            rng: Rc::default(),
+            max_alloc: Rc::default(),
+            output: Rc::default(),
         }))
     }
 
     pub fn new_enclosed(outer: Rc<RefCell<Environment>>) -> Self {
+        let (calls, rng, max_alloc, output) = {
+            let outer = outer.borrow();
+            (
+                outer.calls.clone(),
+                outer.rng.clone(),
+                outer.max_alloc.clone(),
+                outer.output.clone(),
+            )
+        };
         Self {
             store: HashMap::new(),
+            consts: HashSet::new(),
             outer: Some(outer),
+            calls,
+            rng,
+            max_alloc,
+            output,
         }
     }
 
+    /// An environment without any bindings, sharing the call depth, random
+    /// numbers, limits and output of this one
+    pub(super) fn new_isolated(&self) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            calls: self.calls.clone(),
+            rng: self.rng.clone(),
+            max_alloc: self.max_alloc.clone(),
+            output: self.output.clone(),
+            ..Self::default()
+        }))
+    }
+
+    /// Sets how many nested function calls evaluating in this environment
+    /// allows
+    pub fn set_max_depth(&self, depth: usize) {
+        self.calls.max.set(depth);
+    }
+
+    /// Sets the longest string, in bytes, and the most elements of an array
+    /// or hash evaluating in this environment may build
+    pub fn set_max_alloc(&self, len: usize) {
+        self.max_alloc.0.set(len);
+    }
+
+    /// Makes `puts` and `print` evaluated in this environment write to `w`
+    /// instead of stdout
+    pub fn set_output(&self, w: Rc<RefCell<dyn Write>>) {
+        *self.output.borrow_mut() = Output::new(w);
+    }
+
+    pub(super) fn output(&self) -> Output {
+        self.output.borrow().clone()
+    }
+
+    pub(super) fn max_alloc(&self) -> usize {
+        self.max_alloc.0.get()
+    }
+
+    pub(super) fn calls(&self) -> Rc<CallDepth> {
+        self.calls.clone()
+    }
+
+    pub(super) fn rng(&self) -> Rc<Rng> {
+        self.rng.clone()
+    }
+
     pub fn get(&self, name: &Ident) -> Option<Rc<Object>> {
         match self.store.get(name) {
             Some(obj) => Some(obj.clone()),
@@ -37,6 +143,113 @@ impl Environment {
     }
 
     pub fn set(&mut self, name: &Ident, value: Rc<Object>) {
+        self.consts.remove(name);
         self.store.insert(name.into(), value);
     }
+
+    /// Binds a host function which Monkey code calls like any builtin
+    pub fn register_native(&mut self, name: &str, func: Box<NativeFunc>) {
+        let native = Object::Native(NativeFn::new(name, func));
+        self.set(&name.to_string(), Rc::new(native));
+    }
+
+    /// Binds `name` so that `assign` refuses to change it
+    pub fn set_const(&mut self, name: &Ident, value: Rc<Object>) {
+        self.store.insert(name.into(), value);
+        self.consts.insert(name.into());
+    }
+
+    /// Whether the innermost binding of `name` is a constant
+    pub fn is_const(&self, name: &Ident) -> bool {
+        if self.store.contains_key(name) {
+            self.consts.contains(name)
+        } else {
+            self.outer
+                .as_ref()
+                .is_some_and(|o| o.borrow().is_const(name))
+        }
+    }
+
+    /// Every binding visible from this environment sorted by name, shadowed
+    /// names only appear once with their innermost value
+    pub fn bindings(&self) -> Vec<(Ident, Rc<Object>)> {
+        let mut bindings: Vec<_> = self
+            .store
+            .iter()
+            .map(|(name, val)| (name.clone(), val.clone()))
+            .collect();
+        if let Some(outer) = &self.outer {
+            for (name, val) in outer.borrow().bindings() {
+                if !self.store.contains_key(&name) {
+                    bindings.push((name, val));
+                }
+            }
+        }
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    /// Whether `name` is bound here or in an outer environment
+    pub fn contains(&self, name: &Ident) -> bool {
+        self.store.contains_key(name)
+            || self
+                .outer
+                .as_ref()
+                .is_some_and(|o| o.borrow().contains(name))
+    }
+
+    /// Rebinds `name` in the environment which defines it, returns `false`
+    /// if no environment does
+    pub fn assign(&mut self, name: &Ident, value: Rc<Object>) -> bool {
+        if let Some(v) = self.store.get_mut(name) {
+            *v = value;
+            true
+        } else if let Some(outer) = &self.outer {
+            outer.borrow_mut().assign(name, value)
+        } else {
+            false
+        }
+    }
+
+    /// Moves the value of `name` out of the environment which defines it,
+    /// leaving null in its place until it's assigned again
+    pub(super) fn take(&mut self, name: &Ident) -> Option<Rc<Object>> {
+        if let Some(v) = self.store.get_mut(name) {
+            Some(std::mem::replace(v, Rc::new(Object::Null)))
+        } else if let Some(outer) = &self.outer {
+            outer.borrow_mut().take(name)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bindings() {
+        let int = |x| Rc::new(Object::Integer(x));
+        let glob = Environment::new();
+        glob.borrow_mut().set(&"b".into(), int(2));
+        glob.borrow_mut().set(&"a".into(), int(1));
+
+        let mut local = Environment::new_enclosed(glob.clone());
+        local.set(&"c".into(), int(3));
+        local.set(&"a".into(), int(10));
+
+        assert_eq!(
+            glob.borrow().bindings(),
+            [("a".to_string(), int(1)), ("b".to_string(), int(2))]
+        );
+        assert_eq!(
+            local.bindings(),
+            [
+                ("a".to_string(), int(10)),
+                ("b".to_string(), int(2)),
+                ("c".to_string(), int(3)),
+            ]
+        );
+    }
 }
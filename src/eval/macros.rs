@@ -0,0 +1,126 @@
+use super::{eval_block, eval_expr, Environment, EvalResult, MacroObj, Object};
+use crate::{
+    ast::{modify_expr, modify_program, CallExpr, Expression, LetStmt, Program, Statement},
+    error::InterpretError,
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// Removes top level `let name = macro(...)` statements from the program and
+/// binds the macros in `env`
+pub fn define_macros(program: &mut Program, env: &Rc<RefCell<Environment>>) {
+    let statements = std::mem::take(&mut program.statements);
+    let lines = std::mem::take(&mut program.lines);
+    (program.statements, program.lines) = statements
+        .into_iter()
+        .zip(lines)
+        .filter(|(stmt, _)| match stmt {
+            Statement::Let(LetStmt {
+                ident,
+                expr: Expression::Macro(m),
+            }) => {
+                let obj = Object::Macro(MacroObj {
+                    expr: m.clone(),
+                    env: env.clone(),
+                });
+                env.borrow_mut().set(ident, Rc::new(obj));
+                false
+            }
+            _ => true,
+        })
+        .unzip();
+}
+
+/// Replaces every call to a macro defined in `env` with the quoted
+/// expression the macro returns
+pub fn expand_macros(
+    program: Program,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Program, InterpretError> {
+    modify_program(program, &mut |expr| match expr {
+        Expression::Call(c) => match get_macro(&c, env) {
+            Some(m) => expand_macro(m, c.arguments),
+            None => Ok(Expression::Call(c)),
+        },
+        expr => Ok(expr),
+    })
+}
+
+pub(super) fn quote(expr: Expression, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let expr = modify_expr(expr, &mut |expr| match expr {
+        Expression::Call(c) if is_call_to(&c, "unquote") => {
+            if c.arguments.len() != 1 {
+                return Err(InterpretError::arity(
+                    "unquote",
+                    1,
+                    Some(1),
+                    c.arguments.len(),
+                ));
+            }
+            let obj = eval_expr(&c.arguments[0], env)?;
+            object_to_expr(&obj)
+        }
+        expr => Ok(expr),
+    })?;
+
+    Ok(Rc::new(Object::Quote(expr)))
+}
+
+fn expand_macro(m: MacroObj, args: Vec<Expression>) -> Result<Expression, InterpretError> {
+    if args.len() != m.expr.params.len() {
+        let params = m.expr.params.len();
+        return Err(InterpretError::arity(
+            "macro",
+            params,
+            Some(params),
+            args.len(),
+        ));
+    }
+
+    let env = Rc::new(RefCell::new(Environment::new_enclosed(m.env.clone())));
+    for (arg, param) in args.into_iter().zip(m.expr.params.iter()) {
+        env.borrow_mut().set(param, Rc::new(Object::Quote(arg)));
+    }
+
+    let res = eval_block(&m.expr.body, &env)?;
+    let res = match &*res {
+        Object::Return(r) => r.clone(),
+        _ => res,
+    };
+    match &*res {
+        Object::Quote(e) => Ok(e.clone()),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "macro must return QUOTE, got {}",
+            res.kind()
+        ))),
+    }
+}
+
+fn get_macro(c: &CallExpr, env: &Rc<RefCell<Environment>>) -> Option<MacroObj> {
+    let Expression::Ident(i) = &*c.func else {
+        return None;
+    };
+    match &*env.borrow().get(&i.name)? {
+        Object::Macro(m) => Some(m.clone()),
+        _ => None,
+    }
+}
+
+pub(super) fn is_call_to(c: &CallExpr, name: &str) -> bool {
+    matches!(&*c.func, Expression::Ident(i) if i.name == name)
+}
+
+fn object_to_expr(obj: &Object) -> Result<Expression, InterpretError> {
+    match obj {
+        Object::Integer(x) => Ok(Expression::Number(*x)),
+        Object::BigInt(x) => Ok(Expression::BigNumber(x.clone())),
+        Object::Float(x) if x.is_finite() => Ok(Expression::Float(*x)),
+        Object::Bool(b) => Ok(Expression::Bool(*b)),
+        Object::Null => Ok(Expression::Null),
+        Object::String(s) => Ok(Expression::String(s.clone())),
+        Object::Quote(e) => Ok(e.clone()),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "cannot unquote {}",
+            obj.kind()
+        ))),
+    }
+}
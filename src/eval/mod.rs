@@ -1,25 +1,91 @@
 #![allow(dead_code)]
 
 use crate::{
-    ast::{ArrayExpr, Expression, HashExpr, Ident, Program, Statement},
-    builtin::Builtin,
+    ast::{
+        ArrayExpr, AssignStmt, Expression, HashExpr, Ident, InterpolatedExpr, Program, Statement,
+    },
+    builtin::{self, Builtin, CALLBACK_COST},
+    error::InterpretError,
     lexer::TokenType,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, ops::ControlFlow, rc::Rc};
 
+pub use bigint::BigInt;
+use env::CallDepth;
 pub use env::Environment;
+pub use macros::{define_macros, expand_macros};
 pub use object::*;
 
+mod bigint;
 mod env;
+mod macros;
 mod object;
 
+/// Maximum number of nested function calls before evaluation is aborted,
+/// keeps deep recursion from overflowing the native stack
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+/// Largest result `**` may produce, keeps a typo from eating all memory
+const MAX_INT_BITS: u64 = 1 << 20;
+
+/// Longest string, in bytes, and most elements of an array or hash either
+/// engine builds before giving up by default, keeps runaway programs from
+/// eating all memory
+pub const DEFAULT_MAX_ALLOC: usize = 1 << 24;
+
+/// Errors if a string of `len` bytes or a collection of `len` elements is
+/// over the engine's allocation limit `max`
+pub fn check_alloc(len: usize, max: usize) -> Result<(), InterpretError> {
+    if len > max {
+        return Err(InterpretError::Runtime(
+            "allocation limit exceeded".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Tracks `cost` levels of call depth for as long as it is alive
+struct DepthGuard(Rc<CallDepth>, usize);
+
+impl DepthGuard {
+    fn enter(env: &Rc<RefCell<Environment>>, cost: usize) -> Result<Self, InterpretError> {
+        let calls = env.borrow().calls();
+        if calls.depth.get() + cost > calls.max.get() {
+            return Err(InterpretError::StackOverflow(
+                "maximum recursion depth exceeded".to_string(),
+            ));
+        }
+        calls.depth.set(calls.depth.get() + cost);
+        Ok(Self(calls, cost))
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - self.1);
+    }
+}
+
+/// Unwraps what [`eval_value`] or [`eval_exprs`] produced, returning from
+/// the caller with the `return`, `break` or `continue` they ran into
+macro_rules! flow {
+    ($res:expr) => {
+        match $res? {
+            ControlFlow::Continue(val) => val,
+            ControlFlow::Break(signal) => return Ok(signal),
+        }
+    };
+}
+
 pub fn eval_program(prog: Program, env: &Rc<RefCell<Environment>>) -> EvalResult {
     let mut res = Rc::new(Object::Null);
     for stmt in prog.statements {
         res = eval_stmt(&stmt, env)?;
 
-        if let Object::Return(val) = &*res {
-            return Ok(val.clone());
+        match &*res {
+            Object::Return(val) => return Ok(val.clone()),
+            Object::Break | Object::Continue => return Err(loop_control_err(&res)),
+            _ => {}
         }
     }
     Ok(res)
@@ -28,41 +94,138 @@ pub fn eval_program(prog: Program, env: &Rc<RefCell<Environment>>) -> EvalResult
 fn eval_stmt(stmt: &Statement, env: &Rc<RefCell<Environment>>) -> EvalResult {
     match stmt {
         Statement::Let(l) => {
-            let val = eval_expr(&l.expr, env)?;
+            let val = flow!(eval_value(&l.expr, env));
             env.borrow_mut().set(&l.ident, val);
             Ok(Rc::new(Object::Null))
         }
+        Statement::Const(l) => {
+            let val = flow!(eval_value(&l.expr, env));
+            env.borrow_mut().set_const(&l.ident, val);
+            Ok(Rc::new(Object::Null))
+        }
+        Statement::LetArray(l) => {
+            let val = flow!(eval_value(&l.expr, env));
+            let values = destructure(&val, l.names.len(), l.rest.is_some())?;
+            for (name, val) in l.names.iter().chain(&l.rest).zip(values) {
+                env.borrow_mut().set(name, val);
+            }
+            Ok(Rc::new(Object::Null))
+        }
+        Statement::Assign(a) => {
+            if let Some(res) = eval_update(a, env)? {
+                return Ok(res);
+            }
+            let val = flow!(eval_value(&a.expr, env));
+            check_assignable(&a.ident, env)?;
+            env.borrow_mut().assign(&a.ident, val);
+            Ok(Rc::new(Object::Null))
+        }
+        Statement::AssignArray(a) => {
+            let val = flow!(eval_value(&a.expr, env));
+            let values = destructure(&val, a.names.len(), false)?;
+            for name in &a.names {
+                check_assignable(name, env)?;
+            }
+            for (name, val) in a.names.iter().zip(values) {
+                env.borrow_mut().assign(name, val);
+            }
+            Ok(Rc::new(Object::Null))
+        }
+        Statement::AssignIndex(a) => {
+            check_assignable(&a.ident, env)?;
+            let indexes = flow!(eval_exprs(&a.indexes, env));
+            let val = flow!(eval_value(&a.expr, env));
+            // Moved out of the binding, so unless something else refers to
+            // the container it's updated in place
+            let container = env.borrow_mut().take(&a.ident).expect("checked above");
+            let mut container = Rc::unwrap_or_clone(container);
+            let res = set_index(&mut container, &indexes, val);
+            env.borrow_mut().assign(&a.ident, Rc::new(container));
+            res.map(|_| Rc::new(Object::Null))
+        }
         Statement::Return(r) => {
-            let val = eval_expr(&r.expr, env)?;
+            let val = flow!(eval_value(&r.expr, env));
             Ok(Rc::new(Object::Return(val)))
         }
         Statement::Expression(e) => eval_expr(e, env),
+        Statement::While(w) => {
+            while flow!(eval_value(&w.condition, env)).is_truthy() {
+                let res = eval_scoped_block(&w.body, env)?;
+                match &*res {
+                    Object::Break => break,
+                    Object::Return(_) => return Ok(res),
+                    _ => {}
+                }
+            }
+            Ok(Rc::new(Object::Null))
+        }
+        Statement::For(l) => {
+            let iterable = flow!(eval_value(&l.iterable, env));
+            let mut idx = 0;
+            while let Some(item) = iter_item(&iterable, idx)? {
+                idx += 1;
+                let env = Rc::new(RefCell::new(Environment::new_enclosed(env.clone())));
+                env.borrow_mut().set(&l.ident, item);
+                let res = eval_block(&l.body, &env)?;
+                match &*res {
+                    Object::Break => break,
+                    Object::Return(_) => return Ok(res),
+                    _ => {}
+                }
+            }
+            Ok(Rc::new(Object::Null))
+        }
+        Statement::Break => Ok(Rc::new(Object::Break)),
+        Statement::Continue => Ok(Rc::new(Object::Continue)),
     }
 }
 
 fn eval_expr(e: &Expression, env: &Rc<RefCell<Environment>>) -> EvalResult {
     match e {
-        Expression::Ident(i) => eval_ident(i, env),
+        Expression::Ident(i) => eval_ident(&i.name, env),
         Expression::Number(x) => Ok(Rc::new(Object::Integer(*x))),
+        Expression::BigNumber(x) => Ok(Rc::new(Object::BigInt(x.clone()))),
+        Expression::Float(x) => Ok(Rc::new(Object::Float(*x))),
         Expression::String(s) => Ok(Rc::new(Object::String(s.into()))),
+        Expression::Interpolated(i) => eval_interpolated(i, env),
         Expression::Prefix(p) => {
-            let right = eval_expr(&p.right, env)?;
+            let right = flow!(eval_value(&p.right, env));
             eval_prefix(p.operator, right)
         }
+        Expression::Infix(i)
+            if matches!(
+                i.operator,
+                TokenType::NullCoalesce | TokenType::And | TokenType::Or
+            ) =>
+        {
+            let left = flow!(eval_value(&i.left, env));
+            // Whether the left side is already the result
+            let decided = match i.operator {
+                TokenType::NullCoalesce => *left != Object::Null,
+                TokenType::And => !left.is_truthy(),
+                _ => left.is_truthy(),
+            };
+            if decided {
+                Ok(left)
+            } else {
+                eval_expr(&i.right, env)
+            }
+        }
         Expression::Infix(i) => {
-            let left = eval_expr(&i.left, env)?;
-            let right = eval_expr(&i.right, env)?;
-            eval_infix(left, i.operator, right)
+            let left = flow!(eval_value(&i.left, env));
+            let right = flow!(eval_value(&i.right, env));
+            eval_infix(left, i.operator, right, env.borrow().max_alloc())
         }
         Expression::Bool(b) => Ok(Rc::new(Object::Bool(*b))),
+        Expression::Null => Ok(Rc::new(Object::Null)),
         Expression::If(i) => {
-            let cond = eval_expr(&i.condition, env)?;
+            let cond = flow!(eval_value(&i.condition, env));
 
             if cond.is_truthy() {
-                eval_block(&i.if_branch, env)
+                eval_scoped_block(&i.if_branch, env)
             } else {
                 match i.else_branch {
-                    Some(ref b) => eval_block(b, env),
+                    Some(ref b) => eval_scoped_block(b, env),
                     None => Ok(Rc::new(Object::Null)),
                 }
             }
@@ -71,20 +234,56 @@ fn eval_expr(e: &Expression, env: &Rc<RefCell<Environment>>) -> EvalResult {
             expr: f.clone(),
             env: env.clone(),
         }))),
+        Expression::Macro(m) => Ok(Rc::new(Object::Macro(MacroObj {
+            expr: m.clone(),
+            env: env.clone(),
+        }))),
+        Expression::Call(c) if macros::is_call_to(c, "quote") => {
+            if c.arguments.len() != 1 {
+                return Err(InterpretError::arity(
+                    "quote",
+                    1,
+                    Some(1),
+                    c.arguments.len(),
+                ));
+            }
+            macros::quote(c.arguments[0].clone(), env)
+        }
         Expression::Call(c) => {
-            let func = eval_expr(&c.func, env)?;
-            let args = eval_exprs(&c.arguments, env)?;
+            let func = flow!(eval_value(&c.func, env));
+            let args = flow!(eval_exprs(&c.arguments, env));
 
-            apply_func(func, args)
+            match &*func {
+                // Called directly, the expression sees the caller's variables
+                Object::Builtin(Builtin::Parse) => eval_parse(args, env),
+                _ => apply_func(&func, args, env),
+            }
         }
         Expression::Array(a) => eval_arr(a, env),
         Expression::Index(i) => {
-            let left = eval_expr(&i.left, env)?;
-            let index = eval_expr(&i.index, env)?;
+            let left = flow!(eval_value(&i.left, env));
+            let index = flow!(eval_value(&i.index, env));
 
             eval_index(left, index)
         }
+        Expression::Slice(s) => {
+            let left = flow!(eval_value(&s.left, env));
+            let bound = |e: &Option<Box<Expression>>| match e {
+                Some(e) => eval_value(e, env),
+                None => Ok(ControlFlow::Continue(Rc::new(Object::Null))),
+            };
+            let start = flow!(bound(&s.start));
+            let end = flow!(bound(&s.end));
+
+            slice(&left, &start, &end).map(Rc::new)
+        }
+        Expression::Member(m) => {
+            let left = flow!(eval_value(&m.left, env));
+            get_member(&left, &m.name)?
+                .ok_or_else(|| InterpretError::Runtime(format!("unknown member: {}", m)))
+        }
         Expression::Hash(h) => eval_hash(h, env),
+        Expression::Block(b) => eval_scoped_block(b, env),
     }
 }
 
@@ -93,61 +292,203 @@ fn eval_ident(ident: &Ident, env: &Rc<RefCell<Environment>>) -> EvalResult {
         Ok(r)
     } else if let Some(b) = Builtin::from_ident_obj(ident) {
         Ok(b)
+    } else if let Some(m) = builtin::module(ident) {
+        Ok(Rc::new(m))
     } else {
-        Err(format!("identifier not found: {}", ident))
+        Err(InterpretError::Undefined(format!(
+            "identifier not found: {}",
+            ident
+        )))
     }
 }
 
+/// Runs the builtin `format` whatever the program binds to the name
+fn eval_interpolated(i: &InterpolatedExpr, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let template = Object::String(i.template());
+    let values = flow!(eval_exprs(&i.exprs, env));
+    let args = std::iter::once(&template)
+        .chain(values.iter().map(|v| &**v))
+        .collect();
+    let (rng, out, max_alloc) = {
+        let env = env.borrow();
+        (env.rng(), env.output(), env.max_alloc())
+    };
+    Builtin::Format.call(
+        args,
+        &mut |f, args| apply_callback(f, args, env),
+        &rng,
+        &out,
+        max_alloc,
+    )
+}
+
 fn eval_arr(a: &ArrayExpr, env: &Rc<RefCell<Environment>>) -> EvalResult {
-    let elements = a
-        .elements
-        .iter()
-        .map(|e| eval_expr(e, env))
-        .collect::<Result<Vec<_>, _>>()?;
+    check_alloc(a.elements.len(), env.borrow().max_alloc())?;
+    let elements = flow!(eval_exprs(&a.elements, env));
     Ok(Rc::new(Object::Array(ArrayObj { elements })))
 }
 
 fn eval_hash(h: &HashExpr, env: &Rc<RefCell<Environment>>) -> EvalResult {
-    let map = h
-        .pairs
-        .iter()
-        .map(|(k, v)| (eval_expr(k, env), eval_expr(v, env)))
-        .map(|(r1, r2)| r1.map(|r1| r2.map(|r2| (r1, r2))))
-        .collect::<Result<Result<HashMap<_, _>, _>, _>>()??;
+    check_alloc(h.pairs.len(), env.borrow().max_alloc())?;
+    let mut hash = HashObj::default();
+    for (k, v) in &h.pairs {
+        let k = flow!(eval_value(k, env));
+        k.check_hashable()?;
+        hash.insert(k, flow!(eval_value(v, env)));
+    }
+
+    Ok(Rc::new(Object::Hash(hash)))
+}
+
+/// `left.name`, `None` when the hash has no such key. Modules are hashes of
+/// builtins, nothing else has members
+pub fn get_member(left: &Object, name: &str) -> Result<Option<Rc<Object>>, InterpretError> {
+    match left {
+        Object::Hash(h) => Ok(h.get(&Object::String(name.to_string())).cloned()),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "member access not supported: {}",
+            left.kind()
+        ))),
+    }
+}
 
-    Ok(Rc::new(Object::Hash(HashObj { map })))
+/// `e["message"]` of a caught error, any other field is null
+pub fn error_field(e: &InterpretError, field: &Object) -> Object {
+    match field {
+        Object::String(s) if s == "message" => Object::String(e.message().to_string()),
+        _ => Object::Null,
+    }
 }
 
 fn eval_index(left: Rc<Object>, index: Rc<Object>) -> EvalResult {
     match (&*left, &*index) {
-        (Object::Array(left), Object::Integer(index)) => Ok(left
-            .elements
-            .get(*index as usize)
-            .cloned()
-            .unwrap_or(Rc::new(Object::Null))),
+        (Object::Error(e), field) => Ok(Rc::new(error_field(e, field))),
+        (Object::Array(left), &Object::Integer(index)) => {
+            let idx = resolve_index(index, left.elements.len(), "array")?;
+            Ok(left
+                .elements
+                .get(idx)
+                .cloned()
+                .unwrap_or(Rc::new(Object::Null)))
+        }
+        (Object::Range(left), &Object::Integer(index)) => {
+            let idx = resolve_index(index, left.len(), "range")?;
+            Ok(Rc::new(left.get(idx).map_or(Object::Null, Object::Integer)))
+        }
         (Object::Hash(left), _) => {
-            if matches!(
-                *index,
-                Object::Integer(_) | Object::String(_) | Object::Bool(_)
-            ) {
-                Ok(left
-                    .map
-                    .get(&index)
-                    .cloned()
-                    .unwrap_or(Rc::new(Object::Null)))
-            } else {
-                Err(format!("unusable as hash key: {}", index.kind()))
-            }
+            index.check_hashable()?;
+            Ok(left.get(&index).cloned().unwrap_or(Rc::new(Object::Null)))
         }
-        _ => Err(format!("index operator not supported: {}", left.kind())),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "index operator not supported: {}",
+            left.kind()
+        ))),
     }
 }
 
+/// Evaluates `e` for a value, breaking with the `return`, `break` or
+/// `continue` it runs into instead. Those aren't values, so whatever uses
+/// the result has to pass them on
+fn eval_value(
+    e: &Expression,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<ControlFlow<Rc<Object>, Rc<Object>>, InterpretError> {
+    let val = eval_expr(e, env)?;
+    if matches!(*val, Object::Return(_) | Object::Break | Object::Continue) {
+        Ok(ControlFlow::Break(val))
+    } else {
+        Ok(ControlFlow::Continue(val))
+    }
+}
+
+/// Evaluates the expressions in order like [`eval_value`], stopping at the
+/// first one to break
 fn eval_exprs(
     expr: &[Expression],
     env: &Rc<RefCell<Environment>>,
-) -> Result<Vec<Rc<Object>>, String> {
-    expr.iter().map(|e| eval_expr(e, env)).collect()
+) -> Result<ControlFlow<Rc<Object>, Vec<Rc<Object>>>, InterpretError> {
+    let mut values = Vec::with_capacity(expr.len());
+    for e in expr {
+        match eval_value(e, env)? {
+            ControlFlow::Continue(val) => values.push(val),
+            ControlFlow::Break(signal) => return Ok(ControlFlow::Break(signal)),
+        }
+    }
+    Ok(ControlFlow::Continue(values))
+}
+
+/// Runs `name = push(name, ...)` and `name = set(name, ...)` with the array
+/// moved out of `name`, so unless something else refers to it the builtin
+/// updates it in place. Returns what the statement evaluates to, `None` for
+/// any other assignment
+fn eval_update(
+    a: &AssignStmt,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Option<Rc<Object>>, InterpretError> {
+    let Some((callee, rest)) = a.update_call() else {
+        return Ok(None);
+    };
+    let b = match eval_ident(callee, env).as_deref() {
+        Ok(Object::Builtin(b @ (Builtin::Push | Builtin::Set))) => *b,
+        _ => return Ok(None),
+    };
+    let rest = match eval_exprs(rest, env)? {
+        ControlFlow::Continue(rest) => rest,
+        ControlFlow::Break(signal) => return Ok(Some(signal)),
+    };
+    check_assignable(&a.ident, env)?;
+
+    let target = env.borrow_mut().take(&a.ident).expect("checked above");
+    let mut args: Vec<_> = std::iter::once(target)
+        .chain(rest)
+        .map(Rc::unwrap_or_clone)
+        .collect();
+    let (rng, out, max_alloc) = {
+        let env = env.borrow();
+        (env.rng(), env.output(), env.max_alloc())
+    };
+    match b.call_owned(
+        &mut args,
+        &mut |f, args| apply_callback(f, args, env),
+        &rng,
+        &out,
+        max_alloc,
+    ) {
+        Ok(val) => {
+            env.borrow_mut().assign(&a.ident, val);
+            Ok(Some(Rc::new(Object::Null)))
+        }
+        // A failed call leaves the arguments alone, so the old value goes back
+        Err(e) => {
+            let old = std::mem::replace(&mut args[0], Object::Null);
+            env.borrow_mut().assign(&a.ident, Rc::new(old));
+            Err(e)
+        }
+    }
+}
+
+/// Errors unless `name` is bound to something other than a constant
+fn check_assignable(name: &Ident, env: &Rc<RefCell<Environment>>) -> Result<(), InterpretError> {
+    if !env.borrow().contains(name) {
+        return Err(InterpretError::Undefined(format!(
+            "identifier not found: {}",
+            name
+        )));
+    }
+    if env.borrow().is_const(name) {
+        return Err(InterpretError::Runtime(format!(
+            "cannot reassign constant: {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Evaluates `block` in a new environment enclosing `env`, so its `let`s
+/// don't outlive it
+fn eval_scoped_block(block: &[Statement], env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let env = Rc::new(RefCell::new(Environment::new_enclosed(env.clone())));
+    eval_block(block, &env)
 }
 
 fn eval_block(block: &[Statement], env: &Rc<RefCell<Environment>>) -> EvalResult {
@@ -155,7 +496,7 @@ fn eval_block(block: &[Statement], env: &Rc<RefCell<Environment>>) -> EvalResult
     for stmt in block {
         res = eval_stmt(stmt, env)?;
 
-        if matches!(*res, Object::Return(_)) {
+        if matches!(*res, Object::Return(_) | Object::Break | Object::Continue) {
             return Ok(res);
         }
     }
@@ -166,30 +507,41 @@ fn eval_prefix(op: TokenType, right: Rc<Object>) -> EvalResult {
     match op {
         TokenType::Bang => eval_bang_op(right),
         TokenType::Minus => eval_minus_op(right),
+        TokenType::Tilde => eval_bitnot_op(right),
         _ => unreachable!(),
     }
 }
 
-fn eval_infix(left: Rc<Object>, op: TokenType, right: Rc<Object>) -> EvalResult {
+fn eval_infix(left: Rc<Object>, op: TokenType, right: Rc<Object>, max_alloc: usize) -> EvalResult {
     match (&*left, op, &*right) {
-        (&Object::Integer(left), _, &Object::Integer(right)) => {
-            eval_integer_infix_op(left, op, right)
+        (left, TokenType::DotDot | TokenType::DotDotEq, right) => {
+            make_range(left, right, op == TokenType::DotDotEq).map(Rc::new)
+        }
+        (Object::Integer(_) | Object::BigInt(_), _, Object::Integer(_) | Object::BigInt(_)) => {
+            int_infix(&left, op, &right).map(Rc::new)
+        }
+        (Object::Float(_), _, Object::Integer(_) | Object::BigInt(_) | Object::Float(_))
+        | (Object::Integer(_) | Object::BigInt(_), _, Object::Float(_)) => {
+            float_infix(&left, op, &right).map(Rc::new)
+        }
+        (Object::String(left), _, Object::String(right)) => {
+            eval_string_infix_op(left, op, right, max_alloc)
+        }
+        (Object::String(s), TokenType::Star, count @ (Object::Integer(_) | Object::BigInt(_)))
+        | (count @ (Object::Integer(_) | Object::BigInt(_)), TokenType::Star, Object::String(s)) => {
+            repeat_str(s, count, max_alloc).map(Rc::new)
         }
-        (Object::String(left), _, Object::String(right)) => eval_string_infix_op(left, op, right),
         (left, TokenType::Eq, right) => Ok(Rc::new(Object::Bool(left == right))),
         (left, TokenType::NotEq, right) => Ok(Rc::new(Object::Bool(left != right))),
-        (left, op, right) if left.kind() != right.kind() => Err(format!(
-            "type mismatch: {} {} {}",
-            left.kind(),
-            op,
-            right.kind()
+        (left, op, right) if left.kind() != right.kind() => Err(InterpretError::TypeMismatch(
+            format!("type mismatch: {} {} {}", left.kind(), op, right.kind()),
         )),
-        (left, op, right) => Err(format!(
+        (left, op, right) => Err(InterpretError::TypeMismatch(format!(
             "unknown operator: {} {} {}",
             left.kind(),
             op,
             right.kind()
-        )),
+        ))),
     }
 }
 
@@ -197,70 +549,551 @@ fn eval_bang_op(value: Rc<Object>) -> EvalResult {
     Ok(Rc::new(Object::Bool(!value.is_truthy())))
 }
 
+fn eval_bitnot_op(value: Rc<Object>) -> EvalResult {
+    match int_not(&value) {
+        Some(res) => Ok(Rc::new(res)),
+        None => Err(InterpretError::TypeMismatch(format!(
+            "unknown operator: ~{}",
+            value.kind()
+        ))),
+    }
+}
+
 fn eval_minus_op(value: Rc<Object>) -> EvalResult {
-    match *value {
-        Object::Integer(x) => Ok(Rc::new(Object::Integer(-x))),
-        _ => Err(format!("unknown operator: -{}", value.kind())),
+    match num_neg(&value) {
+        Some(res) => Ok(Rc::new(res)),
+        None => Err(InterpretError::TypeMismatch(format!(
+            "unknown operator: -{}",
+            value.kind()
+        ))),
     }
 }
 
-fn eval_integer_infix_op(left: i64, op: TokenType, right: i64) -> EvalResult {
-    match op {
-        TokenType::Plus => Ok(Rc::new(Object::Integer(left + right))),
-        TokenType::Minus => Ok(Rc::new(Object::Integer(left - right))),
-        TokenType::Star => Ok(Rc::new(Object::Integer(left * right))),
-        TokenType::Slash => Ok(Rc::new(Object::Integer(left / right))),
+/// Integer arithmetic shared by both engines. Operands are `Integer` or
+/// `BigInt`, results are promoted to `BigInt` on overflow and demoted again
+/// once they fit an `i64`. `/` truncates toward zero (`-7 / 2 == -3`) while
+/// `~/` floors (`-7 ~/ 2 == -4`). `%` is the remainder of `/`, so it takes
+/// the sign of the left side (`-7 % 2 == -1`)
+pub fn int_infix(left: &Object, op: TokenType, right: &Object) -> Result<Object, InterpretError> {
+    if matches!(
+        op,
+        TokenType::Ampersand | TokenType::Pipe | TokenType::Caret | TokenType::Shl | TokenType::Shr
+    ) {
+        return int_bitwise(left, op, right);
+    }
 
-        TokenType::Lt => Ok(Rc::new(Object::Bool(left < right))),
-        TokenType::Gt => Ok(Rc::new(Object::Bool(left > right))),
-        TokenType::Eq => Ok(Rc::new(Object::Bool(left == right))),
-        TokenType::NotEq => Ok(Rc::new(Object::Bool(left != right))),
+    if let (&Object::Integer(l), &Object::Integer(r)) = (left, right) {
+        let res = match op {
+            TokenType::Plus => l.checked_add(r),
+            TokenType::Minus => l.checked_sub(r),
+            TokenType::Star => l.checked_mul(r),
+            TokenType::Slash if r == 0 => return Err(InterpretError::div_by_zero()),
+            TokenType::Slash => l.checked_div(r),
+            TokenType::FloorDiv if r == 0 => return Err(InterpretError::div_by_zero()),
+            TokenType::FloorDiv => l.checked_div(r).map(|q| {
+                if (l % r != 0) && ((l < 0) != (r < 0)) {
+                    q - 1
+                } else {
+                    q
+                }
+            }),
+            TokenType::Percent if r == 0 => return Err(InterpretError::mod_by_zero()),
+            TokenType::Percent => l.checked_rem(r),
+            TokenType::Pow => u32::try_from(r).ok().and_then(|e| l.checked_pow(e)),
+
+            TokenType::Lt => return Ok(Object::Bool(l < r)),
+            TokenType::Gt => return Ok(Object::Bool(l > r)),
+            TokenType::LtEq => return Ok(Object::Bool(l <= r)),
+            TokenType::GtEq => return Ok(Object::Bool(l >= r)),
+            TokenType::Eq => return Ok(Object::Bool(l == r)),
+            TokenType::NotEq => return Ok(Object::Bool(l != r)),
+            _ => unreachable!(),
+        };
+        if let Some(x) = res {
+            return Ok(Object::Integer(x));
+        }
+    }
+
+    let (l, r) = (to_big(left), to_big(right));
+    let res = match op {
+        TokenType::Plus => l.add(&r),
+        TokenType::Minus => l.sub(&r),
+        TokenType::Star => l.mul(&r),
+        TokenType::Slash => l.div(&r).ok_or_else(InterpretError::div_by_zero)?,
+        TokenType::FloorDiv => l.floor_div(&r).ok_or_else(InterpretError::div_by_zero)?,
+        TokenType::Percent => l.rem(&r).ok_or_else(InterpretError::mod_by_zero)?,
+        TokenType::Pow => big_pow(&l, &r)?,
+
+        TokenType::Lt => return Ok(Object::Bool(l < r)),
+        TokenType::Gt => return Ok(Object::Bool(l > r)),
+        TokenType::LtEq => return Ok(Object::Bool(l <= r)),
+        TokenType::GtEq => return Ok(Object::Bool(l >= r)),
+        TokenType::Eq => return Ok(Object::Bool(l == r)),
+        TokenType::NotEq => return Ok(Object::Bool(l != r)),
+        _ => unreachable!(),
+    };
+    Ok(res.into())
+}
+
+/// Position `idx` refers to in a `kind` of `len` elements, negative ones
+/// counting back from the end. Going past the end is left to the caller,
+/// going past the front is an error
+pub fn resolve_index(idx: i64, len: usize, kind: &str) -> Result<usize, InterpretError> {
+    if idx >= 0 {
+        return Ok(idx as usize);
+    }
+    len.checked_sub(idx.unsigned_abs() as usize).ok_or_else(|| {
+        InterpretError::Runtime(format!(
+            "index out of range: {} for {} of length {}",
+            idx, kind, len
+        ))
+    })
+}
+
+/// `left[start:end]` as a new array, string or range. Null bounds stand for
+/// the front and the end, negative ones count back from the end and both
+/// are clamped to the elements there are, so `[1, 2][1:9] == [2]`
+pub fn slice(left: &Object, start: &Object, end: &Object) -> Result<Object, InterpretError> {
+    let len = match left {
+        Object::Array(a) => a.elements.len(),
+        Object::String(s) => s.chars().count(),
+        Object::Range(r) => r.len(),
+        _ => {
+            return Err(InterpretError::TypeMismatch(format!(
+                "slice operator not supported: {}",
+                left.kind()
+            )))
+        }
+    };
+    let bound = |obj: &Object, default: usize| match obj {
+        Object::Null => Ok(default),
+        &Object::Integer(i) if i < 0 => Ok(len.saturating_sub(i.unsigned_abs() as usize)),
+        &Object::Integer(i) => Ok(len.min(i as usize)),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "slice bounds must be INTEGER, got {}",
+            obj.kind()
+        ))),
+    };
+    let end = bound(end, len)?;
+    let start = bound(start, 0)?.min(end);
+
+    Ok(match left {
+        Object::Array(a) => Object::Array(ArrayObj {
+            elements: a.elements[start..end].to_vec(),
+        }),
+        Object::String(s) => Object::String(s.chars().skip(start).take(end - start).collect()),
+        Object::Range(r) => Object::Range(RangeObj {
+            start: r.start.wrapping_add(start as i64),
+            end: r.start.wrapping_add(end as i64),
+        }),
         _ => unreachable!(),
+    })
+}
+
+/// `container[i][j] = value`, updating the container in place. Arrays only
+/// replace existing elements while hashes also take new keys. Nested
+/// containers shared with other values are copied first, and on errors
+/// `container` is left as it was
+pub fn set_index(
+    container: &mut Object,
+    indexes: &[Rc<Object>],
+    value: Rc<Object>,
+) -> Result<(), InterpretError> {
+    let (index, rest) = indexes
+        .split_first()
+        .expect("index assignment without an index");
+    let update = |slot: &mut Rc<Object>| {
+        if rest.is_empty() {
+            *slot = value;
+            Ok(())
+        } else {
+            set_index(Rc::make_mut(slot), rest, value)
+        }
+    };
+
+    match (container, &**index) {
+        (Object::Array(a), &Object::Integer(i)) => {
+            let len = a.elements.len();
+            let idx = resolve_index(i, len, "array")?;
+            match a.elements.get_mut(idx) {
+                Some(slot) => update(slot),
+                None => Err(InterpretError::Runtime(format!(
+                    "index out of range: {} for array of length {}",
+                    i, len
+                ))),
+            }
+        }
+        (Object::Array(_), idx) => Err(InterpretError::TypeMismatch(format!(
+            "array index must be INTEGER, got {}",
+            idx.kind()
+        ))),
+        (Object::Hash(h), _) => {
+            index.check_hashable()?;
+            match h.get_mut(index) {
+                Some(slot) => update(slot),
+                None => {
+                    let mut slot = Rc::new(Object::Null);
+                    update(&mut slot)?;
+                    h.insert(index.clone(), slot);
+                    Ok(())
+                }
+            }
+        }
+        (container, _) => Err(InterpretError::TypeMismatch(format!(
+            "index assignment not supported: {}",
+            container.kind()
+        ))),
     }
 }
 
-fn eval_string_infix_op(left: &str, op: TokenType, right: &str) -> EvalResult {
+/// `a..b`, or `a..=b` when `inclusive`, shared by both engines
+pub fn make_range(
+    left: &Object,
+    right: &Object,
+    inclusive: bool,
+) -> Result<Object, InterpretError> {
+    let (&Object::Integer(start), &Object::Integer(end)) = (left, right) else {
+        return Err(InterpretError::TypeMismatch(format!(
+            "range bounds must be INTEGER, got {} and {}",
+            left.kind(),
+            right.kind()
+        )));
+    };
+    let end = match inclusive {
+        true => end
+            .checked_add(1)
+            .ok_or_else(|| InterpretError::Runtime(format!("range end too large: {}", end)))?,
+        false => end,
+    };
+    Ok(Object::Range(RangeObj { start, end }))
+}
+
+/// Element `idx` of what a `for` loop iterates over, `None` past the end
+pub fn iter_item(obj: &Object, idx: usize) -> Result<Option<Rc<Object>>, InterpretError> {
+    match obj {
+        Object::Array(a) => Ok(a.elements.get(idx).cloned()),
+        Object::Range(r) => Ok(r.get(idx).map(|x| Rc::new(Object::Integer(x)))),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "cannot iterate over {}",
+            obj.kind()
+        ))),
+    }
+}
+
+/// Float arithmetic shared by both engines, for when either operand is a
+/// `Float` and the other one any number. Integers are converted first, so
+/// `1 + 0.5 == 1.5`. Dividing by zero is an error just like for integers
+/// and `~/` floors the quotient
+pub fn float_infix(left: &Object, op: TokenType, right: &Object) -> Result<Object, InterpretError> {
+    let (Some(l), Some(r)) = (to_float(left), to_float(right)) else {
+        unreachable!()
+    };
+    let res = match op {
+        TokenType::Plus => l + r,
+        TokenType::Minus => l - r,
+        TokenType::Star => l * r,
+        TokenType::Slash | TokenType::FloorDiv if r == 0.0 => {
+            return Err(InterpretError::div_by_zero())
+        }
+        TokenType::Percent if r == 0.0 => return Err(InterpretError::mod_by_zero()),
+        TokenType::Slash => l / r,
+        TokenType::FloorDiv => (l / r).floor(),
+        TokenType::Percent => l % r,
+        TokenType::Pow => l.powf(r),
+
+        TokenType::Lt => return Ok(Object::Bool(l < r)),
+        TokenType::Gt => return Ok(Object::Bool(l > r)),
+        TokenType::LtEq => return Ok(Object::Bool(l <= r)),
+        TokenType::GtEq => return Ok(Object::Bool(l >= r)),
+        TokenType::Eq => return Ok(Object::Bool(l == r)),
+        TokenType::NotEq => return Ok(Object::Bool(l != r)),
+        _ => {
+            return Err(InterpretError::TypeMismatch(format!(
+                "unknown operator: {} {} {}",
+                left.kind(),
+                op,
+                right.kind()
+            )))
+        }
+    };
+    Ok(Object::Float(res))
+}
+
+/// Value of any number as a float, `None` for other objects
+pub fn to_float(obj: &Object) -> Option<f64> {
+    match obj {
+        Object::Integer(x) => Some(*x as f64),
+        Object::BigInt(x) => Some(x.to_f64()),
+        Object::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Negates an `Integer`, `BigInt` or `Float`, `None` for any other object
+pub fn num_neg(obj: &Object) -> Option<Object> {
+    match obj {
+        Object::Float(x) => Some(Object::Float(-x)),
+        Object::Integer(x) => Some(
+            x.checked_neg()
+                .map_or_else(|| BigInt::from(*x).neg().into(), Object::Integer),
+        ),
+        Object::BigInt(x) => Some(x.neg().into()),
+        _ => None,
+    }
+}
+
+/// Bitwise not of an `Integer` or `BigInt`, `None` for any other object
+pub fn int_not(obj: &Object) -> Option<Object> {
+    match obj {
+        Object::Integer(x) => Some(Object::Integer(!x)),
+        // Two's complement, `~x == -x - 1`
+        Object::BigInt(x) => Some(x.neg().sub(&BigInt::from(1)).into()),
+        _ => None,
+    }
+}
+
+/// Bitwise operators work on values which fit an `i64`, only `<<` may
+/// promote its result
+fn int_bitwise(left: &Object, op: TokenType, right: &Object) -> Result<Object, InterpretError> {
+    let (&Object::Integer(l), &Object::Integer(r)) = (left, right) else {
+        return Err(InterpretError::Runtime(format!(
+            "integer too large for bitwise operator {}",
+            op
+        )));
+    };
+
+    let res = match op {
+        TokenType::Ampersand => l & r,
+        TokenType::Pipe => l | r,
+        TokenType::Caret => l ^ r,
+        TokenType::Shl | TokenType::Shr if !(0..64).contains(&r) => {
+            return Err(InterpretError::Runtime(format!(
+                "invalid shift amount: {} {} {}",
+                l, op, r
+            )))
+        }
+        TokenType::Shl if (l << r) >> r != l => {
+            return Ok(BigInt::from(l).mul(&BigInt::from(2).pow(r as u32)).into())
+        }
+        TokenType::Shl => l << r,
+        TokenType::Shr => l >> r,
+        _ => unreachable!(),
+    };
+    Ok(Object::Integer(res))
+}
+
+/// Repeats `s` `count` times, a zero or negative count gives the empty string
+pub fn repeat_str(s: &str, count: &Object, max_alloc: usize) -> Result<Object, InterpretError> {
+    let n = match count {
+        Object::Integer(n) => *n,
+        Object::BigInt(n) if n < &BigInt::default() => 0,
+        _ => i64::MAX,
+    };
+    let n = usize::try_from(n).unwrap_or(0);
+    check_alloc(s.len().saturating_mul(n), max_alloc)?;
+    Ok(Object::String(s.repeat(n)))
+}
+
+fn to_big(obj: &Object) -> BigInt {
+    match obj {
+        Object::Integer(x) => BigInt::from(*x),
+        Object::BigInt(x) => x.clone(),
+        _ => unreachable!(),
+    }
+}
+
+fn big_pow(left: &BigInt, right: &BigInt) -> Result<BigInt, InterpretError> {
+    if right < &BigInt::default() {
+        return Err(InterpretError::Runtime(format!(
+            "negative exponent: {} ** {}",
+            left, right
+        )));
+    }
+    // 0, 1 and -1 stay small no matter the exponent
+    let exp = right.to_i64().and_then(|e| u32::try_from(e).ok());
+    match exp {
+        Some(e) if left.bits() <= 1 || left.bits() * e as u64 <= MAX_INT_BITS => Ok(left.pow(e)),
+        _ => Err(InterpretError::Runtime(format!(
+            "integer overflow: {} ** {}",
+            left, right
+        ))),
+    }
+}
+
+/// Splits an array into its first `names` elements, followed by an array of
+/// the remaining ones if `rest` is set
+pub fn destructure(
+    obj: &Object,
+    names: usize,
+    rest: bool,
+) -> Result<Vec<Rc<Object>>, InterpretError> {
+    let Object::Array(arr) = obj else {
+        return Err(InterpretError::TypeMismatch(format!(
+            "cannot destructure {}",
+            obj.kind()
+        )));
+    };
+
+    let len = arr.elements.len();
+    if rest && len < names {
+        return Err(InterpretError::WrongArity(format!(
+            "cannot destructure array of length {} into at least {} names",
+            len, names
+        )));
+    } else if !rest && len != names {
+        return Err(InterpretError::WrongArity(format!(
+            "cannot destructure array of length {} into {} names",
+            len, names
+        )));
+    }
+
+    let mut values = arr.elements[..names].to_vec();
+    if rest {
+        let elements = arr.elements[names..].to_vec();
+        values.push(Rc::new(Object::Array(ArrayObj { elements })));
+    }
+    Ok(values)
+}
+
+fn eval_string_infix_op(left: &str, op: TokenType, right: &str, max_alloc: usize) -> EvalResult {
     match op {
-        TokenType::Plus => Ok(Rc::new(Object::String(left.to_owned() + right))),
+        TokenType::Plus => {
+            check_alloc(left.len() + right.len(), max_alloc)?;
+            Ok(Rc::new(Object::String(left.to_owned() + right)))
+        }
 
         TokenType::Eq => Ok(Rc::new(Object::Bool(left == right))),
         TokenType::NotEq => Ok(Rc::new(Object::Bool(left != right))),
+        TokenType::Lt => Ok(Rc::new(Object::Bool(left < right))),
+        TokenType::Gt => Ok(Rc::new(Object::Bool(left > right))),
+        TokenType::LtEq => Ok(Rc::new(Object::Bool(left <= right))),
+        TokenType::GtEq => Ok(Rc::new(Object::Bool(left >= right))),
 
-        _ => Err(format!("unknown operator: STRING {} STRING", op)),
+        _ => Err(InterpretError::TypeMismatch(format!(
+            "unknown operator: STRING {} STRING",
+            op
+        ))),
     }
 }
 
-fn apply_func(func: Rc<Object>, args: Vec<Rc<Object>>) -> EvalResult {
-    let func = match &*func {
+/// Calls `func` on behalf of code running in `env`, whose random numbers and
+/// limits builtins go by
+fn apply_func(func: &Object, args: Vec<Rc<Object>>, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let (rng, out, max_alloc) = {
+        let env = env.borrow();
+        (env.rng(), env.output(), env.max_alloc())
+    };
+    let func = match func {
         Object::Func(f) => f,
+        // Arrays nothing else refers to are updated in place, shared ones
+        // get copied like they would be anyway
+        Object::Builtin(b @ (Builtin::Push | Builtin::Set)) => {
+            let mut args: Vec<_> = args.into_iter().map(Rc::unwrap_or_clone).collect();
+            return b.call_owned(
+                &mut args,
+                &mut |f, args| apply_callback(f, args, env),
+                &rng,
+                &out,
+                max_alloc,
+            );
+        }
+        // Called through another function there's no caller scope to use
+        Object::Builtin(Builtin::Parse) => {
+            return eval_parse(args, &env.borrow().new_isolated());
+        }
         Object::Builtin(b) => {
             let args: Vec<_> = args.iter().map(|x| &**x).collect();
-            return b.call(args);
+            return b.call(
+                args,
+                &mut |f, args| apply_callback(f, args, env),
+                &rng,
+                &out,
+                max_alloc,
+            );
+        }
+        Object::Native(n) => return n.call(args),
+        Object::Partial(p) => {
+            let args = p.args.iter().cloned().chain(args).collect();
+            return apply_func(&p.func, args, env);
+        }
+        Object::Memo(m) => {
+            if let Some(res) = m.get(&args)? {
+                return Ok(res);
+            }
+            let res = apply_func(&m.func, args.clone(), env)?;
+            m.insert(args, res.clone());
+            return Ok(res);
+        }
+        _ => {
+            return Err(InterpretError::TypeMismatch(format!(
+                "not a function: {}",
+                func.kind()
+            )))
         }
-        _ => return Err(format!("not a function: {}", func.kind())),
     };
 
     let env = Rc::new(RefCell::new(Environment::new_enclosed(func.env.clone())));
-    if args.len() != func.expr.params.len() {
-        return Err(format!(
-            "function expects {} arguments but {} were given",
-            func.expr.params.len(),
-            args.len()
-        ));
+    let (required, params) = (func.expr.required(), func.expr.params.len());
+    let rest = &func.expr.rest;
+    if args.len() < required || (args.len() > params && rest.is_none()) {
+        let max = rest.is_none().then_some(params);
+        return Err(InterpretError::arity("function", required, max, args.len()));
     }
-
-    for (arg, param) in args.iter().zip(func.expr.params.iter()) {
-        env.borrow_mut().set(param, arg.clone())
-    }
-    let res = eval_block(&func.expr.body, &env)?;
+    // Entered before the defaults, which may call back into the function
+    let _guard = DepthGuard::enter(&env, 1)?;
+    let res = eval_call(func, &args, &env)?;
 
     match &*res {
         Object::Return(r) => Ok(r.clone()),
+        Object::Break | Object::Continue => Err(loop_control_err(&res)),
         _ => Ok(res),
     }
 }
 
-type EvalResult = Result<Rc<Object>, String>;
+/// Binds the arguments of a call to `func` in `env` and runs its body
+fn eval_call(func: &FuncObj, args: &[Rc<Object>], env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let (required, params) = (func.expr.required(), func.expr.params.len());
+    for (arg, param) in args.iter().zip(func.expr.params.iter()) {
+        env.borrow_mut().set(param, arg.clone())
+    }
+    if let Some(rest) = &func.expr.rest {
+        let elements = args.iter().skip(params).cloned().collect();
+        env.borrow_mut()
+            .set(rest, Rc::new(Object::Array(ArrayObj { elements })));
+    }
+    // Defaults see the params before them
+    for (param, default) in func.expr.params[required..]
+        .iter()
+        .zip(&func.expr.defaults)
+        .skip(args.len().min(params) - required)
+    {
+        let val = flow!(eval_value(default, env));
+        env.borrow_mut().set(param, val);
+    }
+    eval_block(&func.expr.body, env)
+}
+
+/// [`apply_func`] for a builtin calling back into `func`, which counts as
+/// [`CALLBACK_COST`] calls
+fn apply_callback(func: &Object, args: Vec<Rc<Object>>, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let _guard = DepthGuard::enter(env, CALLBACK_COST)?;
+    apply_func(func, args, env)
+}
+
+/// `parse(s)`, evaluating the expression in `s` in `env`
+fn eval_parse(args: Vec<Rc<Object>>, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let args: Vec<_> = args.iter().map(|x| &**x).collect();
+    let program = builtin::parse_arg(&args)?;
+    // The string may call back into `parse` through the caller's functions
+    let _guard = DepthGuard::enter(env, 1)?;
+    eval_program(program, env)
+}
+
+fn loop_control_err(obj: &Object) -> InterpretError {
+    InterpretError::Runtime(format!("{} outside loop", obj))
+}
+
+type EvalResult = Result<Rc<Object>, InterpretError>;
 
 #[cfg(test)]
 mod test;
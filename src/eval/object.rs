@@ -1,28 +1,67 @@
-use super::Environment;
-use crate::{ast::FuncExpr, builtin::Builtin, compiler::Bytes};
+use super::{BigInt, Environment};
+use crate::{
+    ast::{Expression, FuncExpr, MacroExpr},
+    builtin::{Builtin, NativeFn},
+    compiler::{Bytes, LineTable},
+    error::InterpretError,
+};
 use std::{cell::RefCell, collections::HashMap, fmt::Display, hash::Hash, rc::Rc};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Object {
     Integer(i64),
+    BigInt(BigInt),
+    Float(f64),
     Bool(bool),
     String(String),
 
     Return(Rc<Object>),
+    Break,
+    Continue,
     Func(FuncObj),
     CompiledFunc(Rc<CompiledFuncObj>),
     Builtin(Builtin),
+    Native(NativeFn),
+    Partial(PartialObj),
+    Memo(MemoObj),
+    /// Error caught by `try`
+    Error(InterpretError),
     Array(ArrayObj),
+    Range(RangeObj),
     Hash(HashObj),
+    Quote(Expression),
+    Macro(MacroObj),
 
     Null,
 }
 
 impl Object {
+    /// Whether the object can be a hash key. Floats can't, a NaN key could
+    /// never be found again
+    pub fn is_hashable(&self) -> bool {
+        matches!(
+            self,
+            Object::Integer(_) | Object::BigInt(_) | Object::String(_) | Object::Bool(_)
+        )
+    }
+
+    /// Fails unless the object can be a hash key
+    pub fn check_hashable(&self) -> Result<(), InterpretError> {
+        if self.is_hashable() {
+            return Ok(());
+        }
+        Err(InterpretError::TypeMismatch(format!(
+            "unusable as hash key: {}",
+            self.kind()
+        )))
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             Object::Integer(0) => false,
             Object::Integer(_) => true,
+            Object::BigInt(_) => true,
+            Object::Float(x) => *x != 0.0,
             Object::Bool(b) => *b,
             Object::Null => false,
             Object::Return(o) => o.is_truthy(),
@@ -32,27 +71,75 @@ impl Object {
 
     pub fn kind(&self) -> &'static str {
         match self {
-            Object::Integer(_) => "INTEGER",
+            Object::Integer(_) | Object::BigInt(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
             Object::Bool(_) => "BOOL",
             Object::String(_) => "STRING",
             Object::Null => "NULL",
             Object::Return(_) => "RETURN",
-            Object::Func(_) => "FUNCTION",
+            Object::Break => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::Func(_) | Object::Partial(_) | Object::Memo(_) => "FUNCTION",
             Object::CompiledFunc(_) => "COMPILED FUNCTION",
-            Object::Builtin(_) => "BUILTIN",
+            Object::Builtin(_) | Object::Native(_) => "BUILTIN",
             Object::Array(_) => "ARRAY",
+            Object::Range(_) => "RANGE",
             Object::Hash(_) => "HASH",
+            Object::Error(_) => "ERROR",
+            Object::Quote(_) => "QUOTE",
+            Object::Macro(_) => "MACRO",
+        }
+    }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self,
+            Object::Func(_)
+                | Object::CompiledFunc(_)
+                | Object::Builtin(_)
+                | Object::Native(_)
+                | Object::Partial(_)
+                | Object::Memo(_)
+        )
+    }
+
+    /// Form shown by the REPL and inside arrays and hashes, strings are
+    /// quoted and escaped. `Display` gives the raw form used by `puts`
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::String(s) => {
+                let mut res = String::from('"');
+                for ch in s.chars() {
+                    match ch {
+                        '"' => res += "\\\"",
+                        '\\' => res += "\\\\",
+                        '\n' => res += "\\n",
+                        '\t' => res += "\\t",
+                        '\r' => res += "\\r",
+                        ch => res.push(ch),
+                    }
+                }
+                res.push('"');
+                res
+            }
+            Object::Return(o) => o.inspect(),
+            _ => self.to_string(),
         }
     }
 }
 
+// Floats can't be hash keys, see `is_hashable`, so a NaN never ends up in a
+// `HashObj` index
+impl Eq for Object {}
+
 impl Hash for Object {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
             Object::Integer(v) => v.hash(state),
+            Object::BigInt(v) => v.hash(state),
             Object::String(v) => v.hash(state),
             Object::Bool(v) => v.hash(state),
-            _ => panic!("Cannot hash object of type {}", self.kind()),
+            _ => unreachable!("{} keys are rejected by `check_hashable`", self.kind()),
         }
     }
 }
@@ -61,15 +148,131 @@ impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Object::Integer(x) => write!(f, "{}", x),
+            Object::BigInt(x) => write!(f, "{}", x),
+            // Keeps the `.0` of whole floats, `3.0` rather than `3`
+            Object::Float(x) => write!(f, "{:?}", x),
             Object::Bool(x) => write!(f, "{}", x),
             Object::String(s) => write!(f, "{}", s),
             Object::Null => write!(f, "null"),
             Object::Return(o) => write!(f, "{}", o),
+            Object::Break => write!(f, "break"),
+            Object::Continue => write!(f, "continue"),
             Object::Func(o) => write!(f, "{}", o),
             Object::CompiledFunc(o) => write!(f, "{}", o),
             Object::Builtin(_) => write!(f, "builtin"),
+            Object::Native(n) => write!(f, "builtin {}", n.name),
+            Object::Partial(p) => write!(f, "{}", p),
+            Object::Memo(m) => write!(f, "memoize({})", m.func),
+            Object::Error(e) => write!(f, "error: {}", e),
             Object::Array(a) => write!(f, "{}", a),
+            Object::Range(r) => write!(f, "{}..{}", r.start, r.end),
             Object::Hash(h) => write!(f, "{}", h),
+            Object::Quote(q) => write!(f, "QUOTE({})", q),
+            Object::Macro(m) => write!(f, "{}", m.expr),
+        }
+    }
+}
+
+/// Demotes to `Integer` when the value fits
+impl From<BigInt> for Object {
+    fn from(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(x) => Object::Integer(x),
+            None => Object::BigInt(value),
+        }
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Integer(value)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        Object::Float(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Bool(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::String(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::String(value.to_string())
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(value: Vec<T>) -> Self {
+        let elements = value.into_iter().map(|x| Rc::new(x.into())).collect();
+        Object::Array(ArrayObj { elements })
+    }
+}
+
+fn expected(kind: &str, got: &Object) -> InterpretError {
+    InterpretError::TypeMismatch(format!("expected {}, got {}", kind, got.kind()))
+}
+
+/// Fails for `BigInt`s which don't fit
+impl TryFrom<&Object> for i64 {
+    type Error = InterpretError;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Integer(x) => Ok(*x),
+            Object::BigInt(x) => Err(InterpretError::Runtime(format!(
+                "integer too large for i64: {}",
+                x
+            ))),
+            _ => Err(expected("INTEGER", value)),
+        }
+    }
+}
+
+impl TryFrom<&Object> for bool {
+    type Error = InterpretError;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Bool(b) => Ok(*b),
+            _ => Err(expected("BOOL", value)),
+        }
+    }
+}
+
+impl TryFrom<&Object> for String {
+    type Error = InterpretError;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(s) => Ok(s.clone()),
+            _ => Err(expected("STRING", value)),
+        }
+    }
+}
+
+/// Converts every element, failing on the first one which doesn't convert
+impl<T> TryFrom<&Object> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Object, Error = InterpretError>,
+{
+    type Error = InterpretError;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Array(a) => a.elements.iter().map(|x| T::try_from(x)).collect(),
+            _ => Err(expected("ARRAY", value)),
         }
     }
 }
@@ -86,11 +289,80 @@ impl Display for FuncObj {
     }
 }
 
+/// Function with its leading arguments already supplied, made by `partial`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PartialObj {
+    pub func: Rc<Object>,
+    pub args: Vec<Rc<Object>>,
+}
+
+impl Display for PartialObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "partial({}", self.func)?;
+        for arg in &self.args {
+            write!(f, ", {}", arg.inspect())?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Function remembering what it returned for each list of arguments, made
+/// by `memoize`. Copies share the cache
+#[derive(Debug, Clone)]
+pub struct MemoObj {
+    pub func: Rc<Object>,
+    cache: Rc<RefCell<MemoCache>>,
+}
+
+type MemoCache = HashMap<Vec<Rc<Object>>, Rc<Object>>;
+
+impl MemoObj {
+    pub fn new(func: Object) -> Self {
+        Self {
+            func: Rc::new(func),
+            cache: Rc::default(),
+        }
+    }
+
+    /// The result of an earlier call with `args`, errors when one of them
+    /// couldn't be a hash key
+    pub fn get(&self, args: &[Rc<Object>]) -> Result<Option<Rc<Object>>, InterpretError> {
+        for arg in args {
+            arg.check_hashable()?;
+        }
+        Ok(self.cache.borrow().get(args).cloned())
+    }
+
+    pub fn insert(&self, args: Vec<Rc<Object>>, res: Rc<Object>) {
+        self.cache.borrow_mut().insert(args, res);
+    }
+}
+
+/// Memoized functions are only equal to their own copies
+impl PartialEq for MemoObj {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.cache, &other.cache)
+    }
+}
+
+impl Eq for MemoObj {}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MacroObj {
+    pub expr: MacroExpr,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+#[derive(Debug, Eq, Clone)]
 pub struct CompiledFuncObj {
     pub instructions: Bytes,
     pub locals: usize,
     pub params: usize,
+    /// How many of the trailing params may be left out by callers
+    pub defaults: usize,
+    /// Whether arguments past `params` are collected into an array
+    pub rest: bool,
+    pub lines: LineTable,
 }
 
 impl CompiledFuncObj {
@@ -99,10 +371,24 @@ impl CompiledFuncObj {
             instructions,
             locals,
             params,
+            defaults: 0,
+            rest: false,
+            lines: LineTable::default(),
         }
     }
 }
 
+/// Line tables are debug info, two functions are equal if they run the same
+impl PartialEq for CompiledFuncObj {
+    fn eq(&self, other: &Self) -> bool {
+        self.instructions == other.instructions
+            && self.locals == other.locals
+            && self.params == other.params
+            && self.defaults == other.defaults
+            && self.rest == other.rest
+    }
+}
+
 impl Display for CompiledFuncObj {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, " {} {} locals", self.instructions, self.locals)
@@ -119,28 +405,118 @@ impl Display for ArrayObj {
         write!(f, "[")?;
         for (idx, s) in self.elements.iter().enumerate() {
             if idx != self.elements.len() - 1 {
-                write!(f, "{}, ", s)?;
+                write!(f, "{}, ", s.inspect())?;
             } else {
-                write!(f, "{}", s)?;
+                write!(f, "{}", s.inspect())?;
             }
         }
         write!(f, "]")
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Integers from `start` up to but without `end`, as built by `a..b`.
+/// `a..=b` is stored with `end` one past `b`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RangeObj {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl RangeObj {
+    pub fn len(&self) -> usize {
+        let len = (self.end as i128 - self.start as i128).max(0);
+        usize::try_from(len).unwrap_or(usize::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `idx`th integer, `None` past the end
+    pub fn get(&self, idx: usize) -> Option<i64> {
+        (idx < self.len()).then(|| self.start.wrapping_add(idx as i64))
+    }
+}
+
+/// Hash map which iterates in insertion order
+#[derive(Debug, Clone, Default)]
 pub struct HashObj {
-    pub map: HashMap<Rc<Object>, Rc<Object>>,
+    pairs: Vec<(Rc<Object>, Rc<Object>)>,
+    /// Position of each key in `pairs`
+    index: HashMap<Rc<Object>, usize>,
+}
+
+impl HashObj {
+    /// Inserting an existing key replaces its value but keeps its position
+    pub fn insert(&mut self, key: Rc<Object>, value: Rc<Object>) {
+        match self.index.get(&key) {
+            Some(&idx) => self.pairs[idx].1 = value,
+            None => {
+                self.index.insert(key.clone(), self.pairs.len());
+                self.pairs.push((key, value));
+            }
+        }
+    }
+
+    /// Removes the key while keeping the order of the remaining pairs
+    pub fn remove(&mut self, key: &Object) -> Option<Rc<Object>> {
+        let idx = self.index.remove(key)?;
+        let (_, value) = self.pairs.remove(idx);
+        for i in self.index.values_mut() {
+            if *i > idx {
+                *i -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn get(&self, key: &Object) -> Option<&Rc<Object>> {
+        self.index.get(key).map(|&idx| &self.pairs[idx].1)
+    }
+
+    pub fn get_mut(&mut self, key: &Object) -> Option<&mut Rc<Object>> {
+        self.index.get(key).map(|&idx| &mut self.pairs[idx].1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Rc<Object>, Rc<Object>)> {
+        self.pairs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+impl FromIterator<(Rc<Object>, Rc<Object>)> for HashObj {
+    fn from_iter<T: IntoIterator<Item = (Rc<Object>, Rc<Object>)>>(iter: T) -> Self {
+        let mut h = Self::default();
+        for (k, v) in iter {
+            h.insert(k, v);
+        }
+        h
+    }
+}
+
+/// Insertion order doesn't matter when comparing
+impl PartialEq for HashObj {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
 }
+impl Eq for HashObj {}
 
 impl Display for HashObj {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{{")?;
-        for (idx, (k, v)) in self.map.iter().enumerate() {
-            if idx != self.map.len() - 1 {
-                write!(f, "{}: {}, ", k, v)?;
+        for (idx, (k, v)) in self.pairs.iter().enumerate() {
+            if idx != self.pairs.len() - 1 {
+                write!(f, "{}: {}, ", k.inspect(), v.inspect())?;
             } else {
-                write!(f, "{}: {}", k, v)?;
+                write!(f, "{}: {}", k.inspect(), v.inspect())?;
             }
         }
         write!(f, "}}")
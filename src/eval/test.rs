@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use super::*;
 use crate::{ast::Parser, lexer::Lexer};
 
@@ -19,6 +17,51 @@ fn eval_int() {
     );
 }
 
+#[test]
+fn eval_float() {
+    let float = |x: f64| Ok(Rc::new(Object::Float(x)));
+    let bool = |b: bool| Ok(Rc::new(Object::Bool(b)));
+    test!(
+        ("2.5", float(2.5)),
+        ("-0.5", float(-0.5)),
+        ("1.5 + 1", float(2.5)),
+        ("1 - 0.25", float(0.75)),
+        ("2 ** 0.5 * 2 ** 0.5 > 1.99", bool(true)),
+        ("7 / 2.0", float(3.5)),
+        ("-7.0 ~/ 2", float(-4.0)),
+        ("2 ** 70 * 0.5", float(590295810358705651712.0)),
+        ("1 == 1.0", bool(true)),
+        ("0.1 + 0.2 != 0.3", bool(true)),
+        ("1.5 < 2", bool(true)),
+        ("2 >= 2.5", bool(false)),
+        ("if (0.0) { 1 } else { 2 }", Ok(Rc::new(Object::Integer(2)))),
+        ("math.abs(-1.5)", float(1.5)),
+        ("math.max(1, 2.5, 2)", float(2.5)),
+        (
+            "1.0 / 0",
+            Err(InterpretError::DivByZero("division by zero".into()))
+        ),
+        (
+            "1.5 & 1",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: FLOAT & INTEGER".into()
+            ))
+        ),
+        (
+            "~1.5",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: ~FLOAT".into()
+            ))
+        ),
+        (
+            "1.5 + \"a\"",
+            Err(InterpretError::TypeMismatch(
+                "type mismatch: FLOAT + STRING".into()
+            ))
+        ),
+    );
+}
+
 #[test]
 fn eval_bool() {
     test!(
@@ -27,6 +70,21 @@ fn eval_bool() {
     );
 }
 
+#[test]
+fn eval_null() {
+    test!(
+        ("null", Ok(Rc::new(Object::Null))),
+        ("null == if (false) { 1 }", Ok(Rc::new(Object::Bool(true)))),
+        (r#"{"a": 1}["b"] == null"#, Ok(Rc::new(Object::Bool(true)))),
+        ("is_null(null) && !null", Ok(Rc::new(Object::Bool(true)))),
+        ("[null, 1][0] ?? 2", Ok(Rc::new(Object::Integer(2)))),
+        (
+            "quote(unquote(null))",
+            Ok(Rc::new(Object::Quote(Expression::Null)))
+        ),
+    );
+}
+
 #[test]
 fn eval_string() {
     test!(
@@ -38,6 +96,29 @@ fn eval_string() {
     );
 }
 
+#[test]
+fn eval_string_repeat() {
+    test!(
+        (
+            r#""=" * 10"#,
+            Ok(Rc::new(Object::String("==========".into())))
+        ),
+        (r#"3 * "ab""#, Ok(Rc::new(Object::String("ababab".into())))),
+        (r#""x" * 0"#, Ok(Rc::new(Object::String("".into())))),
+        (r#""x" * -2"#, Ok(Rc::new(Object::String("".into())))),
+        (
+            r#""ab" * 100000000"#,
+            Err(InterpretError::Runtime("allocation limit exceeded".into()))
+        ),
+        (
+            r#""ab" - 1"#,
+            Err(InterpretError::TypeMismatch(
+                "type mismatch: STRING - INTEGER".into()
+            ))
+        ),
+    );
+}
+
 #[test]
 fn eval_bang() {
     test!(
@@ -70,6 +151,142 @@ fn eval_math() {
     )
 }
 
+#[test]
+fn eval_pow() {
+    test!(
+        ("2 ** 10", Ok(Rc::new(Object::Integer(1024)))),
+        ("2 ** 3 ** 2", Ok(Rc::new(Object::Integer(512)))),
+        ("(2 ** 3) ** 2", Ok(Rc::new(Object::Integer(64)))),
+        ("3 * 2 ** 2", Ok(Rc::new(Object::Integer(12)))),
+        ("5 ** 0", Ok(Rc::new(Object::Integer(1)))),
+        (
+            "2 ** -1",
+            Err(InterpretError::Runtime("negative exponent: 2 ** -1".into()))
+        ),
+        (
+            "2 ** 2000000",
+            Err(InterpretError::Runtime(
+                "integer overflow: 2 ** 2000000".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_bitwise() {
+    test!(
+        ("6 & 3", Ok(Rc::new(Object::Integer(2)))),
+        ("6 | 3", Ok(Rc::new(Object::Integer(7)))),
+        ("6 ^ 3", Ok(Rc::new(Object::Integer(5)))),
+        ("1 << 4", Ok(Rc::new(Object::Integer(16)))),
+        ("-16 >> 2", Ok(Rc::new(Object::Integer(-4)))),
+        ("~0", Ok(Rc::new(Object::Integer(-1)))),
+        (
+            "~(2 ** 64)",
+            Ok(Rc::new(
+                BigInt::from(2).pow(64).neg().sub(&BigInt::from(1)).into()
+            ))
+        ),
+        ("1 + 1 << 2 == 8", Ok(Rc::new(Object::Bool(true)))),
+        ("1 << 63", Ok(Rc::new(BigInt::from(2).pow(63).into()))),
+        ("-1 << 63", Ok(Rc::new(Object::Integer(i64::MIN)))),
+        (
+            "1 << 64",
+            Err(InterpretError::Runtime(
+                "invalid shift amount: 1 << 64".into()
+            ))
+        ),
+        (
+            "1 >> -1",
+            Err(InterpretError::Runtime(
+                "invalid shift amount: 1 >> -1".into()
+            ))
+        ),
+        (
+            "2 ** 64 & 1",
+            Err(InterpretError::Runtime(
+                "integer too large for bitwise operator &".into()
+            ))
+        ),
+        (
+            "~true",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: ~BOOL".into()
+            ))
+        ),
+        (
+            "true & false",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: BOOL & BOOL".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn big_integers() {
+    let fact = "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } };";
+    let program = Parser::new(Lexer::new(format!("{} fact(30)", fact)))
+        .parse()
+        .unwrap();
+    let res = eval_program(program, &Environment::new()).unwrap();
+    assert!(matches!(*res, Object::BigInt(_)));
+    assert_eq!(res.to_string(), "265252859812191058636308480000000");
+
+    test!(
+        (
+            &format!("{} fact(30) / fact(28)", fact),
+            Ok(Rc::new(Object::Integer(870)))
+        ),
+        ("2 ** 64", Ok(Rc::new(BigInt::from(2).pow(64).into()))),
+        (
+            "9223372036854775807 + 1",
+            Ok(Rc::new(BigInt::from(2).pow(63).into()))
+        ),
+        (
+            "-(-9223372036854775807 - 1)",
+            Ok(Rc::new(BigInt::from(2).pow(63).into()))
+        ),
+        (
+            "9223372036854775808",
+            Ok(Rc::new(BigInt::from(2).pow(63).into()))
+        ),
+        ("-9223372036854775808", Ok(Rc::new(Object::Integer(i64::MIN)))),
+        (
+            "0x1_0000_0000_0000_0000",
+            Ok(Rc::new(BigInt::from(2).pow(64).into()))
+        ),
+        ("2 ** 64 - 2 ** 64", Ok(Rc::new(Object::Integer(0)))),
+        ("2 ** 64 / 2 ** 60", Ok(Rc::new(Object::Integer(16)))),
+        ("2 ** 64 > 5", Ok(Rc::new(Object::Bool(true)))),
+        ("-(2 ** 64) < 5", Ok(Rc::new(Object::Bool(true)))),
+        ("2 ** 64 == 2 ** 64", Ok(Rc::new(Object::Bool(true)))),
+        ("2 ** 64 == 2", Ok(Rc::new(Object::Bool(false)))),
+        (
+            "2 ** 64 / 0",
+            Err(InterpretError::DivByZero("division by zero".into()))
+        ),
+        (
+            "-(2 ** 70) ~/ 3",
+            Ok(Rc::new(
+                BigInt::from(2)
+                    .pow(70)
+                    .div(&BigInt::from(3))
+                    .unwrap()
+                    .add(&BigInt::from(1))
+                    .neg()
+                    .into()
+            ))
+        ),
+        (
+            "2 ** 64 + true",
+            Err(InterpretError::TypeMismatch(
+                "type mismatch: INTEGER + BOOL".into()
+            ))
+        ),
+    )
+}
+
 #[test]
 fn eval_comare() {
     test!(
@@ -98,7 +315,19 @@ fn eval_comare() {
         (
             r#" "lorem ipsum" != "good placeholder" "#,
             Ok(Rc::new(Object::Bool(true)))
-        )
+        ),
+        ("1 <= 1", Ok(Rc::new(Object::Bool(true)))),
+        ("2 >= 3", Ok(Rc::new(Object::Bool(false)))),
+        (r#""apple" < "banana""#, Ok(Rc::new(Object::Bool(true)))),
+        (r#""apple" > "banana""#, Ok(Rc::new(Object::Bool(false)))),
+        (r#""b" >= "b""#, Ok(Rc::new(Object::Bool(true)))),
+        (r#""ab" <= "a""#, Ok(Rc::new(Object::Bool(false)))),
+        (
+            r#""a" - "b""#,
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: STRING - STRING".into()
+            ))
+        ),
     )
 }
 
@@ -126,332 +355,2011 @@ fn eval_if() {
 }
 
 #[test]
-fn eval_return() {
+fn eval_empty_blocks() {
     test!(
-        ("return 10;", Ok(Rc::new(Object::Integer(10)))),
-        ("return 10; 9;", Ok(Rc::new(Object::Integer(10)))),
-        ("return 2 * 5; 9;", Ok(Rc::new(Object::Integer(10)))),
-        ("9; return 2 * 5; 9;", Ok(Rc::new(Object::Integer(10)))),
-        (
-            r#"if (10 > 1) {
-                if (10 > 1) {
-                 return 10;
-                }
-                return 1;
-            }"#,
-            Ok(Rc::new(Object::Integer(10)))
-        )
+        ("if (true) {}", Ok(Rc::new(Object::Null))),
+        ("if (false) {}", Ok(Rc::new(Object::Null))),
+        ("if (false) { 10 } else {}", Ok(Rc::new(Object::Null))),
+        ("if (true) { let x = 1; }", Ok(Rc::new(Object::Null))),
+        ("fn() {}()", Ok(Rc::new(Object::Null))),
     )
 }
 
 #[test]
-fn error_handling() {
+fn eval_block_expressions() {
     test!(
-        ("5 + true;", Err("type mismatch: INTEGER + BOOL".into())),
-        ("5 + true; 5;", Err("type mismatch: INTEGER + BOOL".into())),
-        ("-true", Err("unknown operator: -BOOL".into())),
-        ("true + false;", Err("unknown operator: BOOL + BOOL".into())),
         (
-            "5; true + false; 5",
-            Err("unknown operator: BOOL + BOOL".into())
+            "let x = { let a = 1; a + 2 }; x",
+            Ok(Rc::new(Object::Integer(3)))
         ),
         (
-            "if (10 > 1) { true + false; }",
-            Err("unknown operator: BOOL + BOOL".into()),
+            "let a = 10; let b = { let a = 1; a }; [a, b]",
+            Ok(Rc::new(Object::from(vec![10, 1])))
         ),
+        ("{ let a = 1; }", Ok(Rc::new(Object::Null))),
+        ("{ 1; 2 } * { 3 }", Ok(Rc::new(Object::Integer(6)))),
         (
-            r#"if (10 > 1) {
-                if (10 > 1) {
-                 return true + false;
-                }
-                return 1;
-            }"#,
-            Err("unknown operator: BOOL + BOOL".into()),
+            "{ let hidden = 1; 2 }; hidden",
+            Err(InterpretError::Undefined(
+                "identifier not found: hidden".into()
+            ))
         ),
-        ("baz", Err("identifier not found: baz".into())),
+    )
+}
+
+#[test]
+fn allocation_limit() {
+    let limit = || Err(InterpretError::Runtime("allocation limit exceeded".into()));
+    let cases = [
+        ("[1, 2, 3, 4]", Ok(Rc::new(Object::from(vec![1, 2, 3, 4])))),
+        ("[1, 2, 3, 4, 5]", limit()),
+        ("push([1, 2, 3, 4], 5)", limit()),
+        ("{1: 1, 2: 2, 3: 3, 4: 4, 5: 5}", limit()),
+        (r#""ab" + "cd""#, Ok(Rc::new(Object::from("abcd")))),
+        (r#""ab" + "cde""#, limit()),
         (
-            r#" "hello" - "world" "#,
-            Err("unknown operator: STRING - STRING".into())
+            r#"let s = "a"; let double = fn(s) { double(s + s) }; double(s)"#,
+            limit(),
         ),
+        (r#"format("{}{}", "abc", "de")"#, limit()),
+        ("to_json([1, 2])", limit()),
         (
-            r#"{"name": "Monkey"}[fn(x) { x }];"#,
-            Err("unusable as hash key: FUNCTION".into()),
-        )
-    )
+            r#"let x = "a"; let i = 0; while (i < 40) { x = to_json([x, x]); i = i + 1 }"#,
+            limit(),
+        ),
+        ("let f = fn(x) { [x, x, x, x, x] }; f(1)", limit()),
+        (r#"map_values({"a": 1}, fn(x) { range(x, 10) })"#, limit()),
+    ];
+    for (inp, exp) in cases {
+        assert_eq!(eval_with_max_alloc(inp, 4), exp, "{}", inp);
+    }
+
+    // The limit belongs to the environment it was set on
+    test!(("len([1, 2, 3, 4, 5])", Ok(Rc::new(Object::Integer(5)))));
 }
 
 #[test]
-fn eval_let() {
+fn eval_pipeline() {
     test!(
-        ("let a = 5; a;", Ok(Rc::new(Object::Integer(5)))),
-        ("let a = 5 * 5; a;", Ok(Rc::new(Object::Integer(25)))),
-        ("let a = 5; let b = a; b;", Ok(Rc::new(Object::Integer(5)))),
         (
-            "let a = 5; let b = a; let c = a + b + 5; c;",
-            Ok(Rc::new(Object::Integer(15)))
+            "let inc = fn(x) { x + 1 }; let double = fn(x) { x * 2 }; 3 |> inc |> double",
+            Ok(Rc::new(Object::Integer(8)))
+        ),
+        ("[1, 2, 3] |> rest |> len", Ok(Rc::new(Object::Integer(2)))),
+        (
+            "1 |> 2",
+            Err(InterpretError::TypeMismatch(
+                "not a function: INTEGER".into()
+            ))
         ),
     )
 }
 
 #[test]
-fn eval_func() {
+fn eval_logical_operators() {
     test!(
+        ("1 < 2 && 3 < 4", Ok(Rc::new(Object::Bool(true)))),
+        ("1 < 2 && 3 > 4", Ok(Rc::new(Object::Bool(false)))),
+        ("false || 2 > 1", Ok(Rc::new(Object::Bool(true)))),
+        ("0 && crash()", Ok(Rc::new(Object::Integer(0)))),
+        ("1 || crash()", Ok(Rc::new(Object::Integer(1)))),
+        ("0 || \"x\"", Ok(Rc::new(Object::from("x")))),
+        ("1 && 2", Ok(Rc::new(Object::Integer(2)))),
         (
-            "let identity = fn(x) { x; }; identity(5);",
-            Ok(Rc::new(Object::Integer(5)))
+            "let f = fn(x) { x > 0 && x < 10 }; [f(5), f(50)]",
+            Ok(Rc::new(Object::from(vec![true, false])))
         ),
         (
-            "let identity = fn(x) { return x; }; identity(5);",
-            Ok(Rc::new(Object::Integer(5)))
+            "let i = 0; while (i < 10 && i != 3) { i += 1; } i",
+            Ok(Rc::new(Object::Integer(3)))
         ),
         (
-            "let double = fn(x) { x * 2; }; double(5);",
-            Ok(Rc::new(Object::Integer(10)))
+            "1 && crash()",
+            Err(InterpretError::Undefined(
+                "identifier not found: crash".into()
+            ))
         ),
+    )
+}
+
+#[test]
+fn eval_null_coalescing() {
+    test!(
         (
-            "let add = fn(x, y) { x + y; }; add(5, 5);",
-            Ok(Rc::new(Object::Integer(10)))
+            r#"let h = {"a": 1}; h["b"] ?? 5"#,
+            Ok(Rc::new(Object::Integer(5)))
         ),
+        ("if (false) { 1 } ?? 5", Ok(Rc::new(Object::Integer(5)))),
+        ("3 ?? crash()", Ok(Rc::new(Object::Integer(3)))),
+        ("false ?? 1", Ok(Rc::new(Object::Bool(false)))),
+        ("null ?? null ?? 1", Ok(Rc::new(Object::Integer(1)))),
         (
-            "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
-            Ok(Rc::new(Object::Integer(20)))
+            r#"let h = {}; h["a"] ?? h["b"] ?? "c""#,
+            Ok(Rc::new(Object::String("c".into())))
         ),
-        ("fn(x) { x; }(5)", Ok(Rc::new(Object::Integer(5)))),
         (
-            "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1)} }; fact(5)",
-            Ok(Rc::new(Object::Integer(120)))
-        )
-    )
-}
+            "puts() ?? crash()",
+            Err(InterpretError::Undefined(
+                "identifier not found: crash".into()
+            ))
+        ),
+    );
 
-#[test]
-fn array_literal() {
-    test!((
-        "[1, 2 * 2, 3 + 3]",
-        Ok(Rc::new(Object::Array(ArrayObj {
-            elements: vec![
-                Rc::new(Object::Integer(1)),
-                Rc::new(Object::Integer(4)),
-                Rc::new(Object::Integer(6))
-            ]
-        })))
-    ))
+    assert_eq!(eval_with_output(r#"0 ?? print("no")"#), (int(0), "".into()));
+    assert_eq!(
+        eval_with_output(r#"puts() ?? print("yes")"#),
+        (Ok(Rc::new(Object::Null)), "yes".into())
+    );
 }
 
 #[test]
-fn index_arr() {
+fn eval_modules() {
     test!(
-        ("[1, 2, 3][0]", Ok(Rc::new(Object::Integer(1)))),
-        ("[1, 2, 3][1]", Ok(Rc::new(Object::Integer(2)))),
-        ("[1, 2, 3][2]", Ok(Rc::new(Object::Integer(3)))),
-        ("let i = 0; [1][i];", Ok(Rc::new(Object::Integer(1)))),
-        ("[1, 2, 3][1 + 1];", Ok(Rc::new(Object::Integer(3)))),
+        ("math.abs(-3)", int(3)),
+        ("math.abs(4)", int(4)),
+        ("math.abs(-9223372036854775807 - 1) - 1", int(i64::MAX)),
+        ("math.min(3, -1, 2) + math.max(3, 5, 4)", int(4)),
         (
-            "let myArray = [1, 2, 3]; myArray[2];",
-            Ok(Rc::new(Object::Integer(3)))
+            r#"str.upper("ab") + str.trim(" c ")"#,
+            Ok(Rc::new(Object::String("ABc".into())))
         ),
+        ("let m = math; m.max(1, 2)", int(2)),
+        ("len(math)", int(7)),
+        (r#"let h = {"k": 1}; h.k"#, int(1)),
+        ("let math = 5; math", int(5)),
         (
-            "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];",
-            Ok(Rc::new(Object::Integer(6)))
+            "math.bogus",
+            Err(InterpretError::Runtime("unknown member: math.bogus".into()))
         ),
         (
-            "let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]",
-            Ok(Rc::new(Object::Integer(2)))
+            "math.min()",
+            Err(InterpretError::WrongArity(
+                "math.min expects at least 1 argument, got 0".into()
+            ))
+        ),
+        (
+            r#"math.abs("a")"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `math.abs` not supported, got STRING".into()
+            ))
+        ),
+        (
+            "let x = 1; x.y",
+            Err(InterpretError::TypeMismatch(
+                "member access not supported: INTEGER".into()
+            ))
+        ),
+        (
+            "abs(1)",
+            Err(InterpretError::Undefined(
+                "identifier not found: abs".into()
+            ))
         ),
-        ("[1, 2, 3][3]", Ok(Rc::new(Object::Null))),
-        ("[1, 2, 3][-1]", Ok(Rc::new(Object::Null))),
     )
 }
 
 #[test]
-fn hash_literal() {
-    test!((
-        r#"
-    let two = "two";
-    {
-        "one": 10 - 9,
-        two: 1 + 1,
-        "thr" + "ee": 6 / 2,
-        4: 4,
-        true: 5,
-        false: 6
-    }
-    "#,
-        Ok(Rc::new(Object::Hash(HashObj {
-            map: HashMap::from([
-                (
-                    Rc::new(Object::String("one".into())),
-                    Rc::new(Object::Integer(1))
-                ),
-                (
-                    Rc::new(Object::String("two".into())),
-                    Rc::new(Object::Integer(2))
-                ),
-                (
-                    Rc::new(Object::String("three".into())),
-                    Rc::new(Object::Integer(3))
-                ),
-                (Rc::new(Object::Integer(4)), Rc::new(Object::Integer(4))),
-                (Rc::new(Object::Bool(true)), Rc::new(Object::Integer(5))),
-                (Rc::new(Object::Bool(false)), Rc::new(Object::Integer(6))),
-            ])
-        })))
-    ))
-}
-
-#[test]
-fn index_hash() {
+fn eval_radix_literals() {
     test!(
-        (r#"{"foo": 5}["foo"]"#, Ok(Rc::new(Object::Integer(5)))),
-        (r#"{"foo": 5}["bar"]"#, Ok(Rc::new(Object::Null))),
-        (
-            r#"let key = "foo"; {"foo": 5}[key]"#,
-            Ok(Rc::new(Object::Integer(5)))
-        ),
-        (r#"{}["foo"]"#, Ok(Rc::new(Object::Null))),
-        (r#"{5: 5}[5]"#, Ok(Rc::new(Object::Integer(5)))),
-        (r#"{true: 5}[true]"#, Ok(Rc::new(Object::Integer(5)))),
-        (r#"{false: 5}[false]"#, Ok(Rc::new(Object::Integer(5)))),
+        ("0xff + 0o10 + 0b11", Ok(Rc::new(Object::Integer(266)))),
+        ("-0x10", Ok(Rc::new(Object::Integer(-16)))),
+        ("1_000 * 1_000", Ok(Rc::new(Object::Integer(1_000_000)))),
+        ("0b1 << 4 == 0x10", Ok(Rc::new(Object::Bool(true)))),
     )
 }
 
 #[test]
-fn builtin_len() {
+fn eval_floor_div() {
     test!(
-        (r#"len("")"#, Ok(Rc::new(Object::Integer(0)))),
-        (r#"len("four")"#, Ok(Rc::new(Object::Integer(4)))),
-        (r#"len("hello world")"#, Ok(Rc::new(Object::Integer(11)))),
+        ("7 ~/ 2", Ok(Rc::new(Object::Integer(3)))),
+        ("-7 ~/ 2", Ok(Rc::new(Object::Integer(-4)))),
+        ("7 ~/ -2", Ok(Rc::new(Object::Integer(-4)))),
+        ("-7 ~/ -2", Ok(Rc::new(Object::Integer(3)))),
+        ("-8 ~/ 2", Ok(Rc::new(Object::Integer(-4)))),
+        ("-7 / 2", Ok(Rc::new(Object::Integer(-3)))),
+        ("1 + 7 ~/ 2 * 2", Ok(Rc::new(Object::Integer(7)))),
         (
-            r#"len(1)"#,
-            Err("argument to `len` not supported, got INTEGER".into())
+            "(-9223372036854775807 - 1) ~/ -1",
+            Ok(Rc::new(BigInt::from(2).pow(63).into()))
         ),
         (
-            r#"len("one", "two")"#,
-            Err("wrong number of arguments. expected 1, got 2".into())
+            "7 ~/ 0",
+            Err(InterpretError::DivByZero("division by zero".into()))
         ),
-        (r#"len([1, 2, 3, 4])"#, Ok(Rc::new(Object::Integer(4)))),
     )
 }
 
 #[test]
-fn builtin_first() {
+fn eval_mod() {
     test!(
+        ("7 % 3", Ok(Rc::new(Object::Integer(1)))),
+        ("-7 % 2", Ok(Rc::new(Object::Integer(-1)))),
+        ("7 % -2", Ok(Rc::new(Object::Integer(1)))),
+        ("10 % 5 == 0", Ok(Rc::new(Object::Bool(true)))),
+        ("1 + 10 % 4 * 2", Ok(Rc::new(Object::Integer(5)))),
         (
-            r#"first(["a", "b"])"#,
-            Ok(Rc::new(Object::String("a".into())))
+            "(-9223372036854775807 - 1) % -1",
+            Ok(Rc::new(Object::Integer(0)))
         ),
-        (r#"first([])"#, Ok(Rc::new(Object::Null))),
+        ("2 ** 64 % 10", Ok(Rc::new(Object::Integer(6)))),
+        ("-(2 ** 64) % 10", Ok(Rc::new(Object::Integer(-6)))),
+        ("7.5 % 2", Ok(Rc::new(Object::Float(1.5)))),
         (
-            r#"first(1)"#,
-            Err("argument to `first` not supported, got INTEGER".into())
+            "7 % 0",
+            Err(InterpretError::DivByZero("modulo by zero".into()))
         ),
         (
-            r#"first("one", "two")"#,
-            Err("wrong number of arguments. expected 1, got 2".into())
+            "2 ** 64 % 0",
+            Err(InterpretError::DivByZero("modulo by zero".into()))
+        ),
+        (
+            "1.5 % 0",
+            Err(InterpretError::DivByZero("modulo by zero".into()))
         ),
     )
 }
 
 #[test]
-fn builtin_last() {
+fn eval_block_scope() {
     test!(
         (
-            r#"last(["a", "b"])"#,
-            Ok(Rc::new(Object::String("b".into())))
+            "if (true) { let x = 1; }; x",
+            Err(InterpretError::Undefined("identifier not found: x".into()))
         ),
-        (r#"last([])"#, Ok(Rc::new(Object::Null))),
         (
-            r#"last(1)"#,
-            Err("argument to `last` not supported, got INTEGER".into())
+            "let x = 1; if (true) { let x = 2; }; x",
+            Ok(Rc::new(Object::Integer(1)))
         ),
         (
-            r#"last("one", "two")"#,
-            Err("wrong number of arguments. expected 1, got 2".into())
+            "let x = 1; if (true) { [x] = [2]; }; x",
+            Ok(Rc::new(Object::Integer(2)))
+        ),
+        (
+            "let f = if (true) { let y = 5; fn() { y } }; f()",
+            Ok(Rc::new(Object::Integer(5)))
         ),
     )
 }
 
 #[test]
-fn builtin_rest() {
+fn eval_return() {
     test!(
+        ("return 10;", Ok(Rc::new(Object::Integer(10)))),
+        ("return 10; 9;", Ok(Rc::new(Object::Integer(10)))),
+        ("return 2 * 5; 9;", Ok(Rc::new(Object::Integer(10)))),
+        ("9; return 2 * 5; 9;", Ok(Rc::new(Object::Integer(10)))),
         (
-            r#"rest(["a", "b", "c"])"#,
-            Ok(Rc::new(Object::Array(ArrayObj {
-                elements: vec![
+            r#"if (10 > 1) {
+                if (10 > 1) {
+                 return 10;
+                }
+                return 1;
+            }"#,
+            Ok(Rc::new(Object::Integer(10)))
+        )
+    )
+}
+
+#[test]
+fn error_handling() {
+    test!(
+        (
+            "5 + true;",
+            Err(InterpretError::TypeMismatch(
+                "type mismatch: INTEGER + BOOL".into()
+            ))
+        ),
+        (
+            "5 + true; 5;",
+            Err(InterpretError::TypeMismatch(
+                "type mismatch: INTEGER + BOOL".into()
+            ))
+        ),
+        (
+            "-true",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: -BOOL".into()
+            ))
+        ),
+        (
+            "true + false;",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: BOOL + BOOL".into()
+            ))
+        ),
+        (
+            "5; true + false; 5",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: BOOL + BOOL".into()
+            ))
+        ),
+        (
+            "if (10 > 1) { true + false; }",
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: BOOL + BOOL".into()
+            )),
+        ),
+        (
+            r#"if (10 > 1) {
+                if (10 > 1) {
+                 return true + false;
+                }
+                return 1;
+            }"#,
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: BOOL + BOOL".into()
+            )),
+        ),
+        (
+            "baz",
+            Err(InterpretError::Undefined(
+                "identifier not found: baz".into()
+            ))
+        ),
+        (
+            r#" "hello" - "world" "#,
+            Err(InterpretError::TypeMismatch(
+                "unknown operator: STRING - STRING".into()
+            ))
+        ),
+        (
+            r#"{"name": "Monkey"}[fn(x) { x }];"#,
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: FUNCTION".into()
+            )),
+        )
+    )
+}
+
+#[test]
+fn eval_default_params() {
+    test!(
+        (
+            "let f = fn(x, y = 10) { x + y }; f(1)",
+            Ok(Rc::new(Object::Integer(11)))
+        ),
+        (
+            "let f = fn(x, y = 10) { x + y }; f(1, 2)",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let f = fn(x, y = x * 2, z = y + 1) { [x, y, z] }; f(1)[2]",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let n = 5; let f = fn(x = n) { x }; let g = fn() { let n = 1; f() }; g()",
+            Ok(Rc::new(Object::Integer(5)))
+        ),
+        (
+            "fn(x, y = 1) { x }()",
+            Err(InterpretError::WrongArity(
+                "function expects 1 or 2 arguments, got 0".into()
+            ))
+        ),
+        (
+            "fn(x, y = 1) { x }(1, 2, 3)",
+            Err(InterpretError::WrongArity(
+                "function expects 1 or 2 arguments, got 3".into()
+            ))
+        ),
+    );
+
+    // Defaults count toward the depth of the call they belong to
+    assert_eq!(
+        eval_with_max_depth("let f = fn(x, y = f(x)) { x }; f(1)", 20),
+        Err(InterpretError::StackOverflow(
+            "maximum recursion depth exceeded".into()
+        ))
+    );
+}
+
+#[test]
+fn eval_rest_params() {
+    test!(
+        ("fn(first, ...rest) { rest }(1, 2, 3)", ints(&[2, 3])),
+        ("fn(first, ...rest) { rest }(1)", ints(&[])),
+        ("fn(...all) { all }()", ints(&[])),
+        ("fn(a, b = 5, ...rest) { push(rest, b) }(1)", ints(&[5])),
+        (
+            "fn(a, b = 5, ...rest) { push(rest, b) }(1, 2, 3)",
+            ints(&[3, 2])
+        ),
+        (
+            "fn(a, ...rest) { a }()",
+            Err(InterpretError::WrongArity(
+                "function expects at least 1 argument, got 0".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn error_variants() {
+    let program = Parser::new(Lexer::new("10 / (5 - 5)".to_string()))
+        .parse()
+        .unwrap();
+    let res = eval_program(program, &Environment::new());
+    assert!(matches!(res, Err(InterpretError::DivByZero(_))));
+    assert_eq!(res.unwrap_err().to_string(), "division by zero");
+
+    test!(
+        (
+            "len(1, 2)",
+            Err(InterpretError::arity("len", 1, Some(1), 2))
+        ),
+        (
+            "fn(x) { x }()",
+            Err(InterpretError::WrongArity(
+                "function expects 1 argument, got 0".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn let_array() {
+    test!(
+        (
+            "let [a, b, c] = [1, 2, 3]; a + b * c",
+            Ok(Rc::new(Object::Integer(7)))
+        ),
+        (
+            "let [head, ...tail] = [1, 2, 3]; tail",
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::Integer(2)), Rc::new(Object::Integer(3))]
+            })))
+        ),
+        (
+            "let [x, ...xs] = [1]; len(xs) + x",
+            Ok(Rc::new(Object::Integer(1)))
+        ),
+        (
+            "let f = fn(p) { let [x, y] = p; x - y }; f([5, 2])",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let [a] = 1;",
+            Err(InterpretError::TypeMismatch(
+                "cannot destructure INTEGER".into()
+            ))
+        ),
+        (
+            "let [a, b] = [1, 2, 3];",
+            Err(InterpretError::WrongArity(
+                "cannot destructure array of length 3 into 2 names".into()
+            ))
+        ),
+        (
+            "let [a, b, ...c] = [1];",
+            Err(InterpretError::WrongArity(
+                "cannot destructure array of length 1 into at least 2 names".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_assign() {
+    test!(
+        ("let a = 1; a = a + 1; a", Ok(Rc::new(Object::Integer(2)))),
+        (
+            "let a = 1; let f = fn() { a = 5; }; f(); a",
+            Ok(Rc::new(Object::Integer(5)))
+        ),
+        (
+            "let a = 1; let f = fn(a) { a = 5; a }; f(2) * 10 + a",
+            Ok(Rc::new(Object::Integer(51)))
+        ),
+        (
+            "let i = 0; while (i < 3) { i = i + 1; } i",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let a = 10; a += 5; a -= 1; a *= 2 + 1; a /= 2; a",
+            Ok(Rc::new(Object::Integer(21)))
+        ),
+        (
+            "let s = \"a\"; s += \"b\"; s",
+            Ok(Rc::new(Object::from("ab")))
+        ),
+        (
+            "b += 1;",
+            Err(InterpretError::Undefined("identifier not found: b".into()))
+        ),
+        (
+            "b = 1;",
+            Err(InterpretError::Undefined("identifier not found: b".into()))
+        ),
+        (
+            "const PI = 3; PI = 4;",
+            Err(InterpretError::Runtime(
+                "cannot reassign constant: PI".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn assign_array() {
+    test!(
+        (
+            "let a = 1; let b = 2; [a, b] = [b, a]; a * 10 + b",
+            Ok(Rc::new(Object::Integer(21)))
+        ),
+        (
+            "let a = 1; let b = 2; let c = 3; [a, b, c] = [b, c, a]; a * 100 + b * 10 + c",
+            Ok(Rc::new(Object::Integer(231)))
+        ),
+        (
+            "let a = 1; let b = 2; let f = fn() { [a, b] = [b, a]; }; f(); a",
+            Ok(Rc::new(Object::Integer(2)))
+        ),
+        (
+            "let a = 1; [a, b] = [1, 2];",
+            Err(InterpretError::Undefined("identifier not found: b".into()))
+        ),
+        (
+            "let a = 1; let b = 2; [a, b] = [1, 2, 3];",
+            Err(InterpretError::WrongArity(
+                "cannot destructure array of length 3 into 2 names".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_assign_index() {
+    test!(
+        (
+            "let a = [1, 2, 3]; a[0] = 10; a[-1] = 30; a[0] + a[1] + a[2]",
+            Ok(Rc::new(Object::Integer(42)))
+        ),
+        (
+            "let a = [1, 2]; let b = a; a[0] = 5; b[0]",
+            Ok(Rc::new(Object::Integer(1)))
+        ),
+        (
+            "let h = {\"a\": 1}; h[\"a\"] = 2; h[\"b\"] = 3; h[\"a\"] * 10 + h[\"b\"]",
+            Ok(Rc::new(Object::Integer(23)))
+        ),
+        (
+            "let m = [[1, 2], {true: [3]}]; m[0][1] = 4; m[1][true][0] = 5; m[0][1] + m[1][true][0]",
+            Ok(Rc::new(Object::Integer(9)))
+        ),
+        (
+            "let a = [0, 0]; let f = fn(i) { a[i] = i + 1; }; f(0); f(1); a[0] + a[1]",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let a = [[1], 2]; let b = a[0]; a[0][0] = 3; b[0] * 10 + a[0][0]",
+            Ok(Rc::new(Object::Integer(13)))
+        ),
+        (
+            "let h = {}; h[\"a\"][0] = 1;",
+            Err(InterpretError::TypeMismatch(
+                "index assignment not supported: NULL".into()
+            ))
+        ),
+        (
+            "let a = [1]; a[1] = 2;",
+            Err(InterpretError::Runtime(
+                "index out of range: 1 for array of length 1".into()
+            ))
+        ),
+        (
+            "let a = [1]; a[\"x\"] = 2;",
+            Err(InterpretError::TypeMismatch(
+                "array index must be INTEGER, got STRING".into()
+            ))
+        ),
+        (
+            "let h = {}; h[[1]] = 2;",
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: ARRAY".into()
+            ))
+        ),
+        (
+            "let s = \"ab\"; s[0] = \"c\";",
+            Err(InterpretError::TypeMismatch(
+                "index assignment not supported: STRING".into()
+            ))
+        ),
+        (
+            "b[0] = 1;",
+            Err(InterpretError::Undefined("identifier not found: b".into()))
+        ),
+        (
+            "const A = [1]; A[0] = 2;",
+            Err(InterpretError::Runtime("cannot reassign constant: A".into()))
+        ),
+    )
+}
+
+#[test]
+fn eval_const() {
+    test!(
+        ("const PI = 3; PI * 2", Ok(Rc::new(Object::Integer(6)))),
+        (
+            "const PI = 3; let f = fn() { let PI = 4; [PI] = [5]; PI }; f() + PI",
+            Ok(Rc::new(Object::Integer(8)))
+        ),
+        (
+            "const PI = 3; [PI] = [4];",
+            Err(InterpretError::Runtime(
+                "cannot reassign constant: PI".into()
+            ))
+        ),
+        (
+            "const PI = 3; let f = fn() { [PI] = [4]; }; f()",
+            Err(InterpretError::Runtime(
+                "cannot reassign constant: PI".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_while() {
+    test!(
+        (
+            "let i = 0; let s = 0; while (i < 5) { [i, s] = [i + 1, s + i]; } s",
+            Ok(Rc::new(Object::Integer(10)))
+        ),
+        (
+            "let i = 0; while (true) { [i] = [i + 1]; if (i == 3) { break; } } i",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let i = 0; let s = 0; while (i < 5) { [i] = [i + 1]; if (i == 2) { continue; }; [s] = [s + i]; } s",
+            Ok(Rc::new(Object::Integer(13)))
+        ),
+        (
+            "let f = fn() { let i = 0; while (true) { [i] = [i + 1]; if (i > 2) { return i * 10; } } }; f()",
+            Ok(Rc::new(Object::Integer(30)))
+        ),
+        (
+            "let i = 0; while (i < 10000) { [i] = [i + 1]; } i",
+            Ok(Rc::new(Object::Integer(10000)))
+        ),
+        ("while (false) { 1 }", Ok(Rc::new(Object::Null))),
+        (
+            "let f = fn() { break; }; while (true) { f() }",
+            Err(InterpretError::Runtime("break outside loop".into()))
+        ),
+    )
+}
+
+#[test]
+fn eval_for() {
+    test!(
+        (
+            "let s = 0; for (x in [1, 2, 3]) { [s] = [s + x]; } s",
+            Ok(Rc::new(Object::Integer(6)))
+        ),
+        (
+            "let s = 0; for (x in range(10)) { if (x == 2) { continue; }; if (x == 5) { break; }; [s] = [s + x]; } s",
+            Ok(Rc::new(Object::Integer(8)))
+        ),
+        (
+            "let f = fn(xs) { for (x in xs) { if (x > 1) { return x; } } }; f([1, 3, 2])",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let x = 1; for (x in [2]) {} x",
+            Ok(Rc::new(Object::Integer(1)))
+        ),
+        ("for (x in []) { x }", Ok(Rc::new(Object::Null))),
+        (
+            "for (x in 5) {}",
+            Err(InterpretError::TypeMismatch(
+                "cannot iterate over INTEGER".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_slice() {
+    test!(
+        ("[1, 2, 3, 4][1:3]", Ok(Rc::new(Object::from(vec![2, 3])))),
+        ("[1, 2, 3, 4][:2]", Ok(Rc::new(Object::from(vec![1, 2])))),
+        ("[1, 2, 3, 4][-2:]", Ok(Rc::new(Object::from(vec![3, 4])))),
+        ("[1, 2, 3, 4][1:-1]", Ok(Rc::new(Object::from(vec![2, 3])))),
+        ("[1, 2][-9:9]", Ok(Rc::new(Object::from(vec![1, 2])))),
+        ("[1, 2][2:1]", Ok(Rc::new(Object::from(Vec::<i64>::new())))),
+        (
+            "let a = [1, 2]; let b = a[:]; b == a",
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (r#""hello"[1:3]"#, Ok(Rc::new(Object::from("el")))),
+        (r#""héllo"[2:]"#, Ok(Rc::new(Object::from("llo")))),
+        (r#""abc"[:-1]"#, Ok(Rc::new(Object::from("ab")))),
+        (
+            "(0..10)[2:-3]",
+            Ok(Rc::new(Object::Range(RangeObj { start: 2, end: 7 })))
+        ),
+        (
+            "let n = null; [1, 2, 3][n:n]",
+            Ok(Rc::new(Object::from(vec![1, 2, 3])))
+        ),
+        (
+            "5[1:]",
+            Err(InterpretError::TypeMismatch(
+                "slice operator not supported: INTEGER".into()
+            ))
+        ),
+        (
+            r#"[1]["a":]"#,
+            Err(InterpretError::TypeMismatch(
+                "slice bounds must be INTEGER, got STRING".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_range() {
+    let range = |start, end| Ok(Rc::new(Object::Range(RangeObj { start, end })));
+    test!(
+        ("1..4", range(1, 4)),
+        ("let n = 3; 0..=n", range(0, 4)),
+        ("(2..5)[1]", Ok(Rc::new(Object::Integer(3)))),
+        ("(2..5)[3]", Ok(Rc::new(Object::Null))),
+        ("len(2..5) + len(5..2)", Ok(Rc::new(Object::Integer(3)))),
+        (
+            "len(-9223372036854775807 - 1..9223372036854775807)",
+            Ok(Rc::new(
+                BigInt::from(2).pow(64).sub(&BigInt::from(1)).into()
+            ))
+        ),
+        (
+            "let s = 0; for (x in 1..=4) { s += x; } s",
+            Ok(Rc::new(Object::Integer(10)))
+        ),
+        ("to_array(-1..2)", Ok(Rc::new(Object::from(vec![-1, 0, 1])))),
+        (
+            "to_array(3..1)",
+            Ok(Rc::new(Object::from(Vec::<i64>::new())))
+        ),
+        ("1..3 == 1..=2", Ok(Rc::new(Object::Bool(true)))),
+        (
+            r#"1.."a""#,
+            Err(InterpretError::TypeMismatch(
+                "range bounds must be INTEGER, got INTEGER and STRING".into()
+            ))
+        ),
+        (
+            "0..=9223372036854775807",
+            Err(InterpretError::Runtime(
+                "range end too large: 9223372036854775807".into()
+            ))
+        ),
+        (
+            "to_array(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `to_array` not supported, got INTEGER".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_nested_break_continue() {
+    test!(
+        (
+            "let s = 0; for (a in range(10)) { if (a == 1) { continue; }; let b = 0; while (true) { b += 1; if (b > a) { break; }; s += 10 * a + b; } if (a == 3) { break; } } s",
+            Ok(Rc::new(Object::Integer(139)))
+        ),
+        (
+            "let n = 0; let i = 0; while (i < 3) { i += 1; for (x in range(5)) { if (x % 2 == 0) { continue; }; n += x; } } n",
+            Ok(Rc::new(Object::Integer(12)))
+        ),
+    )
+}
+
+#[test]
+fn loop_control_in_expressions() {
+    test!(
+        (
+            "let r = []; for (i in 0..3) { let y = if (i == 1) { break; }; r = push(r, y) } r",
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::Null)],
+            })))
+        ),
+        (
+            "let r = []; for (i in 0..3) { r = push(r, [i, { break; }]) } r",
+            ints(&[])
+        ),
+        (
+            "let r = []; for (i in 0..3) { const y = if (i == 1) { continue; } else { i }; r = push(r, y) } r",
+            ints(&[0, 2])
+        ),
+        (
+            "let r = []; let y = 0; for (i in 0..3) { y = if (i == 1) { break; } else { i }; r = push(r, y) } r",
+            ints(&[0])
+        ),
+        (
+            "let r = []; for (i in 0..3) { r = push(r, i + { if (i == 1) { continue; }; 1 }) } r",
+            ints(&[1, 3])
+        ),
+        (
+            "let r = []; for (i in 0..3) { let [a, b] = [i, { if (i == 1) { continue; }; i }]; r = push(r, a) } r",
+            ints(&[0, 2])
+        ),
+        (
+            "let f = fn() { let y = if (true) { return 5; }; 10 }; f()",
+            int(5)
+        ),
+        ("let f = fn(x = { return 1; }) { 2 }; f()", int(1)),
+        (
+            "let x = if (true) { break; };",
+            Err(InterpretError::Runtime("break outside loop".into()))
+        ),
+    )
+}
+
+#[test]
+fn builtin_range() {
+    test!(
+        ("range(3)", Ok(Rc::new(Object::from(vec![0, 1, 2])))),
+        ("range(-1, 2)", Ok(Rc::new(Object::from(vec![-1, 0, 1])))),
+        ("range(2, 1)", Ok(Rc::new(Object::from(Vec::<i64>::new())))),
+        (
+            "range(0, 1 << 40)",
+            Err(InterpretError::Runtime("allocation limit exceeded".into()))
+        ),
+        (
+            "range(\"a\")",
+            Err(InterpretError::TypeMismatch(
+                "argument to `range` not supported, got STRING".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn loop_control_outside_loop() {
+    test!(
+        (
+            "break;",
+            Err(InterpretError::Runtime("break outside loop".into()))
+        ),
+        (
+            "1; continue; 2",
+            Err(InterpretError::Runtime("continue outside loop".into()))
+        ),
+        (
+            "if (true) { break; }",
+            Err(InterpretError::Runtime("break outside loop".into()))
+        ),
+        (
+            "let f = fn() { continue; }; f();",
+            Err(InterpretError::Runtime("continue outside loop".into()))
+        ),
+    )
+}
+
+#[test]
+fn eval_let() {
+    test!(
+        ("let a = 5; a;", Ok(Rc::new(Object::Integer(5)))),
+        ("let a = 5 * 5; a;", Ok(Rc::new(Object::Integer(25)))),
+        ("let a = 5; let b = a; b;", Ok(Rc::new(Object::Integer(5)))),
+        (
+            "let a = 5; let b = a; let c = a + b + 5; c;",
+            Ok(Rc::new(Object::Integer(15)))
+        ),
+    )
+}
+
+#[test]
+fn eval_unicode_ident() {
+    test!(
+        ("let café = 5; café", Ok(Rc::new(Object::Integer(5)))),
+        (
+            "let 变量 = 2; let μέτρο = 变量 * 3; μέτρο + 变量",
+            Ok(Rc::new(Object::Integer(8)))
+        ),
+    )
+}
+
+#[test]
+fn eval_func() {
+    test!(
+        (
+            "let identity = fn(x) { x; }; identity(5);",
+            Ok(Rc::new(Object::Integer(5)))
+        ),
+        (
+            "let identity = fn(x) { return x; }; identity(5);",
+            Ok(Rc::new(Object::Integer(5)))
+        ),
+        (
+            "let double = fn(x) { x * 2; }; double(5);",
+            Ok(Rc::new(Object::Integer(10)))
+        ),
+        (
+            "let add = fn(x, y) { x + y; }; add(5, 5);",
+            Ok(Rc::new(Object::Integer(10)))
+        ),
+        (
+            "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+            Ok(Rc::new(Object::Integer(20)))
+        ),
+        ("fn(x) { x; }(5)", Ok(Rc::new(Object::Integer(5)))),
+        (
+            "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1)} }; fact(5)",
+            Ok(Rc::new(Object::Integer(120)))
+        )
+    )
+}
+
+#[test]
+fn array_literal() {
+    test!((
+        "[1, 2 * 2, 3 + 3]",
+        Ok(Rc::new(Object::Array(ArrayObj {
+            elements: vec![
+                Rc::new(Object::Integer(1)),
+                Rc::new(Object::Integer(4)),
+                Rc::new(Object::Integer(6))
+            ]
+        })))
+    ))
+}
+
+#[test]
+fn index_arr() {
+    test!(
+        ("[1, 2, 3][0]", Ok(Rc::new(Object::Integer(1)))),
+        ("[1, 2, 3][1]", Ok(Rc::new(Object::Integer(2)))),
+        ("[1, 2, 3][2]", Ok(Rc::new(Object::Integer(3)))),
+        ("let i = 0; [1][i];", Ok(Rc::new(Object::Integer(1)))),
+        ("[1, 2, 3][1 + 1];", Ok(Rc::new(Object::Integer(3)))),
+        (
+            "let myArray = [1, 2, 3]; myArray[2];",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];",
+            Ok(Rc::new(Object::Integer(6)))
+        ),
+        (
+            "let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]",
+            Ok(Rc::new(Object::Integer(2)))
+        ),
+        ("[1, 2, 3][3]", Ok(Rc::new(Object::Null))),
+        ("[1, 2, 3][-1]", Ok(Rc::new(Object::Integer(3)))),
+        ("[1, 2, 3][-3]", Ok(Rc::new(Object::Integer(1)))),
+        (
+            "let a = [1, 2]; a[len(a) - 1] == a[-1]",
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        ("(1..=5)[-2]", Ok(Rc::new(Object::Integer(4)))),
+        (
+            "[1, 2, 3][-4]",
+            Err(InterpretError::Runtime(
+                "index out of range: -4 for array of length 3".into()
+            ))
+        ),
+        (
+            "[][-1]",
+            Err(InterpretError::Runtime(
+                "index out of range: -1 for array of length 0".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn hash_literal() {
+    test!((
+        r#"
+    let two = "two";
+    {
+        "one": 10 - 9,
+        two: 1 + 1,
+        "thr" + "ee": 6 / 2,
+        4: 4,
+        true: 5,
+        false: 6
+    }
+    "#,
+        Ok(Rc::new(Object::Hash(HashObj::from_iter([
+            (
+                Rc::new(Object::String("one".into())),
+                Rc::new(Object::Integer(1))
+            ),
+            (
+                Rc::new(Object::String("two".into())),
+                Rc::new(Object::Integer(2))
+            ),
+            (
+                Rc::new(Object::String("three".into())),
+                Rc::new(Object::Integer(3))
+            ),
+            (Rc::new(Object::Integer(4)), Rc::new(Object::Integer(4))),
+            (Rc::new(Object::Bool(true)), Rc::new(Object::Integer(5))),
+            (Rc::new(Object::Bool(false)), Rc::new(Object::Integer(6))),
+        ]))))
+    ))
+}
+
+#[test]
+fn hash_shorthand() {
+    let hash = |pairs: &[(&str, i64)]| {
+        Ok(Rc::new(Object::Hash(HashObj::from_iter(pairs.iter().map(
+            |&(k, v)| (Rc::new(Object::from(k)), Rc::new(Object::Integer(v))),
+        )))))
+    };
+    test!(
+        ("let x = 1; {x}", hash(&[("x", 1)])),
+        (
+            "let x = 1; let y = 2; {x, y, \"z\": 3}",
+            hash(&[("x", 1), ("y", 2), ("z", 3)])
+        ),
+        ("fn(a) { {a} }(5)", hash(&[("a", 5)])),
+        (
+            "{nope}",
+            Err(InterpretError::Undefined(
+                "identifier not found: nope".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn hash_order() {
+    test!(
+        (
+            "keys({3: 1, 1: 2, 2: 3})",
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Integer(3)),
+                    Rc::new(Object::Integer(1)),
+                    Rc::new(Object::Integer(2)),
+                ]
+            })))
+        ),
+        (
+            "values({3: 1, 1: 2, 2: 3, 3: 4})",
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Integer(4)),
+                    Rc::new(Object::Integer(2)),
+                    Rc::new(Object::Integer(3)),
+                ]
+            })))
+        ),
+        (
+            "keys(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `keys` not supported, got INTEGER".into()
+            ))
+        ),
+    );
+
+    let program = Parser::new(Lexer::new(r#"{"b": 1, "a": 2, "c": 3}"#.into()))
+        .parse()
+        .unwrap();
+    let res = eval_program(program, &Environment::new()).unwrap();
+    assert_eq!(res.to_string(), r#"{"b": 1, "a": 2, "c": 3}"#);
+}
+
+#[test]
+fn builtin_entries() {
+    let pair = |k: &str, v: i64| {
+        Rc::new(Object::Array(ArrayObj {
+            elements: vec![
+                Rc::new(Object::String(k.into())),
+                Rc::new(Object::Integer(v)),
+            ],
+        }))
+    };
+    test!(
+        (
+            r#"entries({"b": 1, "a": 2})"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![pair("b", 1), pair("a", 2)]
+            })))
+        ),
+        (
+            "entries({})",
+            Ok(Rc::new(Object::Array(ArrayObj { elements: vec![] })))
+        ),
+        (
+            "entries([1])",
+            Err(InterpretError::TypeMismatch(
+                "argument to `entries` not supported, got ARRAY".into()
+            ))
+        ),
+        (
+            "entries({}, {})",
+            Err(InterpretError::arity("entries", 1, Some(1), 2))
+        ),
+    );
+}
+
+#[test]
+fn builtin_map_values() {
+    test!(
+        (
+            r#"let n = 10; map_values({"b": 1, "a": 2}, fn(x) { x * n })"#,
+            Ok(Rc::new(Object::Hash(HashObj::from_iter([
+                (
+                    Rc::new(Object::String("b".into())),
+                    Rc::new(Object::Integer(10))
+                ),
+                (
+                    Rc::new(Object::String("a".into())),
+                    Rc::new(Object::Integer(20))
+                ),
+            ]))))
+        ),
+        (
+            r#"map_values({"a": [1, 2]}, len)["a"]"#,
+            Ok(Rc::new(Object::Integer(2)))
+        ),
+        (
+            "map_values({1: 1}, 2)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `map_values` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            "map_values([1], len)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `map_values` not supported, got ARRAY".into()
+            ))
+        ),
+        (
+            "map_values({1: 1}, fn(a, b) { a })",
+            Err(InterpretError::WrongArity(
+                "function expects 2 arguments, got 1".into()
+            ))
+        ),
+    );
+}
+
+#[test]
+fn builtin_delete() {
+    test!(
+        (
+            r#"let h = {"a": 1, "b": 2, "c": 3}; let d = delete(h, "b"); [keys(d), len(keys(h))]"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Array(ArrayObj {
+                        elements: vec![
+                            Rc::new(Object::String("a".into())),
+                            Rc::new(Object::String("c".into())),
+                        ]
+                    })),
+                    Rc::new(Object::Integer(3)),
+                ]
+            })))
+        ),
+        (
+            r#"let d = delete({"a": 1, "c": 3}, "c"); d["a"]"#,
+            Ok(Rc::new(Object::Integer(1)))
+        ),
+        (
+            r#"delete({"a": 1}, "b")"#,
+            Ok(Rc::new(Object::Hash(HashObj::from_iter([(
+                Rc::new(Object::String("a".into())),
+                Rc::new(Object::Integer(1))
+            )]))))
+        ),
+        (
+            "delete({}, [])",
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: ARRAY".into()
+            ))
+        ),
+        (
+            "delete([1], 0)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `delete` not supported, got ARRAY".into()
+            ))
+        ),
+        (
+            "delete({})",
+            Err(InterpretError::arity("delete", 2, Some(2), 1))
+        ),
+    );
+}
+
+#[test]
+fn builtin_rounding() {
+    let float = |x: f64| Ok(Rc::new(Object::Float(x)));
+    test!(
+        ("floor(3.7)", int(3)),
+        ("ceil(3.2)", int(4)),
+        ("round(2.5)", int(3)),
+        ("sqrt(9.0)", float(3.0)),
+        ("floor(-3.5)", int(-4)),
+        ("ceil(-3.5)", int(-3)),
+        ("round(-2.5)", int(-3)),
+        ("floor(7)", int(7)),
+        ("sqrt(2) * sqrt(2) > 1.99", Ok(Rc::new(Object::Bool(true)))),
+        ("math.floor(1.5) + math.sqrt(16)", float(5.0)),
+        (
+            "sqrt(-4.0)",
+            Err(InterpretError::Runtime(
+                "`sqrt` of a negative number: -4.0".into()
+            ))
+        ),
+        (
+            "floor(2.0 ** 70) == 2 ** 70",
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (
+            "ceil(-(2.0 ** 64)) == -(2 ** 64)",
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (
+            "floor(10000000000000000000000.5)",
+            Ok(Rc::new(Object::BigInt(BigInt::from(10_i64.pow(11)).pow(2))))
+        ),
+        (
+            "round(2.0 ** 2000)",
+            Err(InterpretError::Runtime(
+                "`round` of a non-finite number: inf".into()
+            ))
+        ),
+        (
+            r#"round("a")"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `round` not supported, got STRING".into()
+            ))
+        ),
+        (
+            "round(1, 2)",
+            Err(InterpretError::arity("round", 1, Some(1), 2))
+        ),
+    );
+}
+
+#[test]
+fn index_hash() {
+    test!(
+        (r#"{"foo": 5}["foo"]"#, Ok(Rc::new(Object::Integer(5)))),
+        (r#"{"foo": 5}["bar"]"#, Ok(Rc::new(Object::Null))),
+        (
+            r#"let key = "foo"; {"foo": 5}[key]"#,
+            Ok(Rc::new(Object::Integer(5)))
+        ),
+        (r#"{}["foo"]"#, Ok(Rc::new(Object::Null))),
+        (r#"{5: 5}[5]"#, Ok(Rc::new(Object::Integer(5)))),
+        (r#"{true: 5}[true]"#, Ok(Rc::new(Object::Integer(5)))),
+        (r#"{false: 5}[false]"#, Ok(Rc::new(Object::Integer(5)))),
+        (
+            "{1.5: 1}",
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: FLOAT".into()
+            ))
+        ),
+        (
+            "{[1]: 1}",
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: ARRAY".into()
+            ))
+        ),
+        // Floats are never keys, even when equal to one
+        (
+            "{1: 2}[1.0]",
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: FLOAT".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_len() {
+    test!(
+        (r#"len("")"#, Ok(Rc::new(Object::Integer(0)))),
+        (r#"len("four")"#, Ok(Rc::new(Object::Integer(4)))),
+        (r#"len("hello world")"#, Ok(Rc::new(Object::Integer(11)))),
+        (r#"len("héllo")"#, Ok(Rc::new(Object::Integer(5)))),
+        (
+            r#"let s = "héllo"; s[0:len(s) - 1]"#,
+            Ok(Rc::new(Object::from("héll")))
+        ),
+        (
+            r#"len(1)"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `len` not supported, got INTEGER, expected STRING, ARRAY, HASH or RANGE"
+                    .into()
+            ))
+        ),
+        (
+            "len(fn() {})",
+            Err(InterpretError::TypeMismatch(
+                "argument to `len` not supported, got FUNCTION, expected STRING, ARRAY, HASH or RANGE"
+                    .into()
+            ))
+        ),
+        (
+            r#"len("one", "two")"#,
+            Err(InterpretError::WrongArity(
+                "len expects 1 argument, got 2".into()
+            ))
+        ),
+        (r#"len([1, 2, 3, 4])"#, Ok(Rc::new(Object::Integer(4)))),
+        ("len({1: 2, 3: 4})", Ok(Rc::new(Object::Integer(2)))),
+        ("len({})", Ok(Rc::new(Object::Integer(0)))),
+    )
+}
+
+#[test]
+fn builtin_first() {
+    test!(
+        (
+            r#"first(["a", "b"])"#,
+            Ok(Rc::new(Object::String("a".into())))
+        ),
+        (r#"first([])"#, Ok(Rc::new(Object::Null))),
+        (
+            r#"first(1)"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `first` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            r#"first("one", "two")"#,
+            Err(InterpretError::WrongArity(
+                "first expects 1 argument, got 2".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_last() {
+    test!(
+        (
+            r#"last(["a", "b"])"#,
+            Ok(Rc::new(Object::String("b".into())))
+        ),
+        (r#"last([])"#, Ok(Rc::new(Object::Null))),
+        (
+            r#"last(1)"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `last` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            r#"last("one", "two")"#,
+            Err(InterpretError::WrongArity(
+                "last expects 1 argument, got 2".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_rest() {
+    test!(
+        (
+            r#"rest(["a", "b", "c"])"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("b".into())),
+                    Rc::new(Object::String("c".into()))
+                ]
+            })))
+        ),
+        (
+            r#"rest(["a"])"#,
+            Ok(Rc::new(Object::Array(ArrayObj { elements: vec![] })))
+        ),
+        (
+            r#"rest([])"#,
+            Ok(Rc::new(Object::Array(ArrayObj { elements: vec![] })))
+        ),
+        (
+            r#"rest(1)"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `rest` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            r#"rest("one", "two")"#,
+            Err(InterpretError::WrongArity(
+                "rest expects 1 argument, got 2".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_push() {
+    test!(
+        (
+            r#"push(["a", "b"], "c")"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("a".into())),
                     Rc::new(Object::String("b".into())),
                     Rc::new(Object::String("c".into()))
                 ]
             })))
         ),
         (
-            r#"rest(["a"])"#,
-            Ok(Rc::new(Object::Array(ArrayObj { elements: vec![] })))
+            r#"push(["a"], 1)"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("a".into())),
+                    Rc::new(Object::Integer(1))
+                ]
+            })))
+        ),
+        (
+            r#"push(["a"], [1])"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("a".into())),
+                    Rc::new(Object::Array(ArrayObj {
+                        elements: vec![Rc::new(Object::Integer(1))]
+                    }))
+                ]
+            })))
+        ),
+        (
+            r#"push([], "bar")"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::String("bar".into()))]
+            })))
+        ),
+        (
+            r#"push(1, 2)"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `push` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            r#"push([])"#,
+            Err(InterpretError::WrongArity(
+                "push expects 2 arguments, got 1".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_pop() {
+    test!(
+        ("pop([1, 2, 3])", ints(&[1, 2])),
+        ("pop([1])", ints(&[])),
+        ("pop([])", ints(&[])),
+        ("let a = [1, 2]; pop(a); a", ints(&[1, 2])),
+        (
+            "pop(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `pop` not supported, got INTEGER".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_set() {
+    test!(
+        ("set([1, 2, 3], 1, 5)", ints(&[1, 5, 3])),
+        ("let a = [1, 2]; set(a, 0, 9); a", ints(&[1, 2])),
+        (
+            "set([1, 2], 2, 5)",
+            Err(InterpretError::Runtime(
+                "index out of range: 2 for array of length 2".into()
+            ))
+        ),
+        ("set([1, 2, 3], -1, 9)", ints(&[1, 2, 9])),
+        (
+            "set([1, 2], -3, 5)",
+            Err(InterpretError::Runtime(
+                "index out of range: -3 for array of length 2".into()
+            ))
+        ),
+        (
+            r#"set([1], "0", 5)"#,
+            Err(InterpretError::TypeMismatch(
+                "array index must be INTEGER, got STRING".into()
+            ))
+        ),
+        (
+            "set([1], 0)",
+            Err(InterpretError::arity("set", 3, Some(3), 2))
+        ),
+    )
+}
+
+#[test]
+fn quote_unquote() {
+    let infix = |left, op, right| {
+        Expression::Infix(crate::ast::InfixExpr {
+            left: Box::new(left),
+            operator: op,
+            right: Box::new(right),
+        })
+    };
+
+    test!(
+        (
+            "quote(1 + 2)",
+            Ok(Rc::new(Object::Quote(infix(
+                Expression::Number(1),
+                TokenType::Plus,
+                Expression::Number(2)
+            ))))
+        ),
+        (
+            "quote(foobar)",
+            Ok(Rc::new(Object::Quote(Expression::Ident("foobar".into()))))
+        ),
+        (
+            "quote(unquote(4 + 4))",
+            Ok(Rc::new(Object::Quote(Expression::Number(8))))
+        ),
+        (
+            "let x = 8; quote(x + unquote(x * 2))",
+            Ok(Rc::new(Object::Quote(infix(
+                Expression::Ident("x".into()),
+                TokenType::Plus,
+                Expression::Number(16)
+            ))))
+        ),
+        (
+            "let q = quote(4 + 4); quote(unquote(q) * 2)",
+            Ok(Rc::new(Object::Quote(infix(
+                infix(
+                    Expression::Number(4),
+                    TokenType::Plus,
+                    Expression::Number(4)
+                ),
+                TokenType::Star,
+                Expression::Number(2)
+            ))))
+        ),
+        (
+            "quote(unquote(fn(x) { x }))",
+            Err(InterpretError::TypeMismatch(
+                "cannot unquote FUNCTION".into()
+            ))
+        ),
+        (
+            "quote(1, 2)",
+            Err(InterpretError::WrongArity(
+                "quote expects 1 argument, got 2".into()
+            ))
+        ),
+    );
+}
+
+#[test]
+fn macro_expansion() {
+    let cases = [
+        (
+            "let infixExpression = macro() { quote(1 + 2); }; infixExpression();",
+            "(1 + 2)\n",
+        ),
+        (
+            "let reverse = macro(a, b) { quote(unquote(b) - unquote(a)); }; reverse(2 + 2, 10 - 5);",
+            "((10 - 5) - (2 + 2))\n",
+        ),
+    ];
+
+    for (inp, exp) in cases {
+        let mut prog = Parser::new(Lexer::new(inp.into())).parse().unwrap();
+        let env = Environment::new();
+
+        define_macros(&mut prog, &env);
+        let prog = expand_macros(prog, &env).unwrap();
+        assert_eq!(prog.to_string(), exp);
+    }
+
+    let unless = r#"
+        let unless = macro(cond, cons, alt) {
+            quote(if (!(unquote(cond))) { unquote(cons); } else { unquote(alt); });
+        };
+        unless(10 > 5, "not greater", "greater");
+    "#;
+    let mut prog = Parser::new(Lexer::new(unless.into())).parse().unwrap();
+    let env = Environment::new();
+    define_macros(&mut prog, &env);
+    assert!(env.borrow().get(&"unless".to_string()).is_some());
+
+    let prog = expand_macros(prog, &env).unwrap();
+    assert_eq!(
+        eval_program(prog, &Environment::new()),
+        Ok(Rc::new(Object::String("greater".into())))
+    );
+
+    let mut prog = Parser::new(Lexer::new("let m = macro() { 1 }; m();".into()))
+        .parse()
+        .unwrap();
+    let env = Environment::new();
+    define_macros(&mut prog, &env);
+    assert_eq!(
+        expand_macros(prog, &env).map(|p| p.to_string()),
+        Err(InterpretError::TypeMismatch(
+            "macro must return QUOTE, got INTEGER".into()
+        ))
+    );
+}
+
+#[test]
+fn recursion_limit() {
+    // Deep enough recursion would overflow the default test thread stack
+    // before reaching the limit, so give it the size of a main thread
+    std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(|| {
+            test!(
+                (
+                    "let f = fn(x) { f(x + 1) }; f(0);",
+                    Err(InterpretError::StackOverflow(
+                        "maximum recursion depth exceeded".into()
+                    ))
+                ),
+                (
+                    "let f = fn(x) { if (x == 0) { 0 } else { 1 + f(x - 1) } }; f(100);",
+                    Ok(Rc::new(Object::Integer(100)))
+                ),
+            );
+
+            let countdown = "let f = fn(x) { if (x == 0) { 0 } else { 1 + f(x - 1) } }; f(10);";
+            assert_eq!(
+                eval_with_max_depth(countdown, 10),
+                Err(InterpretError::StackOverflow(
+                    "maximum recursion depth exceeded".into()
+                ))
+            );
+            // The limit belongs to the environment it was set on
+            test!((countdown, Ok(Rc::new(Object::Integer(10)))));
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+    // Calls made by builtins count for more, so recursing through them
+    // stops well before it overflows the test thread's stack
+    let limit = || {
+        Err(InterpretError::StackOverflow(
+            "maximum recursion depth exceeded".into(),
+        ))
+    };
+    test!(
+        (
+            r#"let f = fn(x) { map_values({"a": 1}, fn(v) { f(v) }) }; f(1)"#,
+            limit()
+        ),
+        ("let f = fn(x) { each([1], fn(v) { f(v) }) }; f(1)", limit()),
+        ("let f = fn(x) { try(f, x) }; f(1)", limit()),
+    );
+}
+
+#[test]
+fn builtin_chars() {
+    let strings = |s: &[&str]| {
+        Ok(Rc::new(Object::Array(ArrayObj {
+            elements: s
+                .iter()
+                .map(|c| Rc::new(Object::String(c.to_string())))
+                .collect(),
+        })))
+    };
+    test!(
+        (r#"chars("héllo")"#, strings(&["h", "é", "l", "l", "o"])),
+        (r#"len(chars("héllo"))"#, Ok(Rc::new(Object::Integer(5)))),
+        (
+            r#"chars("日本")[1]"#,
+            Ok(Rc::new(Object::String("本".into())))
+        ),
+        (r#"chars("")"#, strings(&[])),
+        (
+            "chars([1])",
+            Err(InterpretError::TypeMismatch(
+                "argument to `chars` not supported, got ARRAY".into()
+            ))
+        ),
+        (
+            r#"chars("a", "b")"#,
+            Err(InterpretError::arity("chars", 1, Some(1), 2))
+        ),
+    );
+}
+
+#[test]
+fn builtin_lines() {
+    let strings = |s: &[&str]| Ok(Rc::new(Object::from(s.to_vec())));
+    test!(
+        (r#"lines("a\nb\nc")"#, strings(&["a", "b", "c"])),
+        (r#"lines("a\nb\n")"#, strings(&["a", "b"])),
+        (r#"lines("a\n\nb\n\n")"#, strings(&["a", "", "b", ""])),
+        (r#"lines("a\r\nb")"#, strings(&["a", "b"])),
+        (r#"lines("\n")"#, strings(&[""])),
+        (r#"lines("")"#, strings(&[])),
+        (
+            r#"lines("""one
+two
+""")"#,
+            strings(&["one", "two"])
+        ),
+        (
+            "lines(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `lines` not supported, got INTEGER".into()
+            ))
+        ),
+    );
+}
+
+#[test]
+fn builtin_is_kind() {
+    let t = || Ok(Rc::new(Object::Bool(true)));
+    let f = || Ok(Rc::new(Object::Bool(false)));
+    test!(
+        ("is_int(1)", t()),
+        ("is_int(2 ** 100)", t()),
+        (r#"is_int("1")"#, f()),
+        (r#"is_string("a")"#, t()),
+        ("is_array([])", t()),
+        ("is_array({})", f()),
+        ("is_hash({})", t()),
+        ("is_fn(fn(x) { x })", t()),
+        ("is_fn(len)", t()),
+        ("is_fn(1)", f()),
+        ("is_null(if (false) { 1 })", t()),
+        ("is_null(0)", f()),
+        (
+            "is_int(1, 2)",
+            Err(InterpretError::arity("is_int", 1, Some(1), 2))
+        ),
+    );
+}
+
+#[test]
+fn builtin_string_case() {
+    test!(
+        (
+            r#"trim("  hi  ")"#,
+            Ok(Rc::new(Object::String("hi".into())))
+        ),
+        (
+            "trim(\"\thi there\n\")",
+            Ok(Rc::new(Object::String("hi there".into())))
+        ),
+        (r#"upper("abc")"#, Ok(Rc::new(Object::String("ABC".into())))),
+        (r#"lower("ABC")"#, Ok(Rc::new(Object::String("abc".into())))),
+        (
+            r#"upper(lower("MiXeD"))"#,
+            Ok(Rc::new(Object::String("MIXED".into())))
+        ),
+        (
+            "trim(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `trim` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            r#"upper("a", "b")"#,
+            Err(InterpretError::WrongArity(
+                "upper expects 1 argument, got 2".into()
+            ))
+        ),
+        (
+            "lower([])",
+            Err(InterpretError::TypeMismatch(
+                "argument to `lower` not supported, got ARRAY".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn eval_raw_string() {
+    test!(
+        (r#"r"\d+\n" + "\n""#, Ok(Rc::new(Object::from("\\d+\\n\n")))),
+        (r#"len(r"{x}")"#, Ok(Rc::new(Object::Integer(3)))),
+    )
+}
+
+#[test]
+fn eval_interpolation() {
+    test!(
+        (
+            r#"let x = 2; "value is ${x + 1}""#,
+            Ok(Rc::new(Object::from("value is 3")))
+        ),
+        (
+            r#"let xs = [1, "a"]; "${xs}: ${len(xs)} items""#,
+            Ok(Rc::new(Object::from("[1, \"a\"]: 2 items")))
+        ),
+        (
+            r#"let h = {"k": "v"}; "${h["k"]}${"${1}"}""#,
+            Ok(Rc::new(Object::from("v1")))
+        ),
+        (
+            r#""${1} {{}} { {} \${1} $${{"a": 2}["a"]}""#,
+            Ok(Rc::new(Object::from("1 {{}} { {} ${1} $2")))
+        ),
+        (
+            r#""${y}""#,
+            Err(InterpretError::Undefined("identifier not found: y".into()))
+        ),
+        // Interpolation doesn't go through whatever is bound to `format`
+        (
+            r#"let format = fn(a) { a }; let x = 1; "v=${x}""#,
+            Ok(Rc::new(Object::from("v=1")))
+        ),
+        (r#"let format = 1; "${1}""#, Ok(Rc::new(Object::from("1")))),
+    )
+}
+
+#[test]
+fn builtin_format() {
+    test!(
+        (
+            r#"format("{} + {} = {}", 1, 2, 3)"#,
+            Ok(Rc::new(Object::String("1 + 2 = 3".into())))
+        ),
+        (
+            r#"format("no placeholders")"#,
+            Ok(Rc::new(Object::String("no placeholders".into())))
+        ),
+        (
+            r#"format("{}: {}", "list", [1, true])"#,
+            Ok(Rc::new(Object::String("list: [1, true]".into())))
+        ),
+        (
+            r#"format("{{{}}} }}{{", 5)"#,
+            Ok(Rc::new(Object::String("{5} }{".into())))
+        ),
+        (
+            r#"format("{} {}", 1)"#,
+            Err(InterpretError::WrongArity(
+                "format string has 2 placeholders but 1 arguments were given".into()
+            ))
         ),
         (
-            r#"rest([])"#,
-            Ok(Rc::new(Object::Array(ArrayObj { elements: vec![] })))
+            r#"format("{}", 1, 2)"#,
+            Err(InterpretError::WrongArity(
+                "format string has 1 placeholders but 2 arguments were given".into()
+            ))
         ),
         (
-            r#"rest(1)"#,
-            Err("argument to `rest` not supported, got INTEGER".into())
+            r#"format("{ }", 1)"#,
+            Err(InterpretError::Runtime(
+                "unmatched `{` in format string".into()
+            ))
         ),
         (
-            r#"rest("one", "two")"#,
-            Err("wrong number of arguments. expected 1, got 2".into())
+            "format(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `format` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            "format()",
+            Err(InterpretError::WrongArity(
+                "format expects at least 1 argument, got 0".into()
+            ))
         ),
     )
 }
 
 #[test]
-fn builtin_push() {
+fn builtin_print() {
+    assert_eq!(
+        eval_with_output(r#"print("a", 1); print("b"); puts("c", 2); print("d")"#),
+        (Ok(Rc::new(Object::Null)), "a1bc\n2\nd".into())
+    );
+    // Code run by `parse` writes where its caller does
+    assert_eq!(
+        eval_with_output(r#"each(["print(1)"], parse)"#),
+        (Ok(Rc::new(Object::Null)), "1".into())
+    );
+}
+
+#[test]
+fn builtin_each() {
+    for (inp, out) in [
+        ("each([1, 2, 3], fn(x) { puts(x) })", "1\n2\n3\n"),
+        (r#"each({"a": 1, "b": 2}, fn(k, v) { print(k, v) })"#, "a1b2"),
+    ] {
+        assert_eq!(
+            eval_with_output(inp),
+            (Ok(Rc::new(Object::Null)), out.into())
+        );
+    }
+
     test!(
         (
-            r#"push(["a", "b"], "c")"#,
-            Ok(Rc::new(Object::Array(ArrayObj {
-                elements: vec![
-                    Rc::new(Object::String("a".into())),
-                    Rc::new(Object::String("b".into())),
-                    Rc::new(Object::String("c".into()))
-                ]
-            })))
+            "each([1], 2)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `each` not supported, got INTEGER".into()
+            ))
         ),
         (
-            r#"push(["a"], 1)"#,
-            Ok(Rc::new(Object::Array(ArrayObj {
-                elements: vec![
-                    Rc::new(Object::String("a".into())),
-                    Rc::new(Object::Integer(1))
-                ]
-            })))
+            "each(1, puts)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `each` not supported, got INTEGER".into()
+            ))
         ),
         (
-            r#"push(["a"], [1])"#,
-            Ok(Rc::new(Object::Array(ArrayObj {
-                elements: vec![
-                    Rc::new(Object::String("a".into())),
-                    Rc::new(Object::Array(ArrayObj {
-                        elements: vec![Rc::new(Object::Integer(1))]
-                    }))
-                ]
-            })))
+            "each({1: 2}, fn(x) { x })",
+            Err(InterpretError::WrongArity(
+                "function expects 1 argument, got 2".into()
+            ))
         ),
+    );
+}
+
+#[test]
+fn inspect_and_display() {
+    assert_eq!(
+        eval_with_output("puts(\"a\nb\"); puts([\"c\", 1]); puts({\"d\": \"e\"})"),
         (
-            r#"push([], "bar")"#,
-            Ok(Rc::new(Object::Array(ArrayObj {
-                elements: vec![Rc::new(Object::String("bar".into()))]
-            })))
+            Ok(Rc::new(Object::Null)),
+            "a\nb\n[\"c\", 1]\n{\"d\": \"e\"}\n".into()
+        )
+    );
+
+    let s = Object::String("a\nb \"q\" \\".into());
+    assert_eq!(s.to_string(), "a\nb \"q\" \\");
+    assert_eq!(s.inspect(), r#""a\nb \"q\" \\""#);
+    assert_eq!(Object::Integer(5).inspect(), "5");
+
+    let arr = Object::Array(ArrayObj {
+        elements: vec![Rc::new(s), Rc::new(Object::Null)],
+    });
+    assert_eq!(arr.to_string(), arr.inspect());
+    assert_eq!(arr.inspect(), r#"["a\nb \"q\" \\", null]"#);
+}
+
+#[test]
+fn evaluation_order() {
+    assert_eq!(
+        eval_with_output(
+            r#"let f = fn() { print("f"); 1 }; let g = fn() { print("g"); 2 }; f() < g()"#
         ),
+        (Ok(Rc::new(Object::Bool(true))), "fg".into())
+    );
+}
+
+#[test]
+fn builtin_assert() {
+    test!(
+        ("assert(1 < 2)", Ok(Rc::new(Object::Null))),
         (
-            r#"push(1, 2)"#,
-            Err("argument to `push` not supported, got INTEGER".into())
+            r#"assert(true, "unused"); 5"#,
+            Ok(Rc::new(Object::Integer(5)))
         ),
         (
-            r#"push([])"#,
-            Err("wrong number of arguments. expected 2, got 1".into())
+            "assert(1 > 2); 5",
+            Err(InterpretError::Runtime("assertion failed".into()))
+        ),
+        (
+            r#"let x = 3; assert(x == 4, format("x was {}", x))"#,
+            Err(InterpretError::Runtime("x was 3".into()))
+        ),
+        (
+            "assert()",
+            Err(InterpretError::arity("assert", 1, Some(2), 0))
         ),
     )
 }
 
+fn int(i: i64) -> EvalResult {
+    Ok(Rc::new(Object::Integer(i)))
+}
+
+fn ints(xs: &[i64]) -> EvalResult {
+    Ok(Rc::new(Object::Array(ArrayObj {
+        elements: xs.iter().map(|x| Rc::new(Object::Integer(*x))).collect(),
+    })))
+}
+
 fn test(cases: &[(&str, EvalResult)]) {
     for (inp, exp) in cases {
         let lexer = Lexer::new(inp.to_string());
@@ -464,3 +2372,443 @@ fn test(cases: &[(&str, EvalResult)]) {
         assert_eq!(&res, exp);
     }
 }
+
+/// Evaluates `inp`, also returning what it wrote with `puts` and `print`
+fn eval_with_output(inp: &str) -> (EvalResult, String) {
+    let prog = Parser::new(Lexer::new(inp.to_string()))
+        .parse()
+        .expect("Skill issue");
+    let out = Rc::new(RefCell::new(Vec::new()));
+    let env = Environment::new();
+    env.borrow().set_output(out.clone());
+    let res = eval_program(prog, &env);
+    (res, String::from_utf8(out.take()).unwrap())
+}
+
+/// Evaluates `inp` in an environment allowing `depth` nested calls
+fn eval_with_max_depth(inp: &str, depth: usize) -> EvalResult {
+    let prog = Parser::new(Lexer::new(inp.to_string()))
+        .parse()
+        .expect("Skill issue");
+    let env = Environment::new();
+    env.borrow().set_max_depth(depth);
+    eval_program(prog, &env)
+}
+
+/// Evaluates `inp` in an environment building nothing over `len` long
+fn eval_with_max_alloc(inp: &str, len: usize) -> EvalResult {
+    let prog = Parser::new(Lexer::new(inp.to_string()))
+        .parse()
+        .expect("Skill issue");
+    let env = Environment::new();
+    env.borrow().set_max_alloc(len);
+    eval_program(prog, &env)
+}
+
+#[test]
+fn shared_arrays_are_copied() {
+    let arr = |elems: Vec<Object>| Ok(Rc::new(Object::from(elems)));
+    test!(
+        (
+            "let a = [1]; let b = push(a, 2); [a, b]",
+            arr(vec![Object::from(vec![1]), Object::from(vec![1, 2])])
+        ),
+        (
+            "let a = [1, 2]; let b = set(a, 0, 5); [a, b]",
+            arr(vec![Object::from(vec![1, 2]), Object::from(vec![5, 2])])
+        ),
+        (
+            "let a = [1]; let f = fn() { a }; push(a, 2); f()",
+            Ok(Rc::new(Object::from(vec![1])))
+        ),
+        (
+            "let a = [1]; push(a, a)",
+            arr(vec![Object::Integer(1), Object::from(vec![1])])
+        ),
+        (
+            "let f = fn(f, n, a) { if (n == 0) { a } else { f(f, n - 1, push(a, n)) } }; f(f, 3, [])",
+            Ok(Rc::new(Object::from(vec![3, 2, 1])))
+        ),        (
+            "let a = [1]; let b = a; a = push(a, 2); [a, b]",
+            arr(vec![Object::from(vec![1, 2]), Object::from(vec![1])])
+        ),
+        (
+            "let f = fn() { let a = [1, 2]; let b = a; a = set(a, 0, 5); [a, b] }; f()",
+            arr(vec![Object::from(vec![5, 2]), Object::from(vec![1, 2])])
+        ),
+        // A failed update leaves the variable as it was
+        (
+            "let a = [1, 2]; try(fn() { a = set(a, 5, 0); }); a",
+            Ok(Rc::new(Object::from(vec![1, 2])))
+        ),
+    )
+}
+
+#[test]
+fn builtin_wrapping() {
+    test!(
+        ("wrapping_add(9223372036854775807, 1)", int(i64::MIN)),
+        ("wrapping_add(2, 3)", int(5)),
+        ("wrapping_sub(-9223372036854775807 - 1, 1)", int(i64::MAX)),
+        ("wrapping_mul(4611686018427387904, 4)", int(0)),
+        ("wrapping_mul(-3, 7)", int(-21)),
+        (
+            "wrapping_add(2 ** 64, 1)",
+            Err(InterpretError::Runtime(
+                "arguments to `wrapping_add` must fit in 64 bits".into()
+            ))
+        ),
+        (
+            r#"wrapping_sub(1, "a")"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `wrapping_sub` not supported, got STRING".into()
+            ))
+        ),
+        (
+            "wrapping_mul(1)",
+            Err(InterpretError::arity("wrapping_mul", 2, Some(2), 1))
+        ),
+    )
+}
+
+#[test]
+fn builtin_parse() {
+    test!(
+        (
+            r#"parse("[1, 2, 3]")"#,
+            Ok(Rc::new(Object::from(vec![1, 2, 3])))
+        ),
+        (
+            r#"let x = 2; parse("x * 3")"#,
+            Ok(Rc::new(Object::Integer(6)))
+        ),
+        (
+            r#"parse("{\"a\": [true]}")["a"][0]"#,
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (
+            r#"parse("fn(x) { x + 1 }")(1)"#,
+            Ok(Rc::new(Object::Integer(2)))
+        ),
+        (
+            r#"parse("[1, 2")"#,
+            Err(InterpretError::Parse("expected ], got eof".into()))
+        ),
+        (
+            r#"parse("let y = 1; y")"#,
+            Err(InterpretError::Runtime(
+                "`parse` expects a single expression".into()
+            ))
+        ),
+        (
+            "parse(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `parse` not supported, got INTEGER".into()
+            ))
+        ),
+    );
+
+    assert_eq!(
+        eval_with_max_depth(r#"let f = fn() { parse("f()") }; f()"#, 20),
+        Err(InterpretError::StackOverflow(
+            "maximum recursion depth exceeded".into()
+        ))
+    );
+
+    // Called through another function it sees none of the caller's
+    // variables, but still draws from its random numbers and keeps its limit
+    test!(
+        (
+            r#"seed(5); let a = map_values({"k": "rand()"}, parse)["k"]; seed(5); a == rand()"#,
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (
+            r#"let x = 1; each(["x"], parse)"#,
+            Err(InterpretError::Undefined("identifier not found: x".into()))
+        ),
+    );
+    assert_eq!(
+        eval_with_max_alloc(r#"map_values({"k": "range(100)"}, parse)"#, 10),
+        Err(InterpretError::Runtime("allocation limit exceeded".into()))
+    );
+}
+
+#[test]
+fn builtin_to_json() {
+    let json = |s: &str| Ok(Rc::new(Object::from(s)));
+    test!(
+        ("to_json(1)", json("1")),
+        ("to_json(2 ** 70)", json("1180591620717411303424")),
+        ("to_json(true)", json("true")),
+        ("to_json([1.5, 2.0])", json("[1.5,2.0]")),
+        (
+            "to_json(10.0 ** 400)",
+            Err(InterpretError::Runtime("cannot convert inf to JSON".into()))
+        ),
+        ("to_json(if (false) { 1 })", json("null")),
+        (r#"to_json("a\"b\\c\nd")"#, json(r#""a\"b\\c\nd""#)),
+        ("to_json([])", json("[]")),
+        ("to_json({})", json("{}")),
+        (
+            r#"let n = if (false) { 1 }; to_json({"b": [1, {"c": n}], "a": "x", 1: true, false: []})"#,
+            json(r#"{"b":[1,{"c":null}],"a":"x","1":true,"false":[]}"#)
+        ),
+        (
+            r#"let h = {"z": 1, "y": 2}; to_json(set([h], 0, delete(h, "z")))"#,
+            json(r#"[{"y":2}]"#)
+        ),
+        (
+            "to_json([1, fn(x) { x }])",
+            Err(InterpretError::TypeMismatch(
+                "cannot convert FUNCTION to JSON".into()
+            ))
+        ),
+        (
+            "to_json({\"f\": len})",
+            Err(InterpretError::TypeMismatch(
+                "cannot convert BUILTIN to JSON".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_from_json() {
+    let data = r#"let data = {"name": "a\"b", "tags": ["x", "y"], "nested": {"n": [1, -2, 2 ** 70], "ok": true}}"#;
+    test!(
+        (
+            &format!("{}; from_json(to_json(data)) == data", data),
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (
+            &format!(
+                "{}; to_json(from_json(to_json(data))) == to_json(data)",
+                data
+            ),
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        (
+            r#"from_json(" [1, {\"a\": null}, \"\\u00e9\\ud83d\\ude00\\n\"] ")"#,
+            Ok(Rc::new(Object::from(vec![
+                Object::Integer(1),
+                Object::Hash(HashObj::from_iter([(
+                    Rc::new(Object::from("a")),
+                    Rc::new(Object::Null)
+                )])),
+                Object::from("é😀\n"),
+            ])))
+        ),
+        (
+            r#"from_json("-9223372036854775808")"#,
+            Ok(Rc::new(Object::Integer(i64::MIN)))
+        ),
+        (
+            r#"from_json("[1, 2")"#,
+            Err(InterpretError::Runtime(
+                "invalid JSON: expected ',' at offset 5".into()
+            ))
+        ),
+        (
+            r#"from_json("{1: 2}")"#,
+            Err(InterpretError::Runtime(
+                "invalid JSON: expected a string key at offset 1".into()
+            ))
+        ),
+        (
+            r#"from_json("[01]")"#,
+            Err(InterpretError::Runtime(
+                "invalid JSON: invalid number at offset 3".into()
+            ))
+        ),
+        (
+            r#"from_json("[1.5, -2e3, 1E-1]")"#,
+            Ok(Rc::new(Object::from(vec![1.5, -2000.0, 0.1])))
+        ),
+        (
+            r#"from_json("1.")"#,
+            Err(InterpretError::Runtime(
+                "invalid JSON: invalid number at offset 2".into()
+            ))
+        ),
+        (
+            r#"from_json("true false")"#,
+            Err(InterpretError::Runtime(
+                "invalid JSON: unexpected trailing characters at offset 5".into()
+            ))
+        ),
+        (
+            r#"from_json("nul")"#,
+            Err(InterpretError::Runtime(
+                "invalid JSON: unexpected character at offset 0".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_partial() {
+    test!(
+        (
+            "let add = fn(a, b) { a + b }; let inc = partial(add, 1); inc(4)",
+            int(5)
+        ),
+        (
+            "let f = fn(a, b, c) { a * 100 + b * 10 + c }; partial(partial(f, 1), 2)(3)",
+            int(123)
+        ),
+        ("partial(fn(a, b) { a - b }, 10, 3)()", int(7)),
+        ("partial(len)([1, 2])", int(2)),
+        (
+            "let sum = fn(...xs) { len(xs) }; partial(sum, 1, 2)(3, 4)",
+            int(4)
+        ),
+        (
+            r#"map_values({"a": 1}, partial(fn(a, b) { a + b }, 10))["a"]"#,
+            int(11)
+        ),
+        ("is_fn(partial(len))", Ok(Rc::new(Object::Bool(true)))),
+        (
+            "partial(1, 2)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `partial` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            "partial(fn(a) { a }, 1)(2)",
+            Err(InterpretError::WrongArity(
+                "function expects 1 argument, got 2".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_memoize() {
+    test!(
+        (
+            r#"
+            let calls = 0;
+            let fib = memoize(fn(n) {
+                [calls] = [calls + 1];
+                if (n < 2) { n } else { fib(n - 1) + fib(n - 2) }
+            });
+            [fib(30), calls]"#,
+            Ok(Rc::new(Object::from(vec![832040, 31])))
+        ),
+        (
+            r#"
+            let calls = 0;
+            let f = memoize(fn(a, b) { [calls] = [calls + 1]; a + b });
+            f(1, 2); f(1, 2); f(2, 1); f("a", "b"); f("a", "b");
+            calls"#,
+            int(3)
+        ),
+        (r#"memoize(len)("ab")"#, int(2)),
+        ("is_fn(memoize(len))", Ok(Rc::new(Object::Bool(true)))),
+        (
+            "memoize(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `memoize` not supported, got INTEGER".into()
+            ))
+        ),
+        (
+            "memoize(len)([1])",
+            Err(InterpretError::TypeMismatch(
+                "unusable as hash key: ARRAY".into()
+            ))
+        ),
+    )
+}
+
+#[test]
+fn builtin_try() {
+    let string = |s: &str| Ok(Rc::new(Object::String(s.into())));
+    test!(
+        (
+            r#"let r = try(fn() { 1 / 0 }); if (is_error(r)) { r["message"] }"#,
+            string("division by zero")
+        ),
+        (
+            r#"try(fn() { let x = 1; nope + x })["message"]"#,
+            string("identifier not found: nope")
+        ),
+        (
+            "try(fn(a, b) { a ~/ b }, 7, 2)",
+            Ok(Rc::new(Object::Integer(3)))
+        ),
+        (
+            r#"try(len, 1)["message"]"#,
+            string("argument to `len` not supported, got INTEGER, expected STRING, ARRAY, HASH or RANGE")
+        ),
+        (
+            r#"let e = try(fn() { assert(false, "boom") }); [e["kind"], e]"#,
+            Ok(Rc::new(Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Null),
+                    Rc::new(Object::Error(InterpretError::Runtime("boom".into()))),
+                ],
+            })))
+        ),
+        (
+            "is_error(try(fn() { 1 }))",
+            Ok(Rc::new(Object::Bool(false)))
+        ),
+        (
+            "try(1)",
+            Err(InterpretError::TypeMismatch(
+                "argument to `try` not supported, got INTEGER".into()
+            ))
+        ),
+    );
+
+    // Running out of stack isn't caught
+    assert_eq!(
+        eval_with_max_depth("let f = fn(n) { f(n + 1) }; try(f, 0)", 20),
+        Err(InterpretError::StackOverflow(
+            "maximum recursion depth exceeded".into()
+        ))
+    );
+}
+
+#[test]
+fn builtin_rand() {
+    // The sequence after `seed(42)` is fixed, changing it breaks programs
+    // relying on it
+    test!(
+        (
+            "seed(42); [rand(), rand()]",
+            Ok(Rc::new(Object::from(vec![
+                9054096345292791428,
+                3415151264702222905
+            ])))
+        ),
+        (
+            "seed(7); let a = rand(); seed(7); a == rand()",
+            Ok(Rc::new(Object::Bool(true)))
+        ),
+        ("seed(1); rand_range(5, 6)", int(5)),
+        (
+            r#"
+            let f = fn(n, lo, hi) {
+                if (n == 0) { return [lo, hi]; }
+                let r = rand_range(-3, 3);
+                f(n - 1, math.min(lo, r), math.max(hi, r))
+            };
+            seed(5);
+            f(60, 0, 0)"#,
+            Ok(Rc::new(Object::from(vec![-3, 2])))
+        ),
+        (
+            "rand_range(3, 3)",
+            Err(InterpretError::Runtime(
+                "`rand_range` expects lo < hi, got 3 and 3".into()
+            ))
+        ),
+        (
+            r#"seed("a")"#,
+            Err(InterpretError::TypeMismatch(
+                "argument to `seed` not supported, got STRING".into()
+            ))
+        ),
+    );
+}
@@ -2,11 +2,20 @@ mod token;
 
 pub use token::*;
 
+use crate::eval::BigInt;
+
 pub struct Lexer {
     input: Vec<char>,
     pos: usize,
     read_pos: usize,
     ch: char,
+    /// 1 based line of `ch`
+    line: usize,
+    /// 1 based column of `ch`
+    col: usize,
+    /// Braces opened inside each string interpolation being lexed, the `}`
+    /// closing one is only seen once its count is back to 0
+    interp: Vec<usize>,
 }
 
 impl Lexer {
@@ -16,14 +25,41 @@ impl Lexer {
             pos: 0,
             read_pos: 0,
             ch: '\0',
+            line: 1,
+            col: 0,
+            interp: vec![],
         };
         s.read();
         s
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Token {
-        self.skip_whitespace();
+        let (line, col);
+        let mut token = match self.skip_whitespace() {
+            Ok(()) => {
+                (line, col) = (self.line, self.col);
+                self.read_token()
+            }
+            Err(start) => {
+                (line, col) = start;
+                Token::new(
+                    TokenType::Illegal,
+                    Some(format!(
+                        "unterminated block comment at line {}:{}",
+                        start.0, start.1
+                    )),
+                )
+            }
+        };
+        token.line = line;
+        token.col = col;
+        token
+    }
+}
 
+impl Lexer {
+    fn read_token(&mut self) -> Token {
         let token = match self.ch {
             '=' => {
                 if self.peek() == '=' {
@@ -41,36 +77,130 @@ impl Lexer {
                     Token::new(TokenType::Bang, None)
                 }
             }
+            '+' | '-' | '*' | '/' if self.peek() == '=' => {
+                let ty = match self.ch {
+                    '+' => TokenType::PlusAssign,
+                    '-' => TokenType::MinusAssign,
+                    '*' => TokenType::StarAssign,
+                    _ => TokenType::SlashAssign,
+                };
+                self.read();
+                Token::new(ty, None)
+            }
             '+' => Token::new(TokenType::Plus, None),
             '-' => Token::new(TokenType::Minus, None),
             '/' => Token::new(TokenType::Slash, None),
-            '*' => Token::new(TokenType::Star, None),
+            '*' => {
+                if self.peek() == '*' {
+                    self.read();
+                    Token::new(TokenType::Pow, None)
+                } else {
+                    Token::new(TokenType::Star, None)
+                }
+            }
+            '&' if self.peek() == '&' => {
+                self.read();
+                Token::new(TokenType::And, None)
+            }
+            '&' => Token::new(TokenType::Ampersand, None),
+            '|' if self.peek() == '|' => {
+                self.read();
+                Token::new(TokenType::Or, None)
+            }
+            '|' if self.peek() == '>' => {
+                self.read();
+                Token::new(TokenType::Pipeline, None)
+            }
+            '|' => Token::new(TokenType::Pipe, None),
+            '?' if self.peek() == '?' => {
+                self.read();
+                Token::new(TokenType::NullCoalesce, None)
+            }
+            '^' => Token::new(TokenType::Caret, None),
+            // `//` is left free for comments
+            '~' if self.peek() == '/' => {
+                self.read();
+                Token::new(TokenType::FloorDiv, None)
+            }
+            '~' => Token::new(TokenType::Tilde, None),
+            '%' => Token::new(TokenType::Percent, None),
             '(' => Token::new(TokenType::LParen, None),
             ')' => Token::new(TokenType::RParen, None),
-            '{' => Token::new(TokenType::LBrace, None),
-            '}' => Token::new(TokenType::RBrace, None),
+            '{' => {
+                if let Some(depth) = self.interp.last_mut() {
+                    *depth += 1;
+                }
+                Token::new(TokenType::LBrace, None)
+            }
+            '}' if self.interp.last() == Some(&0) => {
+                self.interp.pop();
+                self.read_string(true)
+            }
+            '}' => {
+                if let Some(depth) = self.interp.last_mut() {
+                    *depth -= 1;
+                }
+                Token::new(TokenType::RBrace, None)
+            }
             '[' => Token::new(TokenType::LBracket, None),
             ']' => Token::new(TokenType::RBracket, None),
             ',' => Token::new(TokenType::Comma, None),
             ':' => Token::new(TokenType::Colon, None),
+            '.' if self.peek() == '.' && self.peek_nth(1) == '.' => {
+                self.read();
+                self.read();
+                Token::new(TokenType::Ellipsis, None)
+            }
+            '.' if self.peek() == '.' && self.peek_nth(1) == '=' => {
+                self.read();
+                self.read();
+                Token::new(TokenType::DotDotEq, None)
+            }
+            '.' if self.peek() == '.' => {
+                self.read();
+                Token::new(TokenType::DotDot, None)
+            }
+            '.' => Token::new(TokenType::Dot, None),
             ';' => Token::new(TokenType::Semicolon, None),
+            '<' if self.peek() == '<' => {
+                self.read();
+                Token::new(TokenType::Shl, None)
+            }
+            '>' if self.peek() == '>' => {
+                self.read();
+                Token::new(TokenType::Shr, None)
+            }
+            '<' if self.peek() == '=' => {
+                self.read();
+                Token::new(TokenType::LtEq, None)
+            }
+            '>' if self.peek() == '=' => {
+                self.read();
+                Token::new(TokenType::GtEq, None)
+            }
             '<' => Token::new(TokenType::Lt, None),
             '>' => Token::new(TokenType::Gt, None),
             '\0' => Token::new(TokenType::Eof, None),
 
+            'r' if self.peek() == '"' => self.read_raw_string(),
             ch if is_ident_char(ch, true) => return self.read_ident(),
             ch if ch.is_ascii_digit() => return self.read_num(),
-            '"' => self.read_string(),
+            '"' if self.peek() == '"' && self.peek_nth(1) == '"' => self.read_triple_string(),
+            '"' => self.read_string(false),
 
-            _ => Token::new(TokenType::Illegal, None),
+            ch => Token::new(
+                TokenType::Illegal,
+                Some(format!(
+                    "unexpected character '{}' at line {}:{}",
+                    ch, self.line, self.col
+                )),
+            ),
         };
 
         self.read();
         token
     }
-}
 
-impl Lexer {
     fn read_ident(&mut self) -> Token {
         let start = self.pos;
 
@@ -81,31 +211,213 @@ impl Lexer {
         keyword_or_ident(ident)
     }
 
+    /// Digits may be grouped with underscores, `1_000_000`, as long as each
+    /// one sits between two digits
     fn read_num(&mut self) -> Token {
+        if self.ch == '0' && matches!(self.peek(), 'x' | 'o' | 'b') {
+            return self.read_radix_num();
+        }
         let start = self.pos;
 
-        while self.ch.is_ascii_digit() {
+        while self.ch.is_ascii_digit() || self.ch == '_' {
+            self.read();
+        }
+        // `1.abs` stays a member access
+        let ty = if self.ch == '.' && self.peek().is_ascii_digit() {
+            self.read();
+            while self.ch.is_ascii_digit() || self.ch == '_' {
+                self.read();
+            }
+            TokenType::Float
+        } else {
+            TokenType::Number
+        };
+
+        let lit: String = self.input[start..self.pos].iter().collect();
+        if misplaced_underscore(&lit) {
+            return Token::new(
+                TokenType::Illegal,
+                Some(format!("misplaced underscore in number: {}", lit)),
+            );
+        }
+        Token::new(ty, Some(lit.replace('_', "")))
+    }
+
+    /// `0x1F`, `0o17` or `0b1010`. Letters are read along with the digits
+    /// so `0x1G` is one bad literal rather than a number and an identifier
+    fn read_radix_num(&mut self) -> Token {
+        let start = self.pos;
+        let radix = match self.peek() {
+            'x' => 16,
+            'o' => 8,
+            _ => 2,
+        };
+        self.read();
+        self.read();
+
+        let digits_start = self.pos;
+        while self.ch.is_ascii_alphanumeric() || self.ch == '_' {
             self.read();
         }
         let num: String = self.input[start..self.pos].iter().collect();
-        Token::new(TokenType::Number, Some(num))
+        let digits = &num[digits_start - start..];
+        if misplaced_underscore(digits) {
+            return Token::new(
+                TokenType::Illegal,
+                Some(format!("misplaced underscore in number: {}", num)),
+            );
+        }
+        let digits = digits.replace('_', "");
+        if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+            return Token::new(
+                TokenType::Illegal,
+                Some(format!("invalid integer literal: {}", num)),
+            );
+        }
+        let n = match i64::from_str_radix(&digits, radix) {
+            Ok(n) => n.to_string(),
+            Err(_) => BigInt::from_digits(&digits, radix)
+                .expect("checked to be digits")
+                .to_string(),
+        };
+        Token::new(TokenType::Number, Some(n))
+    }
+
+    /// Reads up to the closing quote or the `${` starting an interpolation.
+    /// `continued` is set when resuming after the `}` ending one. Braces on
+    /// their own are plain text, so JSON and `format` templates need no
+    /// escaping, and `\${` is a literal one. Raw and `"""` strings are
+    /// never interpolated
+    fn read_string(&mut self, continued: bool) -> Token {
+        let mut str = String::new();
+        let mut err = None;
+
+        let ty = loop {
+            self.read();
+            match self.ch {
+                '"' | '\0' if continued => break TokenType::StringEnd,
+                '"' | '\0' => break TokenType::String,
+                '$' if self.peek() == '{' && err.is_none() => {
+                    self.read();
+                    self.interp.push(0);
+                    break if continued {
+                        TokenType::StringMid
+                    } else {
+                        TokenType::StringStart
+                    };
+                }
+                '\\' => {
+                    self.read();
+                    match self.read_escape() {
+                        Ok(ch) => str.push(ch),
+                        Err(e) => {
+                            err.get_or_insert(e);
+                        }
+                    }
+                }
+                ch => str.push(ch),
+            }
+        };
+
+        match err {
+            Some(e) => Token::new(TokenType::Illegal, Some(e)),
+            None => Token::new(ty, Some(str)),
+        }
     }
 
-    fn read_string(&mut self) -> Token {
+    /// Decodes the escape whose first char after the backslash is the
+    /// current one. Only peeks past it so a bad escape can't eat the
+    /// closing quote
+    fn read_escape(&mut self) -> Result<char, String> {
+        match self.ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            'x' => {
+                let mut code = 0;
+                for _ in 0..2 {
+                    let digit = self.peek().to_digit(16).ok_or("invalid \\x escape")?;
+                    self.read();
+                    code = code * 16 + digit;
+                }
+                Ok(char::from_u32(code).unwrap())
+            }
+            'u' => {
+                if self.peek() != '{' {
+                    return Err("invalid \\u escape, expected {".into());
+                }
+                self.read();
+                let mut code = 0u32;
+                let mut digits = 0;
+                while let Some(digit) = self.peek().to_digit(16) {
+                    self.read();
+                    code = code.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                }
+                if self.peek() != '}' {
+                    return Err("unterminated \\u{ escape".into());
+                }
+                self.read();
+                match char::from_u32(code) {
+                    Some(ch) if digits > 0 && digits <= 6 => Ok(ch),
+                    _ => Err(format!("invalid code point in \\u escape: {:X}", code)),
+                }
+            }
+            '\0' => Err("unterminated string".into()),
+            ch => Err(format!("unknown escape: \\{}", ch)),
+        }
+    }
+
+    /// `"""..."""`, taken as is including backslashes and newlines
+    fn read_triple_string(&mut self) -> Token {
+        self.read();
+        self.read();
         let start = self.pos + 1;
 
         loop {
             self.read();
-            if self.ch == '"' || self.ch == '\0' {
+            if self.ch == '\0' {
+                return Token::new(TokenType::Illegal, Some("unterminated string".into()));
+            }
+            if self.ch == '"' && self.peek() == '"' && self.peek_nth(1) == '"' {
                 break;
             }
         }
 
+        let str: String = self.input[start..self.pos].iter().collect();
+        self.read();
+        self.read();
+        Token::new(TokenType::String, Some(str))
+    }
+
+    /// `r"..."`, taken as is like `"""` strings, so it can't contain a `"`
+    fn read_raw_string(&mut self) -> Token {
+        self.read();
+        let start = self.pos + 1;
+
+        loop {
+            self.read();
+            match self.ch {
+                '"' => break,
+                '\0' => return Token::new(TokenType::Illegal, Some("unterminated string".into())),
+                _ => {}
+            }
+        }
+
         let str: String = self.input[start..self.pos].iter().collect();
         Token::new(TokenType::String, Some(str))
     }
 
     fn read(&mut self) {
+        if self.ch == '\n' {
+            self.line += 1;
+            self.col = 0;
+        }
+        self.col += 1;
         self.ch = if self.read_pos >= self.input.len() {
             '\0'
         } else {
@@ -116,38 +428,94 @@ impl Lexer {
         self.read_pos += 1;
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_whitespace() {
+    /// Skips whitespace and comments. `//` runs to the end of the line and
+    /// `/* */` may be nested. Fails with the line and column of a block
+    /// comment that is never closed
+    fn skip_whitespace(&mut self) -> Result<(), (usize, usize)> {
+        loop {
+            while self.ch.is_whitespace() {
+                self.read();
+            }
+            match (self.ch, self.peek()) {
+                ('/', '/') => {
+                    while self.ch != '\n' && self.ch != '\0' {
+                        self.read();
+                    }
+                }
+                ('/', '*') => self.skip_block_comment()?,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), (usize, usize)> {
+        let start = (self.line, self.col);
+        let mut depth = 0;
+        loop {
+            match (self.ch, self.peek()) {
+                ('\0', _) => return Err(start),
+                ('/', '*') => {
+                    self.read();
+                    depth += 1;
+                }
+                ('*', '/') => {
+                    self.read();
+                    depth -= 1;
+                    if depth == 0 {
+                        self.read();
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
             self.read();
         }
     }
 
     fn peek(&self) -> char {
-        if self.read_pos >= self.input.len() {
-            '\0'
-        } else {
-            self.input[self.read_pos]
-        }
+        self.peek_nth(0)
+    }
+
+    /// Looks `n` characters past the next one
+    fn peek_nth(&self, n: usize) -> char {
+        self.input.get(self.read_pos + n).copied().unwrap_or('\0')
     }
 }
 
+/// Whether an `_` in a number's digits isn't between two digits
+fn misplaced_underscore(digits: &str) -> bool {
+    digits.starts_with('_')
+        || digits.ends_with('_')
+        || digits.contains("__")
+        || digits.contains("_.")
+        || digits.contains("._")
+}
+
 fn is_ident_char(ch: char, first: bool) -> bool {
     if first {
-        matches!(ch, 'a'..='z' | 'A'..='Z' | '_')
+        ch.is_alphabetic() || ch == '_'
     } else {
-        matches!(ch, 'a'..='z' | 'A'..='Z' | '_' | '0'..='9')
+        ch.is_alphanumeric() || ch == '_'
     }
 }
 
 fn keyword_or_ident(s: String) -> Token {
     match s.as_str() {
         "let" => Token::new(TokenType::Let, None),
+        "const" => Token::new(TokenType::Const, None),
         "fn" => Token::new(TokenType::Fn, None),
+        "macro" => Token::new(TokenType::Macro, None),
         "if" => Token::new(TokenType::If, None),
         "else" => Token::new(TokenType::Else, None),
         "return" => Token::new(TokenType::Return, None),
         "true" => Token::new(TokenType::True, None),
         "false" => Token::new(TokenType::False, None),
+        "null" => Token::new(TokenType::Null, None),
+        "break" => Token::new(TokenType::Break, None),
+        "continue" => Token::new(TokenType::Continue, None),
+        "while" => Token::new(TokenType::While, None),
+        "for" => Token::new(TokenType::For, None),
+        "in" => Token::new(TokenType::In, None),
         _ => Token::new(TokenType::Ident, Some(s)),
     }
 }
@@ -160,8 +528,14 @@ mod test {
     enum TestToken {
         Token(TokenType),
         Number(i64),
+        /// Decimal digits of a number too large for an `i64`
+        BigNumber(&'static str),
+        Float(f64),
         Ident(String),
         String(String),
+        /// Piece of an interpolated string
+        StringPart(TokenType, String),
+        Illegal(String),
     }
 
     impl PartialEq<Token> for TestToken {
@@ -170,12 +544,27 @@ mod test {
                 TestToken::Number(x) => {
                     other.ty == TokenType::Number && other.literal == TokenLiteral::Num(*x)
                 }
+                TestToken::BigNumber(digits) => {
+                    other.ty == TokenType::Number
+                        && other.literal
+                            == TokenLiteral::BigNum(BigInt::from_digits(digits, 10).unwrap())
+                }
+                TestToken::Float(x) => {
+                    other.ty == TokenType::Float && other.literal == TokenLiteral::Float(*x)
+                }
                 TestToken::Ident(s) => {
                     other.ty == TokenType::Ident && other.literal == TokenLiteral::Ident(s.into())
                 }
                 TestToken::String(s) => {
                     other.ty == TokenType::String && other.literal == TokenLiteral::String(s.into())
                 }
+                TestToken::StringPart(t, s) => {
+                    other.ty == *t && other.literal == TokenLiteral::String(s.into())
+                }
+                TestToken::Illegal(s) => {
+                    other.ty == TokenType::Illegal
+                        && other.literal == TokenLiteral::String(s.into())
+                }
                 TestToken::Token(t) => other.ty == *t,
             }
         }
@@ -192,8 +581,10 @@ let add = fn(x, y) {
 };
 
 let result = add(five, ten);
-!-/*5;
+!-/ *5;
 5 < 10 > 5;
+2 ** 3;
+[...];
 
 if (5 < 10) {
     return true;
@@ -261,6 +652,14 @@ if (5 < 10) {
             TestToken::Token(TokenType::Gt),
             TestToken::Number(5),
             TestToken::Token(TokenType::Semicolon),
+            TestToken::Number(2),
+            TestToken::Token(TokenType::Pow),
+            TestToken::Number(3),
+            TestToken::Token(TokenType::Semicolon),
+            TestToken::Token(TokenType::LBracket),
+            TestToken::Token(TokenType::Ellipsis),
+            TestToken::Token(TokenType::RBracket),
+            TestToken::Token(TokenType::Semicolon),
             //
             TestToken::Token(TokenType::If),
             TestToken::Token(TokenType::LParen),
@@ -312,4 +711,396 @@ if (5 < 10) {
             assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
         }
     }
+
+    #[test]
+    fn bitwise_tokens() {
+        let mut lexer = Lexer::new("6 & 3 | ~x ^ 1 << 4 >> 2 < > <= >= ~/ % |>".into());
+        let expected = vec![
+            TestToken::Number(6),
+            TestToken::Token(TokenType::Ampersand),
+            TestToken::Number(3),
+            TestToken::Token(TokenType::Pipe),
+            TestToken::Token(TokenType::Tilde),
+            TestToken::Ident("x".into()),
+            TestToken::Token(TokenType::Caret),
+            TestToken::Number(1),
+            TestToken::Token(TokenType::Shl),
+            TestToken::Number(4),
+            TestToken::Token(TokenType::Shr),
+            TestToken::Number(2),
+            TestToken::Token(TokenType::Lt),
+            TestToken::Token(TokenType::Gt),
+            TestToken::Token(TokenType::LtEq),
+            TestToken::Token(TokenType::GtEq),
+            TestToken::Token(TokenType::FloorDiv),
+            TestToken::Token(TokenType::Percent),
+            TestToken::Token(TokenType::Pipeline),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn compound_assign_tokens() {
+        let mut lexer = Lexer::new("a += 1 -= *= /= ** =".into());
+        let expected = vec![
+            TestToken::Ident("a".into()),
+            TestToken::Token(TokenType::PlusAssign),
+            TestToken::Number(1),
+            TestToken::Token(TokenType::MinusAssign),
+            TestToken::Token(TokenType::StarAssign),
+            TestToken::Token(TokenType::SlashAssign),
+            TestToken::Token(TokenType::Pow),
+            TestToken::Token(TokenType::Assign),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn dot_token() {
+        let mut lexer = Lexer::new("math.abs(...xs)".into());
+        let expected = vec![
+            TestToken::Ident("math".into()),
+            TestToken::Token(TokenType::Dot),
+            TestToken::Ident("abs".into()),
+            TestToken::Token(TokenType::LParen),
+            TestToken::Token(TokenType::Ellipsis),
+            TestToken::Ident("xs".into()),
+            TestToken::Token(TokenType::RParen),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn logical_tokens() {
+        let mut lexer = Lexer::new("a && b || c & d | e".into());
+        let expected = vec![
+            TestToken::Ident("a".into()),
+            TestToken::Token(TokenType::And),
+            TestToken::Ident("b".into()),
+            TestToken::Token(TokenType::Or),
+            TestToken::Ident("c".into()),
+            TestToken::Token(TokenType::Ampersand),
+            TestToken::Ident("d".into()),
+            TestToken::Token(TokenType::Pipe),
+            TestToken::Ident("e".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn null_coalesce_token() {
+        let mut lexer = Lexer::new("a ?? b ? c".into());
+        let expected = vec![
+            TestToken::Ident("a".into()),
+            TestToken::Token(TokenType::NullCoalesce),
+            TestToken::Ident("b".into()),
+            TestToken::Illegal("unexpected character '?' at line 1:8".into()),
+            TestToken::Ident("c".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let mut lexer = Lexer::new("9223372036854775807 9223372036854775808".into());
+
+        assert_eq!(TestToken::Number(i64::MAX), lexer.next());
+        assert_eq!(TestToken::BigNumber("9223372036854775808"), lexer.next());
+        assert_eq!(TestToken::Token(TokenType::Eof), lexer.next());
+    }
+
+    #[test]
+    fn radix_literals() {
+        let input =
+            "0x1F 0o17 0b1010 0xff.x 0x7fffffffffffffff 0x8000000000000000 0x 0b102 0o8 0x1G";
+        let expected = vec![
+            TestToken::Number(31),
+            TestToken::Number(15),
+            TestToken::Number(10),
+            TestToken::Number(255),
+            TestToken::Token(TokenType::Dot),
+            TestToken::Ident("x".into()),
+            TestToken::Number(i64::MAX),
+            TestToken::BigNumber("9223372036854775808"),
+            TestToken::Illegal("invalid integer literal: 0x".into()),
+            TestToken::Illegal("invalid integer literal: 0b102".into()),
+            TestToken::Illegal("invalid integer literal: 0o8".into()),
+            TestToken::Illegal("invalid integer literal: 0x1G".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn digit_separators() {
+        let input =
+            "1_000_000 0.000_5 0xff_ff 0b1010_1010 1_ 1__0 1_.5 0x_1 9_223_372_036_854_775_808";
+        let expected = vec![
+            TestToken::Number(1_000_000),
+            TestToken::Float(0.000_5),
+            TestToken::Number(0xff_ff),
+            TestToken::Number(0b1010_1010),
+            TestToken::Illegal("misplaced underscore in number: 1_".into()),
+            TestToken::Illegal("misplaced underscore in number: 1__0".into()),
+            TestToken::Illegal("misplaced underscore in number: 1_.5".into()),
+            TestToken::Illegal("misplaced underscore in number: 0x_1".into()),
+            TestToken::BigNumber("9223372036854775808"),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn float_literal() {
+        let mut lexer = Lexer::new("2.5 0.25 1.x 2..5 1..=3".into());
+        let expected = vec![
+            TestToken::Float(2.5),
+            TestToken::Float(0.25),
+            TestToken::Number(1),
+            TestToken::Token(TokenType::Dot),
+            TestToken::Ident("x".into()),
+            TestToken::Number(2),
+            TestToken::Token(TokenType::DotDot),
+            TestToken::Number(5),
+            TestToken::Number(1),
+            TestToken::Token(TokenType::DotDotEq),
+            TestToken::Number(3),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn illegal_character() {
+        let mut lexer = Lexer::new("let a = 1;\n  a @ 2 $\t#".into());
+        for _ in 0..6 {
+            lexer.next();
+        }
+        assert_eq!(
+            TestToken::Illegal("unexpected character '@' at line 2:5".into()),
+            lexer.next()
+        );
+        assert_eq!(TestToken::Number(2), lexer.next());
+        assert_eq!(
+            TestToken::Illegal("unexpected character '$' at line 2:9".into()),
+            lexer.next()
+        );
+        assert_eq!(
+            TestToken::Illegal("unexpected character '#' at line 2:11".into()),
+            lexer.next()
+        );
+    }
+
+    #[test]
+    fn line_comments() {
+        let input = "// leading
+let x = 5; // trailing / not a division
+// a // b
+x / 2 //";
+        let expected = vec![
+            TestToken::Token(TokenType::Let),
+            TestToken::Ident("x".into()),
+            TestToken::Token(TokenType::Assign),
+            TestToken::Number(5),
+            TestToken::Token(TokenType::Semicolon),
+            TestToken::Ident("x".into()),
+            TestToken::Token(TokenType::Slash),
+            TestToken::Number(2),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn block_comments() {
+        let input = "/* leading */ 1 /* a /* nested */ still
+comment */ * /**/ 2 /*/ */
+3 /* open /* */";
+        let expected = vec![
+            TestToken::Number(1),
+            TestToken::Token(TokenType::Star),
+            TestToken::Number(2),
+            TestToken::Number(3),
+            TestToken::Illegal("unterminated block comment at line 3:3".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let input = r#""a ${x + {"k": "${y}"}["k"]} b {} {{ $ \${ ${z}""#;
+        let expected = vec![
+            TestToken::StringPart(TokenType::StringStart, "a ".into()),
+            TestToken::Ident("x".into()),
+            TestToken::Token(TokenType::Plus),
+            TestToken::Token(TokenType::LBrace),
+            TestToken::String("k".into()),
+            TestToken::Token(TokenType::Colon),
+            TestToken::StringPart(TokenType::StringStart, "".into()),
+            TestToken::Ident("y".into()),
+            TestToken::StringPart(TokenType::StringEnd, "".into()),
+            TestToken::Token(TokenType::RBrace),
+            TestToken::Token(TokenType::LBracket),
+            TestToken::String("k".into()),
+            TestToken::Token(TokenType::RBracket),
+            TestToken::StringPart(TokenType::StringMid, " b {} {{ $ ${ ".into()),
+            TestToken::Ident("z".into()),
+            TestToken::StringPart(TokenType::StringEnd, "".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn raw_string() {
+        let input = r#"r"C:\dir\n{x}" r"" r rx"a" r"open"#;
+        let expected = vec![
+            TestToken::String(r"C:\dir\n{x}".into()),
+            TestToken::String("".into()),
+            TestToken::Ident("r".into()),
+            TestToken::Ident("rx".into()),
+            TestToken::String("a".into()),
+            TestToken::Illegal("unterminated string".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn triple_quoted_string() {
+        let input = r#"let s = """{"a": "\d+"}
+\n""";
+"""""" """x"#;
+        let expected = vec![
+            TestToken::Token(TokenType::Let),
+            TestToken::Ident("s".into()),
+            TestToken::Token(TokenType::Assign),
+            TestToken::String("{\"a\": \"\\d+\"}\n\\n".into()),
+            TestToken::Token(TokenType::Semicolon),
+            TestToken::String("".into()),
+            TestToken::Illegal("unterminated string".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+        assert_eq!(lexer.next().line, 3);
+    }
+
+    #[test]
+    fn token_positions() {
+        let mut lexer = Lexer::new("let a = 1;\n  b".into());
+        let positions: Vec<_> = std::iter::from_fn(|| {
+            let t = lexer.next();
+            (t.ty != TokenType::Eof).then_some((t.line, t.col))
+        })
+        .collect();
+        assert_eq!(positions, [(1, 1), (1, 5), (1, 7), (1, 9), (1, 10), (2, 3)]);
+    }
+
+    #[test]
+    fn string_escapes() {
+        let input =
+            r#""\x41" "\u{1F600}" "a\n\t\"b\\\0" "\xZZ" 1 "\u{110000}" "\u{41" "\u41" "\q""#;
+        let expected = vec![
+            TestToken::String("A".into()),
+            TestToken::String("\u{1F600}".into()),
+            TestToken::String("a\n\t\"b\\\0".into()),
+            TestToken::Illegal("invalid \\x escape".into()),
+            TestToken::Number(1),
+            TestToken::Illegal("invalid code point in \\u escape: 110000".into()),
+            TestToken::Illegal("unterminated \\u{ escape".into()),
+            TestToken::Illegal("invalid \\u escape, expected {".into()),
+            TestToken::Illegal("unknown escape: \\q".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
+
+    #[test]
+    fn unicode_ident() {
+        let input = "let café = ñandú2 + 变量; fn 9a";
+
+        let expected = vec![
+            TestToken::Token(TokenType::Let),
+            TestToken::Ident("café".into()),
+            TestToken::Token(TokenType::Assign),
+            TestToken::Ident("ñandú2".into()),
+            TestToken::Token(TokenType::Plus),
+            TestToken::Ident("变量".into()),
+            TestToken::Token(TokenType::Semicolon),
+            TestToken::Token(TokenType::Fn),
+            TestToken::Number(9),
+            TestToken::Ident("a".into()),
+            TestToken::Token(TokenType::Eof),
+        ];
+
+        let mut lexer = Lexer::new(input.into());
+
+        for (i, e) in expected.into_iter().enumerate() {
+            assert_eq!(e, lexer.next(), "Invalid token at index {}", i);
+        }
+    }
 }
@@ -1,9 +1,14 @@
+use crate::eval::BigInt;
 use std::fmt::Display;
 
 #[derive(Debug, Clone)]
 pub struct Token {
     pub ty: TokenType,
     pub literal: TokenLiteral,
+    /// Source line the token starts on, set by the lexer
+    pub line: usize,
+    /// Column of the token's first character on that line
+    pub col: usize,
 }
 
 impl Token {
@@ -14,28 +19,62 @@ impl Token {
                 Self {
                     ty,
                     literal: TokenLiteral::Ident(lit),
+                    line: 0,
+                    col: 0,
                 }
             }
             TokenType::Number => {
                 let lit = literal.expect("Expected a literal for number token");
+                let literal = match lit.parse() {
+                    Ok(n) => TokenLiteral::Num(n),
+                    Err(_) => TokenLiteral::BigNum(
+                        BigInt::from_digits(&lit, 10)
+                            .expect("Expected a number literal for number token"),
+                    ),
+                };
+                Self {
+                    ty,
+                    literal,
+                    line: 0,
+                    col: 0,
+                }
+            }
+            TokenType::Float => {
+                let lit = literal.expect("Expected a literal for float token");
                 let lit = lit
                     .parse()
-                    .expect("Expected a number literal for number token");
+                    .expect("Expected a float literal for float token");
                 Self {
                     ty,
-                    literal: TokenLiteral::Num(lit),
+                    literal: TokenLiteral::Float(lit),
+                    line: 0,
+                    col: 0,
                 }
             }
-            TokenType::String => {
+            TokenType::String
+            | TokenType::StringStart
+            | TokenType::StringMid
+            | TokenType::StringEnd => {
                 let lit = literal.expect("Expected a literal for string token");
                 Self {
                     ty,
                     literal: TokenLiteral::String(lit),
+                    line: 0,
+                    col: 0,
                 }
             }
+            // Optionally describes what went wrong
+            TokenType::Illegal => Self {
+                literal: TokenLiteral::String(literal.unwrap_or_else(|| ty.to_string())),
+                ty,
+                line: 0,
+                col: 0,
+            },
             _ if literal.is_none() => Self {
                 literal: TokenLiteral::String(ty.to_string()),
                 ty,
+                line: 0,
+                col: 0,
             },
             _ => {
                 panic!("Token type: {:?} doesn't require any literal", ty)
@@ -47,25 +86,66 @@ impl Token {
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TokenType {
     Let,
+    Const,
     Fn,
+    Macro,
     If,
     Else,
     Return,
     True,
     False,
+    Null,
+    Break,
+    Continue,
+    While,
+    For,
+    In,
 
     Ident,
     Number,
+    Float,
     String,
+    /// An interpolated string is split into the text before the first
+    /// `${`, the text between each `}` and the next `${` and the text after
+    /// the last `}`, with the expressions' tokens in between
+    StringStart,
+    StringMid,
+    StringEnd,
 
     Assign,
+    /// `+=`, `-=`, `*=` and `/=`
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
     Bang,
     Plus,
     Minus,
     Slash,
+    FloorDiv,
+    Percent,
     Star,
+    Pow,
+    Ampersand,
+    Pipe,
+    /// `|>`, passes the left side to the function on the right
+    Pipeline,
+    /// `??`, the right side when the left one is null
+    NullCoalesce,
+    /// `&&` and `||`, which skip the right side once the left one decides
+    And,
+    Or,
+    Caret,
+    Tilde,
+    Shl,
+    Shr,
     Comma,
     Colon,
+    Ellipsis,
+    /// `..` and `..=`, building a range of integers
+    DotDot,
+    DotDotEq,
+    Dot,
     Semicolon,
     LParen,
     RParen,
@@ -76,6 +156,8 @@ pub enum TokenType {
 
     Lt,
     Gt,
+    LtEq,
+    GtEq,
     Eq,
     NotEq,
 
@@ -90,23 +172,54 @@ impl Display for TokenType {
             "{}",
             match self {
                 TokenType::Let => "let",
+                TokenType::Const => "const",
                 TokenType::Fn => "fn",
+                TokenType::Macro => "macro",
                 TokenType::If => "if",
                 TokenType::Else => "else",
                 TokenType::Return => "return",
                 TokenType::True => "true",
                 TokenType::False => "false",
+                TokenType::Null => "null",
+                TokenType::Break => "break",
+                TokenType::Continue => "continue",
+                TokenType::While => "while",
+                TokenType::For => "for",
+                TokenType::In => "in",
                 TokenType::Ident => "ident",
                 TokenType::Number => "number",
-                TokenType::String => "string",
+                TokenType::Float => "float",
+                TokenType::String | TokenType::StringStart => "string",
+                TokenType::StringMid | TokenType::StringEnd => "}",
                 TokenType::Assign => "=",
+                TokenType::PlusAssign => "+=",
+                TokenType::MinusAssign => "-=",
+                TokenType::StarAssign => "*=",
+                TokenType::SlashAssign => "/=",
                 TokenType::Bang => "!",
                 TokenType::Plus => "+",
                 TokenType::Minus => "-",
                 TokenType::Slash => "/",
+                TokenType::FloorDiv => "~/",
+                TokenType::Percent => "%",
                 TokenType::Star => "*",
+                TokenType::Pow => "**",
+                TokenType::Ampersand => "&",
+                TokenType::Pipe => "|",
+                TokenType::Pipeline => "|>",
+                TokenType::NullCoalesce => "??",
+                TokenType::And => "&&",
+                TokenType::Or => "||",
+                TokenType::Caret => "^",
+                TokenType::Tilde => "~",
+                TokenType::Shl => "<<",
+                TokenType::Shr => ">>",
                 TokenType::Comma => ",",
                 TokenType::Colon => ":",
+                TokenType::Ellipsis => "...",
+                TokenType::DotDot => "..",
+                TokenType::DotDotEq => "..=",
+                TokenType::Dot => ".",
                 TokenType::Semicolon => ";",
                 TokenType::LParen => "(",
                 TokenType::RParen => ")",
@@ -116,6 +229,8 @@ impl Display for TokenType {
                 TokenType::RBracket => "]",
                 TokenType::Lt => "<",
                 TokenType::Gt => ">",
+                TokenType::LtEq => "<=",
+                TokenType::GtEq => ">=",
                 TokenType::Eq => "==",
                 TokenType::NotEq => "!=",
                 TokenType::Illegal => "illegal",
@@ -129,6 +244,9 @@ impl Display for TokenType {
 pub enum TokenLiteral {
     Ident(String),
     Num(i64),
+    /// A number literal too large for an `i64`
+    BigNum(BigInt),
+    Float(f64),
     String(String),
 }
 
@@ -147,6 +265,13 @@ impl TokenLiteral {
         }
     }
 
+    pub fn float(&self) -> Option<f64> {
+        match self {
+            &TokenLiteral::Float(n) => Some(n),
+            _ => None,
+        }
+    }
+
     pub fn string(&self) -> Option<&str> {
         match self {
             TokenLiteral::String(s) => Some(s.as_str()),
@@ -0,0 +1,74 @@
+#![feature(variant_count)]
+
+use ast::Parser;
+use compiler::Compiler;
+use error::InterpretError;
+use eval::{define_macros, eval_program, expand_macros, Environment, Object};
+use lexer::Lexer;
+use std::{cell::RefCell, rc::Rc};
+use vm::Vm;
+
+pub mod ast;
+pub mod builtin;
+pub mod compiler;
+pub mod error;
+pub mod eval;
+pub mod lexer;
+pub mod repl;
+pub mod vm;
+
+/// Evaluates the source with the tree-walking interpreter in a fresh environment
+pub fn eval_str(src: &str) -> Result<Object, InterpretError> {
+    eval_in(src, Environment::new(), Environment::new())
+}
+
+/// Like [`eval_str`], but also returns everything the program wrote with
+/// `puts` and `print` instead of sending it to stdout
+pub fn eval_capturing(src: &str) -> (Result<Object, InterpretError>, String) {
+    let out = Rc::new(RefCell::new(Vec::new()));
+    let (env, macro_env) = (Environment::new(), Environment::new());
+    env.borrow().set_output(out.clone());
+    macro_env.borrow().set_output(out.clone());
+    let res = eval_in(src, env, macro_env);
+
+    let out = String::from_utf8_lossy(&out.take()).into_owned();
+    (res, out)
+}
+
+/// Evaluates the source in `env`, expanding its macros in `macro_env`
+fn eval_in(
+    src: &str,
+    env: Rc<RefCell<Environment>>,
+    macro_env: Rc<RefCell<Environment>>,
+) -> Result<Object, InterpretError> {
+    let mut program = parse(src)?;
+
+    define_macros(&mut program, &macro_env);
+    let program = expand_macros(program, &macro_env)?;
+
+    eval_program(program, &env).map(Rc::unwrap_or_clone)
+}
+
+/// Compiles the source and runs it on a fresh VM, returning the last popped value
+pub fn compile_and_run(src: &str) -> Result<Object, InterpretError> {
+    let program = parse(src)?;
+
+    let mut comp = Compiler::default();
+    comp.compile(program)?;
+
+    let mut vm = Vm::new(comp.bytecode());
+    vm.run()?;
+    Ok(vm.last_popped().cloned().unwrap_or(Object::Null))
+}
+
+/// Reads the file and evaluates it with [`eval_str`]
+pub fn run_file(path: &str) -> Result<Object, InterpretError> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|e| InterpretError::Io(format!("{}: {}", path, e)))?;
+    eval_str(&src)
+}
+
+fn parse(src: &str) -> Result<ast::Program, InterpretError> {
+    let lexer = Lexer::new(src.to_string());
+    Ok(Parser::new(lexer).parse()?)
+}
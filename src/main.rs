@@ -1,16 +1,4 @@
-#![feature(variant_count)]
-
-use ast::Parser;
-use eval::{eval_program, Environment};
-use lexer::Lexer;
-
-mod ast;
-mod builtin;
-mod compiler;
-mod eval;
-mod lexer;
-mod repl;
-mod vm;
+use monkey_interp::{repl, run_file};
 
 fn main() {
     let mut args = std::env::args().skip(1);
@@ -19,22 +7,10 @@ fn main() {
         0 => repl::start(),
         1 => {
             let file = args.next().unwrap();
-            run(&file)
+            if let Err(e) = run_file(&file) {
+                println!("Evaluation error: {}", e)
+            }
         }
         _ => println!("Usage: monkey [file]"),
     }
 }
-
-fn run(file: &str) {
-    let contents = std::fs::read_to_string(file).expect("Failed to open file");
-
-    let lexer = Lexer::new(contents);
-    let mut parser = Parser::new(lexer);
-
-    let env = Environment::new();
-    let program = parser.parse().unwrap();
-
-    if let Err(e) = eval_program(program, &env) {
-        println!("Evaluation error: {}", e)
-    }
-}
@@ -1,57 +1,87 @@
 use crate::{
     ast::Parser,
+    builtin::Rng,
     compiler::{Compiler, SymbolTableRef},
+    error::InterpretError,
     eval::Object,
     lexer::Lexer,
     vm::Vm,
 };
-use std::io::Write;
+use std::{cell::RefCell, io::Write, rc::Rc};
 
 pub fn start() {
-    let mut comp_state = None;
-    let mut vm_state = None;
+    let mut repl = Repl::default();
 
     loop {
-        match run(&mut comp_state, &mut vm_state) {
-            Ok(o) => println!("{}", o),
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).unwrap();
+
+        match repl.run_line(&input) {
+            Ok(o) => println!("{}", o.inspect()),
             Err(s) => println!("Errors: {}", s),
         }
     }
 }
 
-fn run(
-    comp_state: &mut Option<(SymbolTableRef, Vec<Object>)>,
-    vm_state: &mut Option<Vec<Object>>,
-) -> Result<Object, String> {
-    print!("> ");
-    std::io::stdout().flush().unwrap();
-
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-
-    let lexer = Lexer::new(input);
-    let mut parser = Parser::new(lexer);
-
-    let program = parser.parse().map_err(|e| {
-        e.into_iter().fold(String::new(), |mut acc, e| {
-            acc += &format!("{:?}", e);
-            acc
-        })
-    })?;
-
-    let mut comp = match comp_state {
-        Some((s, c)) => Compiler::new_with_state(s.clone(), c.clone()),
-        None => Compiler::default(),
-    };
-    comp.compile(program)?;
-    comp_state.replace(comp.state());
-
-    let mut vm = match vm_state {
-        Some(s) => Vm::new_with_state(comp.bytecode(), s.clone()),
-        None => Vm::new(comp.bytecode()),
-    };
-    vm.run()?;
-    vm_state.replace(vm.state());
-
-    Ok(vm.last_popped().clone())
+/// Compiles and runs one line at a time, keeping the symbols, constants and
+/// globals of earlier lines around for later ones
+#[derive(Default)]
+pub struct Repl {
+    compiler: Option<(SymbolTableRef, Vec<Object>)>,
+    globals: Option<Vec<Object>>,
+    rng: Rc<Rng>,
+}
+
+impl Repl {
+    /// Runs the statements in `src`, returning the last popped value. The
+    /// line is compiled against a copy of the symbols, which only replaces
+    /// them once it ran without errors, so a failed line defines nothing
+    pub fn run_line(&mut self, src: &str) -> Result<Object, InterpretError> {
+        let program = Parser::new(Lexer::new(src.to_string())).parse()?;
+
+        let mut comp = match &self.compiler {
+            Some((s, c)) => {
+                let symbols = Rc::new(RefCell::new(s.borrow().snapshot()));
+                Compiler::new_with_state(symbols, c.clone())
+            }
+            None => Compiler::default(),
+        };
+        comp.compile(program)?;
+        let state = comp.state();
+
+        let mut vm = match self.globals.take() {
+            Some(g) => Vm::new_with_state(comp.bytecode(), g),
+            None => Vm::new(comp.bytecode()),
+        };
+        vm.set_rng(self.rng.clone());
+        let ran = vm.run();
+        // Globals of symbols that weren't committed are overwritten by later
+        // lines, which get the same slots
+        self.globals = Some(vm.state());
+        ran?;
+
+        self.compiler = Some(state);
+        Ok(vm.last_popped().cloned().unwrap_or(Object::Null))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failed_lines_free_their_slots() {
+        let mut repl = Repl::default();
+        repl.run_line("let a = 1").unwrap();
+        assert!(repl.run_line("let b = 2; nope").is_err());
+        assert!(repl.run_line("let c = 3; c / 0").is_err());
+        assert_eq!(repl.run_line("let d = 4; d"), Ok(Object::Integer(4)));
+
+        let (symbols, _) = repl.compiler.as_ref().unwrap();
+        let index = symbols.borrow().resolve("d").map(|sym| sym.index);
+        assert_eq!(index, Some(1));
+    }
 }
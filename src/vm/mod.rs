@@ -1,24 +1,53 @@
 #![allow(dead_code)]
 
-use std::rc::Rc;
+use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
 
 use crate::{
-    builtin::Builtin,
-    compiler::{Bytecode, Bytes, OpCode},
-    eval::{CompiledFuncObj, Object},
+    builtin::{self, Builtin, NativeFn, NativeFunc, Output, Rng, CALLBACK_COST},
+    compiler::{Bytecode, Bytes, Compiler, OpCode, SymbolTableRef},
+    error::InterpretError,
+    eval::{
+        check_alloc, destructure, error_field, float_infix, get_member, int_infix, int_not,
+        iter_item, make_range, num_neg, repeat_str, resolve_index, set_index, slice,
+        CompiledFuncObj, MemoObj, Object, PartialObj, DEFAULT_MAX_ALLOC,
+    },
+    lexer::TokenType,
 };
 
 const STACK_SIZE: usize = 2048;
 const GLOBALS_SIZE: usize = 0xFFFF;
+pub const DEFAULT_MAX_FRAMES: usize = 512;
 
 struct Frame {
     func: Rc<CompiledFuncObj>,
     ip: usize,
     sp: usize,
+    /// Number of arguments the caller passed
+    args: usize,
+}
+
+/// The variable an update or index assignment stores to
+#[derive(Clone, Copy)]
+enum Var {
+    Global(u16),
+    Local(u8),
+}
+
+/// What a single [`Vm::step`] executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepInfo {
+    /// Offset of the instruction in the function it belongs to
+    pub ip: usize,
+    pub op: OpCode,
+    pub operands: Vec<u32>,
+    /// Top of the stack after the instruction ran
+    pub stack_top: Option<Object>,
 }
 
 pub struct Vm {
     constants: Vec<Object>,
+    /// See [`Bytecode::symbols`]
+    symbols: Option<SymbolTableRef>,
 
     globals: Vec<Object>,
     stack: Box<[Object; STACK_SIZE]>,
@@ -26,6 +55,16 @@ pub struct Vm {
     sp: usize,
 
     frames: Vec<Frame>,
+    max_frames: usize,
+    /// Calls from outside the bytecode still running, each counts as
+    /// [`CALLBACK_COST`] frames
+    callbacks: usize,
+    /// Whether anything has been popped, so `last_popped` has a value
+    popped: bool,
+    rng: Rc<Rng>,
+    out: Output,
+    /// See [`DEFAULT_MAX_ALLOC`]
+    max_alloc: usize,
 }
 
 impl Vm {
@@ -35,19 +74,30 @@ impl Vm {
                 instructions: b.instructions,
                 locals: 0,
                 params: 0,
+                defaults: 0,
+                rest: false,
+                lines: b.lines,
             }),
             ip: 0,
             sp: 0,
+            args: 0,
         };
         Vm {
             // instructions: b.instructions,
             constants: b.constants,
+            symbols: b.symbols,
 
             globals: vec![Object::Null; GLOBALS_SIZE],
             stack: vec![Object::Null; STACK_SIZE].try_into().unwrap(),
             sp: 0,
 
             frames: vec![frame],
+            max_frames: DEFAULT_MAX_FRAMES,
+            callbacks: 0,
+            popped: false,
+            rng: Rc::default(),
+            out: Output::default(),
+            max_alloc: DEFAULT_MAX_ALLOC,
         }
     }
 
@@ -59,177 +109,481 @@ impl Vm {
                 instructions: b.instructions,
                 locals: 0,
                 params: 0,
+                defaults: 0,
+                rest: false,
+                lines: b.lines,
             }),
             ip: 0,
             sp: 0,
+            args: 0,
         };
 
         Self {
             constants: b.constants,
+            symbols: b.symbols,
             globals,
             stack: vec![Object::Null; STACK_SIZE].try_into().unwrap(),
             sp: 0,
 
             frames: vec![frame],
+            max_frames: DEFAULT_MAX_FRAMES,
+            callbacks: 0,
+            popped: false,
+            rng: Rc::default(),
+            out: Output::default(),
+            max_alloc: DEFAULT_MAX_ALLOC,
         }
     }
 
+    pub fn new_with_max_frames(b: Bytecode, max_frames: usize) -> Self {
+        Self {
+            max_frames,
+            ..Self::new(b)
+        }
+    }
+
+    /// Makes `rand` draw from `rng`, so the sequence carries on from
+    /// another run
+    pub fn set_rng(&mut self, rng: Rc<Rng>) {
+        self.rng = rng;
+    }
+
+    /// Makes `puts` and `print` write to `w` instead of stdout
+    pub fn set_output(&mut self, w: Rc<RefCell<dyn Write>>) {
+        self.out = Output::new(w);
+    }
+
+    /// Sets the longest string, in bytes, and the most elements of an array
+    /// or hash the program may build
+    pub fn set_max_alloc(&mut self, len: usize) {
+        self.max_alloc = len;
+    }
+
+    /// Makes the global `index` a host function, the compiler has to know
+    /// the name as a global, see [`Compiler::define_native`]
+    ///
+    /// [`Compiler::define_native`]: crate::compiler::Compiler::define_native
+    pub fn register_native(&mut self, index: u16, name: &str, func: Box<NativeFunc>) {
+        self.globals[index as usize] = Object::Native(NativeFn::new(name, func));
+    }
+
     pub fn state(&self) -> Vec<Object> {
         self.globals.clone()
     }
 
+    /// Runs the program to its end. It doesn't go through [`Vm::step`], which
+    /// would decode the operands and clone the top of the stack for every
+    /// instruction only to throw them away
     pub fn run(&mut self) -> RunResult {
-        while self.ip() < self.instructions().len() {
-            let op: OpCode = self.instructions().read(self.ip());
-            *self.ip_mut() += 1;
-
-            match op {
-                OpCode::Constant => {
-                    let const_idx: u16 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 2;
-                    self.push(self.constants[const_idx as usize].clone())?;
-                }
-                OpCode::Add
-                | OpCode::Sub
-                | OpCode::Mul
-                | OpCode::Div
-                | OpCode::Greater
-                | OpCode::Eq
-                | OpCode::NotEq => self.execute_bin_op(op)?,
-                OpCode::Pop => {
-                    self.pop();
-                }
-                OpCode::True => self.push(Object::Bool(true))?,
-                OpCode::False => self.push(Object::Bool(false))?,
-                OpCode::Minus => {
-                    let right = self.pop();
-                    match right {
-                        Object::Integer(right) => self.push(Object::Integer(-right))?,
-                        _ => return Err(format!("unknown operator: -{}", right.kind())),
+        while self.execute_next()?.is_some() {}
+        Ok(())
+    }
+
+    /// Like [`Vm::run`], also counting how many times each opcode executed.
+    /// The instruction that failed, if any, isn't counted
+    pub fn run_profiled(&mut self) -> (RunResult, HashMap<OpCode, usize>) {
+        let mut counts = HashMap::new();
+        loop {
+            match self.execute_next() {
+                Ok(Some((_, op))) => *counts.entry(op).or_default() += 1,
+                Ok(None) => return (Ok(()), counts),
+                Err(e) => return (Err(e), counts),
+            }
+        }
+    }
+
+    /// Executes a single instruction, `None` once the program has ended
+    pub fn step(&mut self) -> Result<Option<StepInfo>, InterpretError> {
+        // Decoded up front, executing may switch to another frame
+        let ip = self.ip();
+        let operands = match self.instructions().try_read::<OpCode>(ip) {
+            Ok(op) => self
+                .operands(op, ip + 1)
+                .map_err(|e| e.located(|| format!("at {:04} {}{}", ip, op, self.line(ip))))?,
+            // The end of the program or a bad opcode, left to `execute_next`
+            Err(_) => Vec::new(),
+        };
+        let Some((ip, op)) = self.execute_next()? else {
+            return Ok(None);
+        };
+        Ok(Some(StepInfo {
+            ip,
+            op,
+            operands,
+            stack_top: self.stack_top().cloned(),
+        }))
+    }
+
+    /// Executes the next instruction, returning its offset and opcode. Unlike
+    /// [`Vm::step`] it neither decodes the operands nor copies the top of the
+    /// stack, which may be a large array
+    fn execute_next(&mut self) -> Result<Option<(usize, OpCode)>, InterpretError> {
+        if self.ip() >= self.instructions().len() {
+            return Ok(None);
+        }
+
+        let ip = self.ip();
+        let op: OpCode = self
+            .instructions()
+            .try_read(ip)
+            .map_err(|e| e.located(|| format!("at {:04}{}", ip, self.line(ip))))?;
+        *self.ip_mut() += 1;
+
+        self.execute(op)
+            .map_err(|e| e.located(|| format!("at {:04} {}{}", ip, op, self.line(ip))))?;
+
+        Ok(Some((ip, op)))
+    }
+
+    pub fn stack_top(&self) -> Option<&Object> {
+        if self.sp == 0 {
+            None
+        } else {
+            Some(&self.stack[self.sp - 1])
+        }
+    }
+
+    /// Value most recently popped off the stack, `None` if nothing was
+    pub fn last_popped(&self) -> Option<&Object> {
+        if self.popped {
+            self.stack.get(self.sp)
+        } else {
+            None
+        }
+    }
+}
+
+impl Vm {
+    /// Operands of `op`, which start at `offset`
+    fn operands(&self, op: OpCode, mut offset: usize) -> Result<Vec<u32>, InterpretError> {
+        let mut operands = vec![];
+        for width in op.def().operands {
+            let operand = match width {
+                1 => self.instructions().try_read::<u8>(offset)? as u32,
+                2 => self.instructions().try_read::<u16>(offset)? as u32,
+                _ => unreachable!(),
+            };
+            operands.push(operand);
+            offset += width;
+        }
+        Ok(operands)
+    }
+
+    /// `, line N` for the instruction at `ip` in the current frame, empty
+    /// when the bytecode has no line info
+    fn line(&self, ip: usize) -> String {
+        self.frame()
+            .func
+            .lines
+            .line_at(ip)
+            .map(|l| format!(", line {}", l))
+            .unwrap_or_default()
+    }
+
+    fn execute(&mut self, op: OpCode) -> RunResult {
+        match op {
+            OpCode::Constant => {
+                let const_idx: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
+                let constant =
+                    self.constants
+                        .get(const_idx as usize)
+                        .ok_or(InterpretError::Runtime(format!(
+                            "invalid constant index {}",
+                            const_idx
+                        )))?;
+                self.push(constant.clone())?;
+            }
+            OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::FloorDiv
+            | OpCode::Mod
+            | OpCode::Pow
+            | OpCode::Greater
+            | OpCode::Less
+            | OpCode::GreaterEq
+            | OpCode::LessEq
+            | OpCode::BitAnd
+            | OpCode::BitOr
+            | OpCode::BitXor
+            | OpCode::Shl
+            | OpCode::Shr
+            | OpCode::Eq
+            | OpCode::NotEq => self.execute_bin_op(op)?,
+            OpCode::Pop => {
+                self.pop()?;
+            }
+            OpCode::True => self.push(Object::Bool(true))?,
+            OpCode::False => self.push(Object::Bool(false))?,
+            OpCode::Minus => {
+                let right = self.pop()?;
+                match num_neg(&right) {
+                    Some(res) => self.push(res)?,
+                    None => {
+                        return Err(InterpretError::TypeMismatch(format!(
+                            "unknown operator: -{}",
+                            right.kind()
+                        )))
                     }
                 }
-                OpCode::Bang => {
-                    let right = self.pop();
-                    self.push(Object::Bool(!right.is_truthy()))?
-                }
-                OpCode::JumpNotTrue => {
-                    let jmp_to: u16 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 2;
-
-                    let cond = self.pop();
-                    if !cond.is_truthy() {
-                        *self.ip_mut() = jmp_to as usize;
+            }
+            OpCode::BitNot => {
+                let right = self.pop()?;
+                match int_not(&right) {
+                    Some(res) => self.push(res)?,
+                    None => {
+                        return Err(InterpretError::TypeMismatch(format!(
+                            "unknown operator: ~{}",
+                            right.kind()
+                        )))
                     }
                 }
-                OpCode::Jump => {
-                    let jmp_to: u16 = self.instructions().read(self.ip());
+            }
+            OpCode::Bang => {
+                let right = self.pop()?;
+                self.push(Object::Bool(!right.is_truthy()))?
+            }
+            OpCode::JumpNotTrue => {
+                let jmp_to: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
+
+                let cond = self.pop()?;
+                if !cond.is_truthy() {
                     *self.ip_mut() = jmp_to as usize;
                 }
-                OpCode::SetGlobal => {
-                    let idx: u16 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 2;
+            }
+            OpCode::JumpNotNull | OpCode::JumpFalsy | OpCode::JumpTruthy => {
+                let jmp_to: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
 
-                    self.globals[idx as usize] = self.pop();
+                let top = self.peek(0)?;
+                let jump = match op {
+                    OpCode::JumpNotNull => *top != Object::Null,
+                    OpCode::JumpFalsy => !top.is_truthy(),
+                    _ => top.is_truthy(),
+                };
+                if jump {
+                    *self.ip_mut() = jmp_to as usize;
+                } else {
+                    self.pop()?;
                 }
-                OpCode::GetGlobal => {
-                    let idx: u16 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 2;
+            }
+            OpCode::JumpIfArg => {
+                let param: u8 = self.instructions().try_read(self.ip())?;
+                let jmp_to: u16 = self.instructions().try_read(self.ip() + 1)?;
+                *self.ip_mut() += 3;
 
-                    self.push(self.globals[idx as usize].clone())?
+                if (param as usize) < self.frame().args {
+                    *self.ip_mut() = jmp_to as usize;
                 }
-                OpCode::Array => {
-                    let len: u16 = self.instructions().read(self.ip());
-                    let len = len as usize;
-                    *self.ip_mut() += 2;
-
-                    let mut arr = vec![Object::Null.into(); len];
-                    for i in (0..len).rev() {
-                        arr[i] = Rc::new(self.pop());
-                    }
+            }
+            OpCode::Jump => {
+                let jmp_to: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() = jmp_to as usize;
+            }
+            OpCode::SetGlobal => {
+                let idx: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
 
-                    self.push(Object::Array(crate::eval::ArrayObj { elements: arr }))?
-                }
-                OpCode::Hash => {
-                    let len: u16 = self.instructions().read(self.ip());
-                    let len = len as usize;
-                    *self.ip_mut() += 2;
-
-                    let mut pairs = vec![];
-                    for _ in 0..len {
-                        let v = Rc::new(self.pop());
-                        let k = Rc::new(self.pop());
-                        pairs.push((k, v));
-                    }
-                    self.push(Object::Hash(crate::eval::HashObj {
-                        map: pairs.into_iter().collect(),
-                    }))?
-                }
-                OpCode::Index => {
-                    let index = self.pop();
-                    let left = self.pop();
-                    self.execute_index_op(left, index)?;
-                }
-                OpCode::Call => {
-                    let args: u8 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 1;
+                let val = self.pop()?;
+                *self.global_mut(idx)? = val;
+            }
+            OpCode::GetGlobal => {
+                let idx: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
 
-                    self.execute_call(args)?;
+                let val = self.global_mut(idx)?.clone();
+                self.push(val)?
+            }
+            OpCode::Array => {
+                let len: u16 = self.instructions().try_read(self.ip())?;
+                let len = len as usize;
+                *self.ip_mut() += 2;
+                check_alloc(len, self.max_alloc)?;
+
+                let mut arr = vec![Object::Null.into(); len];
+                for i in (0..len).rev() {
+                    arr[i] = Rc::new(self.pop()?);
                 }
-                OpCode::ReturnValue => {
-                    let val = self.pop();
-                    self.sp = self.pop_frame().sp - 1;
-                    self.push(val)?;
+
+                self.push(Object::Array(crate::eval::ArrayObj { elements: arr }))?
+            }
+            OpCode::Hash => {
+                let len: u16 = self.instructions().try_read(self.ip())?;
+                let len = len as usize;
+                *self.ip_mut() += 2;
+                check_alloc(len, self.max_alloc)?;
+
+                let mut pairs = vec![];
+                for _ in 0..len {
+                    let v = Rc::new(self.pop()?);
+                    let k = Rc::new(self.pop()?);
+                    pairs.push((k, v));
                 }
-                OpCode::Return => {
-                    self.sp = self.pop_frame().sp - 1;
-                    self.push(Object::Null)?;
+                pairs.reverse();
+                for (k, _) in &pairs {
+                    k.check_hashable()?;
                 }
-                OpCode::SetLocal => {
-                    let idx: u8 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 1;
+                let hash = pairs.into_iter().collect();
+                self.push(Object::Hash(hash))?
+            }
+            OpCode::GetMember => {
+                let idx: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
 
-                    let val = self.pop();
-                    self.stack[self.frame().sp + idx as usize] = val;
-                }
-                OpCode::GetLocal => {
-                    let idx: u8 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 1;
+                let Object::String(name) =
+                    self.constants
+                        .get(idx as usize)
+                        .ok_or(InterpretError::Runtime(format!(
+                            "invalid constant index {}",
+                            idx
+                        )))?
+                else {
+                    return Err(InterpretError::Runtime(format!(
+                        "member name {} isn't a string",
+                        idx
+                    )));
+                };
+                let member = name.clone();
+                let name = member.rsplit('.').next().unwrap_or_default();
+                let left = self.pop()?;
+                let member = get_member(&left, name)?
+                    .ok_or_else(|| InterpretError::Runtime(format!("unknown member: {}", member)))?;
+                self.push(Rc::unwrap_or_clone(member))?
+            }
+            OpCode::Slice => {
+                let end = self.pop()?;
+                let start = self.pop()?;
+                let left = self.pop()?;
+                self.push(slice(&left, &start, &end)?)?;
+            }
+            OpCode::SetIndexGlobal => {
+                let idx: u16 = self.instructions().try_read(self.ip())?;
+                let count: u8 = self.instructions().try_read(self.ip() + 2)?;
+                *self.ip_mut() += 3;
+
+                self.set_index(Var::Global(idx), count)?;
+            }
+            OpCode::SetIndexLocal => {
+                let idx: u8 = self.instructions().try_read(self.ip())?;
+                let count: u8 = self.instructions().try_read(self.ip() + 1)?;
+                *self.ip_mut() += 2;
+
+                self.set_index(Var::Local(idx), count)?;
+            }
+            OpCode::Range => {
+                let inclusive: u8 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 1;
 
-                    let val = self.stack[self.frame().sp + idx as usize].clone();
-                    self.push(val)?;
+                let end = self.pop()?;
+                let start = self.pop()?;
+                self.push(make_range(&start, &end, inclusive != 0)?)?;
+            }
+            OpCode::Index => {
+                let index = self.pop()?;
+                let left = self.pop()?;
+                self.execute_index_op(left, index)?;
+            }
+            OpCode::Unpack => {
+                let names: u16 = self.instructions().try_read(self.ip())?;
+                let rest: u8 = self.instructions().try_read(self.ip() + 2)?;
+                *self.ip_mut() += 3;
+
+                let val = self.pop()?;
+                // Pushed in reverse so the following sets pop them in order
+                for v in destructure(&val, names as usize, rest != 0)?
+                    .into_iter()
+                    .rev()
+                {
+                    self.push(Rc::unwrap_or_clone(v))?;
                 }
-                OpCode::GetBuiltin => {
-                    let idx: u8 = self.instructions().read(self.ip());
-                    *self.ip_mut() += 1;
+            }
+            OpCode::Iter => {
+                iter_item(self.peek(0)?, 0)?;
+                self.push(Object::Integer(0))?
+            }
+            OpCode::IterNext => {
+                let jmp_to: u16 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 2;
 
-                    let builtin =
-                        Builtin::from_u8(idx).ok_or(&format!("unknown builtin {}", idx))?;
-                    self.push(Object::Builtin(builtin))?;
+                let &Object::Integer(idx) = self.peek(0)? else {
+                    return Err(InterpretError::Runtime(
+                        "loop position isn't an integer".to_string(),
+                    ));
+                };
+                match iter_item(self.peek(1)?, idx as usize)? {
+                    Some(item) => {
+                        self.stack[self.sp - 1] = Object::Integer(idx + 1);
+                        self.push(Rc::unwrap_or_clone(item))?
+                    }
+                    None => *self.ip_mut() = jmp_to as usize,
                 }
-                _ => todo!(),
             }
-        }
+            OpCode::IterEnd => {
+                self.pop()?;
+                self.pop()?;
+            }
+            OpCode::Call => {
+                let args: u8 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 1;
 
-        Ok(())
-    }
+                self.execute_call(args)?;
+            }
+            OpCode::ReturnValue => {
+                let val = self.pop()?;
+                self.sp = self.pop_frame()?.sp - 1;
+                self.push(val)?;
+            }
+            OpCode::Return => {
+                self.sp = self.pop_frame()?.sp - 1;
+                self.push(Object::Null)?;
+            }
+            OpCode::SetLocal => {
+                let idx: u8 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 1;
 
-    pub fn stack_top(&self) -> Option<&Object> {
-        if self.sp == 0 {
-            None
-        } else {
-            Some(&self.stack[self.sp - 1])
+                let slot = self.local_slot(idx)?;
+                self.stack[slot] = self.pop()?;
+            }
+            OpCode::GetLocal => {
+                let idx: u8 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 1;
+
+                let val = self.stack[self.local_slot(idx)?].clone();
+                self.push(val)?;
+            }
+            OpCode::UpdateGlobal => {
+                let idx: u16 = self.instructions().try_read(self.ip())?;
+                let args: u8 = self.instructions().try_read(self.ip() + 2)?;
+                *self.ip_mut() += 3;
+
+                self.update(Var::Global(idx), args)?;
+            }
+            OpCode::UpdateLocal => {
+                let idx: u8 = self.instructions().try_read(self.ip())?;
+                let args: u8 = self.instructions().try_read(self.ip() + 1)?;
+                *self.ip_mut() += 2;
+
+                self.update(Var::Local(idx), args)?;
+            }
+            OpCode::GetBuiltin => {
+                let idx: u8 = self.instructions().try_read(self.ip())?;
+                *self.ip_mut() += 1;
+
+                let builtin = Builtin::from_u8(idx)
+                    .ok_or(InterpretError::Runtime(format!("unknown builtin {}", idx)))?;
+                self.push(Object::Builtin(builtin))?;
+            }
         }
-    }
 
-    pub fn last_popped(&self) -> &Object {
-        &self.stack[self.sp]
+        Ok(())
     }
-}
 
-impl Vm {
     fn push(&mut self, obj: Object) -> RunResult {
         if self.sp >= STACK_SIZE {
-            Err(format!("Stack overflow"))
+            Err(InterpretError::StackOverflow("Stack overflow".to_string()))
         } else {
             self.stack[self.sp] = obj;
             self.sp += 1;
@@ -237,128 +591,417 @@ impl Vm {
         }
     }
 
-    fn pop(&mut self) -> Object {
-        let obj = self.stack[self.sp - 1].clone();
+    fn pop(&mut self) -> Result<Object, InterpretError> {
+        let obj = self.peek(0)?.clone();
         self.sp -= 1;
-        obj
+        self.popped = true;
+        Ok(obj)
+    }
+
+    /// The value `depth` slots below the top of the stack
+    fn peek(&self, depth: usize) -> Result<&Object, InterpretError> {
+        self.sp
+            .checked_sub(depth + 1)
+            .map(|i| &self.stack[i])
+            .ok_or_else(|| InterpretError::Runtime("stack underflow".to_string()))
+    }
+
+    fn global_mut(&mut self, idx: u16) -> Result<&mut Object, InterpretError> {
+        self.globals
+            .get_mut(idx as usize)
+            .ok_or_else(|| InterpretError::Runtime(format!("invalid global index {}", idx)))
+    }
+
+    /// Position on the stack of the current frame's `idx`th local
+    fn local_slot(&self, idx: u8) -> Result<usize, InterpretError> {
+        let frame = self.frame();
+        if idx as usize >= frame.func.locals {
+            return Err(InterpretError::Runtime(format!(
+                "invalid local index {}",
+                idx
+            )));
+        }
+        Ok(frame.sp + idx as usize)
+    }
+
+    fn var_mut(&mut self, var: Var) -> Result<&mut Object, InterpretError> {
+        match var {
+            Var::Global(idx) => self.global_mut(idx),
+            Var::Local(idx) => {
+                let slot = self.local_slot(idx)?;
+                Ok(&mut self.stack[slot])
+            }
+        }
+    }
+
+    /// Calls the builtin below the other `args - 1` arguments with the value
+    /// of `var` moved in front of them, then stores the result in `var`. If
+    /// the call fails `var` keeps its value
+    fn update(&mut self, var: Var, args: u8) -> RunResult {
+        let rest = (args as usize)
+            .checked_sub(1)
+            .ok_or_else(|| InterpretError::Runtime("update without arguments".to_string()))?;
+        let b = match self.peek(rest)? {
+            Object::Builtin(b) => *b,
+            o => {
+                return Err(InterpretError::TypeMismatch(format!(
+                    "cannot update a variable with {:?}",
+                    o
+                )))
+            }
+        };
+
+        let target = std::mem::replace(self.var_mut(var)?, Object::Null);
+        let rest = self.stack[(self.sp - rest)..self.sp]
+            .iter_mut()
+            .map(|o| std::mem::replace(o, Object::Null));
+        let mut args: Vec<Object> = std::iter::once(target).chain(rest).collect();
+        // Drop the other arguments and the builtin itself
+        self.sp -= args.len();
+
+        let (rng, out, max_alloc) = (self.rng.clone(), self.out.clone(), self.max_alloc);
+        match b.call_owned(
+            &mut args,
+            &mut |f, args| self.call_value(f, args).map(Rc::new),
+            &rng,
+            &out,
+            max_alloc,
+        ) {
+            Ok(val) => *self.var_mut(var)? = val,
+            Err(e) => {
+                *self.var_mut(var)? = std::mem::replace(&mut args[0], Object::Null);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pops the new value and the `count` indexes below it and stores the
+    /// value at those indexes of `var`, updating it in place
+    fn set_index(&mut self, var: Var, count: u8) -> RunResult {
+        if count == 0 {
+            return Err(InterpretError::Runtime(
+                "index assignment without an index".to_string(),
+            ));
+        }
+        let value = Rc::new(self.pop()?);
+        let mut indexes = (0..count)
+            .map(|_| self.pop().map(Rc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+        indexes.reverse();
+        set_index(self.var_mut(var)?, &indexes, value)
     }
 
     fn execute_call(&mut self, args: u8) -> RunResult {
-        match self
-            .stack
-            .get(self.sp - 1 - args as usize)
-            .expect("nothing to call")
-        {
+        match self.peek(args as usize)? {
             Object::CompiledFunc(c) => self.call_func(args, c.clone()),
             Object::Builtin(b) => self.call_builtin(args, *b),
-            o => return Err(format!("cannot call object {:?}", o)),
+            Object::Native(n) => self.call_native(args, n.clone()),
+            Object::Partial(p) => self.call_partial(args, p.clone()),
+            Object::Memo(m) => self.call_memo(args, m.clone()),
+            o => Err(InterpretError::TypeMismatch(format!(
+                "not a function: {}",
+                o.kind()
+            ))),
         }
     }
 
     fn call_builtin(&mut self, args: u8, b: Builtin) -> RunResult {
-        let args: Vec<Object> = self.stack[(self.sp - args as usize)..self.sp]
-            .into_iter()
-            .map(|x| x.clone())
+        if b == Builtin::Parse {
+            let args = self.stack[(self.sp - args as usize)..self.sp].to_vec();
+            let o = self.run_parsed(&args.iter().collect::<Vec<_>>(), false)?;
+            self.sp -= args.len() + 1;
+            return self.push(o);
+        }
+
+        // The arguments are popped either way, so the builtin gets to own them
+        let mut args: Vec<Object> = self.stack[(self.sp - args as usize)..self.sp]
+            .iter_mut()
+            .map(|o| std::mem::replace(o, Object::Null))
             .collect();
-        let a: Vec<&Object> = args.iter().collect();
+        // Drop the arguments and the builtin itself
+        self.sp -= args.len() + 1;
 
-        let o: Object = b.call(a)?;
+        let (rng, out, max_alloc) = (self.rng.clone(), self.out.clone(), self.max_alloc);
+        let o: Object = b.call_owned(
+            &mut args,
+            &mut |f, args| self.call_value(f, args).map(Rc::new),
+            &rng,
+            &out,
+            max_alloc,
+        )?;
         self.push(o)
     }
 
+    /// `parse(s)` compiles the expression in `s` against the globals of the
+    /// program and runs it on a VM of its own sharing them. Unlike in the
+    /// evaluator the locals of the calling function aren't visible, a local
+    /// shadowing a global doesn't hide it either. When `isolated`, as when
+    /// called through another function, no variables are visible at all.
+    /// Its constants are only kept when functions it returns refer to them
+    fn run_parsed(&mut self, args: &[&Object], isolated: bool) -> Result<Object, InterpretError> {
+        let program = builtin::parse_arg(args)?;
+        let symbols = match &self.symbols {
+            Some(s) if !isolated => Rc::new(RefCell::new(s.borrow().snapshot())),
+            _ => Compiler::default().state().0,
+        };
+        let constants = self.constants.len();
+        let mut compiler = Compiler::new_with_state(symbols, std::mem::take(&mut self.constants));
+        let compiled = compiler.compile(program);
+        let mut b = compiler.bytecode();
+        if let Err(e) = compiled {
+            b.constants.truncate(constants);
+            self.constants = b.constants;
+            return Err(e);
+        }
+
+        let mut vm = match isolated {
+            true => Vm::new(b),
+            false => Vm::new_with_state(b, std::mem::take(&mut self.globals)),
+        };
+        vm.set_rng(self.rng.clone());
+        vm.out = self.out.clone();
+        vm.set_max_alloc(self.max_alloc);
+        // The nested run counts toward the call stack limit like a callback
+        let used = self.frames.len() + (self.callbacks + 1) * CALLBACK_COST;
+        vm.max_frames = self.max_frames.saturating_sub(used);
+        let ran = vm.run();
+        self.constants = std::mem::take(&mut vm.constants);
+        if !isolated {
+            self.globals = std::mem::take(&mut vm.globals);
+        }
+
+        let res = ran.map(|_| vm.last_popped().cloned().unwrap_or(Object::Null));
+        if !res.as_ref().is_ok_and(holds_compiled_func) {
+            self.constants.truncate(constants);
+        }
+        res
+    }
+
+    fn call_native(&mut self, args: u8, n: NativeFn) -> RunResult {
+        let args: Vec<_> = self.stack[(self.sp - args as usize)..self.sp]
+            .iter()
+            .map(|o| Rc::new(o.clone()))
+            .collect();
+        // Drop the arguments and the native itself
+        self.sp -= args.len() + 1;
+        let o = n.call(args)?;
+        self.push(Rc::unwrap_or_clone(o))
+    }
+
+    /// Replaces the partial with the function it wraps and puts the bound
+    /// arguments in front of the passed ones before calling it
+    fn call_partial(&mut self, args: u8, p: PartialObj) -> RunResult {
+        let total = u8::try_from(args as usize + p.args.len())
+            .map_err(|_| InterpretError::Runtime("too many arguments".to_string()))?;
+        let start = self.sp - args as usize;
+        let passed: Vec<_> = (start..self.sp)
+            .map(|i| std::mem::replace(&mut self.stack[i], Object::Null))
+            .collect();
+        self.stack[start - 1] = Rc::unwrap_or_clone(p.func);
+        self.sp = start;
+        for arg in p.args.into_iter().map(Rc::unwrap_or_clone).chain(passed) {
+            self.push(arg)?;
+        }
+        self.execute_call(total)
+    }
+
+    /// Pushes the cached result, running the wrapped function to get it
+    /// when these arguments weren't seen before
+    fn call_memo(&mut self, args: u8, m: MemoObj) -> RunResult {
+        let args: Vec<_> = self.stack[(self.sp - args as usize)..self.sp]
+            .iter_mut()
+            .map(|o| Rc::new(std::mem::replace(o, Object::Null)))
+            .collect();
+        // Drop the arguments and the memoized function itself
+        self.sp -= args.len() + 1;
+
+        let res = match m.get(&args)? {
+            Some(res) => res,
+            None => {
+                let res = Rc::new(self.call_value(&m.func, args.clone())?);
+                m.insert(args, res.clone());
+                res
+            }
+        };
+        self.push(Rc::unwrap_or_clone(res))
+    }
+
+    /// Calls `f` from outside the bytecode, running until it returns. When
+    /// it fails the frames and stack are unwound to where they were, so a
+    /// caller like `try` can go on running
+    fn call_value(&mut self, f: &Object, args: Vec<Rc<Object>>) -> Result<Object, InterpretError> {
+        let (depth, sp) = (self.frames.len(), self.sp);
+        self.callbacks += 1;
+        let res = self.run_call(f, args);
+        self.callbacks -= 1;
+        if res.is_err() {
+            self.frames.truncate(depth);
+            self.sp = sp;
+        }
+        res
+    }
+
+    fn run_call(&mut self, f: &Object, args: Vec<Rc<Object>>) -> Result<Object, InterpretError> {
+        // Called through another function there's no caller scope to use
+        if let Object::Builtin(Builtin::Parse) = f {
+            let args: Vec<_> = args.iter().map(|a| &**a).collect();
+            return self.run_parsed(&args, true);
+        }
+        let depth = self.frames.len();
+        let argc = args.len();
+        self.push(f.clone())?;
+        for a in args {
+            self.push(Rc::unwrap_or_clone(a))?;
+        }
+        self.execute_call(argc as u8)?;
+        while self.frames.len() > depth {
+            if self.execute_next()?.is_none() {
+                return Err(InterpretError::Runtime(
+                    "function ended without returning".to_string(),
+                ));
+            }
+        }
+        self.pop()
+    }
+
     fn call_func(&mut self, args: u8, func: Rc<CompiledFuncObj>) -> RunResult {
-        if args as usize != func.params {
-            return Err(format!(
-                "wrong number of arguments. expected {}, got {}",
-                func.params, args
-            ));
+        let mut args = args as usize;
+        let required = func.params - func.defaults;
+        if args < required || (args > func.params && !func.rest) {
+            let max = (!func.rest).then_some(func.params);
+            return Err(InterpretError::arity("function", required, max, args));
+        }
+        let sp = self.sp - args;
+        if func.rest {
+            // The surplus arguments sit where the rest param goes
+            let start = sp + func.params;
+            let elements = (start..self.sp)
+                .map(|i| Rc::new(std::mem::replace(&mut self.stack[i], Object::Null)))
+                .collect();
+            self.sp = start;
+            self.push(Object::Array(crate::eval::ArrayObj { elements }))?;
+            args = args.min(func.params);
         }
         let locals = func.locals;
+        if sp + locals > STACK_SIZE {
+            return Err(InterpretError::StackOverflow("Stack overflow".to_string()));
+        }
         self.push_frame(Frame {
             func,
             ip: 0,
-            sp: self.sp - args as usize,
-        });
-        self.sp += locals;
+            sp,
+            args,
+        })?;
+        // Arguments are the first locals, so they are already in place, the
+        // function fills in the defaults of missing ones itself
+        self.sp = sp + locals;
         Ok(())
     }
 
     fn execute_index_op(&mut self, left: Object, index: Object) -> RunResult {
         match (&left, &index) {
-            (Object::Array(a), Object::Integer(i)) => {
+            (Object::Error(e), field) => self.push(error_field(e, field)),
+            (Object::Array(a), &Object::Integer(i)) => {
                 let el = a
                     .elements
-                    .get(*i as usize)
+                    .get(resolve_index(i, a.elements.len(), "array")?)
                     .map(|i| Rc::unwrap_or_clone(i.clone()))
                     .unwrap_or(Object::Null);
                 self.push(el)
             }
+            (Object::Range(r), &Object::Integer(i)) => {
+                let idx = resolve_index(i, r.len(), "range")?;
+                self.push(r.get(idx).map_or(Object::Null, Object::Integer))
+            }
             (Object::Hash(h), _) => {
+                index.check_hashable()?;
                 let el = h
-                    .map
                     .get(&index)
                     .map(|i| Rc::unwrap_or_clone(i.clone()))
                     .unwrap_or(Object::Null);
                 self.push(el)
             }
-            _ => Err(format!(
+            _ => Err(InterpretError::TypeMismatch(format!(
                 "index operator not supported: {} {}",
                 left.kind(),
                 index.kind()
-            )),
+            ))),
         }
     }
 
     fn execute_bin_op(&mut self, op: OpCode) -> RunResult {
-        let right = self.pop();
-        let left = self.pop();
+        let right = self.pop()?;
+        let left = self.pop()?;
 
         match (&left, &right) {
-            (Object::Integer(left), Object::Integer(right)) => match op {
-                OpCode::Add => self.push(Object::Integer(left + right)),
-                OpCode::Sub => self.push(Object::Integer(left - right)),
-                OpCode::Mul => self.push(Object::Integer(left * right)),
-                OpCode::Div => self.push(Object::Integer(left / right)),
-                OpCode::Eq => self.push(Object::Bool(left == right)),
-                OpCode::NotEq => self.push(Object::Bool(left != right)),
-                OpCode::Greater => self.push(Object::Bool(left > right)),
-                _ => unreachable!(),
-            },
+            (Object::Integer(_) | Object::BigInt(_), Object::Integer(_) | Object::BigInt(_)) => {
+                self.push(int_infix(&left, infix_token(op), &right)?)
+            }
+            (Object::Float(_), Object::Integer(_) | Object::BigInt(_) | Object::Float(_))
+            | (Object::Integer(_) | Object::BigInt(_), Object::Float(_)) => {
+                self.push(float_infix(&left, infix_token(op), &right)?)
+            }
             (Object::String(l), Object::String(r)) => match op {
-                OpCode::Add => self.push(Object::String(l.to_owned() + r)),
-                _ => Err(format!(
+                OpCode::Add => {
+                    check_alloc(l.len() + r.len(), self.max_alloc)?;
+                    self.push(Object::String(l.to_owned() + r))
+                }
+                OpCode::Greater => self.push(Object::Bool(l > r)),
+                OpCode::Less => self.push(Object::Bool(l < r)),
+                OpCode::GreaterEq => self.push(Object::Bool(l >= r)),
+                OpCode::LessEq => self.push(Object::Bool(l <= r)),
+                OpCode::Eq => self.push(Object::Bool(l == r)),
+                OpCode::NotEq => self.push(Object::Bool(l != r)),
+                _ => Err(InterpretError::TypeMismatch(format!(
                     "unknown operation: {} {} {}",
                     left.kind(),
                     op,
                     right.kind()
-                )),
+                ))),
             },
+            (Object::String(s), count @ (Object::Integer(_) | Object::BigInt(_)))
+            | (count @ (Object::Integer(_) | Object::BigInt(_)), Object::String(s))
+                if op == OpCode::Mul =>
+            {
+                self.push(repeat_str(s, count, self.max_alloc)?)
+            }
             _ if left.kind() == right.kind() => match op {
                 OpCode::Eq => self.push(Object::Bool(left == right)),
                 OpCode::NotEq => self.push(Object::Bool(left != right)),
-                _ => Err(format!(
+                _ => Err(InterpretError::TypeMismatch(format!(
                     "unknown operation: {} {} {}",
                     left.kind(),
                     op,
                     right.kind()
-                )),
+                ))),
             },
-            _ => Err(format!(
+            _ => Err(InterpretError::TypeMismatch(format!(
                 "unknown operation: {} {} {}",
                 left.kind(),
                 op,
                 right.kind()
-            )),
+            ))),
         }
     }
 
-    fn push_frame(&mut self, frame: Frame) {
+    fn push_frame(&mut self, frame: Frame) -> RunResult {
+        if self.frames.len() + self.callbacks * CALLBACK_COST >= self.max_frames {
+            return Err(InterpretError::StackOverflow(
+                "call stack exceeded".to_string(),
+            ));
+        }
         self.frames.push(frame);
+        Ok(())
     }
 
-    fn pop_frame(&mut self) -> Frame {
-        assert!(self.frames.len() > 1, "Cannot leave out of main frame");
-        self.frames.pop().unwrap()
+    fn pop_frame(&mut self) -> Result<Frame, InterpretError> {
+        if self.frames.len() <= 1 {
+            return Err(InterpretError::Runtime(
+                "return outside function".to_string(),
+            ));
+        }
+        Ok(self.frames.pop().expect("checked above"))
     }
 
     fn instructions(&self) -> &Bytes {
@@ -386,7 +1029,49 @@ impl Vm {
     }
 }
 
-pub type RunResult = Result<(), String>;
+pub type RunResult = Result<(), InterpretError>;
+
+/// Whether `obj` holds a compiled function, whose instructions refer to
+/// constants by their index
+fn holds_compiled_func(obj: &Object) -> bool {
+    match obj {
+        // The cache of a memoized function may hold some too
+        Object::CompiledFunc(_) | Object::Memo(_) => true,
+        Object::Partial(p) => {
+            holds_compiled_func(&p.func) || p.args.iter().any(|a| holds_compiled_func(a))
+        }
+        Object::Array(a) => a.elements.iter().any(|e| holds_compiled_func(e)),
+        Object::Hash(h) => h
+            .iter()
+            .any(|(k, v)| holds_compiled_func(k) || holds_compiled_func(v)),
+        _ => false,
+    }
+}
+
+/// Operator of a binary opcode, as taken by the arithmetic shared with eval
+fn infix_token(op: OpCode) -> TokenType {
+    match op {
+        OpCode::Add => TokenType::Plus,
+        OpCode::Sub => TokenType::Minus,
+        OpCode::Mul => TokenType::Star,
+        OpCode::Div => TokenType::Slash,
+        OpCode::FloorDiv => TokenType::FloorDiv,
+        OpCode::Mod => TokenType::Percent,
+        OpCode::Pow => TokenType::Pow,
+        OpCode::Eq => TokenType::Eq,
+        OpCode::NotEq => TokenType::NotEq,
+        OpCode::Greater => TokenType::Gt,
+        OpCode::Less => TokenType::Lt,
+        OpCode::GreaterEq => TokenType::GtEq,
+        OpCode::LessEq => TokenType::LtEq,
+        OpCode::BitAnd => TokenType::Ampersand,
+        OpCode::BitOr => TokenType::Pipe,
+        OpCode::BitXor => TokenType::Caret,
+        OpCode::Shl => TokenType::Shl,
+        OpCode::Shr => TokenType::Shr,
+        _ => unreachable!(),
+    }
+}
 
 #[cfg(test)]
 mod test;
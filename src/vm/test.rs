@@ -1,11 +1,11 @@
 use super::*;
 use crate::{
     ast::Parser,
-    compiler::Compiler,
-    eval::{ArrayObj, HashObj},
+    compiler::{Compiler, Instruction},
+    eval::{ArrayObj, BigInt, HashObj, RangeObj},
     lexer::Lexer,
 };
-use std::{collections::HashMap, rc::Rc};
+use std::rc::Rc;
 
 macro_rules! test {
     ($($case:expr),* $(,)?) => {
@@ -38,18 +38,145 @@ fn integer_math() {
         ("-10", Object::Integer(-10)),
         ("-50 + 100 + -50", Object::Integer(0)),
         ("(5 + 10 * 2 + 15 / 3) * 2 + -10", Object::Integer(50)),
+        ("-7 / 2", Object::Integer(-3)),
+        ("7 ~/ 2", Object::Integer(3)),
+        ("-7 ~/ 2", Object::Integer(-4)),
+        ("0xff + 0o10 + 0b11", Object::Integer(266)),
+        ("1_000 * 1_000", Object::Integer(1_000_000)),
+        ("7 % 3", Object::Integer(1)),
+        ("-7 % 2", Object::Integer(-1)),
+        ("1 + 10 % 4 * 2", Object::Integer(5)),
     )
 }
 
+#[test]
+fn pow() {
+    test!(
+        ("2 ** 10", Object::Integer(1024)),
+        ("2 ** 3 ** 2", Object::Integer(512)),
+        ("(2 ** 3) ** 2", Object::Integer(64)),
+    );
+    test_err!(
+        (
+            "2 ** -1",
+            "negative exponent: 2 ** -1 (at 0007 OpPow, line 1)"
+        ),
+        (
+            "2 ** 2000000",
+            "integer overflow: 2 ** 2000000 (at 0006 OpPow, line 1)"
+        ),
+    )
+}
+
+#[test]
+fn float_math() {
+    test!(
+        ("2.5", Object::Float(2.5)),
+        ("-0.5", Object::Float(-0.5)),
+        ("1.5 + 1", Object::Float(2.5)),
+        ("1 - 0.25", Object::Float(0.75)),
+        ("7 / 2.0", Object::Float(3.5)),
+        ("-7.0 ~/ 2", Object::Float(-4.0)),
+        ("2 ** -1.0", Object::Float(0.5)),
+        ("1 == 1.0", Object::Bool(true)),
+        ("1.5 < 2", Object::Bool(true)),
+        ("2 >= 2.5", Object::Bool(false)),
+        ("if (0.0) { 1 } else { 2 }", Object::Integer(2)),
+        ("math.min(3, 0.5, 1)", Object::Float(0.5)),
+        ("[1.5, 2.0]", Object::from(vec![1.5, 2.0])),
+        (
+            "let x = 3.7; [floor(x), ceil(x), round(x)]",
+            Object::from(vec![3, 4, 4])
+        ),
+        ("let x = 9; sqrt(x)", Object::Float(3.0)),
+    );
+    test_err!(
+        ("1.0 / 0", "division by zero (at 0006 OpDiv, line 1)"),
+        (
+            "1.5 & 1",
+            "unknown operator: FLOAT & INTEGER (at 0006 OpBitAnd, line 1)"
+        ),
+        (
+            "~1.5",
+            "unknown operator: ~FLOAT (at 0003 OpBitNot, line 1)"
+        ),
+    );
+}
+
+#[test]
+fn bitwise() {
+    test!(
+        ("6 & 3", Object::Integer(2)),
+        ("6 | 3 ^ 1", Object::Integer(6)),
+        ("1 << 4", Object::Integer(16)),
+        ("-16 >> 2", Object::Integer(-4)),
+        ("~0", Object::Integer(-1)),
+        ("1 << 63", BigInt::from(2).pow(63).into()),
+    );
+    test_err!(
+        (
+            "1 << 64",
+            "invalid shift amount: 1 << 64 (at 0006 OpShl, line 1)"
+        ),
+        (
+            "~true",
+            "unknown operator: ~BOOL (at 0001 OpBitNot, line 1)"
+        ),
+    )
+}
+
+#[test]
+fn big_integers() {
+    let fact = "let fact = fn(f, n) { if (n < 2) { 1 } else { n * f(f, n - 1) } };";
+    let program = Parser::new(Lexer::new(format!("{} fact(fact, 30)", fact)))
+        .parse()
+        .unwrap();
+    let mut compiler = Compiler::default();
+    compiler.compile(program).unwrap();
+    let mut vm = Vm::new(compiler.bytecode());
+    vm.run().unwrap();
+    assert!(matches!(vm.last_popped(), Some(Object::BigInt(_))));
+    assert_eq!(
+        vm.last_popped().unwrap().to_string(),
+        "265252859812191058636308480000000"
+    );
+
+    test!(
+        (
+            &format!("{} fact(fact, 30) / fact(fact, 28)", fact),
+            Object::Integer(870)
+        ),
+        ("2 ** 64", BigInt::from(2).pow(64).into()),
+        (
+            "-(-9223372036854775807 - 1)",
+            BigInt::from(2).pow(63).into()
+        ),
+        ("9223372036854775808", BigInt::from(2).pow(63).into()),
+        ("-9223372036854775808", Object::Integer(i64::MIN)),
+        ("0x1_0000_0000_0000_0000", BigInt::from(2).pow(64).into()),
+        ("2 ** 64 - 2 ** 64", Object::Integer(0)),
+        ("2 ** 64 > 5", Object::Bool(true)),
+        ("5 < -(2 ** 64)", Object::Bool(false)),
+    );
+}
+
 #[test]
 fn bool_expressions() {
     test!(
         ("true", Object::Bool(true)),
         ("false", Object::Bool(false)),
+        ("null == null", Object::Bool(true)),
+        (r#"{"a": 1}["b"] == null"#, Object::Bool(true)),
         ("1 < 2", Object::Bool(true)),
         ("1 > 2", Object::Bool(false)),
         ("1 < 1", Object::Bool(false)),
         ("1 > 1", Object::Bool(false)),
+        ("1 <= 1", Object::Bool(true)),
+        ("2 >= 3", Object::Bool(false)),
+        (r#""apple" < "banana""#, Object::Bool(true)),
+        (r#""apple" > "banana""#, Object::Bool(false)),
+        (r#""b" >= "b""#, Object::Bool(true)),
+        (r#""ab" <= "a""#, Object::Bool(false)),
         ("1 == 1", Object::Bool(true)),
         ("1 != 1", Object::Bool(false)),
         ("1 == 2", Object::Bool(false)),
@@ -92,6 +219,171 @@ fn conditionals() {
     )
 }
 
+#[test]
+fn while_loop() {
+    test!(
+        (
+            "let i = 0; let s = 0; while (i < 5) { [i, s] = [i + 1, s + i]; } s",
+            Object::Integer(10)
+        ),
+        (
+            "let i = 0; while (true) { [i] = [i + 1]; if (i == 3) { break; } } i",
+            Object::Integer(3)
+        ),
+        (
+            "let i = 0; let s = 0; while (i < 5) { [i] = [i + 1]; if (i == 2) { continue; }; [s] = [s + i]; } s",
+            Object::Integer(13)
+        ),
+        (
+            "let f = fn() { let i = 0; while (true) { [i] = [i + 1]; if (i > 2) { return i * 10; } } }; f()",
+            Object::Integer(30)
+        ),
+        (
+            "let i = 0; while (i < 10000) { [i] = [i + 1]; } i",
+            Object::Integer(10000)
+        ),
+        ("while (false) {}", Object::Null),
+        ("let i = 0; while (i < 3) { i += 1; }", Object::Null),
+        ("while (true) { break; }", Object::Null),
+    );
+}
+
+#[test]
+fn for_loop() {
+    test!(
+        (
+            "let s = 0; for (x in [1, 2, 3]) { [s] = [s + x]; } s",
+            Object::Integer(6)
+        ),
+        (
+            "let s = 0; for (x in range(10)) { if (x == 2) { continue; }; if (x == 5) { break; }; [s] = [s + x]; } s",
+            Object::Integer(8)
+        ),
+        (
+            "let f = fn(xs) { for (x in xs) { if (x > 1) { return x; } } }; f([1, 3, 2])",
+            Object::Integer(3)
+        ),
+        (
+            "let f = fn() { let n = 0; for (a in range(3)) { for (b in range(a)) { [n] = [n + b]; } } n }; f()",
+            Object::Integer(1)
+        ),
+        ("if (true) { for (x in [1]) { x } }", Object::Null),
+        ("for (x in 1..4) {}", Object::Null),
+        ("for (x in [1, 2]) { break; }", Object::Null),
+        ("range(-1, 2)", Object::from(vec![-1, 0, 1])),
+    );
+    test_err!((
+        "for (x in 5) {}",
+        "cannot iterate over INTEGER (at 0003 OpIter, line 1)"
+    ));
+}
+
+#[test]
+fn slice() {
+    test!(
+        ("[1, 2, 3, 4][1:3]", Object::from(vec![2, 3])),
+        ("let a = [1, 2, 3]; a[-2:]", Object::from(vec![2, 3])),
+        ("[1, 2][:9]", Object::from(vec![1, 2])),
+        (r#""hello"[:-3]"#, Object::from("he")),
+        (r#"let s = "hello"; s[len(s) - 2:]"#, Object::from("lo")),
+        ("(1..=4)[1:]", Object::Range(RangeObj { start: 2, end: 5 })),
+    );
+    test_err!((
+        "[1][true:]",
+        "slice bounds must be INTEGER, got BOOL (at 0010 OpSlice, line 1)"
+    ));
+}
+
+#[test]
+fn range_literal() {
+    test!(
+        ("1..4", Object::Range(RangeObj { start: 1, end: 4 })),
+        ("let n = 3; (0..=n)[3]", Object::Integer(3)),
+        ("(0..3)[3]", Object::Null),
+        ("len(2..5)", Object::Integer(3)),
+        (
+            "let s = 0; for (x in 1..=4) { s += x; } s",
+            Object::Integer(10)
+        ),
+        (
+            "let f = fn(n) { let s = []; for (i in 0..n) { s = push(s, i * i); } s }; f(4)",
+            Object::from(vec![0, 1, 4, 9])
+        ),
+        ("to_array(-1..2)", Object::from(vec![-1, 0, 1])),
+    );
+    test_err!((
+        "1..true",
+        "range bounds must be INTEGER, got INTEGER and BOOL (at 0004 OpRange, line 1)"
+    ));
+}
+
+#[test]
+fn nested_break_continue() {
+    test!(
+        (
+            "let s = 0; for (a in range(10)) { if (a == 1) { continue; }; let b = 0; while (true) { b += 1; if (b > a) { break; }; s += 10 * a + b; } if (a == 3) { break; } } s",
+            Object::Integer(139)
+        ),
+        (
+            "let n = 0; let i = 0; while (i < 3) { i += 1; for (x in range(5)) { if (x % 2 == 0) { continue; }; n += x; } } n",
+            Object::Integer(12)
+        ),
+    );
+}
+
+#[test]
+fn break_continue_in_expressions() {
+    test!(
+        (
+            "let s = 0; for (x in [1, 2, 3]) { s += [100, if (x == 2) { continue; } else { x }][1]; } s",
+            Object::Integer(4)
+        ),
+        (
+            "let i = 0; while (i < 5000) { i += 1; let a = [1, 2, if (true) { continue; } else { 3 }]; } i",
+            Object::Integer(5000)
+        ),
+        (
+            "let s = 0; for (x in 1..10) { s += x * {\"a\": 2, \"b\": if (x > 3) { break; } else { 1 }}.b; } s",
+            Object::Integer(6)
+        ),
+        (
+            "let f = fn(a, b) { a + b }; let n = 0; while (true) { n = f(n, if (n > 2) { break; } else { 1 }); } n",
+            Object::Integer(3)
+        ),
+    );
+}
+
+#[test]
+fn empty_blocks() {
+    test!(
+        ("if (true) {}", Object::Null),
+        ("if (false) {}", Object::Null),
+        ("if (false) { 10 } else {}", Object::Null),
+        ("if (true) { let x = 1; }", Object::Null),
+        (
+            "let a = if (true) {}; let b = 2; [a, b]",
+            Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::Null), Rc::new(Object::Integer(2))]
+            })
+        ),
+        ("fn() {}()", Object::Null),
+    )
+}
+
+#[test]
+fn block_expressions() {
+    test!(
+        ("let x = { let a = 1; a + 2 }; x", Object::Integer(3)),
+        ("{ let a = 1; }", Object::Null),
+        ("{ 1; 2 } * { 3 }", Object::Integer(6)),
+        (
+            "fn(b) { let x = { let a = b * 2; a + 1 }; x }(3)",
+            Object::Integer(7)
+        ),
+        ("fn() { let x = { return 5; }; 1 }()", Object::Integer(5)),
+    )
+}
+
 #[test]
 fn global_let() {
     test!(
@@ -113,9 +405,27 @@ fn strings() {
             r#" "mon" + "key" + "banana" "#,
             Object::String("monkeybanana".into())
         ),
+        (r#" "a" == "a" "#, Object::Bool(true)),
+        (r#" "a" != "a" "#, Object::Bool(false)),
+        (r#" "a" == "b" "#, Object::Bool(false)),
+        (r#" "a" != "b" "#, Object::Bool(true)),
     )
 }
 
+#[test]
+fn string_repeat() {
+    test!(
+        (r#""=" * 10"#, Object::String("==========".into())),
+        (r#"3 * "ab""#, Object::String("ababab".into())),
+        (r#""x" * 0"#, Object::String("".into())),
+        (r#""x" * -2"#, Object::String("".into())),
+    );
+    test_err!((
+        r#""ab" * 100000000"#,
+        "allocation limit exceeded (at 0006 OpMul, line 1)"
+    ));
+}
+
 #[test]
 fn arrays() {
     test!(
@@ -145,36 +455,122 @@ fn arrays() {
 
 #[test]
 fn hashes() {
+    test!(
+        ("{}", Object::Hash(HashObj::default())),
+        (
+            "{1: 2, 2: 3}",
+            Object::Hash(HashObj::from_iter([
+                (Rc::new(Object::Integer(1)), Rc::new(Object::Integer(2))),
+                (Rc::new(Object::Integer(2)), Rc::new(Object::Integer(3))),
+            ]))
+        ),
+        (
+            "{1 + 1: 2 * 2, 3 + 3: 4 * 4}",
+            Object::Hash(HashObj::from_iter([
+                (Rc::new(Object::Integer(2)), Rc::new(Object::Integer(4))),
+                (Rc::new(Object::Integer(6)), Rc::new(Object::Integer(16))),
+            ]))
+        ),
+    );
+    test_err!(
+        (
+            "{1.5: 1}",
+            "unusable as hash key: FLOAT (at 0006 OpHash, line 1)"
+        ),
+        (
+            "{[1]: 1}",
+            "unusable as hash key: ARRAY (at 0009 OpHash, line 1)"
+        ),
+        // Floats are never keys, even when equal to one
+        (
+            "{1: 2}[1.0]",
+            "unusable as hash key: FLOAT (at 0012 OpIndex, line 1)"
+        ),
+    )
+}
+
+#[test]
+fn hash_shorthand() {
+    test!(
+        (
+            "let x = 1; {x}",
+            Object::Hash(HashObj::from_iter([(
+                Rc::new(Object::from("x")),
+                Rc::new(Object::Integer(1))
+            )]))
+        ),
+        (
+            "fn(a, b) { {a, b} }(1, 2)",
+            Object::Hash(HashObj::from_iter([
+                (Rc::new(Object::from("a")), Rc::new(Object::Integer(1))),
+                (Rc::new(Object::from("b")), Rc::new(Object::Integer(2))),
+            ]))
+        ),
+    )
+}
+
+#[test]
+fn hash_order() {
     test!(
         (
-            "{}",
-            Object::Hash(HashObj {
-                map: HashMap::new()
+            "keys(delete({3: 1, 1: 2, 2: 3}, 1))",
+            Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::Integer(3)), Rc::new(Object::Integer(2))]
             })
         ),
         (
-            "{1: 2, 2: 3}",
-            Object::Hash(HashObj {
-                map: [
-                    (Rc::new(Object::Integer(1)), Rc::new(Object::Integer(2))),
-                    (Rc::new(Object::Integer(2)), Rc::new(Object::Integer(3))),
+            "keys({3: 1, 1: 2, 2: 3})",
+            Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Integer(3)),
+                    Rc::new(Object::Integer(1)),
+                    Rc::new(Object::Integer(2)),
                 ]
-                .into()
             })
         ),
         (
-            "{1 + 1: 2 * 2, 3 + 3: 4 * 4}",
-            Object::Hash(HashObj {
-                map: [
-                    (Rc::new(Object::Integer(2)), Rc::new(Object::Integer(4))),
-                    (Rc::new(Object::Integer(6)), Rc::new(Object::Integer(16))),
+            "values({3: 1, 1: 2, 2: 3, 3: 4})",
+            Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Integer(4)),
+                    Rc::new(Object::Integer(2)),
+                    Rc::new(Object::Integer(3)),
                 ]
-                .into()
             })
         ),
     )
 }
 
+#[test]
+fn hash_builtins() {
+    test!(
+        (
+            r#"entries({"b": 1, "a": 2})[1]"#,
+            Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("a".into())),
+                    Rc::new(Object::Integer(2)),
+                ]
+            })
+        ),
+        (
+            r#"let h = map_values({"b": 1, "a": 2}, fn(x) { x * 10 }); [h["a"], len(h)]"#,
+            Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::Integer(20)), Rc::new(Object::Integer(2))]
+            })
+        ),
+        (
+            r#"let f = fn(h) { map_values(h, fn(x) { len(x) }) }; f({"a": [1, 2]})["a"]"#,
+            Object::Integer(2)
+        ),
+        (r#"map_values({"a": [1]}, len)["a"]"#, Object::Integer(1)),
+    );
+    test_err!((
+        "map_values({1: 1}, fn(a, b) { a })",
+        "function expects 2 arguments, got 1 (at 0014 OpCall, line 1)"
+    ))
+}
+
 #[test]
 fn index() {
     test!(
@@ -183,7 +579,9 @@ fn index() {
         ("[[1, 1, 1]][0][0]", Object::Integer(1)),
         ("[][0]", Object::Null),
         ("[1, 2, 3][99]", Object::Null),
-        ("[1][-1]", Object::Null),
+        ("[1][-1]", Object::Integer(1)),
+        ("let a = [1, 2, 3]; a[-1] + a[-3]", Object::Integer(4)),
+        ("(1..=5)[-2]", Object::Integer(4)),
         ("{1: 1, 2: 2}[1]", Object::Integer(1)),
         (
             r#"{1: 1, 2: 2, "abc": "def"}["abc"]"#,
@@ -191,7 +589,17 @@ fn index() {
         ),
         ("{1: 1}[0]", Object::Null),
         ("{}[0]", Object::Null),
-    )
+    );
+    test_err!(
+        (
+            "[1, 2][-3]",
+            "index out of range: -3 for array of length 2 (at 0013 OpIndex, line 1)"
+        ),
+        (
+            "(0..2)[-3]",
+            "index out of range: -3 for range of length 2 (at 0012 OpIndex, line 1)"
+        ),
+    );
 }
 
 #[test]
@@ -249,6 +657,17 @@ fn functions_no_return() {
     )
 }
 
+#[test]
+fn redefined_locals() {
+    test!(
+        ("fn(a) { let a = a + 1; a }(1)", Object::Integer(2)),
+        (
+            "fn(xs) { let n = 0; for (x in xs) { n += x; } for (x in xs) { n += x; } n }([1, 2])",
+            Object::Integer(6)
+        ),
+    )
+}
+
 #[test]
 fn higher_oreder_funcs() {
     test!(
@@ -358,54 +777,251 @@ fn funcs_with_arguments() {
 }
 
 #[test]
-fn call_with_wrong_arguments() {
-    test_err!(
+fn default_params() {
+    test!(
+        ("let f = fn(x, y = 10) { x + y }; f(1)", Object::Integer(11)),
         (
-            "fn() { 1; }(1);",
-            "wrong number of arguments. expected 0, got 1"
+            "let f = fn(x, y = 10) { x + y }; f(1, 2)",
+            Object::Integer(3)
         ),
         (
-            "fn(a) { a; }();",
-            "wrong number of arguments. expected 1, got 0"
+            "let f = fn(x, y = x * 2, z = y + 1) { let w = 4; [x, y, z, w] }; f(1)[2]",
+            Object::Integer(3)
         ),
+        ("let f = fn(x = 3) { x }; f()", Object::Integer(3)),
         (
-            "fn(a, b) { a + b; }(1);",
-            "wrong number of arguments. expected 2, got 1"
+            "let n = 5; let f = fn(x = n) { x }; f()",
+            Object::Integer(5)
         ),
-    )
+    );
+    test_err!((
+        "fn(x, y = 1) { x }()",
+        "function expects 1 or 2 arguments, got 0 (at 0003 OpCall, line 1)"
+    ));
 }
 
 #[test]
-fn builtins() {
+fn rest_params() {
+    let ints = |xs: &[i64]| {
+        Object::Array(ArrayObj {
+            elements: xs.iter().map(|x| Rc::new(Object::Integer(*x))).collect(),
+        })
+    };
     test!(
-        (r#"len("")"#, Object::Integer(0)),
-        (r#"len("four")"#, Object::Integer(4)),
-        (r#"len("hello world")"#, Object::Integer(11)),
-        (r#"len([1, 2, 3, 4])"#, Object::Integer(4)),
-        (r#"first(["a", "b"])"#, Object::String("a".into())),
-        (r#"first([])"#, Object::Null),
-        (r#"last(["a", "b"])"#, Object::String("b".into())),
-        (r#"last([])"#, Object::Null),
+        ("fn(first, ...rest) { rest }(1, 2, 3)", ints(&[2, 3])),
+        ("fn(first, ...rest) { rest }(1)", ints(&[])),
         (
-            r#"rest(["a", "b", "c"])"#,
-            Object::Array(ArrayObj {
-                elements: vec![
-                    Rc::new(Object::String("b".into())),
-                    Rc::new(Object::String("c".into()))
-                ]
-            })
+            "fn(first, ...rest) { let x = first * 10; push(rest, x) }(1, 2, 3, 4)",
+            ints(&[2, 3, 4, 10])
         ),
+        ("fn(a, b = 5, ...rest) { push(rest, b) }(1)", ints(&[5])),
         (
-            r#"rest(["a"])"#,
-            Object::Array(ArrayObj { elements: vec![] })
+            "fn(a, b = 5, ...rest) { push(rest, b) }(1, 2, 3)",
+            ints(&[3, 2])
         ),
-        (r#"rest([])"#, Object::Array(ArrayObj { elements: vec![] })),
+        ("len(fn(...all) { all }())", Object::Integer(0)),
+    );
+    test_err!((
+        "fn(a, ...rest) { a }()",
+        "function expects at least 1 argument, got 0 (at 0003 OpCall, line 1)"
+    ));
+}
+
+#[test]
+fn allocation_limit() {
+    let run = |inp: &str| {
+        let program = Parser::new(Lexer::new(inp.to_string())).parse().unwrap();
+        let mut compiler = Compiler::default();
+        compiler.compile(program).unwrap();
+
+        let mut vm = Vm::new(compiler.bytecode());
+        vm.set_max_alloc(4);
+        vm.run()
+            .map(|_| vm.last_popped().cloned().unwrap())
+            .map_err(|e| e.to_string())
+    };
+
+    assert_eq!(run("[1, 2, 3, 4]"), Ok(Object::from(vec![1, 2, 3, 4])));
+    assert_eq!(run(r#""ab" + "cd""#), Ok(Object::from("abcd")));
+    for (inp, exp) in [
         (
-            r#"push(["a", "b"], "c")"#,
-            Object::Array(ArrayObj {
-                elements: vec![
-                    Rc::new(Object::String("a".into())),
-                    Rc::new(Object::String("b".into())),
+            "[1, 2, 3, 4, 5]",
+            "allocation limit exceeded (at 0015 OpArray, line 1)",
+        ),
+        (
+            "let x = 1; {x: 1, 2: 2, 3: 3, 4: 4, 5: 5}",
+            "allocation limit exceeded (at 0036 OpHash, line 1)",
+        ),
+        (
+            r#"let s = "ab"; s + "cde""#,
+            "allocation limit exceeded (at 0012 OpAdd, line 1)",
+        ),
+        (
+            "let a = [1, 2, 3, 4]; push(a, 5)",
+            "allocation limit exceeded (at 0026 OpCall, line 1)",
+        ),
+        // Calls that allocate aren't folded, which would skip the limit
+        (
+            "to_array(range(0, 10))",
+            "allocation limit exceeded (at 0010 OpCall, line 1)",
+        ),
+        (
+            r#"format("{}{}", "abc", "de")"#,
+            "allocation limit exceeded (at 0011 OpCall, line 1)",
+        ),
+        (
+            r#"let x = "a"; let i = 0; while (i < 40) { x = to_json([x, x]); i = i + 1 }"#,
+            "allocation limit exceeded (at 0033 OpCall, line 1)",
+        ),
+        // The VM running the parsed code has the same limit
+        (
+            r#"parse("[1, 2, 3, 4, 5]")"#,
+            "allocation limit exceeded (at 0015 OpArray, line 1)",
+        ),
+    ] {
+        assert_eq!(run(inp), Err(exp.to_string()), "{}", inp);
+    }
+
+    // The limit belongs to the VM it was set on
+    test!(("len([1, 2, 3, 4, 5])", Object::Integer(5)));
+}
+
+#[test]
+fn pipeline() {
+    test!(
+        (
+            "let inc = fn(x) { x + 1 }; let double = fn(x) { x * 2 }; 3 |> inc |> double",
+            Object::Integer(8)
+        ),
+        ("[1, 2, 3] |> rest |> len", Object::Integer(2)),
+    )
+}
+
+#[test]
+fn logical_operators() {
+    test!(
+        ("1 < 2 && 3 < 4", Object::Bool(true)),
+        ("1 < 2 && 3 > 4", Object::Bool(false)),
+        ("false || 2 > 1", Object::Bool(true)),
+        ("0 && assert(false)", Object::Integer(0)),
+        ("1 || assert(false)", Object::Integer(1)),
+        ("0 || \"x\"", Object::from("x")),
+        ("1 && 2", Object::Integer(2)),
+        (
+            "let f = fn(x) { x > 0 && x < 10 }; [f(5), f(50)]",
+            Object::from(vec![true, false])
+        ),
+        (
+            "let i = 0; while (i < 10 && i != 3) { i += 1; } i",
+            Object::Integer(3)
+        ),
+    );
+}
+
+#[test]
+fn null_coalescing() {
+    test!(
+        (r#"let h = {"a": 1}; h["b"] ?? 5"#, Object::Integer(5)),
+        ("if (false) { 1 } ?? 5", Object::Integer(5)),
+        ("3 ?? assert(false)", Object::Integer(3)),
+        ("false ?? 1", Object::Bool(false)),
+        ("null ?? null ?? 1", Object::Integer(1)),
+        ("let x = null; x ?? [x][0] ?? 2", Object::Integer(2)),
+        (
+            r#"let h = {}; h["a"] ?? h["b"] ?? "c""#,
+            Object::String("c".into())
+        ),
+        (
+            "let f = fn(x) { x ?? 0 }; [f(1), f(if (false) { 1 })]",
+            Object::from(vec![1, 0])
+        ),
+    )
+}
+
+#[test]
+fn modules() {
+    test!(
+        ("math.abs(-3)", Object::Integer(3)),
+        ("let f = fn(x) { math.abs(x) }; f(-4)", Object::Integer(4)),
+        (
+            "let f = fn(a, b) { math.min(a, b) + math.max(a, b) }; f(3, -1)",
+            Object::Integer(2)
+        ),
+        (
+            r#"let f = fn(s) { str.upper(s) }; f("ab")"#,
+            Object::String("AB".into())
+        ),
+        ("let m = math; m.max(1, 2)", Object::Integer(2)),
+        (r#"let h = {"k": 1}; h.k"#, Object::Integer(1)),
+    );
+    test_err(&[
+        (
+            "let m = math; m.bogus",
+            "unknown member: m.bogus (at 0009 OpGetMember, line 1)",
+        ),
+        (
+            r#"let h = {"a": 1}; h.b"#,
+            "unknown member: h.b (at 0015 OpGetMember, line 1)",
+        ),
+    ])
+}
+
+#[test]
+fn call_with_wrong_arguments() {
+    test_err!(
+        (
+            "fn() { 1; }(1);",
+            "function expects 0 arguments, got 1 (at 0006 OpCall, line 1)"
+        ),
+        (
+            "fn(a) { a; }();",
+            "function expects 1 argument, got 0 (at 0003 OpCall, line 1)"
+        ),
+        (
+            "fn(a, b) { a + b; }(1);",
+            "function expects 2 arguments, got 1 (at 0006 OpCall, line 1)"
+        ),
+        ("3()", "not a function: INTEGER (at 0003 OpCall, line 1)"),
+    )
+}
+
+#[test]
+fn builtins() {
+    test!(
+        (r#"len("")"#, Object::Integer(0)),
+        (r#"len("four")"#, Object::Integer(4)),
+        (r#"len("hello world")"#, Object::Integer(11)),
+        (r#"len("héllo")"#, Object::Integer(5)),
+        (r#"let s = "héllo"; s[0:len(s) - 1]"#, Object::from("héll")),
+        (r#"len([1, 2, 3, 4])"#, Object::Integer(4)),
+        (r#"len([1]) + len([1, 2]) * 2"#, Object::Integer(5)),
+        ("len({1: 2, 3: 4})", Object::Integer(2)),
+        ("len(pop([1, 2, 3]))", Object::Integer(2)),
+        ("set([1, 2, 3], 1, 5)[1]", Object::Integer(5)),
+        (r#"first(["a", "b"])"#, Object::String("a".into())),
+        (r#"first([])"#, Object::Null),
+        (r#"last(["a", "b"])"#, Object::String("b".into())),
+        (r#"last([])"#, Object::Null),
+        (
+            r#"rest(["a", "b", "c"])"#,
+            Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("b".into())),
+                    Rc::new(Object::String("c".into()))
+                ]
+            })
+        ),
+        (
+            r#"rest(["a"])"#,
+            Object::Array(ArrayObj { elements: vec![] })
+        ),
+        (r#"rest([])"#, Object::Array(ArrayObj { elements: vec![] })),
+        (
+            r#"push(["a", "b"], "c")"#,
+            Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::String("a".into())),
+                    Rc::new(Object::String("b".into())),
                     Rc::new(Object::String("c".into()))
                 ]
             })
@@ -437,47 +1053,559 @@ fn builtins() {
             })
         ),
     );
+    // Literal arguments are checked when compiling, see the compiler tests
+    test_err!(
+        (
+            "let x = 1; len(x)",
+            "argument to `len` not supported, got INTEGER, expected STRING, ARRAY, HASH or RANGE (at 0011 OpCall, line 1)"
+        ),
+        (
+            "let x = 1; first(x)",
+            "argument to `first` not supported, got INTEGER (at 0011 OpCall, line 1)"
+        ),
+        (
+            "let x = 1; push(x, 2)",
+            "argument to `push` not supported, got INTEGER (at 0014 OpCall, line 1)"
+        ),
+        (
+            r#"let f = len; f("one", "two")"#,
+            "len expects 1 argument, got 2 (at 0014 OpCall, line 1)"
+        ),
+        (
+            r#"let f = first; f("one", "two")"#,
+            "first expects 1 argument, got 2 (at 0014 OpCall, line 1)"
+        ),
+        (
+            r#"let f = last; f("one", "two")"#,
+            "last expects 1 argument, got 2 (at 0014 OpCall, line 1)"
+        ),
+        (
+            r#"let f = rest; f("one", "two")"#,
+            "rest expects 1 argument, got 2 (at 0014 OpCall, line 1)"
+        ),
+        (
+            r#"let f = push; f([])"#,
+            "push expects 2 arguments, got 1 (at 0011 OpCall, line 1)"
+        ),
+    )
+}
+
+#[test]
+fn folding_keeps_function_constants() {
+    test!(
+        ("first([fn() { 5 }])()", Object::Integer(5)),
+        (
+            "let g = first([fn(x) { x + 100 }]); g(1)",
+            Object::Integer(101)
+        ),
+        ("set([1, 2, 3], 0, fn() { 1 })[0]()", Object::Integer(1)),
+    )
+}
+
+#[test]
+fn let_array() {
+    test!(
+        ("let [a, b, c] = [1, 2, 3]; a + b * c", Object::Integer(7)),
+        (
+            "let [head, ...tail] = [1, 2, 3]; tail",
+            Object::Array(ArrayObj {
+                elements: vec![Rc::new(Object::Integer(2)), Rc::new(Object::Integer(3))]
+            })
+        ),
+        (
+            "let f = fn(p) { let [x, ...y] = p; x - len(y) }; f([5, 2, 2])",
+            Object::Integer(3)
+        ),
+    );
     test_err!(
-        (r#"len(1)"#, "argument to `len` not supported, got INTEGER"),
         (
-            r#"len("one", "two")"#,
-            "wrong number of arguments. expected 1, got 2"
+            "let [a] = 1;",
+            "cannot destructure INTEGER (at 0003 OpUnpack, line 1)"
         ),
         (
-            r#"first(1)"#,
-            "argument to `first` not supported, got INTEGER"
+            "let [a, b] = [1];",
+            "cannot destructure array of length 1 into 2 names (at 0006 OpUnpack, line 1)"
         ),
+    )
+}
+
+#[test]
+fn assign() {
+    test!(
+        ("let a = 1; a = a + 1; a", Object::Integer(2)),
+        ("let f = fn(a) { a = a * 10; a }; f(2)", Object::Integer(20)),
+        (
+            "let f = fn() { let i = 0; while (i < 3) { i = i + 1; } i }; f()",
+            Object::Integer(3)
+        ),
+        (
+            "let a = 1; let f = fn() { a = 5; }; f(); a",
+            Object::Integer(5)
+        ),
+        (
+            "let a = 10; a += 5; a -= 1; a *= 2 + 1; a /= 2; a",
+            Object::Integer(21)
+        ),
+        (
+            "let f = fn(xs) { let s = 0; for (x in xs) { s += x; } s }; f([1, 2, 3])",
+            Object::Integer(6)
+        ),
+    );
+}
+
+#[test]
+fn assign_array() {
+    test!(
         (
-            r#"first("one", "two")"#,
-            "wrong number of arguments. expected 1, got 2"
+            "let a = 1; let b = 2; [a, b] = [b, a]; a * 10 + b",
+            Object::Integer(21)
         ),
         (
-            r#"last(1)"#,
-            "argument to `last` not supported, got INTEGER"
+            "let a = 1; let b = 2; let c = 3; [a, b, c] = [b, c, a]; a * 100 + b * 10 + c",
+            Object::Integer(231)
         ),
         (
-            r#"last("one", "two")"#,
-            "wrong number of arguments. expected 1, got 2"
+            "let f = fn(a, b) { [a, b] = [b, a]; a - b }; f(1, 2)",
+            Object::Integer(1)
         ),
+    );
+    test_err!((
+        "let a = 1; [a] = [1, 2];",
+        "cannot destructure array of length 2 into 1 names (at 0015 OpUnpack, line 1)"
+    ))
+}
+
+#[test]
+fn assign_index() {
+    test!(
         (
-            r#"rest(1)"#,
-            "argument to `rest` not supported, got INTEGER"
+            "let a = [1, 2, 3]; a[0] = 10; a[-1] = 30; a[0] + a[1] + a[2]",
+            Object::Integer(42)
         ),
         (
-            r#"rest("one", "two")"#,
-            "wrong number of arguments. expected 1, got 2"
+            "let a = [1, 2]; let b = a; a[0] = 5; b[0]",
+            Object::Integer(1)
         ),
         (
-            r#"push(1, 2)"#,
-            "argument to `push` not supported, got INTEGER"
+            "let h = {\"a\": 1}; h[\"a\"] = 2; h[\"b\"] = 3; h[\"a\"] * 10 + h[\"b\"]",
+            Object::Integer(23)
         ),
         (
-            r#"push([])"#,
-            "wrong number of arguments. expected 2, got 1"
+            "let m = [[1, 2], {true: [3]}]; m[0][1] = 4; m[1][true][0] = 5; m[0][1] + m[1][true][0]",
+            Object::Integer(9)
+        ),
+        (
+            "let f = fn(xs) { for (i in 0..len(xs)) { xs[i] = xs[i] * 2; } xs }; f([1, 2])[1]",
+            Object::Integer(4)
+        ),
+        (
+            "let a = [[1], 2]; let b = a[0]; a[0][0] = 3; b[0] * 10 + a[0][0]",
+            Object::Integer(13)
+        ),
+    );
+    test_err!(
+        (
+            "let a = [1]; a[1] = 2;",
+            "index out of range: 1 for array of length 1 (at 0015 OpSetIndexGlobal, line 1)"
+        ),
+        (
+            "let s = \"ab\"; s[0] = \"c\";",
+            "index assignment not supported: STRING (at 0012 OpSetIndexGlobal, line 1)"
         ),
     )
 }
 
+#[test]
+fn last_popped() {
+    let run = |inp: &str| {
+        let program = Parser::new(Lexer::new(inp.to_string())).parse().unwrap();
+        let mut compiler = Compiler::default();
+        compiler.compile(program).unwrap();
+        let mut vm = Vm::new(compiler.bytecode());
+        vm.run().unwrap();
+        vm.last_popped().cloned()
+    };
+
+    assert_eq!(run(""), None);
+    assert_eq!(run("1; 2"), Some(Object::Integer(2)));
+}
+
+#[test]
+fn step() {
+    let program = Parser::new(Lexer::new("1 + 2".into())).parse().unwrap();
+    let mut compiler = Compiler::default();
+    compiler.compile(program).unwrap();
+    let mut vm = Vm::new(compiler.bytecode());
+
+    let mut step = || {
+        vm.step()
+            .unwrap()
+            .map(|s| (s.ip, s.op, s.operands, s.stack_top))
+    };
+    assert_eq!(
+        step(),
+        Some((0, OpCode::Constant, vec![1], Some(Object::Integer(1))))
+    );
+    assert_eq!(
+        step(),
+        Some((3, OpCode::Constant, vec![2], Some(Object::Integer(2))))
+    );
+    assert_eq!(
+        step(),
+        Some((6, OpCode::Add, vec![], Some(Object::Integer(3))))
+    );
+    assert_eq!(step(), Some((7, OpCode::Pop, vec![], None)));
+    assert_eq!(step(), None);
+    assert_eq!(step(), None);
+}
+
+#[test]
+fn run_profiled() {
+    let input = r#"
+    let count = fn(f, n) { if (n == 0) { 0 } else { f(f, n - 1) } };
+    count(count, 100)
+    "#;
+    let program = Parser::new(Lexer::new(input.into())).parse().unwrap();
+    let mut compiler = Compiler::default();
+    compiler.compile(program).unwrap();
+    let mut vm = Vm::new(compiler.bytecode());
+
+    let (res, profile) = vm.run_profiled();
+    assert_eq!(res, Ok(()));
+    assert_eq!(vm.last_popped(), Some(&Object::Integer(0)));
+
+    assert_eq!(profile[&OpCode::Call], 101);
+    assert_eq!(profile[&OpCode::ReturnValue], 101);
+    assert_eq!(profile[&OpCode::Eq], 101);
+    assert_eq!(profile[&OpCode::Sub], 100);
+    assert_eq!(profile[&OpCode::Pop], 1);
+    assert!(!profile.contains_key(&OpCode::Add));
+
+    let mut hottest: Vec<_> = profile.into_iter().collect();
+    hottest.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    assert_eq!(hottest[0], (OpCode::GetLocal, 401));
+    assert_eq!(hottest[1], (OpCode::Constant, 204));
+}
+
+#[test]
+fn malformed_bytecode() {
+    let run = |instructions: Bytes| {
+        let mut vm = Vm::new(Bytecode {
+            instructions,
+            constants: vec![Object::Null, Object::Integer(1)],
+            ..Default::default()
+        });
+        vm.run().map_err(|e| e.to_string())
+    };
+
+    let mut truncated = Bytes::default();
+    truncated.push(Instruction::new(OpCode::Constant, &[1]));
+    truncated.push(Instruction::new(OpCode::Array, &[1]));
+    truncated.remove(4);
+    assert_eq!(
+        run(truncated),
+        Err("unexpected end of bytecode (at 0003 OpArray)".to_string())
+    );
+
+    let mut bad_constant = Bytes::default();
+    bad_constant.push(Instruction::new(OpCode::Constant, &[7]));
+    assert_eq!(
+        run(bad_constant),
+        Err("invalid constant index 7 (at 0000 OpConstant)".to_string())
+    );
+
+    let mut bad_member = Bytes::default();
+    bad_member.push(Instruction::new(OpCode::Constant, &[1]));
+    bad_member.push(Instruction::new(OpCode::GetMember, &[7]));
+    assert_eq!(
+        run(bad_member),
+        Err("invalid constant index 7 (at 0003 OpGetMember)".to_string())
+    );
+
+    let mut bad_opcode = Bytes::default();
+    bad_opcode.push(Instruction::new(OpCode::Constant, &[1]));
+    bad_opcode.push(0xFFu8);
+    assert_eq!(
+        run(bad_opcode),
+        Err("invalid opcode 255 (at 0003)".to_string())
+    );
+
+    let single = |op, operands: &[u32]| Instruction::new(op, operands).make();
+    for (instructions, exp) in [
+        (single(OpCode::Pop, &[]), "stack underflow (at 0000 OpPop)"),
+        (single(OpCode::Add, &[]), "stack underflow (at 0000 OpAdd)"),
+        (
+            single(OpCode::Call, &[0]),
+            "stack underflow (at 0000 OpCall)",
+        ),
+        (
+            single(OpCode::IterNext, &[0]),
+            "stack underflow (at 0000 OpIterNext)",
+        ),
+        (
+            single(OpCode::GetLocal, &[3]),
+            "invalid local index 3 (at 0000 OpGetLocal)",
+        ),
+        (
+            single(OpCode::GetGlobal, &[0xFFFF]),
+            "invalid global index 65535 (at 0000 OpGetGlobal)",
+        ),
+        (
+            single(OpCode::Return, &[]),
+            "return outside function (at 0000 OpReturn)",
+        ),
+    ] {
+        assert_eq!(run(instructions), Err(exp.to_string()));
+    }
+}
+
+#[test]
+fn div_by_zero() {
+    test_err!(
+        ("1 / 0", "division by zero (at 0006 OpDiv, line 1)"),
+        ("1 ~/ 0", "division by zero (at 0006 OpFloorDiv, line 1)"),
+        ("1 % 0", "modulo by zero (at 0006 OpMod, line 1)"),
+    );
+
+    let program = Parser::new(Lexer::new("let x = 0; 5 / x".to_string()))
+        .parse()
+        .unwrap();
+    let mut compiler = Compiler::default();
+    compiler.compile(program).unwrap();
+    let mut vm = Vm::new(compiler.bytecode());
+    let err = vm.run().unwrap_err();
+    assert!(matches!(err.unlocated(), InterpretError::DivByZero(_)));
+}
+
+#[test]
+fn error_offset() {
+    test_err!(
+        (
+            "1; 2; 5 + true",
+            "unknown operation: INTEGER OpAdd BOOL (at 0012 OpAdd, line 1)"
+        ),
+        ("-true", "unknown operator: -BOOL (at 0001 OpMinus, line 1)"),
+        (
+            "let f = fn(x) {\n  x / 0\n};\nf(1);",
+            "division by zero (at 0005 OpDiv, line 2)"
+        ),
+    )
+}
+
+#[test]
+fn call_stack_limit() {
+    // Calls made by builtins count for more, so recursing through them
+    // stops well before it overflows the test thread's stack
+    test_err!(
+        (
+            "let f = fn(f, x) { f(f, x + 1) }; f(f, 0);",
+            "call stack exceeded (at 0010 OpCall, line 1)"
+        ),
+        (
+            r#"let f = fn(x) { map_values({"a": 1}, fn(v) { f(v) }) }; f(1)"#,
+            "call stack exceeded (at 0014 OpCall, line 1)"
+        ),
+        (
+            "let f = fn(x) { each([1], fn(v) { f(v) }) }; f(1)",
+            "call stack exceeded (at 0011 OpCall, line 1)"
+        ),
+        (
+            "let f = fn(x) { try(f, x) }; f(1)",
+            "call stack exceeded (at 0007 OpCall, line 1)"
+        ),
+    );
+
+    let cases = [
+        (
+            "let f = fn(f, x) { if (x == 0) { 0 } else { 1 + f(f, x - 1) } }; f(f, 8);",
+            Ok(()),
+        ),
+        (
+            "let f = fn(f, x) { if (x == 0) { 0 } else { 1 + f(f, x - 1) } }; f(f, 9);",
+            Err(InterpretError::Located(
+                Box::new(InterpretError::StackOverflow(
+                    "call stack exceeded".to_string(),
+                )),
+                "at 0028 OpCall, line 1".to_string(),
+            )),
+        ),
+    ];
+    for (inp, exp) in cases {
+        let program = Parser::new(Lexer::new(inp.to_string())).parse().unwrap();
+        let mut compiler = Compiler::default();
+        compiler.compile(program).unwrap();
+
+        let mut vm = Vm::new_with_max_frames(compiler.bytecode(), 10);
+        assert_eq!(vm.run(), exp, "{}", inp);
+    }
+}
+
+#[test]
+fn builtin_string_case() {
+    test!(
+        (r#"trim("  hi  ")"#, Object::String("hi".into())),
+        (r#"upper("abc")"#, Object::String("ABC".into())),
+        (r#"lower("ABC")"#, Object::String("abc".into())),
+        (r#"chars("héllo")[1]"#, Object::String("é".into())),
+        (r#"len(chars("héllo"))"#, Object::Integer(5)),
+        (r#"lines("a\nb\n")"#, Object::from(vec!["a", "b"])),
+    )
+}
+
+#[test]
+fn builtin_wrapping() {
+    test!(
+        (
+            "let x = 9223372036854775807; wrapping_add(x, 1)",
+            Object::Integer(i64::MIN)
+        ),
+        (
+            "wrapping_sub(-9223372036854775807 - 1, 1)",
+            Object::Integer(i64::MAX)
+        ),
+        ("wrapping_mul(-3, 7)", Object::Integer(-21)),
+    )
+}
+
+#[test]
+fn builtin_is_kind() {
+    test!(
+        ("is_int(1)", Object::Bool(true)),
+        (r#"is_string(1)"#, Object::Bool(false)),
+        ("is_fn(fn(x) { x })", Object::Bool(true)),
+        ("is_fn(len)", Object::Bool(true)),
+        ("is_null(if (false) { 1 })", Object::Bool(true)),
+    )
+}
+
+#[test]
+fn builtin_format() {
+    test!((
+        r#"format("{} + {} = {}", 1, 2, 1 + 2)"#,
+        Object::String("1 + 2 = 3".into())
+    ))
+}
+
+#[test]
+fn interpolation() {
+    test!(
+        (
+            r#"let x = 2; "value is ${x + 1}""#,
+            Object::from("value is 3")
+        ),
+        (
+            r#"let f = fn(n) { "${n}${if (n > 0) { f(n - 1) }}" }; f(2)"#,
+            Object::from("210null")
+        ),
+        (
+            r#""${1} {{}} { {} \${1}""#,
+            Object::from("1 {{}} { {} ${1}")
+        ),
+        (
+            r#"let format = fn(a) { a }; let x = 1; "v=${x}""#,
+            Object::from("v=1")
+        ),
+        (r#"let format = 1; "${1}""#, Object::from("1")),
+        (
+            r#"let f = fn() { let format = 2; "${format}" }; f()"#,
+            Object::from("2")
+        ),
+    )
+}
+
+#[test]
+fn builtin_print() {
+    assert_eq!(
+        run_with_output(r#"print("x = ", 1); puts(2); print(3)"#),
+        (Object::Null, "x = 12\n3".into())
+    );
+    // Code run by `parse` writes where its caller does
+    assert_eq!(
+        run_with_output(r#"parse("print(1)")"#),
+        (Object::Null, "1".into())
+    );
+}
+
+#[test]
+fn recursive_let() {
+    test!(
+        (
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            Object::Integer(610)
+        ),
+        (
+            "const count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } }; count(5)",
+            Object::Integer(5)
+        ),
+    );
+}
+
+#[test]
+fn builtin_each() {
+    for (inp, out) in [
+        ("each([1, 2, 3], fn(x) { puts(x) })", "1\n2\n3\n"),
+        (
+            r#"let f = fn(h) { each(h, fn(k, v) { print(k, v) }) }; f({"a": 1, "b": 2})"#,
+            "a1b2",
+        ),
+        ("each([1], print)", "1"),
+    ] {
+        assert_eq!(run_with_output(inp), (Object::Null, out.into()));
+    }
+
+    test_err(&[
+        (
+            "each([1], 2)",
+            "argument to `each` not supported, got INTEGER (at 0011 OpCall, line 1)",
+        ),
+        // Only where the callback failed, not also the call to `each`
+        (
+            "each([1], fn(x) { x / 0 })",
+            "division by zero (at 0005 OpDiv, line 1)",
+        ),
+    ]);
+}
+
+#[test]
+fn evaluation_order() {
+    assert_eq!(
+        run_with_output(
+            r#"let f = fn() { print("f"); 1 }; let g = fn() { print("g"); 2 }; f() < g()"#
+        ),
+        (Object::Bool(true), "fg".into())
+    );
+    test!(("2 < 1", Object::Bool(false)));
+}
+
+#[test]
+fn builtin_assert() {
+    test!(
+        ("assert(1 < 2)", Object::Null),
+        (r#"assert(true, "unused"); 5"#, Object::Integer(5)),
+    );
+    test_err!(
+        ("assert(false)", "assertion failed (at 0003 OpCall, line 1)"),
+        (
+            r#"assert(1 > 2, "math is broken")"#,
+            "math is broken (at 0012 OpCall, line 1)"
+        ),
+    )
+}
+
+#[test]
+fn builtin_rounding() {
+    test!(
+        ("floor(7)", Object::Integer(7)),
+        ("ceil(-3)", Object::Integer(-3)),
+        ("round(0)", Object::Integer(0)),
+        ("sqrt(16)", Object::Float(4.0)),
+    );
+    test_err!((
+        "sqrt(-4)",
+        "`sqrt` of a negative number: -4 (at 0006 OpCall, line 1)"
+    ));
+}
+
 fn test(cases: &[(&str, Object)]) {
     for (inp, exp) in cases {
         let lexer = Lexer::new(inp.to_string());
@@ -492,10 +1620,26 @@ fn test(cases: &[(&str, Object)]) {
         let mut vm = Vm::new(bytecode);
         vm.run().unwrap();
 
-        assert_eq!(vm.last_popped(), exp, "{}\n{}", s, inp);
+        assert_eq!(vm.last_popped(), Some(exp), "{}\n{}", s, inp);
     }
 }
 
+/// Runs `inp`, also returning what it wrote with `puts` and `print`
+fn run_with_output(inp: &str) -> (Object, String) {
+    let program = Parser::new(Lexer::new(inp.to_string()))
+        .parse()
+        .expect("Skill issue");
+    let mut compiler = Compiler::default();
+    compiler.compile(program).expect("Skill issue");
+
+    let out = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut vm = Vm::new(compiler.bytecode());
+    vm.set_output(out.clone());
+    vm.run().unwrap();
+    let res = vm.last_popped().cloned().unwrap();
+    (res, String::from_utf8(out.take()).unwrap())
+}
+
 fn test_err(cases: &[(&str, &str)]) {
     for (inp, exp) in cases {
         let lexer = Lexer::new(inp.to_string());
@@ -510,7 +1654,252 @@ fn test_err(cases: &[(&str, &str)]) {
 
         match vm.run() {
             Ok(_) => panic!("test did not error:\n{}", inp),
-            Err(e) => assert_eq!(&e, exp),
+            Err(e) => assert_eq!(&e.to_string(), exp),
         }
     }
 }
+
+#[test]
+fn shared_arrays_are_copied() {
+    let arr = |elems: Vec<Object>| Object::from(elems);
+    test!(
+        (
+            "let a = [1]; let b = push(a, 2); [a, b]",
+            arr(vec![Object::from(vec![1]), Object::from(vec![1, 2])])
+        ),
+        (
+            "let a = [1, 2]; let b = set(a, 0, 5); [a, b]",
+            arr(vec![Object::from(vec![1, 2]), Object::from(vec![5, 2])])
+        ),
+        (
+            "let a = [1]; let f = fn() { a }; push(a, 2); f()",
+            Object::from(vec![1])
+        ),
+        (
+            "let a = [1]; push(a, a)",
+            arr(vec![Object::Integer(1), Object::from(vec![1])])
+        ),
+        (
+            "let f = fn(f, n, a) { if (n == 0) { a } else { f(f, n - 1, push(a, n)) } }; f(f, 3, [])",
+            Object::from(vec![3, 2, 1])
+        ),        (
+            "let a = [1]; let b = a; a = push(a, 2); [a, b]",
+            arr(vec![Object::from(vec![1, 2]), Object::from(vec![1])])
+        ),
+        (
+            "let f = fn() { let a = [1, 2]; let b = a; a = set(a, 0, 5); [a, b] }; f()",
+            arr(vec![Object::from(vec![5, 2]), Object::from(vec![1, 2])])
+        ),
+        ("set([1, 2, 3], -1, 9)", Object::from(vec![1, 2, 9])),
+        ("let a = [1, 2, 3]; a = set(a, -3, 9); a", Object::from(vec![9, 2, 3])),
+        // A failed update leaves the variable as it was
+        (
+            "let a = [1, 2]; try(fn() { a = set(a, 5, 0); }); a",
+            Object::from(vec![1, 2])
+        ),
+        (
+            "let a = [1, 2]; try(fn() { a = set(a, -3, 0); }); a",
+            Object::from(vec![1, 2])
+        ),
+    )
+}
+
+#[test]
+fn builtin_parse() {
+    test!(
+        (r#"parse("[1, 2, 3]")"#, Object::from(vec![1, 2, 3])),
+        (r#"parse("fn(x) { x * 2 }")(4)"#, Object::Integer(8)),
+        (r#"let f = parse; f("len(\"abc\")")"#, Object::Integer(3)),
+        (r#"let x = 5; parse("x + 1")"#, Object::Integer(6)),
+        (r#"let x = 1; let f = fn() { parse("x * 2") }; f()"#, Object::Integer(2)),
+        (r#"let x = 1; parse("{ x = 2; }"); x"#, Object::Integer(2)),
+        (r#"let x = 1; parse("{ let x = 3; }"); x"#, Object::Integer(1)),
+        // Unlike in the evaluator the locals of the caller aren't visible
+        (r#"let x = 1; let f = fn() { let x = 2; parse("x") }; f()"#, Object::Integer(1)),
+    );
+    test_err(&[
+        (
+            r#"let f = fn() { let y = 1; parse("y") }; f()"#,
+            "undefined symbol: y (line 1:1) (at 0010 OpCall, line 1)",
+        ),
+        (
+            r#"let x = 1; each(["x"], parse)"#,
+            "undefined symbol: x (line 1:1) (at 0016 OpCall, line 1)",
+        ),
+        (
+            r#"let f = fn() { parse("f()") }; f()"#,
+            "call stack exceeded (at 0003 OpCall, line 1)",
+        ),
+        (
+            r#"parse("1; 2")"#,
+            "`parse` expects a single expression (at 0005 OpCall, line 1)",
+        ),
+    ])
+}
+
+#[test]
+fn parse_drops_unused_constants() {
+    // Each call compiles 100 constants, which would run past the `u16`
+    // constant operand if they were all kept
+    let ints: Vec<_> = (0..100).map(|i| i.to_string()).collect();
+    let src = format!(
+        r#"let s = "[{}]"; let i = 0; while (i < 700) {{ parse(s); i += 1; }} [parse("42"), parse("\"hi\"")]"#,
+        ints.join(", ")
+    );
+    test!((
+        src.as_str(),
+        Object::Array(ArrayObj {
+            elements: vec![
+                Rc::new(Object::Integer(42)),
+                Rc::new(Object::String("hi".into())),
+            ],
+        })
+    ));
+}
+
+#[test]
+fn builtin_to_json() {
+    test!(
+        (
+            r#"let x = [1, "a"]; to_json({"x": x, "y": puts()})"#,
+            Object::from(r#"{"x":[1,"a"],"y":null}"#)
+        ),
+        (r#"to_json([true, "\n"])"#, Object::from(r#"[true,"\n"]"#)),
+    );
+    test_err(&[(
+        "let f = fn() { 1 }; to_json([f])",
+        "cannot convert COMPILED FUNCTION to JSON (at 0014 OpCall, line 1)",
+    )])
+}
+
+#[test]
+fn builtin_from_json() {
+    test!((
+        r#"let s = "{\"a\": [1, true]}"; from_json(s)["a"]"#,
+        Object::from(vec![Object::Integer(1), Object::Bool(true)])
+    ));
+    test_err(&[(
+        r#"let s = "["; from_json(s)"#,
+        "invalid JSON: unexpected end of input at offset 1 (at 0011 OpCall, line 1)",
+    )])
+}
+
+#[test]
+fn builtin_try() {
+    test!(
+        (
+            r#"let r = try(fn() { 1 / 0 }); if (is_error(r)) { r["message"] }"#,
+            // The same message as in the evaluator, the location is kept apart
+            Object::String("division by zero".into())
+        ),
+        (
+            "let div = fn(a, b) { a / b }; let f = fn(x) { try(div, 6, x) }; [f(2), is_error(f(0)), f(3)]",
+            Object::Array(ArrayObj {
+                elements: vec![
+                    Rc::new(Object::Integer(3)),
+                    Rc::new(Object::Bool(true)),
+                    Rc::new(Object::Integer(2)),
+                ],
+            })
+        ),
+        ("try(fn(a, b) { a ~/ b }, 7, 2)", Object::Integer(3)),
+        ("is_error(try(fn() { 1 }))", Object::Bool(false)),
+    );
+    for src in ["try(fn() { 1 / 0 })", "try(len, 1)"] {
+        let vm = crate::compile_and_run(src).unwrap();
+        assert_eq!(vm, crate::eval_str(src).unwrap(), "{}", src);
+        assert!(!vm.to_string().contains("(at "), "{}", vm);
+    }
+    test_err(&[(
+        "let f = fn(n) { f(n + 1) }; try(f, 0)",
+        "call stack exceeded (at 0009 OpCall, line 1)",
+    )])
+}
+
+#[test]
+fn builtin_rand() {
+    test!(
+        (
+            "seed(42); [rand(), rand()]",
+            Object::from(vec![9054096345292791428, 3415151264702222905])
+        ),
+        (
+            "let f = fn() { seed(3); rand_range(0, 100) }; f() == f()",
+            Object::Bool(true)
+        ),
+        // The VM running the parsed code carries on the same sequence
+        (
+            r#"seed(42); [rand(), parse("rand()")]"#,
+            Object::from(vec![9054096345292791428, 3415151264702222905])
+        ),
+    );
+    test_err(&[(
+        "rand_range(2, 1)",
+        "`rand_range` expects lo < hi, got 2 and 1 (at 0008 OpCall, line 1)",
+    )])
+}
+
+#[test]
+fn builtin_memoize() {
+    test!(
+        (
+            r#"
+            let calls = 0;
+            let f = memoize(fn(a, b) { [calls] = [calls + 1]; a + b });
+            let g = fn(h) { h(1, 2) + h(1, 2) };
+            [g(f), f(2, 1), f(1, 2), f("a", "b"), f("a", "b"), calls]"#,
+            Object::Array(ArrayObj {
+                elements: [
+                    Object::Integer(6),
+                    Object::Integer(3),
+                    Object::Integer(3),
+                    Object::String("ab".into()),
+                    Object::String("ab".into()),
+                    Object::Integer(3),
+                ]
+                .into_iter()
+                .map(Rc::new)
+                .collect(),
+            })
+        ),
+        (r#"memoize(len)("ab")"#, Object::Integer(2)),
+        // Only fast when the recursive calls go through the cache
+        (
+            r#"
+            let fib = memoize(fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } });
+            fib(30)"#,
+            Object::Integer(832040)
+        ),
+    );
+    test_err(&[(
+        "memoize(len)([1])",
+        "unusable as hash key: ARRAY (at 0012 OpCall, line 1)",
+    )])
+}
+
+#[test]
+fn builtin_partial() {
+    test!(
+        (
+            "let add = fn(a, b) { a + b }; let inc = partial(add, 1); inc(4)",
+            Object::Integer(5)
+        ),
+        (
+            "let f = fn(a, b, c) { a * 100 + b * 10 + c }; partial(partial(f, 1), 2)(3)",
+            Object::Integer(123)
+        ),
+        (
+            "let f = fn(a, b) { a - b }; let g = fn(h) { h() + 1 }; g(partial(f, 10, 3))",
+            Object::Integer(8)
+        ),
+        ("partial(push, [1])(2)", Object::from(vec![1, 2])),
+        (
+            "let sum = fn(...xs) { len(xs) }; partial(sum, 1, 2)(3, 4)",
+            Object::Integer(4)
+        ),
+    );
+    test_err(&[(
+        "let x = 1; partial(x, 2)",
+        "argument to `partial` not supported, got INTEGER (at 0014 OpCall, line 1)",
+    )])
+}
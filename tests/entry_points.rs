@@ -0,0 +1,296 @@
+use monkey_interp::{
+    ast::Parser,
+    compile_and_run,
+    compiler::Compiler,
+    error::InterpretError,
+    eval::{eval_program, Environment, Object},
+    eval_capturing, eval_str,
+    lexer::Lexer,
+    repl::Repl,
+    run_file,
+    vm::Vm,
+};
+use std::rc::Rc;
+
+const PROGRAM: &str = r#"
+let add = fn(a, b) { a + b };
+let twice = fn(f, x) { f(f(x, x), x) };
+twice(add, 3) * len([1, 2])
+"#;
+
+#[test]
+fn eval_str_runs_program() {
+    assert_eq!(eval_str(PROGRAM), Ok(Object::Integer(18)));
+    assert_eq!(eval_str("let x = 1; x"), Ok(Object::Integer(1)));
+    assert_eq!(
+        eval_str("x"),
+        Err(InterpretError::Undefined(
+            "identifier not found: x".to_string()
+        ))
+    );
+}
+
+#[test]
+fn chained_comparison_is_rejected() {
+    let err = Err(InterpretError::Parse(
+        "comparisons need parentheses to be chained: 1 < 2 < 3".to_string(),
+    ));
+    assert_eq!(eval_str("1 < 2 < 3"), err);
+    assert_eq!(compile_and_run("1 < 2 < 3"), err);
+}
+
+#[test]
+fn compile_and_run_runs_program() {
+    assert_eq!(compile_and_run(PROGRAM), Ok(Object::Integer(18)));
+    assert_eq!(
+        compile_and_run("x"),
+        Err(InterpretError::Undefined(
+            "undefined symbol: x (line 1:1)".to_string()
+        ))
+    );
+}
+
+#[test]
+fn empty_program() {
+    assert_eq!(eval_str(""), Ok(Object::Null));
+    assert_eq!(compile_and_run(""), Ok(Object::Null));
+}
+
+#[test]
+fn entry_points_start_fresh() {
+    assert_eq!(eval_str("let x = 1; x"), Ok(Object::Integer(1)));
+    assert!(eval_str("x").is_err());
+
+    assert_eq!(compile_and_run("let x = 1; x"), Ok(Object::Integer(1)));
+    assert!(compile_and_run("x").is_err());
+}
+
+#[test]
+fn parse_errors_are_reported() {
+    assert!(matches!(
+        eval_str("let = 1;"),
+        Err(InterpretError::Parse(_))
+    ));
+    assert!(matches!(eval_str("1 @ 2"), Err(InterpretError::Lex(_))));
+    assert_eq!(
+        eval_str("let = 1; 1 +;").map_err(|e| e.to_string()),
+        Err("expected ident, got =; unexpected token: =; unexpected token: ;".to_string())
+    );
+    assert!(compile_and_run("let = 1;").is_err());
+}
+
+#[test]
+fn run_file_runs_program() {
+    let path = std::env::temp_dir().join("monkey_run_file_test.monkey");
+    std::fs::write(&path, PROGRAM).unwrap();
+
+    let res = run_file(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(res, Ok(Object::Integer(18)));
+
+    assert!(matches!(
+        run_file("does/not/exist.monkey"),
+        Err(InterpretError::Io(_))
+    ));
+}
+
+#[test]
+fn eval_capturing_returns_output() {
+    let (res, out) = eval_capturing(r#"puts("a"); puts(1, [2]); print("b"); 3"#);
+    assert_eq!(res, Ok(Object::Integer(3)));
+    assert_eq!(out, "a\n1\n[2]\nb");
+
+    let (res, out) = eval_capturing(r#"puts("before"); x"#);
+    assert!(res.is_err());
+    assert_eq!(out, "before\n");
+}
+
+#[test]
+fn objects_convert_to_and_from_rust() {
+    assert_eq!(Object::from(5), Object::Integer(5));
+    assert_eq!(i64::try_from(&Object::from(-3)), Ok(-3));
+    assert_eq!(bool::try_from(&Object::from(true)), Ok(true));
+    assert_eq!(String::try_from(&Object::from("hi")), Ok("hi".to_string()));
+    assert_eq!(
+        Vec::<i64>::try_from(&Object::from(vec![1, 2, 3])),
+        Ok(vec![1, 2, 3])
+    );
+
+    let res = eval_str(r#"[upper("a"), "b"]"#).unwrap();
+    assert_eq!(
+        Vec::<String>::try_from(&res),
+        Ok(vec!["A".into(), "b".into()])
+    );
+
+    assert_eq!(
+        i64::try_from(&Object::from("1")),
+        Err(InterpretError::TypeMismatch(
+            "expected INTEGER, got STRING".to_string()
+        ))
+    );
+    assert!(Vec::<i64>::try_from(&Object::from(vec!["a"])).is_err());
+    assert!(i64::try_from(&eval_str("2 ** 70").unwrap()).is_err());
+}
+
+fn double(args: Vec<Rc<Object>>) -> Result<Rc<Object>, InterpretError> {
+    match &args[..] {
+        [x] => Ok(Rc::new(Object::from(i64::try_from(&**x)? * 2))),
+        _ => Err(InterpretError::WrongArity(format!(
+            "double expects 1 argument, got {}",
+            args.len()
+        ))),
+    }
+}
+
+#[test]
+fn natives_are_callable() {
+    let src = "let f = fn(x) { double(x) + 1 }; [f(4), map_values({1: 2}, double)[1]]";
+    let expected = Object::from(vec![Object::Integer(9), Object::Integer(4)]);
+
+    let env = Environment::new();
+    env.borrow_mut().register_native("double", Box::new(double));
+    let program = Parser::new(Lexer::new(src.to_string())).parse().unwrap();
+    assert_eq!(
+        eval_program(program, &env).map(Rc::unwrap_or_clone),
+        Ok(expected.clone())
+    );
+
+    let program = Parser::new(Lexer::new(src.to_string())).parse().unwrap();
+    let mut comp = Compiler::default();
+    let idx = comp.define_native("double");
+    comp.compile(program).unwrap();
+    let mut vm = Vm::new(comp.bytecode());
+    vm.register_native(idx, "double", Box::new(double));
+    vm.run().unwrap();
+    assert_eq!(vm.last_popped(), Some(&expected));
+
+    let env = Environment::new();
+    env.borrow_mut().register_native("double", Box::new(double));
+    let program = Parser::new(Lexer::new("double(1, 2)".to_string()))
+        .parse()
+        .unwrap();
+    assert!(matches!(
+        eval_program(program, &env),
+        Err(InterpretError::WrongArity(_))
+    ));
+}
+
+#[test]
+fn repl_keeps_state_between_lines() {
+    let mut repl = Repl::default();
+    assert_eq!(repl.run_line("let a = 1"), Ok(Object::Integer(1)));
+    assert_eq!(repl.run_line("let b = a + 1"), Ok(Object::Integer(2)));
+    assert_eq!(repl.run_line("a + b"), Ok(Object::Integer(3)));
+    assert_eq!(
+        repl.run_line("let add = fn(x) { x + a }; add(b)"),
+        Ok(Object::Integer(3))
+    );
+    assert_eq!(repl.run_line("add(10)"), Ok(Object::Integer(11)));
+}
+
+#[test]
+fn repl_keeps_the_seed_between_lines() {
+    let mut repl = Repl::default();
+    assert_eq!(repl.run_line("seed(42)"), Ok(Object::Null));
+    assert_eq!(
+        repl.run_line("rand()"),
+        Ok(Object::Integer(9054096345292791428))
+    );
+    assert_eq!(
+        repl.run_line("rand()"),
+        Ok(Object::Integer(3415151264702222905))
+    );
+}
+
+#[test]
+fn repl_recovers_from_bad_lines() {
+    let mut repl = Repl::default();
+    assert_eq!(repl.run_line("let a = 1"), Ok(Object::Integer(1)));
+    assert!(repl.run_line("let = ;").is_err());
+    assert!(repl.run_line("let b = 2; nope").is_err());
+    assert!(repl.run_line("let c = a / 0").is_err());
+    assert_eq!(repl.run_line("a"), Ok(Object::Integer(1)));
+    for name in ["b", "c"] {
+        assert_eq!(
+            repl.run_line(name),
+            Err(InterpretError::Undefined(format!(
+                "undefined symbol: {} (line 1:1)",
+                name
+            )))
+        );
+    }
+    assert_eq!(repl.run_line("let b = a + 1; b"), Ok(Object::Integer(2)));
+}
+
+/// Both engines share `Object`, so apart from functions they must agree
+/// on every value. Errors only have to be of the same kind, the VM adds
+/// the failing instruction around them
+#[test]
+fn engines_agree() {
+    let programs = [
+        PROGRAM,
+        "1 + 2 * 3 - 4 / 2",
+        "2 ** 70 / 2 ** 68",
+        "-7 ~/ 2",
+        "9223372036854775807 + 1",
+        "[-9223372036854775808, 99999999999999999999 - 1]",
+        r#""a" + "b" * 3"#,
+        r#"["a" == "a", "a" != "a", "a" == "b", "ab" != "b"]"#,
+        "[1, 2 + 3, [4]][1]",
+        r#"let h = {"a": 1, 2: [true], false: "x"}; [h["a"], h[2], h[false], h["nope"]]"#,
+        r#"{"b": 1, "a": 2}"#,
+        "let [a, b, ...c] = [1, 2, 3, 4]; [a, b, c]",
+        "let fib = fn(f, n) { if (n < 2) { n } else { f(f, n - 1) + f(f, n - 2) } }; fib(fib, 15)",
+        "let f = fn(a, b = a * 2, ...rest) { [a, b, rest] }; [f(1), f(1, 5, 6, 7)]",
+        "if (1 > 2) { 10 } else { 20 }",
+        "if (false) { 10 }",
+        "while (false) {}",
+        "for (x in 1..4) {}",
+        "let x = { let a = 1; a + 2 }; x * 2",
+        "let x = 1; let y = { let x = 2; x }; [x, y]",
+        "if (true) { let z = 1; } z",
+        "if (false) { 1 } else { let z = 2; } z",
+        "let x = 1; if (true) { let x = 2; } x",
+        "let x = 1; let y = if (x > 0) { let x = 3; x * 2 }; [x, y]",
+        "let y = { let z = 2; z }; z",
+        "for (x in [1, 2]) { x; }; x",
+        "let x = 0; for (x in [1, 2]) { x; }; x",
+        "let r = []; for (i in 0..3) { let y = if (i == 1) { break; }; r = push(r, y) } r",
+        "let r = []; for (i in 0..3) { r = push(r, [i, { break; }]) } r",
+        "let r = []; for (i in 0..3) { const y = if (i == 1) { continue; } else { i }; r = push(r, y) } r",
+        "let f = fn() { let y = if (true) { return 5; }; 10 }; f()",
+        "let a = [[1], 2]; let b = a; a[0][0] = 3; a[1] = 4; [a, b]",
+        r#"let h = {"a": [1]}; h["a"][0] = 2; h["b"] = 3; h"#,
+        r#"let s = "a,b"; [len(s), upper(s), chars(s), lines("x\ny")]"#,
+        r#"map_values({"a": 1}, fn(v) { v + 1 })"#,
+        "partial(fn(a, b) { a - b }, 10)(3)",
+        r#"from_json(to_json({"k": [1, "two", true]}))"#,
+        "push(push([], 1), 2) |> len",
+        r#"seed(5); [map_values({"k": "rand()"}, parse)["k"], rand()]"#,
+        r#"let x = 5; [parse("x + 1"), parse("{ x = 2; }"), x]"#,
+        r#"let x = 1; each(["x"], parse)"#,
+        "wrapping_mul(9223372036854775807, 2)",
+        "[floor(10000000000000000000000.5), ceil(-(2.0 ** 64))]",
+        "round(2.0 ** 2000)",
+        "1 / 0",
+        "undefined_name",
+        "len(1)",
+        "fn(a) { a }(1, 2)",
+        r#"1 + "a""#,
+    ];
+
+    for src in programs {
+        match (eval_str(src), compile_and_run(src)) {
+            (Ok(e), Ok(v)) => assert_eq!(e, v, "{}", src),
+            (Err(e), Err(v)) => assert_eq!(
+                std::mem::discriminant(&e),
+                std::mem::discriminant(v.unlocated()),
+                "{}: {} vs {}",
+                src,
+                e,
+                v
+            ),
+            (e, v) => panic!("{}: engines disagree, {:?} vs {:?}", src, e, v),
+        }
+    }
+}